@@ -0,0 +1,86 @@
+//! Benchmarks for the layout model.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use icrate::Foundation::{CGPoint, CGRect, CGSize};
+use nimbus_wm::{
+    actor::{
+        app::WindowId,
+        layout::{LayoutEvent, LayoutManager},
+    },
+    model::LayoutKind,
+    sys::screen::SpaceId,
+};
+
+const SIZES: [u32; 3] = [10, 100, 1000];
+
+fn screen() -> CGRect {
+    CGRect::new(CGPoint::new(0., 0.), CGSize::new(1920., 1080.))
+}
+
+/// Creates a layout with `count` windows spread evenly across ten apps.
+fn make_layout(count: u32) -> (LayoutManager, SpaceId) {
+    let space = SpaceId::new(1);
+    let mut layout = LayoutManager::new();
+    _ = layout.handle_event(LayoutEvent::SpaceExposed(
+        space,
+        screen().size,
+        LayoutKind::default(),
+    ));
+    let apps = 10.min(count);
+    for pid in 1..=apps {
+        let windows = (1..=count / apps).map(|idx| WindowId::new(pid as i32, idx)).collect();
+        _ = layout.handle_event(LayoutEvent::WindowsOnScreenUpdated(
+            space, pid as i32, windows,
+        ));
+    }
+    (layout, space)
+}
+
+fn calculate_layout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_layout");
+    for count in SIZES {
+        let (layout, space) = make_layout(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| layout.calculate_layout(black_box(space), black_box(screen())))
+        });
+    }
+    group.finish();
+}
+
+fn add_and_remove_window(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_and_remove_window");
+    for count in SIZES {
+        let (mut layout, space) = make_layout(count);
+        let wid = WindowId::new(1000, 1);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                _ = layout.handle_event(LayoutEvent::WindowAdded(space, wid));
+                _ = layout.handle_event(LayoutEvent::WindowRemoved(wid));
+            })
+        });
+    }
+    group.finish();
+}
+
+fn serialization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialization");
+    for count in SIZES {
+        let (layout, _) = make_layout(count);
+        let serialized = layout.serialize_to_string();
+        group.bench_with_input(BenchmarkId::new("serialize", count), &count, |b, _| {
+            b.iter(|| layout.serialize_to_string())
+        });
+        group.bench_with_input(BenchmarkId::new("deserialize", count), &count, |b, _| {
+            b.iter(|| LayoutManager::deserialize_from_str(black_box(&serialized)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    calculate_layout,
+    add_and_remove_window,
+    serialization
+);
+criterion_main!(benches);
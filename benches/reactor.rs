@@ -0,0 +1,132 @@
+//! Benchmarks for event handling in the reactor.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use icrate::Foundation::{CGPoint, CGRect, CGSize};
+use nimbus_wm::{
+    actor::{
+        app::{pid_t, AppThreadHandle, Request, WindowId, WindowInfo},
+        reactor::{AppState, Event, Reactor},
+    },
+    sys::{app::AppInfo, screen::SpaceId, window_server::WindowServerId},
+};
+use tracing::Span;
+
+const SIZES: [u32; 3] = [10, 100, 1000];
+
+/// A reactor showing one screen, and the requests it sends to apps.
+struct Bench {
+    reactor: Reactor,
+    requests_tx: Sender<(Span, Request)>,
+    requests_rx: Receiver<(Span, Request)>,
+}
+
+impl Bench {
+    fn new() -> Bench {
+        let mut reactor = Reactor::new_for_test();
+        let screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1920., 1080.));
+        reactor.handle_events(vec![Event::ScreenParametersChanged(
+            vec![screen],
+            vec![Some(SpaceId::new(1))],
+        )]);
+        let (requests_tx, requests_rx) = channel();
+        Bench {
+            reactor,
+            requests_tx,
+            requests_rx,
+        }
+    }
+
+    fn launch_events(&self, pid: pid_t, count: u32) -> Vec<Event> {
+        let app = AppState {
+            info: AppInfo {
+                bundle_id: Some(format!("com.bench{pid}")),
+                localized_name: Some(format!("Bench{pid}")),
+                bundle_path: None,
+                icon_path: None,
+                group: None,
+            },
+            handle: AppThreadHandle::new_for_test(self.requests_tx.clone()),
+            main_window: None,
+            is_frontmost: false,
+        };
+        vec![
+            Event::ApplicationLaunched(pid, app),
+            Event::WindowsDiscovered {
+                pid,
+                new: (1..=count).map(|idx| (WindowId::new(pid, idx), window(idx))).collect(),
+                known_visible: vec![],
+            },
+        ]
+    }
+
+    fn handle_events(&mut self, events: Vec<Event>) {
+        self.reactor.handle_events(events);
+        // Keep requests from piling up across iterations.
+        self.requests_rx.try_iter().for_each(drop);
+    }
+}
+
+fn window(idx: u32) -> WindowInfo {
+    WindowInfo {
+        is_standard: true,
+        title: format!("Window{idx}"),
+        frame: CGRect::new(CGPoint::new(0., 0.), CGSize::new(500., 500.)),
+        sys_id: WindowServerId::new(idx),
+        subrole: "AXStandardWindow".to_string(),
+        level: 0,
+        is_resizable: true,
+        is_minimized: false,
+        is_fullscreen: false,
+        parent: None,
+        is_pip: false,
+    }
+}
+
+/// Launches and terminates an app with many windows.
+fn app_lifecycle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("app_lifecycle");
+    for count in SIZES {
+        let mut bench = Bench::new();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter_batched(
+                || {
+                    let mut events = bench.launch_events(1, count);
+                    events.push(Event::ApplicationTerminated(1));
+                    events
+                },
+                |events| bench.handle_events(events),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Creates and destroys one window while many others are on screen.
+fn window_churn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("window_churn");
+    for count in SIZES {
+        let mut bench = Bench::new();
+        let events = bench.launch_events(1, count);
+        bench.handle_events(events);
+        let wid = WindowId::new(1, count + 1);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter_batched(
+                || {
+                    vec![
+                        Event::WindowCreated(wid, window(count + 1)),
+                        Event::WindowDestroyed(wid),
+                    ]
+                },
+                |events| bench.handle_events(events),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, app_lifecycle, window_churn);
+criterion_main!(benches);
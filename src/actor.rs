@@ -5,7 +5,11 @@
 //! overall behavior of the window manager.
 
 pub mod app;
+pub mod borders;
+pub mod ipc;
 pub mod layout;
+pub mod mouse;
 pub mod notification_center;
 pub mod reactor;
+pub mod split_handles;
 pub mod wm_controller;
@@ -5,24 +5,26 @@
 
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
+    mem,
     num::NonZeroU32,
     rc::{Rc, Weak},
     sync::{
         atomic::{AtomicI32, Ordering},
-        mpsc::{channel, Receiver, Sender},
+        mpsc::{channel, Receiver, Sender, TrySendError},
         Arc, Mutex,
     },
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use accessibility::{AXUIElement, AXUIElementActions, AXUIElementAttributes};
 use accessibility_sys::{
     kAXApplicationActivatedNotification, kAXApplicationDeactivatedNotification,
-    kAXMainWindowChangedNotification, kAXTitleChangedNotification,
-    kAXUIElementDestroyedNotification, kAXWindowCreatedNotification,
+    kAXErrorAPIDisabled, kAXErrorNotificationAlreadyRegistered,
+    kAXFocusedWindowChangedNotification, kAXMainWindowChangedNotification,
+    kAXTitleChangedNotification, kAXUIElementDestroyedNotification, kAXWindowCreatedNotification,
     kAXWindowDeminiaturizedNotification, kAXWindowMiniaturizedNotification,
     kAXWindowMovedNotification, kAXWindowResizedNotification, kAXWindowRole,
 };
@@ -36,13 +38,16 @@ use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, instrument, trace, warn, Span};
 
 use crate::{
-    actor::reactor::{AppState, Event, Requested, TransactionId},
+    actor::reactor::{self, AppState, Event, Requested, TransactionId},
+    metrics::{self, Timing},
     sys::{
-        app::running_apps,
         geometry::{ToCGType, ToICrate},
+        menu,
         observer::Observer,
-        run_loop::WakeupHandle,
-        window_server::WindowServerId,
+        run_loop::{self, WakeupHandle},
+        screen::active_display_bounds,
+        thread::ThreadCpu,
+        window_server::{order_window, WindowOrder, WindowServerId},
     },
 };
 
@@ -52,6 +57,11 @@ pub use crate::sys::app::{pid_t, AppInfo, WindowInfo};
 ///
 /// This identifier is only valid for the lifetime of the process that owns it.
 /// It is not stable across restarts of the window manager.
+///
+/// Windows are identified by their window server id (a `CGWindowID`) where
+/// possible, which stays the same for as long as the window exists. Windows
+/// the window server gives no id get one counting down from the top of the
+/// range, so that it can't be mistaken for a window server id.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct WindowId {
     pub pid: pid_t,
@@ -59,8 +69,8 @@ pub struct WindowId {
 }
 
 impl WindowId {
-    #[cfg(test)]
-    pub(crate) fn new(pid: pid_t, idx: u32) -> WindowId {
+    #[cfg(any(test, feature = "bench"))]
+    pub fn new(pid: pid_t, idx: u32) -> WindowId {
         WindowId {
             pid,
             idx: NonZeroU32::new(idx).unwrap(),
@@ -71,14 +81,17 @@ impl WindowId {
 pub struct AppThreadHandle {
     requests_tx: Sender<(Span, Request)>,
     wakeup: WakeupHandle,
+    /// The app thread, which is missing for the fake apps in tests.
+    thread: Option<ThreadCpu>,
 }
 
 impl AppThreadHandle {
-    #[cfg(test)]
-    pub(crate) fn new_for_test(requests_tx: Sender<(Span, Request)>) -> Self {
+    #[cfg(any(test, feature = "bench"))]
+    pub fn new_for_test(requests_tx: Sender<(Span, Request)>) -> Self {
         let this = AppThreadHandle {
             requests_tx,
             wakeup: WakeupHandle::for_current_thread(0, || {}),
+            thread: None,
         };
         this
     }
@@ -88,6 +101,12 @@ impl AppThreadHandle {
         self.wakeup.wake();
         Ok(())
     }
+
+    /// Returns the CPU time the app thread has used so far, which is mostly
+    /// spent handling the app's notifications and our requests.
+    pub fn cpu_time(&self) -> Option<Duration> {
+        self.thread?.time()
+    }
 }
 
 impl Debug for AppThreadHandle {
@@ -98,11 +117,26 @@ impl Debug for AppThreadHandle {
 
 #[derive(Debug, Clone)]
 pub enum Request {
+    /// Sends a VisibleWindowsChanged event with the windows that appeared or
+    /// disappeared since the last time the visible windows were reported.
     GetVisibleWindows,
+    /// Sends an ApplicationActivated or ApplicationDeactivated event with the
+    /// current state of the app.
+    ///
+    /// This is used to recover from missed accessibility notifications.
+    GetActivationState,
 
     SetWindowFrame(WindowId, CGRect, TransactionId),
     SetWindowPos(WindowId, CGPoint, TransactionId),
 
+    /// Moves the window out of the visible area of the screens.
+    ///
+    /// Unlike minimizing, this is instant to undo: the next SetWindowFrame
+    /// request restores the window. A single pixel of the window is left on
+    /// screen, because the system moves windows that are entirely off-screen
+    /// back into view.
+    HideWindow(WindowId),
+
     /// Temporarily suspends position and size update events for this window.
     BeginWindowAnimation(WindowId),
     /// Resumes position and size events for the window. One position and size
@@ -110,6 +144,21 @@ pub enum Request {
     EndWindowAnimation(WindowId),
 
     Raise(WindowId, RaiseToken),
+    /// Brings a single window forward and gives it focus within its app,
+    /// without activating the app.
+    ///
+    /// Activating an app brings all of its windows forward, so this should be
+    /// used when the app already has keyboard focus.
+    RaiseWindowOnly(WindowId, RaiseToken),
+    /// Moves the window to the front of its level without focusing it or
+    /// activating the app.
+    OrderFront(WindowId),
+    /// Replaces the observer for the app's notifications with a new one, for
+    /// when the old one seems to have stopped working.
+    Rewatch,
+    /// Asks the app to reopen the closed window with the given title, using
+    /// its menu item for that if it has one, and activates the app.
+    ReopenWindow(String, RaiseToken),
 }
 
 /// Prevents stale activation requests from happening after more recent ones.
@@ -143,37 +192,247 @@ impl RaiseToken {
     }
 }
 
-pub fn spawn_initial_app_threads(events_tx: Sender<(Span, Event)>) {
-    for (pid, info) in running_apps(None) {
-        spawn_app_thread(pid, info, events_tx.clone());
-    }
-}
-
-pub fn spawn_app_thread(pid: pid_t, info: AppInfo, events_tx: Sender<(Span, Event)>) {
+pub fn spawn_app_thread(pid: pid_t, info: AppInfo, events_tx: reactor::Sender) {
     thread::spawn(move || app_thread_main(pid, info, events_tx));
 }
 
 struct State {
     app: AXUIElement,
     windows: HashMap<WindowId, WindowState>,
-    events_tx: Sender<(Span, Event)>,
+    events_tx: reactor::Sender,
+    /// Frame changes that did not fit in the reactor's channel. Only the
+    /// latest one for each window is kept.
+    held_frames: HashMap<WindowId, (Span, Event)>,
     requests_rx: Receiver<(Span, Request)>,
     pid: pid_t,
     running_app: Id<NSRunningApplication>,
     bundle_id: Option<String>,
-    last_window_idx: u32,
+    /// The id last given to a window without a window server id.
+    last_fallback_idx: u32,
     observer: Observer,
+    /// The windows last reported to the reactor as visible, so we only need to
+    /// send what changed.
+    visible: HashSet<WindowId>,
+    /// This state, for callbacks scheduled on the run loop.
+    this: Weak<RefCell<State>>,
 }
 
 struct WindowState {
     elem: AXUIElement,
     last_seen_txid: TransactionId,
+    /// Measured over the first frames we set for the window.
+    decoration_offset: DecorationProbe,
+    frame_notifs: NotificationRate,
+}
+
+/// Limits how often move and resize notifications from a window are passed
+/// on to the reactor.
+///
+/// Some games and video apps move or resize a window thousands of times a
+/// second. Once a window sends more notifications than [`Self::LIMIT`] in a
+/// second it is marked as noisy, and its frame is only sent every
+/// [`Self::INTERVAL`] until it calms down.
+#[derive(Debug, Default)]
+struct NotificationRate {
+    /// The start of the current one second period, and the notifications
+    /// received during it.
+    period: Option<(Instant, u32)>,
+    is_noisy: bool,
+    /// When the frame was last sent while noisy.
+    last_sent: Option<Instant>,
+    /// A frame was held back, and will be sent when the interval is up.
+    is_flush_scheduled: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Forward {
+    Now,
+    /// Send the frame after this long, when the interval is up.
+    Later(Duration),
+    /// A frame will already be sent later.
+    Skip,
+}
+
+/// The window an app's main window changed to, as found by
+/// [`State::report_main_window`].
+#[derive(Debug)]
+pub enum MainWindow {
+    /// A window we already track.
+    Known(WindowId),
+    /// A window we just started tracking, which the reactor hasn't heard of.
+    New(WindowId, WindowInfo),
+    /// No window, or one we can't manage.
+    None,
+}
+
+impl MainWindow {
+    /// Returns the events that tell the reactor about the change.
+    pub fn into_events(self, pid: pid_t) -> Vec<Event> {
+        match self {
+            MainWindow::Known(wid) => vec![Event::ApplicationMainWindowChanged(pid, Some(wid))],
+            MainWindow::New(wid, info) => vec![
+                Event::WindowCreated(wid, info),
+                Event::ApplicationMainWindowChanged(pid, Some(wid)),
+            ],
+            MainWindow::None => vec![Event::ApplicationMainWindowChanged(pid, None)],
+        }
+    }
+}
+
+impl NotificationRate {
+    const PERIOD: Duration = Duration::from_secs(1);
+    const LIMIT: u32 = 200;
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Counts a notification received at `now`, and returns when its frame
+    /// should be sent.
+    fn notify(&mut self, now: Instant) -> Forward {
+        match &mut self.period {
+            Some((start, count)) if now - *start < Self::PERIOD => *count += 1,
+            period => {
+                // The window calms down once a whole period passes without a
+                // storm, including one with no notifications at all.
+                let calm = match period {
+                    Some((start, count)) => {
+                        *count <= Self::LIMIT || now - *start >= 2 * Self::PERIOD
+                    }
+                    None => true,
+                };
+                if calm {
+                    self.is_noisy = false;
+                }
+                *period = Some((now, 1));
+            }
+        }
+        if self.period.is_some_and(|(_, count)| count > Self::LIMIT) {
+            self.is_noisy = true;
+        }
+        if !self.is_noisy {
+            return Forward::Now;
+        }
+        if self.is_flush_scheduled {
+            return Forward::Skip;
+        }
+        match self.last_sent {
+            Some(last) if now - last < Self::INTERVAL => {
+                self.is_flush_scheduled = true;
+                Forward::Later(Self::INTERVAL - (now - last))
+            }
+            _ => {
+                self.last_sent = Some(now);
+                Forward::Now
+            }
+        }
+    }
+
+    /// Records that a held back frame was sent at `now`.
+    fn flushed(&mut self, now: Instant) {
+        self.is_flush_scheduled = false;
+        self.last_sent = Some(now);
+    }
+}
+
+/// How far the origin a window reports is from the one we asked it to take.
+///
+/// Apps that draw their own title bar, or hide it, can report an origin that
+/// is consistently offset from the one we set. Only the origin is measured:
+/// a different size usually means the window refused the size we asked for,
+/// e.g. because of its minimum size, and asking for a bigger one would not
+/// help.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct DecorationOffset {
+    x: f64,
+    y: f64,
+}
+
+impl DecorationOffset {
+    /// Differences larger than this are not from decorations, but from the
+    /// window refusing the position, e.g. because it would not fit.
+    const MAX: f64 = 64.0;
+
+    fn measure(requested: CGRect, actual: CGRect) -> Option<DecorationOffset> {
+        let offset = DecorationOffset {
+            x: (actual.origin.x - requested.origin.x).round(),
+            y: (actual.origin.y - requested.origin.y).round(),
+        };
+        if offset.x.abs() > Self::MAX || offset.y.abs() > Self::MAX {
+            return None;
+        }
+        Some(offset)
+    }
+
+    /// Returns the frame to ask for so the window reports `frame`.
+    fn compensate(&self, frame: CGRect) -> CGRect {
+        CGRect::new(self.compensate_origin(frame.origin), frame.size)
+    }
+
+    fn compensate_origin(&self, origin: CGPoint) -> CGPoint {
+        CGPoint::new(origin.x - self.x, origin.y - self.y)
+    }
+}
+
+/// Settles on a [`DecorationOffset`] for a window once the same one has been
+/// measured for [`Self::SAMPLES`] frames in a row.
+///
+/// A single measurement can be thrown off by a window that is still animating
+/// or that moved itself, and a wrong offset would stick for the life of the
+/// window.
+#[derive(Debug, Default)]
+struct DecorationProbe {
+    candidate: Option<DecorationOffset>,
+    matches: u32,
+    settled: Option<DecorationOffset>,
+}
+
+impl DecorationProbe {
+    const SAMPLES: u32 = 3;
+
+    /// The offset to compensate for, which is none until one has settled.
+    fn offset(&self) -> DecorationOffset {
+        self.settled.unwrap_or_default()
+    }
+
+    /// Records the frame a window reported after we asked for `requested`,
+    /// and returns the offset if this settled it.
+    fn observe(&mut self, requested: CGRect, actual: CGRect) -> Option<DecorationOffset> {
+        if self.settled.is_some() {
+            return None;
+        }
+        let Some(offset) = DecorationOffset::measure(requested, actual) else {
+            self.candidate = None;
+            self.matches = 0;
+            return None;
+        };
+        if self.candidate == Some(offset) {
+            self.matches += 1;
+        } else {
+            self.candidate = Some(offset);
+            self.matches = 1;
+        }
+        if self.matches < Self::SAMPLES {
+            return None;
+        }
+        self.settled = Some(offset);
+        self.settled
+    }
 }
 
+/// How long to wait before trying again to send frame changes that did not
+/// fit in the reactor's channel.
+const HELD_FRAME_RETRY: Duration = Duration::from_millis(50);
+
+/// How long to wait before trying again to watch an app that could not be
+/// watched. The wait doubles after each attempt.
+const WATCH_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// With the backoff, this gives up on an app after about a minute.
+const MAX_WATCH_ATTEMPTS: u32 = 10;
+
 const APP_NOTIFICATIONS: &[&str] = &[
     kAXApplicationActivatedNotification,
     kAXApplicationDeactivatedNotification,
     kAXMainWindowChangedNotification,
+    kAXFocusedWindowChangedNotification,
     kAXWindowCreatedNotification,
 ];
 
@@ -190,18 +449,25 @@ const WINDOW_ANIMATION_NOTIFICATIONS: &[&str] =
     &[kAXWindowMovedNotification, kAXWindowResizedNotification];
 
 impl State {
-    #[instrument(skip_all, fields(?info))]
-    #[must_use]
-    fn init(&mut self, handle: AppThreadHandle, info: AppInfo) -> bool {
-        // Register for notifications on the application element.
+    /// Registers for notifications on the application element.
+    ///
+    /// This often fails until a moment after the app launches, and can be
+    /// called again until it succeeds.
+    fn watch_app(&self) -> Result<(), accessibility::Error> {
         for notif in APP_NOTIFICATIONS {
-            let res = self.observer.add_notification(&self.app, notif);
-            if let Err(err) = res {
-                debug!(pid = ?self.pid, ?err, "Watching app failed");
-                return false;
+            match self.observer.add_notification(&self.app, notif) {
+                Ok(()) | Err(accessibility::Error::Ax(kAXErrorNotificationAlreadyRegistered)) => {}
+                Err(err) => return Err(err),
             }
         }
+        Ok(())
+    }
 
+    /// Reports the app and its windows to the reactor, once [`Self::watch_app`]
+    /// succeeded. `retried` is whether watching the app failed at first.
+    #[instrument(skip_all, fields(?info))]
+    #[must_use]
+    fn init(&mut self, handle: AppThreadHandle, info: AppInfo, retried: bool) -> bool {
         // Now that we will observe new window events, read the list of windows.
         let Ok(initial_window_elements) = self.app.windows() else {
             // This is probably not a normal application, or it has exited.
@@ -216,11 +482,12 @@ impl State {
             let Ok(info) = WindowInfo::try_from(&elem) else {
                 continue;
             };
-            let Some(wid) = self.register_window(elem) else {
+            let Some(wid) = self.register_window(elem, info.sys_id) else {
                 continue;
             };
             windows.push((wid, info));
         }
+        self.visible = windows.iter().map(|(wid, _)| *wid).collect();
 
         // Send the ApplicationLaunched event.
         let app_state = AppState {
@@ -251,6 +518,9 @@ impl State {
             debug!(pid = ?self.pid, "Failed to send ApplicationLaunched event, exiting thread");
             return false;
         };
+        if retried {
+            self.send_event(Event::ApplicationObservable(self.pid));
+        }
 
         true
     }
@@ -262,42 +532,62 @@ impl State {
                 let window_elems = match self.app.windows() {
                     Ok(elems) => elems,
                     Err(e) => {
-                        // Send an empty event so that any previously known
-                        // windows for this app are cleared.
-                        self.send_event(Event::WindowsDiscovered {
+                        // Report every window as hidden so that any previously
+                        // known windows for this app are cleared.
+                        let hidden = self.visible.drain().collect();
+                        self.send_event(Event::VisibleWindowsChanged {
                             pid: self.pid,
                             new: Default::default(),
-                            known_visible: Default::default(),
+                            shown: Default::default(),
+                            hidden,
                         });
                         return Err(e);
                     }
                 };
-                let mut new = Vec::with_capacity(window_elems.len() as usize);
-                let mut known_visible = Vec::with_capacity(window_elems.len() as usize);
+                let mut new = vec![];
+                let mut shown = vec![];
+                let mut visible = HashSet::with_capacity(window_elems.len() as usize);
                 for elem in window_elems.iter() {
                     let elem = elem.clone();
                     // FIXME: This check is quadratic.
                     if let Ok(id) = self.id(&elem) {
-                        known_visible.push(id);
+                        if !self.visible.contains(&id) {
+                            shown.push(id);
+                        }
+                        visible.insert(id);
                         continue;
                     }
                     let Ok(info) = WindowInfo::try_from(&elem) else {
                         continue;
                     };
-                    let Some(wid) = self.register_window(elem) else {
+                    let Some(wid) = self.register_window(elem, info.sys_id) else {
                         continue;
                     };
+                    visible.insert(wid);
                     new.push((wid, info));
                 }
-                self.send_event(Event::WindowsDiscovered {
+                let hidden = self.visible.difference(&visible).copied().collect();
+                self.visible = visible;
+                self.send_event(Event::VisibleWindowsChanged {
                     pid: self.pid,
                     new,
-                    known_visible,
+                    shown,
+                    hidden,
                 });
             }
+            Request::GetActivationState => {
+                let is_frontmost = self.app.frontmost().map(|b| b.into()).unwrap_or(false);
+                if is_frontmost {
+                    let main = self.app.main_window().ok().and_then(|w| self.id(&w).ok());
+                    self.send_event(Event::ApplicationActivated(self.pid, main));
+                } else {
+                    self.send_event(Event::ApplicationDeactivated(self.pid));
+                }
+            }
             Request::SetWindowPos(wid, pos, txid) => {
                 let window = self.window_mut(wid)?;
                 window.last_seen_txid = txid;
+                let pos = window.decoration_offset.offset().compensate_origin(pos);
                 trace("set_position", &window.elem, || {
                     window.elem.set_position(pos.to_cgtype())
                 })?;
@@ -312,26 +602,42 @@ impl State {
             Request::SetWindowFrame(wid, frame, txid) => {
                 let window = self.window_mut(wid)?;
                 window.last_seen_txid = txid;
+                let target = frame;
+                let frame = window.decoration_offset.offset().compensate(target);
                 trace("set_position", &window.elem, || {
                     window.elem.set_position(frame.origin.to_cgtype())
                 })?;
                 trace("set_size", &window.elem, || {
                     window.elem.set_size(frame.size.to_cgtype())
                 })?;
-                let frame = trace("frame", &window.elem, || window.elem.frame())?;
+                let actual = trace("frame", &window.elem, || window.elem.frame())?.to_icrate();
+                if let Some(offset) = window.decoration_offset.observe(target, actual) {
+                    if offset != DecorationOffset::default() {
+                        debug!(?wid, ?offset, "Compensating for window decorations");
+                    }
+                }
                 self.send_event(Event::WindowFrameChanged(
                     wid,
-                    frame.to_icrate(),
+                    actual,
                     txid,
                     Requested(true),
                 ));
             }
+            Request::HideWindow(wid) => {
+                let window = self.window(wid)?;
+                let Some(pos) = hidden_position(&active_display_bounds()) else {
+                    return Ok(());
+                };
+                trace("set_position", &window.elem, || {
+                    window.elem.set_position(pos.to_cgtype())
+                })?;
+            }
             Request::BeginWindowAnimation(wid) => {
                 let window = self.window(wid)?;
                 self.stop_notifications_for_animation(&window.elem);
             }
             Request::EndWindowAnimation(wid) => {
-                let &WindowState { ref elem, last_seen_txid } = self.window(wid)?;
+                let &WindowState { ref elem, last_seen_txid, .. } = self.window(wid)?;
                 self.restart_notifications_after_animation(elem);
                 let frame = trace("frame", elem, || elem.frame())?;
                 self.send_event(Event::WindowFrameChanged(
@@ -381,24 +687,41 @@ impl State {
                 // meantime, we would "undo" our activation in favor of the app
                 // that is supposed to be activated. This requires taking into
                 // account user-initiated activations.
+                token.with(self.pid, || self.activate());
+            }
+            Request::RaiseWindowOnly(wid, token) => {
+                let window = self.window(wid)?;
                 token
                     .with(self.pid, || {
-                        // This option is deprecated, but there is no alternative.
-                        #[allow(non_upper_case_globals)]
-                        const NSApplicationActivateIgnoringOtherApps:
-                            NSApplicationActivationOptions = 1 << 1;
-                        let success = unsafe {
-                            // This should be marked as safe.
-                            self.running_app
-                                .activateWithOptions(NSApplicationActivateIgnoringOtherApps)
-                        };
-                        if !success {
-                            warn!(?self.pid, "Failed to activate app");
+                        let reordered = WindowServerId::try_from(&window.elem)
+                            .map(|id| order_window(id, WindowOrder::Above, None).is_ok())
+                            .unwrap_or(false);
+                        if !reordered {
+                            // Raising with the accessibility API only reorders
+                            // windows within the app, which is fine here.
+                            trace("raise", &window.elem, || window.elem.raise())?;
                         }
-                        Ok(())
+                        trace("set_main", &window.elem, || window.elem.set_main(true))
                     })
                     .unwrap_or(Ok(()))?;
             }
+            Request::OrderFront(wid) => {
+                let window = self.window(wid)?;
+                let id = WindowServerId::try_from(&window.elem)?;
+                if let Err(err) = order_window(id, WindowOrder::Above, None) {
+                    debug!(?wid, ?err, "Could not order window to the front");
+                }
+            }
+            Request::Rewatch => {
+                self.rewatch()?;
+            }
+            Request::ReopenWindow(title, token) => {
+                if !menu::press_reopen_item(&self.app, &title) {
+                    // Activating an app without windows usually opens one.
+                    debug!(?title, "App has no menu item to reopen the window");
+                }
+                token.with(self.pid, || self.activate());
+            }
         }
         Ok(())
     }
@@ -424,19 +747,25 @@ impl State {
                 self.send_event(Event::ApplicationDeactivated(self.pid));
             }
             kAXMainWindowChangedNotification => {
-                let main = self.id(&elem).ok();
-                if main.is_none() {
-                    // I suspect we may hit this at some point (before receiving the
-                    // window created notification); it isn't handled correctly today.
-                    error!("Got MainWindowChanged on unknown window {elem:?}");
-                }
-                self.send_event(Event::ApplicationMainWindowChanged(self.pid, main));
+                self.report_main_window(elem);
+            }
+            kAXFocusedWindowChangedNotification => {
+                // Some apps only send this when cycling their windows with
+                // Cmd+`, so check whether the main window changed with it.
+                let Ok(main) = self.app.main_window() else {
+                    return;
+                };
+                self.report_main_window(main);
             }
             kAXWindowCreatedNotification => {
+                if self.id(&elem).is_ok() {
+                    // Already registered when it became the main window.
+                    return;
+                }
                 let Ok(window) = WindowInfo::try_from(&elem) else {
                     return;
                 };
-                let Some(wid) = self.register_window(elem) else {
+                let Some(wid) = self.register_window(elem, window.sys_id) else {
                     return;
                 };
                 self.send_event(Event::WindowCreated(wid, window));
@@ -446,6 +775,7 @@ impl State {
                     return;
                 };
                 self.windows.remove(&wid);
+                self.visible.remove(&wid);
                 self.send_event(Event::WindowDestroyed(wid));
             }
             kAXWindowMovedNotification | kAXWindowResizedNotification => {
@@ -457,74 +787,251 @@ impl State {
                 let Ok(wid) = self.id(&elem) else {
                     return;
                 };
-                let last_seen = self.window(wid).unwrap().last_seen_txid;
-                let Ok(frame) = elem.frame() else {
-                    return;
-                };
-                self.send_event(Event::WindowFrameChanged(
-                    wid,
-                    frame.to_icrate(),
-                    last_seen,
-                    Requested(false),
-                ));
+                let window = self.window_mut(wid).unwrap();
+                let was_noisy = window.frame_notifs.is_noisy;
+                let forward = window.frame_notifs.notify(Instant::now());
+                if window.frame_notifs.is_noisy && !was_noisy {
+                    warn!(
+                        ?wid,
+                        bundle_id = ?self.bundle_id,
+                        "Window is sending too many move and resize notifications; \
+                         limiting how often its frame is updated"
+                    );
+                }
+                match forward {
+                    Forward::Now => self.send_frame(wid),
+                    Forward::Later(delay) => {
+                        let this = self.this.clone();
+                        run_loop::run_after(delay, move || {
+                            let Some(state) = this.upgrade() else { return };
+                            let mut state = state.borrow_mut();
+                            let Ok(window) = state.window_mut(wid) else { return };
+                            window.frame_notifs.flushed(Instant::now());
+                            state.send_frame(wid);
+                        });
+                    }
+                    Forward::Skip => (),
+                }
             }
             kAXWindowMiniaturizedNotification => {}
             kAXWindowDeminiaturizedNotification => {}
-            kAXTitleChangedNotification => {}
+            kAXTitleChangedNotification => {
+                let Ok(wid) = self.id(&elem) else {
+                    return;
+                };
+                let Ok(title) = elem.title() else {
+                    return;
+                };
+                self.send_event(Event::WindowTitleChanged(wid, title.to_string()));
+            }
             _ => {
                 error!("Unhandled notification {notif:?} on {elem:#?}");
             }
         }
     }
 
+    /// Reads the frame of a window and sends it to the reactor.
+    fn send_frame(&mut self, wid: WindowId) {
+        let Ok(window) = self.window(wid) else { return };
+        let last_seen = window.last_seen_txid;
+        let Ok(frame) = window.elem.frame() else {
+            return;
+        };
+        self.send_event(Event::WindowFrameChanged(
+            wid,
+            frame.to_icrate(),
+            last_seen,
+            Requested(false),
+        ));
+    }
+
+    fn activate(&self) {
+        // This option is deprecated, but there is no alternative.
+        #[allow(non_upper_case_globals)]
+        const NSApplicationActivateIgnoringOtherApps: NSApplicationActivationOptions = 1 << 1;
+        let success = unsafe {
+            // This should be marked as safe.
+            self.running_app.activateWithOptions(NSApplicationActivateIgnoringOtherApps)
+        };
+        if !success {
+            warn!(?self.pid, "Failed to activate app");
+        }
+    }
+
+    /// The name of the app in timings.
+    fn metrics_name(&self) -> String {
+        self.bundle_id.clone().unwrap_or_else(|| format!("pid {}", self.pid))
+    }
+
+    /// Tells the reactor that `elem` is now the main window, so that commands
+    /// start from it.
+    ///
+    /// The main window can change before we get the notification that it was
+    /// created, in which case we register it first.
+    fn report_main_window(&mut self, elem: AXUIElement) {
+        let main = match self.id(&elem) {
+            Ok(wid) => MainWindow::Known(wid),
+            Err(_) => {
+                let window = WindowInfo::try_from(&elem).ok();
+                let new = window.and_then(|window| {
+                    let wid = self.register_window(elem.clone(), window.sys_id)?;
+                    Some(MainWindow::New(wid, window))
+                });
+                new.unwrap_or_else(|| {
+                    debug!("Main window changed to a window we can't manage: {elem:?}");
+                    MainWindow::None
+                })
+            }
+        };
+        for event in main.into_events(self.pid) {
+            self.send_event(event);
+        }
+    }
+
+    /// Starts tracking a window, identified by `sys_id` if the window server
+    /// gave it one.
+    ///
+    /// The window server reuses the id of a closed window for new windows. If
+    /// we still have a window under the id, we must have missed that it was
+    /// closed, so we tell the reactor now.
     #[must_use]
-    fn register_window(&mut self, elem: AXUIElement) -> Option<WindowId> {
-        if !register_notifs(&elem, self) {
+    fn register_window(&mut self, elem: AXUIElement, sys_id: WindowServerId) -> Option<WindowId> {
+        if !self.watch_window(&elem) {
             return None;
         }
-        let idx = WindowServerId::try_from(&elem)
-            .or_else(|e| {
-                info!("Could not get window server id for {elem:?}: {e}");
-                Err(e)
-            })
-            .ok()
-            .map(|id| NonZeroU32::new(id.as_u32()).expect("Window server id was 0"))
-            .unwrap_or_else(|| {
-                self.last_window_idx += 1;
-                NonZeroU32::new(self.last_window_idx).unwrap()
-            });
+        let idx = NonZeroU32::new(sys_id.as_u32()).unwrap_or_else(|| {
+            info!("Window server id was 0 for {elem:?}");
+            self.fallback_idx()
+        });
         let wid = WindowId { pid: self.pid, idx };
+        if self.windows.get(&wid).is_some_and(|window| window.elem != elem) {
+            info!(?wid, "Window server id was reused; the old window is gone");
+            self.windows.remove(&wid);
+            self.visible.remove(&wid);
+            self.held_frames.remove(&wid);
+            self.send_event(Event::WindowDestroyed(wid));
+        }
         let old = self.windows.insert(
             wid,
             WindowState {
                 elem,
                 last_seen_txid: TransactionId::default(),
+                decoration_offset: DecorationProbe::default(),
+                frame_notifs: NotificationRate::default(),
             },
         );
-        assert!(old.is_none(), "Duplicate window id {wid:?}");
-        return Some(wid);
-
-        fn register_notifs(win: &AXUIElement, state: &State) -> bool {
-            // Filter out elements that aren't regular windows.
-            match win.role() {
-                Ok(role) if role == kAXWindowRole => (),
-                _ => return false,
-            }
-            for notif in WINDOW_NOTIFICATIONS {
-                let res = state.observer.add_notification(win, notif);
-                if let Err(err) = res {
-                    trace!("Watching failed with error {err:?} on window {win:#?}");
-                    return false;
-                }
+        if old.is_some() {
+            debug!(?wid, "Window was registered twice");
+        }
+        Some(wid)
+    }
+
+    /// Returns an unused id for a window without a window server id.
+    fn fallback_idx(&mut self) -> NonZeroU32 {
+        loop {
+            self.last_fallback_idx -= 1;
+            let idx = NonZeroU32::new(self.last_fallback_idx).unwrap();
+            if !self.windows.contains_key(&WindowId { pid: self.pid, idx }) {
+                return idx;
+            }
+        }
+    }
+
+    /// Registers for notifications on a window. Returns false if it isn't a
+    /// regular window or can't be watched.
+    fn watch_window(&self, win: &AXUIElement) -> bool {
+        // Filter out elements that aren't regular windows.
+        match win.role() {
+            Ok(role) if role == kAXWindowRole => (),
+            _ => return false,
+        }
+        for notif in WINDOW_NOTIFICATIONS {
+            let res = self.observer.add_notification(win, notif);
+            if let Err(err) = res {
+                trace!("Watching failed with error {err:?} on window {win:#?}");
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Replaces the observer with a new one and registers for the same
+    /// notifications, for when the accessibility connection to the app broke
+    /// and the old observer went silent.
+    fn rewatch(&mut self) -> Result<(), accessibility::Error> {
+        let this = self.this.clone();
+        self.observer = Observer::new(self.pid)?.install(move |elem, notif| {
+            if let Some(state) = this.upgrade() {
+                state.borrow_mut().handle_notification(elem, notif)
+            }
+        });
+        self.watch_app()?;
+        for window in self.windows.values() {
+            if !self.watch_window(&window.elem) {
+                // The window is probably gone, and its destroyed
+                // notification was one we missed.
+                debug!(elem = ?window.elem, "Could not watch window again");
             }
-            true
         }
+        Ok(())
     }
 
-    fn send_event(&self, event: Event) {
+    fn send_event(&mut self, event: Event) {
+        if let Event::WindowFrameChanged(wid, ..) = event {
+            self.send_frame_event(wid, event);
+            return;
+        }
+        // Other events are never dropped, and must not overtake the frames
+        // held back before them.
+        self.flush_held_frames();
         self.events_tx.send((Span::current(), event)).unwrap();
     }
 
+    /// Sends a frame change if there is room in the reactor's channel, and
+    /// otherwise holds it back in place of any older frame for the window.
+    fn send_frame_event(&mut self, wid: WindowId, event: Event) {
+        let msg = (Span::current(), event);
+        if !self.held_frames.is_empty() {
+            self.held_frames.insert(wid, msg);
+            return;
+        }
+        match self.events_tx.try_send(msg) {
+            Ok(()) => (),
+            Err(TrySendError::Full(msg)) => {
+                debug!(pid = ?self.pid, "Reactor channel is full; holding back frame changes");
+                self.held_frames.insert(wid, msg);
+                self.retry_held_frames_later();
+            }
+            Err(TrySendError::Disconnected(_)) => panic!("Reactor is gone"),
+        }
+    }
+
+    fn retry_held_frames_later(&self) {
+        let this = self.this.clone();
+        run_loop::run_after(HELD_FRAME_RETRY, move || {
+            if let Some(state) = this.upgrade() {
+                state.borrow_mut().retry_held_frames();
+            }
+        });
+    }
+
+    fn retry_held_frames(&mut self) {
+        while let Some(&wid) = self.held_frames.keys().next() {
+            let msg = self.held_frames.remove(&wid).unwrap();
+            if let Err(TrySendError::Full(msg)) = self.events_tx.try_send(msg) {
+                self.held_frames.insert(wid, msg);
+                self.retry_held_frames_later();
+                return;
+            }
+        }
+    }
+
+    fn flush_held_frames(&mut self) {
+        for (_, msg) in mem::take(&mut self.held_frames) {
+            self.events_tx.send(msg).unwrap();
+        }
+    }
+
     fn window(&self, wid: WindowId) -> Result<&WindowState, accessibility::Error> {
         assert_eq!(wid.pid, self.pid);
         self.windows.get(&wid).ok_or(accessibility::Error::NotFound)
@@ -567,7 +1074,7 @@ impl State {
     }
 }
 
-fn app_thread_main(pid: pid_t, info: AppInfo, events_tx: Sender<(Span, Event)>) {
+fn app_thread_main(pid: pid_t, info: AppInfo, events_tx: reactor::Sender) {
     let app = AXUIElement::application(pid);
     let running_app: Id<NSRunningApplication> = unsafe {
         // For some reason this binding isn't generated in icrate.
@@ -581,9 +1088,9 @@ fn app_thread_main(pid: pid_t, info: AppInfo, events_tx: Sender<(Span, Event)>)
 
     // Create our app state and set up the observer callback.
     let state = Rc::new_cyclic(|weak: &Weak<RefCell<State>>| {
-        let weak = weak.clone();
+        let observer_weak = weak.clone();
         let observer = observer.install(move |elem, notif| {
-            if let Some(state) = weak.upgrade() {
+            if let Some(state) = observer_weak.upgrade() {
                 state.borrow_mut().handle_notification(elem, notif)
             }
         });
@@ -592,22 +1099,50 @@ fn app_thread_main(pid: pid_t, info: AppInfo, events_tx: Sender<(Span, Event)>)
             app: app.clone(),
             windows: HashMap::new(),
             events_tx,
+            held_frames: HashMap::new(),
             requests_rx,
             pid,
             running_app,
             bundle_id: info.bundle_id.clone(),
-            last_window_idx: 0,
+            last_fallback_idx: u32::MAX,
             observer,
+            visible: HashSet::new(),
+            this: weak.clone(),
         })
     });
 
     // Set up our request handler.
     let st = state.clone();
     let wakeup = WakeupHandle::for_current_thread(0, move || handle_requests(&st));
-    let handle = AppThreadHandle { requests_tx, wakeup };
+    let handle = AppThreadHandle {
+        requests_tx,
+        wakeup,
+        thread: Some(ThreadCpu::current()),
+    };
+
+    // Apps often can't be watched until a moment after they launch, so keep
+    // trying for a while.
+    let mut attempts = 1;
+    let mut delay = WATCH_RETRY_DELAY;
+    while let Err(err) = state.borrow().watch_app() {
+        let is_terminated = unsafe { state.borrow().running_app.isTerminated() };
+        if attempts == MAX_WATCH_ATTEMPTS || is_terminated {
+            debug!(
+                ?pid,
+                ?err,
+                attempts,
+                "Watching app failed; exiting app thread"
+            );
+            return;
+        }
+        trace!(?pid, ?err, ?delay, "Watching app failed; trying again");
+        thread::sleep(delay);
+        delay *= 2;
+        attempts += 1;
+    }
 
     // Initialize the app.
-    if !state.borrow_mut().init(handle, info) {
+    if !state.borrow_mut().init(handle, info, attempts > 1) {
         return;
     }
 
@@ -622,8 +1157,22 @@ fn app_thread_main(pid: pid_t, info: AppInfo, events_tx: Sender<(Span, Event)>)
         while let Ok((span, request)) = state.requests_rx.try_recv() {
             let _guard = span.enter();
             debug!(?state.bundle_id, ?state.pid, ?request, "Got request");
-            match state.handle_request(request.clone()) {
-                Ok(()) => (),
+            let start = Instant::now();
+            let result = state.handle_request(request.clone());
+            // Most requests are neither timed nor slow, so only then do we
+            // build the app's name.
+            let elapsed = start.elapsed();
+            if metrics::is_live() || elapsed > metrics::APP_REQUEST_BUDGET {
+                metrics::record(Timing::AppRequest(state.metrics_name()), elapsed);
+            }
+            match result {
+                Ok(()) => {}
+                // Every request fails this way while our Accessibility
+                // permission is revoked. The reactor pauses until it is back,
+                // so there is no need to log each one.
+                Err(accessibility::Error::Ax(kAXErrorAPIDisabled)) => {
+                    trace!(?state.pid, ?request, "Accessibility API is disabled");
+                }
                 Err(err) => {
                     error!(?state.bundle_id, ?state.pid, ?request, "Error handling request: {err}");
                 }
@@ -632,6 +1181,14 @@ fn app_thread_main(pid: pid_t, info: AppInfo, events_tx: Sender<(Span, Event)>)
     }
 }
 
+/// Returns the position used to hide windows: the bottom-right corner of the
+/// screen arrangement, with a single pixel left visible.
+fn hidden_position(screens: &[CGRect]) -> Option<CGPoint> {
+    let max_x = screens.iter().map(|s| s.max().x).reduce(f64::max)?;
+    let max_y = screens.iter().map(|s| s.max().y).reduce(f64::max)?;
+    Some(CGPoint::new(max_x - 1.0, max_y - 1.0))
+}
+
 fn trace<T>(
     desc: &str,
     elem: &AXUIElement,
@@ -647,3 +1204,93 @@ fn trace<T>(
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use icrate::Foundation::{CGPoint, CGRect, CGSize};
+
+    use super::{DecorationOffset, DecorationProbe, Forward, NotificationRate};
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> CGRect {
+        CGRect::new(CGPoint::new(x, y), CGSize::new(width, height))
+    }
+
+    #[test]
+    fn it_compensates_for_decoration_offsets() {
+        // A window that reports its frame without a 28 point title bar.
+        let requested = rect(0., 0., 600., 1000.);
+        let mut probe = DecorationProbe::default();
+        for _ in 1..DecorationProbe::SAMPLES {
+            assert_eq!(None, probe.observe(requested, rect(0., 28., 600., 972.)));
+        }
+        let offset = probe.observe(requested, rect(0., 28., 600., 972.));
+        assert_eq!(Some(DecorationOffset { x: 0., y: 28. }), offset);
+        // Only the origin is adjusted.
+        assert_eq!(
+            rect(100., -28., 500., 1000.),
+            probe.offset().compensate(rect(100., 0., 500., 1000.))
+        );
+
+        // Windows that refuse a position don't have an offset.
+        assert_eq!(
+            None,
+            DecorationOffset::measure(requested, rect(0., 200., 600., 1000.))
+        );
+    }
+
+    #[test]
+    fn it_only_settles_on_consistent_decoration_offsets() {
+        let requested = rect(0., 0., 600., 1000.);
+        let mut probe = DecorationProbe::default();
+        probe.observe(requested, rect(0., 28., 600., 972.));
+        probe.observe(requested, rect(0., 28., 600., 972.));
+        // A window that was still moving breaks the streak.
+        assert_eq!(None, probe.observe(requested, rect(10., 5., 600., 972.)));
+        assert_eq!(None, probe.observe(requested, rect(0., 28., 600., 972.)));
+        assert_eq!(DecorationOffset::default(), probe.offset());
+
+        // Refusals reset it too.
+        probe.observe(requested, rect(0., 28., 600., 972.));
+        probe.observe(requested, rect(0., 500., 600., 972.));
+        probe.observe(requested, rect(0., 28., 600., 972.));
+        probe.observe(requested, rect(0., 28., 600., 972.));
+        assert_eq!(DecorationOffset::default(), probe.offset());
+        probe.observe(requested, rect(0., 28., 600., 972.));
+        assert_eq!(DecorationOffset { x: 0., y: 28. }, probe.offset());
+    }
+
+    #[test]
+    fn it_limits_noisy_windows() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut rate = NotificationRate::default();
+        for _ in 0..NotificationRate::LIMIT {
+            assert_eq!(Forward::Now, rate.notify(start));
+        }
+        assert!(!rate.is_noisy);
+
+        // Going over the limit sends one frame, then holds the rest back until
+        // the interval is up.
+        assert_eq!(Forward::Now, rate.notify(ms(1)));
+        assert!(rate.is_noisy);
+        assert_eq!(
+            Forward::Later(Duration::from_millis(90)),
+            rate.notify(ms(11))
+        );
+        assert_eq!(Forward::Skip, rate.notify(ms(50)));
+        rate.flushed(ms(101));
+        assert_eq!(
+            Forward::Later(Duration::from_millis(99)),
+            rate.notify(ms(102))
+        );
+        rate.flushed(ms(201));
+
+        // A quiet period calms the window down.
+        assert_eq!(Forward::Now, rate.notify(ms(1500)));
+        assert!(rate.is_noisy, "the storm was in the previous period");
+        assert_eq!(Forward::Now, rate.notify(ms(2600)));
+        assert!(!rate.is_noisy);
+    }
+}
@@ -0,0 +1,244 @@
+//! This actor draws borders around windows, styled by the rules in the
+//! config.
+//!
+//! The reactor tells us which windows should have borders whenever their
+//! frames or state change, and the WM controller tells us the colors for the
+//! current appearance.
+//!
+//! We also draw the layout preview, which shows where the layout would put
+//! each window without moving it, the switcher listing the windows being
+//! cycled through, and the outline of the frame a window is being resized
+//! to.
+use std::sync::Arc;
+
+use icrate::Foundation::{CGRect, MainThreadMarker};
+use tracing::{warn, Span};
+
+use crate::{
+    config::{BorderConfig, BorderRule, BorderRules},
+    sys::{
+        appearance::Appearance,
+        overlay::{Color, OverlayWindow, Palette},
+        window_server::WindowServerId,
+    },
+};
+
+pub type Sender = tokio::sync::mpsc::UnboundedSender<(Span, Event)>;
+pub type Receiver = tokio::sync::mpsc::UnboundedReceiver<(Span, Event)>;
+
+pub fn channel() -> (Sender, Receiver) {
+    tokio::sync::mpsc::unbounded_channel()
+}
+
+#[derive(Debug)]
+pub enum Event {
+    WindowsChanged(Vec<Window>),
+    PaletteChanged(Palette),
+    /// The tiles of the layout preview, or none to hide it.
+    PreviewChanged(Vec<PreviewTile>),
+    /// The border rules changed because a different profile became active.
+    RulesChanged(Vec<BorderRule>),
+    /// The windows being cycled through, or none to hide the switcher.
+    SwitcherChanged(Option<Switcher>),
+    /// The frame a window is being resized to, in CG (top-left origin)
+    /// coordinates, or none to hide the outline.
+    ResizePreviewChanged(Option<CGRect>),
+}
+
+/// A window that should have a border, with the state border rules can match
+/// on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub id: WindowServerId,
+    /// The frame of the window in CG (top-left origin) coordinates.
+    pub frame: CGRect,
+    /// The bundle id of the app that owns the window.
+    pub app: Option<Arc<str>>,
+    pub title: Arc<str>,
+    pub is_focused: bool,
+    /// Whether the window is outside of the layout.
+    pub is_floating: bool,
+}
+
+/// Where the layout would put a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewTile {
+    pub title: String,
+    /// The frame in CG (top-left origin) coordinates.
+    pub frame: CGRect,
+}
+
+/// The windows being cycled through, most recently used first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Switcher {
+    /// The app name and title of each window.
+    pub entries: Vec<String>,
+    /// The index of the window that was raised last.
+    pub selected: usize,
+}
+
+/// The width of the border around preview tiles, in points.
+const PREVIEW_BORDER_WIDTH: f64 = 3.0;
+
+pub struct Borders {
+    config: BorderConfig,
+    rules: BorderRules,
+    receiver: Receiver,
+    palette: Palette,
+    windows: Vec<Window>,
+    /// One overlay for each window, reused as the windows change.
+    overlays: Vec<OverlayWindow>,
+    preview: Vec<PreviewTile>,
+    preview_overlays: Vec<OverlayWindow>,
+    switcher: Option<Switcher>,
+    switcher_overlay: Option<OverlayWindow>,
+    resize_preview: Option<CGRect>,
+    resize_overlay: Option<OverlayWindow>,
+}
+
+impl Borders {
+    pub fn new(config: BorderConfig, receiver: Receiver) -> Self {
+        Borders {
+            rules: BorderRules::new(config.rules.clone()),
+            config,
+            receiver,
+            palette: Palette::default_for(Appearance::default()),
+            windows: vec![],
+            overlays: vec![],
+            preview: vec![],
+            preview_overlays: vec![],
+            switcher: None,
+            switcher_overlay: None,
+            resize_preview: None,
+            resize_overlay: None,
+        }
+    }
+
+    pub async fn run(mut self) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            warn!("Borders must run on the main thread");
+            return;
+        };
+        while let Some((span, event)) = self.receiver.recv().await {
+            let _guard = span.enter();
+            match event {
+                Event::WindowsChanged(windows) => {
+                    self.rules.retain(&windows);
+                    self.windows = windows;
+                    self.draw(mtm);
+                }
+                Event::PaletteChanged(palette) => {
+                    self.palette = palette;
+                    self.draw(mtm);
+                    self.draw_preview(mtm);
+                    self.draw_switcher(mtm);
+                    self.draw_resize_preview(mtm);
+                }
+                Event::PreviewChanged(preview) => {
+                    self.preview = preview;
+                    self.draw_preview(mtm);
+                }
+                Event::RulesChanged(rules) => {
+                    self.rules = BorderRules::new(rules);
+                    self.draw(mtm);
+                }
+                Event::SwitcherChanged(switcher) => {
+                    self.switcher = switcher;
+                    self.draw_switcher(mtm);
+                }
+                Event::ResizePreviewChanged(frame) => {
+                    self.resize_preview = frame;
+                    self.draw_resize_preview(mtm);
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, mtm: MainThreadMarker) {
+        if !self.config.enabled {
+            return;
+        }
+        let styled: Vec<_> = self
+            .windows
+            .iter()
+            .filter_map(|window| {
+                let style = self.config.style(&mut self.rules, window, &self.palette)?;
+                Some((window, style))
+            })
+            .collect();
+        while self.overlays.len() < styled.len() {
+            self.overlays.push(OverlayWindow::new(mtm));
+        }
+        for (overlay, (window, style)) in self.overlays.iter().zip(&styled) {
+            overlay.set_frame(window.frame);
+            overlay.set_border(style.width, style.color);
+            overlay.show_above(window.id);
+        }
+        for overlay in &self.overlays[styled.len()..] {
+            overlay.hide();
+        }
+    }
+
+    fn draw_preview(&mut self, mtm: MainThreadMarker) {
+        while self.preview_overlays.len() < self.preview.len() {
+            self.preview_overlays.push(OverlayWindow::new(mtm));
+        }
+        let fill = self.preview_fill();
+        for (overlay, tile) in self.preview_overlays.iter().zip(&self.preview) {
+            overlay.set_frame(tile.frame);
+            overlay.set_labeled_box(
+                &tile.title,
+                PREVIEW_BORDER_WIDTH,
+                self.palette.focused_border,
+                fill,
+                self.palette.text,
+            );
+            overlay.show();
+        }
+        for overlay in &self.preview_overlays[self.preview.len()..] {
+            overlay.hide();
+        }
+    }
+
+    fn draw_resize_preview(&mut self, mtm: MainThreadMarker) {
+        let Some(frame) = self.resize_preview else {
+            if let Some(overlay) = &self.resize_overlay {
+                overlay.hide();
+            }
+            return;
+        };
+        let fill = self.preview_fill();
+        let overlay = self.resize_overlay.get_or_insert_with(|| OverlayWindow::new(mtm));
+        overlay.set_frame(frame);
+        overlay.set_filled_box(PREVIEW_BORDER_WIDTH, self.palette.focused_border, fill);
+        overlay.show();
+    }
+
+    /// The see-through color inside preview outlines.
+    fn preview_fill(&self) -> Color {
+        let Color(red, green, blue, _) = self.palette.focused_border;
+        Color(red, green, blue, 0.25)
+    }
+
+    fn draw_switcher(&mut self, mtm: MainThreadMarker) {
+        let Some(switcher) = &self.switcher else {
+            if let Some(overlay) = &self.switcher_overlay {
+                overlay.hide();
+            }
+            return;
+        };
+        let text: Vec<_> = switcher
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let marker = if idx == switcher.selected { '▸' } else { ' ' };
+                format!("{marker} {entry}")
+            })
+            .collect();
+        let overlay = self.switcher_overlay.get_or_insert_with(|| OverlayWindow::new(mtm));
+        overlay.set_background(self.palette.background);
+        overlay.set_text(&text.join("\n"), self.palette.text);
+        overlay.show();
+    }
+}
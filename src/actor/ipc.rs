@@ -0,0 +1,283 @@
+//! The IPC actor lets other processes, like the `nimbus` command line, send
+//! commands and queries to the running window manager.
+//!
+//! Clients connect to a Unix domain socket and send one JSON message per line.
+//! Each message is answered with a single line of JSON, except for
+//! subscriptions, which are answered with a line whenever something changes.
+//!
+//! Commands are given an id, which is logged with the command and returned
+//! with its result so the two can be matched up.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info_span, warn, Span};
+
+use crate::{
+    actor::{
+        reactor::{self, CommandResult, Query, QueryResponse, SpaceData, StackData},
+        wm_controller::{self, WmEvent},
+    },
+    logging,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Message {
+    Command(reactor::Command),
+    /// Runs the commands in order, all at once, so the layout is only updated
+    /// after the last one. Stops at the first command that fails.
+    Batch(Vec<reactor::Command>),
+    Query(Query),
+    /// Returns the most recent log lines.
+    RecentLogs,
+    /// Switches to the named profile, or back to the scheduled profile if
+    /// `None`.
+    UseProfile(Option<String>),
+    /// Sends the spaces, as in [`Query::Spaces`], now and whenever they
+    /// change, for as long as the connection is open.
+    SubscribeSpaces,
+    /// Sends the stacked and tabbed containers, as in [`Query::Stacks`], now
+    /// and whenever they change, for as long as the connection is open.
+    SubscribeStacks,
+    /// Handles the events in order as if apps and the system had sent them,
+    /// for reproducing bugs against the real layout. Only debug builds have
+    /// this.
+    #[cfg(debug_assertions)]
+    InjectEvents(Vec<reactor::InjectedEvent>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    /// The result of a command. Errors mean the command did nothing, and say
+    /// why.
+    Command {
+        id: u64,
+        result: CommandResult,
+    },
+    /// The results of a batch, one for each command that ran.
+    Batch {
+        id: u64,
+        results: Vec<CommandResult>,
+    },
+    Query(QueryResponse),
+    Logs(Vec<String>),
+    /// The profile that is active after switching, if any.
+    Profile(Option<String>),
+    Spaces(Vec<SpaceData>),
+    Stacks(Vec<StackData>),
+    /// Every injected event was handled.
+    #[cfg(debug_assertions)]
+    Injected,
+    Error(String),
+}
+
+pub struct IpcServer {
+    listener: UnixListener,
+    events_tx: wm_controller::Sender,
+}
+
+impl IpcServer {
+    /// Starts listening on the socket at `path` and handles clients on a
+    /// background thread.
+    ///
+    /// Any existing socket at `path` is assumed to be left over from a previous
+    /// run and is removed.
+    pub fn spawn(path: PathBuf, events_tx: wm_controller::Sender) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+        let listener = UnixListener::bind(&path)?;
+        let this = IpcServer { listener, events_tx };
+        thread::spawn(move || this.run());
+        Ok(())
+    }
+
+    fn run(self) {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept IPC connection: {e}");
+                    continue;
+                }
+            };
+            let events_tx = self.events_tx.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_client(stream, &events_tx) {
+                    debug!("IPC client disconnected: {e}");
+                }
+            });
+        }
+    }
+}
+
+fn handle_client(stream: UnixStream, events_tx: &wm_controller::Sender) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    for line in reader.lines() {
+        let response = match serde_json::from_str(&line?) {
+            Ok(Message::SubscribeSpaces) => {
+                let span = info_span!("ipc::subscribe_spaces");
+                return stream(
+                    writer,
+                    events_tx,
+                    span,
+                    reactor::Event::SubscribeSpaces,
+                    Response::Spaces,
+                );
+            }
+            Ok(Message::SubscribeStacks) => {
+                let span = info_span!("ipc::subscribe_stacks");
+                return stream(
+                    writer,
+                    events_tx,
+                    span,
+                    reactor::Event::SubscribeStacks,
+                    Response::Stacks,
+                );
+            }
+            Ok(message) => handle_message(message, events_tx),
+            Err(e) => Response::Error(format!("Invalid message: {e}")),
+        };
+        write_response(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+fn write_response(writer: &mut UnixStream, response: &Response) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, response)?;
+    writer.write_all(b"\n")
+}
+
+/// Subscribes with the event made by `subscribe` and writes each update to
+/// the client, until it disconnects.
+fn stream<T>(
+    mut writer: UnixStream,
+    events_tx: &wm_controller::Sender,
+    span: Span,
+    subscribe: impl FnOnce(mpsc::Sender<T>) -> reactor::Event,
+    respond: impl Fn(T) -> Response,
+) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let event = WmEvent::ReactorEvent(subscribe(tx));
+    if events_tx.send((span, event)).is_err() {
+        let error = Response::Error("Window manager is shutting down".into());
+        return write_response(&mut writer, &error);
+    }
+    // The reactor stops sending once a write fails and `rx` is dropped.
+    for update in rx {
+        write_response(&mut writer, &respond(update))?;
+    }
+    Ok(())
+}
+
+/// The id of the next command, unique for this run of the window manager.
+static NEXT_COMMAND_ID: AtomicU64 = AtomicU64::new(1);
+
+fn handle_message(message: Message, events_tx: &wm_controller::Sender) -> Response {
+    match message {
+        Message::Command(cmd) => {
+            let id = NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed);
+            let span = info_span!("ipc::command", id, ?cmd);
+            request(events_tx, span, |tx| {
+                WmEvent::ReactorEvent(reactor::Event::CommandWithResult(cmd, tx))
+            })
+            .map_or_else(|e| e, |result| Response::Command { id, result })
+        }
+        Message::Batch(cmds) => {
+            let id = NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed);
+            let span = info_span!("ipc::batch", id, ?cmds);
+            request(events_tx, span, |tx| {
+                WmEvent::ReactorEvent(reactor::Event::CommandBatch(cmds, tx))
+            })
+            .map_or_else(|e| e, |results| Response::Batch { id, results })
+        }
+        Message::Query(query) => {
+            let span = info_span!("ipc::query", ?query);
+            request(events_tx, span, |tx| {
+                WmEvent::ReactorEvent(reactor::Event::Query(query, tx))
+            })
+            .map_or_else(|e| e, Response::Query)
+        }
+        Message::RecentLogs => Response::Logs(logging::recent_logs()),
+        Message::SubscribeSpaces | Message::SubscribeStacks => {
+            Response::Error("Subscriptions are handled by the connection".into())
+        }
+        Message::UseProfile(name) => {
+            let span = info_span!("ipc::use_profile", ?name);
+            match request(events_tx, span, |tx| WmEvent::UseProfile(name, tx)) {
+                Ok(Ok(active)) => Response::Profile(active),
+                Ok(Err(e)) => Response::Error(e),
+                Err(e) => e,
+            }
+        }
+        #[cfg(debug_assertions)]
+        Message::InjectEvents(events) => {
+            let span = info_span!("ipc::inject_events", count = events.len());
+            let make_event = |tx| WmEvent::ReactorEvent(reactor::Event::Inject(events, tx));
+            match request(events_tx, span, make_event) {
+                Ok(Ok(())) => Response::Injected,
+                Ok(Err(e)) => Response::Error(e),
+                Err(e) => e,
+            }
+        }
+    }
+}
+
+/// Sends an event to the window manager and waits for its reply on the
+/// channel passed to `make_event`, returning an error response if there is
+/// none.
+fn request<T>(
+    events_tx: &wm_controller::Sender,
+    span: Span,
+    make_event: impl FnOnce(mpsc::Sender<T>) -> WmEvent,
+) -> Result<T, Response> {
+    let (tx, rx) = mpsc::channel();
+    let event = make_event(tx);
+    if events_tx.send((span, event)).is_err() {
+        return Err(Response::Error("Window manager is shutting down".into()));
+    }
+    rx.recv().map_err(|_| Response::Error("No response from window manager".into()))
+}
+
+/// Sends a message to the window manager listening at `path` and calls
+/// `on_response` with each line it sends back, until it disconnects.
+pub fn subscribe(
+    path: &Path,
+    message: &Message,
+    mut on_response: impl FnMut(Response),
+) -> io::Result<()> {
+    let mut stream = UnixStream::connect(path)?;
+    serde_json::to_writer(&mut stream, message)?;
+    stream.write_all(b"\n")?;
+    for line in BufReader::new(stream).lines() {
+        on_response(serde_json::from_str(&line?)?);
+    }
+    Ok(())
+}
+
+/// Sends a single message to the window manager listening at `path` and waits
+/// for its response.
+pub fn send_message(path: &Path, message: &Message) -> io::Result<Response> {
+    let mut stream = UnixStream::connect(path)?;
+    serde_json::to_writer(&mut stream, message)?;
+    stream.write_all(b"\n")?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
@@ -1,18 +1,25 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{self, File},
     io::{Read, Write},
+    mem,
     path::PathBuf,
 };
 
-use icrate::Foundation::{CGRect, CGSize};
+use icrate::Foundation::{CGPoint, CGRect, CGSize};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error};
 
 use crate::{
-    actor::app::{pid_t, WindowId},
-    model::{Direction, LayoutId, LayoutKind, LayoutTree, Orientation},
-    sys::screen::SpaceId,
+    actor::{
+        app::{pid_t, WindowId},
+        reactor::CommandError,
+    },
+    model::{
+        AdoptStrategy, Direction, Group, LayoutId, LayoutKind, LayoutSpec, LayoutTree, NodeId,
+        Orientation, Place, Split, SplitOrientation, WindowMatcher,
+    },
+    sys::screen::{self, SpaceId, SpaceUuid},
 };
 
 /// Actor that manages the layout tree.
@@ -25,6 +32,30 @@ pub struct LayoutManager {
     tree: LayoutTree,
     active_layouts: HashMap<SpaceId, LayoutId>,
     space_configurations: HashMap<(SpaceId, Size), LayoutId>,
+    /// The uuid of each space at the time the layout was saved, used to find
+    /// the same spaces when restoring.
+    #[serde(default)]
+    space_uuids: HashMap<SpaceId, SpaceUuid>,
+    /// The spaces the user turned off, kept here so that they stay off after
+    /// a restart.
+    #[serde(default)]
+    disabled_spaces: HashSet<SpaceUuid>,
+    /// Whether new windows split the selected window instead of joining the
+    /// root container. This comes from the config, so it isn't saved.
+    #[serde(skip)]
+    split_new_windows: bool,
+    /// The most tiles each app's windows can take up, by bundle id. This
+    /// comes from the config, so it isn't saved.
+    #[serde(skip)]
+    window_limits: HashMap<String, usize>,
+    /// How to arrange the windows already open on a space when we create its
+    /// layout. This comes from the config, so it isn't saved.
+    #[serde(skip)]
+    adopt_strategy: AdoptStrategy,
+    /// Layouts created since we started that the user has not changed yet,
+    /// whose windows are arranged by `adopt_strategy` as they are found.
+    #[serde(skip)]
+    fresh_layouts: HashSet<LayoutId>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -42,19 +73,37 @@ impl From<CGSize> for Size {
     }
 }
 
+impl From<Size> for CGSize {
+    fn from(value: Size) -> Self {
+        CGSize::new(f64::from(value.width), f64::from(value.height))
+    }
+}
+
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum LayoutCommand {
     Shuffle,
     NextWindow,
     PrevWindow,
     MoveFocus(Direction),
+    /// Focuses the nth child (starting at 1) of the container holding the
+    /// selected window, like choosing a tab by number.
+    FocusChildIndex(usize),
     Ascend,
     Descend,
     MoveNode(Direction),
-    Split(Orientation),
+    Split(SplitOrientation),
     Group(Orientation),
     Ungroup,
+    /// Collapses the selected tile to a thin bar so its neighbors take up its
+    /// space, or expands it back to its old size.
+    ToggleShade,
+    /// Flips the whole layout, so windows trade places left to right for
+    /// `Horizontal`, or top to bottom for `Vertical`.
+    Mirror(Orientation),
+    /// Turns the whole layout a quarter turn clockwise, so rows become
+    /// columns and columns become rows.
+    Rotate,
     Debug,
     Serialize,
     SaveAndExit(PathBuf),
@@ -63,10 +112,17 @@ pub enum LayoutCommand {
 #[derive(Debug, Clone, PartialEq)]
 pub enum LayoutEvent {
     WindowsOnScreenUpdated(SpaceId, pid_t, Vec<WindowId>),
+    /// An app with the given bundle id started.
+    AppLaunched(pid_t, Option<String>),
     AppClosed(pid_t),
     WindowAdded(SpaceId, WindowId),
+    /// A window was added that should go at the given place, like a window
+    /// reopened where it was closed. Window limits don't apply to it.
+    WindowAddedAt(SpaceId, WindowId, Place),
     WindowRemoved(WindowId),
     WindowRaised(SpaceId, Option<WindowId>),
+    /// The user dropped one tiled window onto another, and they trade places.
+    WindowsSwapped(SpaceId, WindowId, WindowId),
     WindowResized {
         space: SpaceId,
         wid: WindowId,
@@ -74,7 +130,12 @@ pub enum LayoutEvent {
         new_frame: CGRect,
         screen: CGRect,
     },
-    SpaceExposed(SpaceId, CGSize),
+    /// The space is visible on a screen of the given size. If the space has
+    /// no layout yet, one is created with the given kind.
+    SpaceExposed(SpaceId, CGSize, LayoutKind),
+    /// The space was deleted in Mission Control. Its layouts are forgotten,
+    /// as if it never had one.
+    SpaceRemoved(SpaceId),
 }
 
 #[must_use]
@@ -89,35 +150,116 @@ impl LayoutManager {
             tree: LayoutTree::new(),
             active_layouts: Default::default(),
             space_configurations: Default::default(),
+            space_uuids: Default::default(),
+            disabled_spaces: Default::default(),
+            split_new_windows: false,
+            window_limits: Default::default(),
+            adopt_strategy: AdoptStrategy::default(),
+            fresh_layouts: Default::default(),
         }
     }
 
+    /// Sets how to arrange the windows already open on a space when we start
+    /// tiling it.
+    pub fn set_adopt_strategy(&mut self, adopt_strategy: AdoptStrategy) {
+        self.adopt_strategy = adopt_strategy;
+    }
+
+    /// Sets the most tiles each app's windows can take up, by bundle id.
+    /// Windows beyond the limit are stacked with the app's other windows.
+    pub fn set_window_limits(&mut self, window_limits: HashMap<String, usize>) {
+        self.window_limits = window_limits;
+    }
+
+    /// Sets whether closing a window gives its siblings equal shares of the
+    /// space, instead of growing each in proportion to its size.
+    pub fn set_balance_on_close(&mut self, balance_on_close: bool) {
+        self.tree.set_balance_on_close(balance_on_close);
+    }
+
+    /// Sets whether new windows split the selected window, choosing the
+    /// orientation from its shape, instead of joining the root container.
+    pub fn set_split_new_windows(&mut self, split_new_windows: bool) {
+        self.split_new_windows = split_new_windows;
+    }
+
+    /// Returns the spaces the user turned off.
+    pub fn disabled_spaces(&self) -> &HashSet<SpaceUuid> {
+        &self.disabled_spaces
+    }
+
+    /// Records the spaces the user turned off, to be saved with the layout.
+    pub fn set_disabled_spaces(&mut self, disabled_spaces: HashSet<SpaceUuid>) {
+        self.disabled_spaces = disabled_spaces;
+    }
+
     pub fn handle_event(&mut self, event: LayoutEvent) -> EventResponse {
         debug!(?event);
         match event {
-            LayoutEvent::SpaceExposed(space, size) => {
+            LayoutEvent::SpaceExposed(space, size, kind) => {
                 let layout =
                     self.space_configurations.entry((space, size.into())).or_insert_with(|| {
                         if let Some(&active) = self.active_layouts.get(&space) {
                             self.tree.clone_layout(active)
                         } else {
-                            self.tree.create_layout()
+                            let layout = self.tree.create_layout();
+                            self.tree.set_layout(self.tree.root(layout), kind);
+                            self.fresh_layouts.insert(layout);
+                            layout
                         }
                     });
                 self.active_layouts.insert(space, *layout);
             }
+            LayoutEvent::SpaceRemoved(space) => {
+                self.space_uuids.remove(&space);
+                let mut removed: Vec<_> = self.active_layouts.remove(&space).into_iter().collect();
+                self.space_configurations.retain(|&(other, _), layout| {
+                    if other == space {
+                        removed.push(*layout);
+                    }
+                    other != space
+                });
+                for layout in removed {
+                    self.fresh_layouts.remove(&layout);
+                    self.tree.remove_layout(layout);
+                }
+            }
             LayoutEvent::WindowsOnScreenUpdated(space, pid, windows) => {
                 // The windows may already be in the layout if we restored a saved state, so
                 // make sure not to duplicate or erase them here.
                 let layout = self.layout(space);
                 self.tree.set_windows_for_app(layout, pid, windows);
             }
+            LayoutEvent::AppLaunched(pid, bundle_id) => {
+                let limit = bundle_id.and_then(|id| self.window_limits.get(&id).copied());
+                self.tree.set_window_limit(pid, limit);
+            }
             LayoutEvent::AppClosed(pid) => {
                 self.tree.remove_windows_for_app(pid);
+                self.tree.set_window_limit(pid, None);
             }
             LayoutEvent::WindowAdded(space, wid) => {
                 let layout = self.layout(space);
-                self.tree.add_window(layout, self.tree.root(layout), wid);
+                self.fresh_layouts.remove(&layout);
+                let selection = self.tree.selection(layout);
+                let parent = if self.split_new_windows
+                    && self.tree.window_at(selection).is_some()
+                    && !self.tree.is_at_window_limit(layout, wid.pid)
+                {
+                    let orientation =
+                        self.split_orientation(space, selection, SplitOrientation::Auto);
+                    self.tree.nest_in_container(layout, selection, LayoutKind::from(orientation))
+                } else {
+                    self.tree.root(layout)
+                };
+                self.tree.add_window_within_limit(layout, parent, wid);
+            }
+            LayoutEvent::WindowAddedAt(space, wid, place) => {
+                let layout = self.layout(space);
+                if self.tree.add_window_at(layout, place, wid).is_none() {
+                    return self.handle_event(LayoutEvent::WindowAdded(space, wid));
+                }
+                self.fresh_layouts.remove(&layout);
             }
             LayoutEvent::WindowRemoved(wid) => {
                 self.tree.remove_window(wid);
@@ -130,6 +272,12 @@ impl LayoutManager {
                     }
                 }
             }
+            LayoutEvent::WindowsSwapped(space, a, b) => {
+                let layout = self.layout(space);
+                if self.tree.swap_windows(layout, a, b) {
+                    self.fresh_layouts.remove(&layout);
+                }
+            }
             LayoutEvent::WindowResized {
                 space,
                 wid,
@@ -139,6 +287,7 @@ impl LayoutManager {
             } => {
                 let layout = self.layout(space);
                 if let Some(node) = self.tree.window_node(layout, wid) {
+                    self.fresh_layouts.remove(&layout);
                     self.tree.set_frame_from_resize(node, old_frame, new_frame, screen);
                 }
             }
@@ -146,15 +295,23 @@ impl LayoutManager {
         EventResponse::default()
     }
 
-    pub fn handle_command(&mut self, space: SpaceId, command: LayoutCommand) -> EventResponse {
+    /// Runs a command on the layout of `space`.
+    ///
+    /// Errors mean the command did nothing.
+    pub fn handle_command(
+        &mut self,
+        space: SpaceId,
+        command: LayoutCommand,
+    ) -> Result<EventResponse, CommandError> {
         let layout = self.layout(space);
+        self.fresh_layouts.remove(&layout);
         debug!("Tree:\n{}", self.tree.draw_tree(layout).trim());
         debug!(selection = ?self.tree.selection(layout));
         match command {
             LayoutCommand::Shuffle => {
                 // TODO
                 // self.window_order.shuffle(&mut rand::thread_rng());
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
             LayoutCommand::NextWindow => {
                 // TODO
@@ -169,34 +326,41 @@ impl LayoutManager {
                     .tree
                     .traverse(self.tree.selection(layout), direction)
                     .and_then(|new| self.tree.window_at(new));
-                let Some(new) = new else {
-                    return EventResponse::default();
-                };
-                EventResponse { raise_window: Some(new) }
+                let new = new.ok_or(CommandError::NoWindowInDirection(direction))?;
+                Ok(EventResponse { raise_window: Some(new) })
+            }
+            LayoutCommand::FocusChildIndex(n) => {
+                let new = self
+                    .tree
+                    .nth_child_of_selection(layout, n)
+                    .and_then(|node| self.tree.window_at(node));
+                let new = new.ok_or(CommandError::NoSuchChild(n))?;
+                Ok(EventResponse { raise_window: Some(new) })
             }
             LayoutCommand::Ascend => {
                 self.tree.ascend_selection(layout);
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
             LayoutCommand::Descend => {
                 self.tree.descend_selection(layout);
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
             LayoutCommand::MoveNode(direction) => {
                 let selection = self.tree.selection(layout);
                 self.tree.move_node(layout, selection, direction);
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
             LayoutCommand::Split(orientation) => {
                 let selection = self.tree.selection(layout);
+                let orientation = self.split_orientation(space, selection, orientation);
                 self.tree.nest_in_container(layout, selection, LayoutKind::from(orientation));
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
             LayoutCommand::Group(orientation) => {
                 if let Some(parent) = self.tree.selection(layout).parent(self.tree.map()) {
                     self.tree.set_layout(parent, LayoutKind::group(orientation));
                 }
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
             LayoutCommand::Ungroup => {
                 if let Some(parent) = self.tree.selection(layout).parent(self.tree.map()) {
@@ -204,23 +368,115 @@ impl LayoutManager {
                         self.tree.set_layout(parent, self.tree.last_ungrouped_layout(parent))
                     }
                 }
-                EventResponse::default()
+                Ok(EventResponse::default())
+            }
+            LayoutCommand::ToggleShade => {
+                if !self.tree.toggle_shade(self.tree.selection(layout)) {
+                    return Err(CommandError::NothingToShade);
+                }
+                Ok(EventResponse::default())
+            }
+            LayoutCommand::Mirror(orientation) => {
+                self.tree.mirror(layout, orientation);
+                Ok(EventResponse::default())
+            }
+            LayoutCommand::Rotate => {
+                self.tree.rotate(layout);
+                Ok(EventResponse::default())
             }
             LayoutCommand::Debug => {
                 self.tree.print_tree(layout);
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
             LayoutCommand::Serialize => {
                 println!("{}", self.serialize_to_string());
-                EventResponse::default()
+                Ok(EventResponse::default())
             }
-            LayoutCommand::SaveAndExit(path) => match self.save(path) {
-                Ok(()) => std::process::exit(0),
-                Err(e) => {
-                    error!("Could not save layout: {e}");
-                    std::process::exit(3);
+            LayoutCommand::SaveAndExit(path) => {
+                self.space_uuids = screen::space_uuids();
+                match self.save(path) {
+                    Ok(()) => std::process::exit(0),
+                    Err(e) => {
+                        error!("Could not save layout: {e}");
+                        std::process::exit(3);
+                    }
                 }
-            },
+            }
+        }
+    }
+
+    /// Returns each space with a layout, the number of windows in it, and the
+    /// kind of its top-level container.
+    pub fn space_summaries(&self) -> Vec<(SpaceId, usize, LayoutKind)> {
+        self.active_layouts
+            .iter()
+            .map(|(&space, &layout)| {
+                let kind = self.tree.layout(self.tree.root(layout));
+                (space, self.tree.window_count(layout), kind)
+            })
+            .collect()
+    }
+
+    /// Returns the window to focus when `wid` leaves the layout of `space`.
+    pub fn window_after(&self, space: SpaceId, wid: WindowId) -> Option<WindowId> {
+        self.tree.window_after(self.layout(space), wid)
+    }
+
+    /// Returns where `wid` is in the layout of `space`, so another window can
+    /// take its place.
+    pub fn window_place(&self, space: SpaceId, wid: WindowId) -> Option<Place> {
+        self.tree.window_place(self.layout(space), wid)
+    }
+
+    /// Describes the layout of `space`, using `describe` to identify each
+    /// window.
+    pub fn export(
+        &self,
+        space: SpaceId,
+        describe: impl Fn(WindowId) -> WindowMatcher,
+    ) -> LayoutSpec {
+        self.tree.export(self.layout(space), describe)
+    }
+
+    /// Rearranges the windows of `space` into the layout described by `spec`.
+    /// See [`LayoutTree::import`].
+    pub fn import(
+        &mut self,
+        space: SpaceId,
+        spec: &LayoutSpec,
+        matches: impl Fn(&WindowMatcher, WindowId) -> bool,
+    ) {
+        let layout = self.layout(space);
+        self.fresh_layouts.remove(&layout);
+        self.tree.import(layout, spec, matches)
+    }
+
+    /// Rebuilds the layout of `space` to match where its windows are now, as
+    /// given by `frame`. See [`LayoutTree::capture`].
+    pub fn capture(&mut self, space: SpaceId, frame: impl Fn(WindowId) -> Option<CGRect>) {
+        let layout = self.layout(space);
+        self.fresh_layouts.remove(&layout);
+        self.tree.capture(layout, frame)
+    }
+
+    /// Arranges the windows of `space` by the adoption strategy, if we
+    /// created its layout and it has not been changed since. `frame` gives
+    /// where each window is now.
+    ///
+    /// Apps report their windows one at a time, so this runs again as each
+    /// app's windows are found, until [`LayoutManager::finish_adoption`].
+    pub fn adopt(&mut self, space: SpaceId, frame: impl Fn(WindowId) -> Option<CGRect>) {
+        let layout = self.layout(space);
+        if self.fresh_layouts.contains(&layout) {
+            self.tree.adopt(layout, self.adopt_strategy, frame);
+        }
+    }
+
+    /// Stops arranging the windows of `space` by the adoption strategy, once
+    /// every window that was there when we found it has been reported.
+    pub fn finish_adoption(&mut self, space: SpaceId) {
+        if let Some(layout) = self.active_layouts.get(&space) {
+            self.fresh_layouts.remove(layout);
         }
     }
 
@@ -230,14 +486,80 @@ impl LayoutManager {
         self.tree.calculate_layout(layout, screen)
     }
 
+    /// Returns the stacked and tabbed containers in the space.
+    pub fn groups(&self, space: SpaceId, screen: CGRect) -> Vec<Group> {
+        self.tree.groups(self.layout(space), screen)
+    }
+
+    /// Returns the boundaries between tiles in the space.
+    pub fn calculate_splits(&self, space: SpaceId, screen: CGRect) -> Vec<Split> {
+        self.tree.calculate_splits(self.layout(space), screen)
+    }
+
+    /// Moves a boundary returned by [`LayoutManager::calculate_splits`] by
+    /// `delta` points.
+    ///
+    /// Returns false if the boundary no longer exists.
+    pub fn move_split(
+        &mut self,
+        space: SpaceId,
+        node: NodeId,
+        orientation: Orientation,
+        delta: f64,
+        screen: CGRect,
+    ) -> bool {
+        let length = match orientation {
+            Orientation::Horizontal => screen.size.width,
+            Orientation::Vertical => screen.size.height,
+        };
+        let layout = self.layout(space);
+        self.fresh_layouts.remove(&layout);
+        self.tree.move_split(layout, node, orientation, delta / length)
+    }
+
     fn layout(&self, space: SpaceId) -> LayoutId {
         self.active_layouts[&space]
     }
 
+    /// Returns the size of the screen the space was last exposed on.
+    fn screen_size(&self, space: SpaceId) -> Option<CGSize> {
+        let layout = self.layout(space);
+        self.space_configurations
+            .iter()
+            .find(|&(_, &config_layout)| config_layout == layout)
+            .map(|(&(_, size), _)| size.into())
+    }
+
+    /// Resolves the orientation for splitting `node`, using its shape on the
+    /// screen for [`SplitOrientation::Auto`].
+    fn split_orientation(
+        &self,
+        space: SpaceId,
+        node: NodeId,
+        orientation: SplitOrientation,
+    ) -> Orientation {
+        let layout = self.layout(space);
+        let frame = self.screen_size(space).and_then(|size| {
+            self.tree
+                .calculate_node_frame(layout, node, CGRect::new(CGPoint::new(0.0, 0.0), size))
+        });
+        // Without a screen, split side by side as we would on most screens.
+        orientation.resolve(frame.map_or(CGSize::new(0.0, 0.0), |frame| frame.size))
+    }
+
     pub fn load(path: PathBuf) -> anyhow::Result<Self> {
         let mut buf = String::new();
         File::open(path)?.read_to_string(&mut buf)?;
-        Ok(ron::from_str(&buf)?)
+        let mut this = Self::deserialize_from_str(&buf)?;
+        this.remap_spaces(&screen::space_uuids());
+        Ok(this)
+    }
+
+    /// Parses the output of [`LayoutManager::serialize_to_string`].
+    ///
+    /// Unlike [`LayoutManager::load`], this does not update the space ids.
+    pub fn deserialize_from_str(buf: &str) -> anyhow::Result<Self> {
+        Ok(ron::from_str(buf)?)
     }
 
     fn save(&self, path: PathBuf) -> std::io::Result<()> {
@@ -248,7 +570,37 @@ impl LayoutManager {
         Ok(())
     }
 
-    fn serialize_to_string(&self) -> String {
+    /// Updates the space ids in a restored layout to the ids the same spaces
+    /// have now, which may have changed since it was saved.
+    ///
+    /// Layouts for spaces that no longer exist are forgotten.
+    fn remap_spaces(&mut self, current: &HashMap<SpaceId, SpaceUuid>) {
+        let saved = mem::take(&mut self.space_uuids);
+        if saved.is_empty() {
+            // Saved by a version that did not record uuids; assume the ids
+            // are still valid.
+            return;
+        }
+        let by_uuid: HashMap<&SpaceUuid, SpaceId> =
+            current.iter().map(|(space, uuid)| (uuid, *space)).collect();
+        let remap = |space: SpaceId| by_uuid.get(saved.get(&space)?).copied();
+        let active_layouts = mem::take(&mut self.active_layouts);
+        let old_len = active_layouts.len();
+        self.active_layouts = active_layouts
+            .into_iter()
+            .filter_map(|(space, layout)| Some((remap(space)?, layout)))
+            .collect();
+        self.space_configurations = mem::take(&mut self.space_configurations)
+            .into_iter()
+            .filter_map(|((space, size), layout)| Some(((remap(space)?, size), layout)))
+            .collect();
+        debug!(
+            "Restored layouts for {} of {old_len} spaces",
+            self.active_layouts.len()
+        );
+    }
+
+    pub fn serialize_to_string(&self) -> String {
         ron::ser::to_string(&self).unwrap()
     }
 
@@ -286,6 +638,151 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_restores_layouts_for_spaces_whose_id_changed() {
+        use LayoutEvent::*;
+        let mut mgr = LayoutManager::new();
+        let (space1, space2) = (SpaceId::new(1), SpaceId::new(2));
+        let pid = 1;
+        let screen = rect(0, 0, 120, 120);
+        _ = mgr.handle_event(SpaceExposed(space1, screen.size, LayoutKind::default()));
+        _ = mgr.handle_event(WindowsOnScreenUpdated(space1, pid, make_windows(pid, 2)));
+        _ = mgr.handle_event(SpaceExposed(space2, screen.size, LayoutKind::default()));
+        _ = mgr.handle_event(WindowsOnScreenUpdated(
+            space2,
+            pid + 1,
+            make_windows(pid + 1, 1),
+        ));
+        let layout1 = mgr.layout_sorted(space1, screen);
+
+        mgr.space_uuids = HashMap::from([
+            (space1, SpaceUuid::new("one")),
+            (space2, SpaceUuid::new("two")),
+        ]);
+        mgr.set_disabled_spaces(HashSet::from([SpaceUuid::new("two")]));
+        let mut restored: LayoutManager = ron::from_str(&mgr.serialize_to_string()).unwrap();
+        assert_eq!(
+            &HashSet::from([SpaceUuid::new("two")]),
+            restored.disabled_spaces()
+        );
+        // After a restart, the first space has a different id and the second no
+        // longer exists.
+        let new_space1 = SpaceId::new(3);
+        restored.remap_spaces(&HashMap::from([
+            (new_space1, SpaceUuid::new("one")),
+            (space1, SpaceUuid::new("three")),
+        ]));
+        assert_eq!(layout1, restored.layout_sorted(new_space1, screen));
+        assert!(!restored.active_layouts.contains_key(&space1));
+        assert!(!restored.active_layouts.contains_key(&space2));
+    }
+
+    #[test]
+    fn it_forgets_the_layouts_of_removed_spaces() {
+        use LayoutEvent::*;
+        let mut mgr = LayoutManager::new();
+        let (space1, space2) = (SpaceId::new(1), SpaceId::new(2));
+        let (small, large) = (rect(0, 0, 120, 120), rect(0, 0, 240, 120));
+        let wid = WindowId::new(1, 1);
+        _ = mgr.handle_event(SpaceExposed(space1, small.size, LayoutKind::default()));
+        _ = mgr.handle_event(WindowsOnScreenUpdated(space1, 1, vec![wid]));
+        _ = mgr.handle_event(SpaceExposed(space1, large.size, LayoutKind::default()));
+        _ = mgr.handle_event(SpaceExposed(space2, small.size, LayoutKind::default()));
+        mgr.space_uuids = HashMap::from([
+            (space1, SpaceUuid::new("one")),
+            (space2, SpaceUuid::new("two")),
+        ]);
+        let layouts: Vec<_> = mgr.space_configurations.values().copied().collect();
+
+        _ = mgr.handle_event(SpaceRemoved(space1));
+        assert!(!mgr.active_layouts.contains_key(&space1));
+        assert!(!mgr.space_uuids.contains_key(&space1));
+        assert_eq!(1, mgr.space_configurations.len());
+        for layout in layouts {
+            assert_eq!(None, mgr.tree.window_node(layout, wid));
+        }
+        assert!(mgr.active_layouts.contains_key(&space2));
+        assert!(mgr.space_uuids.contains_key(&space2));
+    }
+
+    #[test]
+    fn it_only_adopts_windows_until_adoption_finishes() {
+        use LayoutEvent::*;
+        let mut mgr = LayoutManager::new();
+        mgr.set_adopt_strategy(AdoptStrategy::Stack);
+        let space = SpaceId::new(1);
+        let screen = rect(0, 0, 1200, 600);
+        _ = mgr.handle_event(SpaceExposed(space, screen.size, LayoutKind::default()));
+        _ = mgr.handle_event(WindowsOnScreenUpdated(space, 1, make_windows(1, 2)));
+        let side_by_side = mgr.layout_sorted(space, screen);
+        mgr.adopt(space, |_| Some(screen));
+        assert_ne!(side_by_side, mgr.layout_sorted(space, screen));
+
+        mgr.finish_adoption(space);
+        _ = mgr.handle_event(WindowsOnScreenUpdated(space, 2, make_windows(2, 1)));
+        let before = mgr.layout_sorted(space, screen);
+        mgr.adopt(space, |_| Some(screen));
+        assert_eq!(before, mgr.layout_sorted(space, screen));
+    }
+
+    #[test]
+    fn it_splits_new_windows_by_aspect_ratio() {
+        use LayoutEvent::*;
+        let mut mgr = LayoutManager::new();
+        mgr.set_split_new_windows(true);
+        let space = SpaceId::new(1);
+        let screen = rect(0, 0, 1200, 600);
+        _ = mgr.handle_event(SpaceExposed(space, screen.size, LayoutKind::default()));
+        for idx in 1..=4 {
+            let wid = WindowId::new(1, idx);
+            _ = mgr.handle_event(WindowAdded(space, wid));
+            _ = mgr.handle_event(WindowRaised(space, Some(wid)));
+        }
+        assert_eq!(
+            vec![
+                (WindowId::new(1, 1), rect(0, 0, 600, 600)),
+                (WindowId::new(1, 2), rect(600, 0, 300, 600)),
+                (WindowId::new(1, 3), rect(900, 0, 300, 300)),
+                (WindowId::new(1, 4), rect(900, 300, 300, 300)),
+            ],
+            mgr.layout_sorted(space, screen),
+        );
+
+        // The selected window is now wider than it is tall.
+        _ = mgr.handle_command(space, LayoutCommand::Split(SplitOrientation::Auto));
+        _ = mgr.handle_event(WindowAdded(space, WindowId::new(1, 5)));
+        assert_eq!(
+            vec![
+                (WindowId::new(1, 4), rect(900, 300, 150, 300)),
+                (WindowId::new(1, 5), rect(1050, 300, 150, 300)),
+            ],
+            mgr.layout_sorted(space, screen)[3..],
+        );
+    }
+
+    #[test]
+    fn it_focuses_children_by_index() {
+        use LayoutEvent::*;
+        let mut mgr = LayoutManager::new();
+        let space = SpaceId::new(1);
+        let pid = 1;
+        let screen = rect(0, 0, 120, 120);
+        _ = mgr.handle_event(SpaceExposed(space, screen.size, LayoutKind::default()));
+        _ = mgr.handle_event(WindowsOnScreenUpdated(space, pid, make_windows(pid, 3)));
+        _ = mgr.handle_event(WindowRaised(space, Some(WindowId::new(pid, 1))));
+
+        let response = mgr.handle_command(space, LayoutCommand::FocusChildIndex(3)).unwrap();
+        assert_eq!(Some(WindowId::new(pid, 3)), response.raise_window);
+        assert_eq!(
+            Some(CommandError::NoSuchChild(4)),
+            mgr.handle_command(space, LayoutCommand::FocusChildIndex(4)).err()
+        );
+        assert_eq!(
+            Some(CommandError::NoSuchChild(0)),
+            mgr.handle_command(space, LayoutCommand::FocusChildIndex(0)).err()
+        );
+    }
+
     #[test]
     fn it_maintains_separate_layouts_for_each_screen_size() {
         use LayoutEvent::*;
@@ -295,7 +792,7 @@ mod tests {
 
         // Set up the starting layout.
         let screen1 = rect(0, 0, 120, 120);
-        _ = mgr.handle_event(SpaceExposed(space, screen1.size));
+        _ = mgr.handle_event(SpaceExposed(space, screen1.size, LayoutKind::default()));
         _ = mgr.handle_event(WindowsOnScreenUpdated(space, pid, make_windows(pid, 3)));
         _ = mgr.handle_event(WindowRaised(space, Some(WindowId::new(pid, 1))));
         _ = mgr.handle_command(space, LayoutCommand::MoveNode(Direction::Up));
@@ -310,7 +807,7 @@ mod tests {
 
         // Introduce new screen size.
         let screen2 = rect(0, 0, 1200, 1200);
-        _ = mgr.handle_event(SpaceExposed(space, screen2.size));
+        _ = mgr.handle_event(SpaceExposed(space, screen2.size, LayoutKind::default()));
         assert_eq!(
             vec![
                 (WindowId::new(pid, 1), rect(0, 0, 1200, 600)),
@@ -333,7 +830,7 @@ mod tests {
         );
 
         // Switch back to the first size; the layout should be the same as before.
-        _ = mgr.handle_event(SpaceExposed(space, screen1.size));
+        _ = mgr.handle_event(SpaceExposed(space, screen1.size, LayoutKind::default()));
         assert_eq!(
             vec![
                 (WindowId::new(pid, 1), rect(0, 0, 120, 60)),
@@ -344,7 +841,7 @@ mod tests {
         );
 
         // Switch back to the second size.
-        _ = mgr.handle_event(SpaceExposed(space, screen2.size));
+        _ = mgr.handle_event(SpaceExposed(space, screen2.size, LayoutKind::default()));
         assert_eq!(
             vec![
                 (WindowId::new(pid, 1), rect(0, 0, 400, 1200)),
@@ -0,0 +1,308 @@
+//! The mouse actor watches the cursor and runs the commands bound to hot
+//! corners. It also passes drags on windows we manage with the window drag
+//! modifiers held on to the reactor, which moves or resizes the window under
+//! the cursor, and double-clicks on title bars if they are configured to do
+//! something.
+//!
+//! The WM controller tells us where the screens are whenever they change.
+
+use std::sync::{Arc, Mutex};
+
+use icrate::Foundation::{CGPoint, CGRect};
+use tracing::{info_span, warn, Span};
+
+use crate::{
+    actor::{
+        app::WindowId,
+        reactor::{self, Command},
+        wm_controller::{self, WmCommand, WmEvent},
+    },
+    config::{HotCornersConfig, TitleBarConfig, WindowDragConfig},
+    sys::{
+        event::{DoubleClickTap, DragEvent, DragTap, MouseEvent, MouseTap},
+        geometry::{Contains, Corner},
+    },
+};
+
+pub type Sender = tokio::sync::mpsc::UnboundedSender<(Span, Event)>;
+type WeakSender = tokio::sync::mpsc::WeakUnboundedSender<(Span, Event)>;
+pub type Receiver = tokio::sync::mpsc::UnboundedReceiver<(Span, Event)>;
+
+/// Creates the channel for the actor. The WM controller is created with the
+/// sender before the actor itself, which needs the controller's sender.
+pub fn channel() -> (Sender, Receiver) {
+    tokio::sync::mpsc::unbounded_channel()
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// The frames of all screens, in CG (top-left origin) coordinates.
+    ScreenParametersChanged(Vec<CGRect>),
+    Mouse(MouseEvent),
+    Drag(DragEvent),
+    TitleBarDoubleClicked(WindowId),
+}
+
+/// How close the cursor has to be to the corner of a screen, in points.
+const CORNER_SIZE: f64 = 2.0;
+
+/// The height of a standard title bar, in points.
+const TITLE_BAR_HEIGHT: f64 = 28.0;
+
+/// The frames of the visible windows on managed spaces, starting with the
+/// frontmost.
+///
+/// The reactor keeps these up to date. The event taps read them as clicks
+/// happen, because they have to decide right away whether to keep a click from
+/// the app.
+#[derive(Clone, Default, Debug)]
+pub struct WindowFrames(Arc<Mutex<Vec<WindowFrame>>>);
+
+#[derive(Clone, Copy, Debug)]
+pub struct WindowFrame {
+    pub wid: WindowId,
+    pub frame: CGRect,
+    /// Whether the window has a title bar that can be double-clicked.
+    pub is_standard: bool,
+}
+
+impl WindowFrames {
+    pub fn set(&self, windows: Vec<WindowFrame>) {
+        *self.0.lock().unwrap() = windows;
+    }
+
+    /// Returns the window at `point`, if any.
+    pub fn window_at(&self, point: CGPoint) -> Option<WindowId> {
+        self.frontmost_at(point).map(|window| window.wid)
+    }
+
+    /// Returns the window whose title bar is at `point`, unless another
+    /// window covers it there.
+    pub fn title_bar_at(&self, point: CGPoint) -> Option<WindowId> {
+        let window = self.frontmost_at(point)?;
+        let in_title_bar = point.y < window.frame.origin.y + TITLE_BAR_HEIGHT;
+        (window.is_standard && in_title_bar).then_some(window.wid)
+    }
+
+    fn frontmost_at(&self, point: CGPoint) -> Option<WindowFrame> {
+        let windows = self.0.lock().unwrap();
+        windows.iter().find(|window| window.frame.contains(point)).copied()
+    }
+}
+
+pub struct Mouse {
+    hot_corners: HotCornersConfig,
+    window_drag: WindowDragConfig,
+    title_bar: TitleBarConfig,
+    events_tx: wm_controller::Sender,
+    receiver: Receiver,
+    sender: WeakSender,
+    screens: Vec<CGRect>,
+    /// The corner the cursor is in. Commands run only when it enters one, so
+    /// resting the cursor in a corner doesn't repeat them.
+    corner: Option<Corner>,
+    mouse_tap: Option<MouseTap>,
+    drag_tap: Option<DragTap>,
+    double_click_tap: Option<DoubleClickTap>,
+}
+
+impl Mouse {
+    pub fn new(
+        hot_corners: HotCornersConfig,
+        window_drag: WindowDragConfig,
+        title_bar: TitleBarConfig,
+        events_tx: wm_controller::Sender,
+        sender: &Sender,
+        receiver: Receiver,
+    ) -> Self {
+        Mouse {
+            hot_corners,
+            window_drag,
+            title_bar,
+            events_tx,
+            receiver,
+            sender: sender.downgrade(),
+            screens: vec![],
+            corner: None,
+            mouse_tap: None,
+            drag_tap: None,
+            double_click_tap: None,
+        }
+    }
+
+    pub async fn run(mut self) {
+        let window_frames = WindowFrames::default();
+        if !self.hot_corners.is_empty() {
+            let sender = self.sender.clone();
+            self.mouse_tap = MouseTap::new(move |event| {
+                // Only movement can bring the cursor into a corner.
+                if !matches!(event, MouseEvent::Moved(_)) {
+                    return;
+                }
+                let Some(sender) = sender.upgrade() else { return };
+                _ = sender.send((Span::none(), Event::Mouse(event)));
+            });
+            if self.mouse_tap.is_none() {
+                warn!("Could not watch the mouse; hot corners are disabled");
+            }
+        }
+        if self.window_drag.is_enabled() {
+            let sender = self.sender.clone();
+            let tap_window_frames = window_frames.clone();
+            self.drag_tap = DragTap::new(
+                &self.window_drag.modifiers,
+                move |point| tap_window_frames.window_at(point).is_some(),
+                move |event| {
+                    let Some(sender) = sender.upgrade() else { return };
+                    _ = sender.send((Span::none(), Event::Drag(event)));
+                },
+            );
+            if self.drag_tap.is_none() {
+                warn!("Could not watch the mouse; window dragging is disabled");
+            }
+        }
+        if self.title_bar.double_click.is_some() {
+            let sender = self.sender.clone();
+            let tap_window_frames = window_frames.clone();
+            self.double_click_tap = DoubleClickTap::new(move |point| {
+                let Some(wid) = tap_window_frames.title_bar_at(point) else {
+                    return false;
+                };
+                let Some(sender) = sender.upgrade() else { return false };
+                _ = sender.send((Span::none(), Event::TitleBarDoubleClicked(wid)));
+                true
+            });
+            if self.double_click_tap.is_none() {
+                warn!("Could not watch the mouse; title bar actions are disabled");
+            }
+        }
+        if self.drag_tap.is_some() || self.double_click_tap.is_some() {
+            let event = reactor::Event::WatchWindowFrames(window_frames);
+            _ = self.events_tx.send((Span::none(), WmEvent::ReactorEvent(event)));
+        }
+        if self.mouse_tap.is_none() && self.drag_tap.is_none() && self.double_click_tap.is_none() {
+            // Don't watch every mouse movement for nothing.
+            return;
+        }
+        while let Some((span, event)) = self.receiver.recv().await {
+            let _guard = span.enter();
+            self.handle_event(event);
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::ScreenParametersChanged(screens) => self.screens = screens,
+            Event::Mouse(MouseEvent::Moved(position)) => {
+                let corner = corner_at(&self.screens, position);
+                if corner == self.corner {
+                    return;
+                }
+                self.corner = corner;
+                let Some(cmd) = corner.and_then(|corner| self.command(corner)) else {
+                    return;
+                };
+                let span = info_span!("mouse::hot_corner", ?corner);
+                let cmd = WmCommand::ReactorCommand(cmd.clone());
+                _ = self.events_tx.send((span, WmEvent::Command(cmd)));
+            }
+            Event::Mouse(_) => (),
+            Event::Drag(event) => {
+                let event = reactor::Event::WindowDrag(event);
+                _ = self.events_tx.send((Span::current(), WmEvent::ReactorEvent(event)));
+            }
+            Event::TitleBarDoubleClicked(wid) => {
+                let Some(action) = self.title_bar.double_click else {
+                    return;
+                };
+                let event = reactor::Event::TitleBarDoubleClicked(wid, action);
+                _ = self.events_tx.send((Span::current(), WmEvent::ReactorEvent(event)));
+            }
+        }
+    }
+
+    fn command(&self, corner: Corner) -> Option<&Command> {
+        match corner {
+            Corner::TopLeft => self.hot_corners.top_left.as_ref(),
+            Corner::TopRight => self.hot_corners.top_right.as_ref(),
+            Corner::BottomLeft => self.hot_corners.bottom_left.as_ref(),
+            Corner::BottomRight => self.hot_corners.bottom_right.as_ref(),
+        }
+    }
+}
+
+/// Returns the corner of a screen that `point` is in, if any.
+fn corner_at(screens: &[CGRect], point: CGPoint) -> Option<Corner> {
+    screens.iter().find_map(|screen| {
+        let left = point.x - screen.origin.x;
+        let right = screen.origin.x + screen.size.width - point.x;
+        let top = point.y - screen.origin.y;
+        let bottom = screen.origin.y + screen.size.height - point.y;
+        // The right and bottom edges belong to the next screen over.
+        if left < 0.0 || top < 0.0 || right <= 0.0 || bottom <= 0.0 {
+            return None;
+        }
+        let near = |dist: f64| dist <= CORNER_SIZE;
+        match (near(left), near(right), near(top), near(bottom)) {
+            (true, _, true, _) => Some(Corner::TopLeft),
+            (_, true, true, _) => Some(Corner::TopRight),
+            (true, _, _, true) => Some(Corner::BottomLeft),
+            (_, true, _, true) => Some(Corner::BottomRight),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use icrate::Foundation::{CGPoint, CGRect, CGSize};
+
+    use super::{corner_at, WindowFrame, WindowFrames};
+    use crate::{actor::app::WindowId, sys::geometry::Corner};
+
+    #[test]
+    fn it_finds_the_corner_under_the_cursor() {
+        let rect = |x, y, w, h| CGRect::new(CGPoint::new(x, y), CGSize::new(w, h));
+        let screens = [rect(0., 0., 1000., 800.), rect(1000., -200., 1200., 1000.)];
+        let corner = |x, y| corner_at(&screens, CGPoint::new(x, y));
+        assert_eq!(Some(Corner::TopLeft), corner(0., 0.));
+        assert_eq!(Some(Corner::BottomRight), corner(999., 799.));
+        assert_eq!(Some(Corner::TopRight), corner(2199., -200.));
+        assert_eq!(Some(Corner::BottomLeft), corner(1000., 799.5));
+        assert_eq!(None, corner(500., 0.));
+        assert_eq!(None, corner(0., 400.));
+        assert_eq!(None, corner(1010., 0.));
+    }
+
+    #[test]
+    fn it_finds_the_window_and_title_bar_under_the_cursor() {
+        let rect = |x, y, w, h| CGRect::new(CGPoint::new(x, y), CGSize::new(w, h));
+        let window_frames = WindowFrames::default();
+        let (front, back, dialog) = (
+            WindowId::new(1, 1),
+            WindowId::new(2, 1),
+            WindowId::new(2, 2),
+        );
+        let window = |wid, frame, is_standard| WindowFrame { wid, frame, is_standard };
+        window_frames.set(vec![
+            window(dialog, rect(600., 300., 200., 200.), false),
+            window(front, rect(100., 20., 400., 400.), true),
+            window(back, rect(0., 0., 1000., 800.), true),
+        ]);
+        let title_bar_at = |x, y| window_frames.title_bar_at(CGPoint::new(x, y));
+        assert_eq!(Some(back), title_bar_at(50., 10.));
+        assert_eq!(Some(back), title_bar_at(200., 10.));
+        // The front window covers the title bar of the back one.
+        assert_eq!(Some(front), title_bar_at(200., 25.));
+        assert_eq!(None, title_bar_at(200., 100.));
+        assert_eq!(None, title_bar_at(50., 100.));
+        assert_eq!(None, title_bar_at(1100., 10.));
+        assert_eq!(None, title_bar_at(650., 310.));
+
+        let window_at = |x, y| window_frames.window_at(CGPoint::new(x, y));
+        assert_eq!(Some(front), window_at(200., 100.));
+        assert_eq!(Some(dialog), window_at(650., 310.));
+        assert_eq!(Some(back), window_at(50., 700.));
+        assert_eq!(None, window_at(1100., 10.));
+    }
+}
@@ -1,7 +1,7 @@
 //! This actor manages the global notification queue, which tells us when an
 //! application is launched or focused or the screen state changes.
 
-use std::{cell::RefCell, future, mem};
+use std::{cell::RefCell, ffi::c_void, future, mem, ptr};
 
 use icrate::{
     objc2::{
@@ -10,13 +10,16 @@ use icrate::{
         sel, ClassType, DeclaredClass, Encode, Encoding,
     },
     AppKit::{self, NSApplication, NSRunningApplication, NSWorkspace, NSWorkspaceApplicationKey},
-    Foundation::{MainThreadMarker, NSNotification, NSNotificationCenter, NSObject},
+    Foundation::{
+        ns_string, MainThreadMarker, NSDistributedNotificationCenter, NSKeyValueObservingOptionNew,
+        NSNotification, NSNotificationCenter, NSObject, NSString,
+    },
 };
 use tracing::{info_span, trace, warn, Span};
 
 use crate::{
     actor::app::AppInfo, actor::reactor::Event, sys::app::NSRunningApplicationExt,
-    sys::screen::ScreenCache,
+    sys::appearance::current_appearance, sys::keyboard, sys::screen::ScreenCache,
 };
 
 use super::wm_controller::{self, WmEvent};
@@ -67,6 +70,28 @@ declare_class! {
             trace!("{notif:#?}");
             self.handle_app_event(notif);
         }
+
+        #[method(recvKeyboardLayoutChanged:)]
+        fn recv_keyboard_layout_changed(&self, notif: &NSNotification) {
+            trace!("{notif:#?}");
+            self.send_wm_event(WmEvent::KeyboardLayoutChanged);
+        }
+
+        #[method(observeValueForKeyPath:ofObject:change:context:)]
+        fn observe_value_for_key_path(
+            &self,
+            key_path: Option<&NSString>,
+            _object: Option<&NSObject>,
+            _change: Option<&NSObject>,
+            _context: *mut c_void,
+        ) {
+            trace!(?key_path, "Observed value change");
+            if key_path.is_some_and(|path| path == ns_string!("effectiveAppearance")) {
+                self.send_appearance();
+            } else {
+                self.send_frontmost_app();
+            }
+        }
     }
 }
 
@@ -97,6 +122,7 @@ impl NotificationCenterInner {
         let mut screen_cache = self.ivars().screen_cache.borrow_mut();
         let frames = screen_cache.update_screen_config();
         let spaces = screen_cache.get_screen_spaces();
+        self.send_wm_event(WmEvent::DisplaysChanged(screen_cache.displays()));
         self.send_event(Event::ScreenParametersChanged(frames, spaces));
     }
 
@@ -105,6 +131,23 @@ impl NotificationCenterInner {
         self.send_event(Event::SpaceChanged(spaces));
     }
 
+    fn send_appearance(&self) {
+        let span = info_span!("notification_center::send_appearance");
+        let _guard = span.enter();
+        let appearance = current_appearance(MainThreadMarker::new().unwrap());
+        self.send_wm_event(WmEvent::AppearanceChanged(appearance));
+    }
+
+    /// Reports the frontmost app as seen by NSWorkspace, so the reactor can
+    /// correct its state if it missed an activation event from the app.
+    fn send_frontmost_app(&self) {
+        let span = info_span!("notification_center::send_frontmost_app");
+        let _guard = span.enter();
+        let workspace = unsafe { NSWorkspace::sharedWorkspace() };
+        let pid = unsafe { workspace.frontmostApplication() }.map(|app| app.pid());
+        self.send_event(Event::FrontmostAppChanged(pid));
+    }
+
     fn handle_app_event(&self, notif: &NSNotification) {
         use AppKit::*;
         let Some(app) = self.running_application(notif) else {
@@ -217,6 +260,35 @@ impl NotificationCenter {
                 workspace,
             );
         };
+        // Keyboard layout changes are only posted to the distributed center.
+        unsafe {
+            NSDistributedNotificationCenter::defaultCenter().addObserver_selector_name_object(
+                &handler,
+                sel!(recvKeyboardLayoutChanged:),
+                Some(keyboard::layout_changed_notification()),
+                None,
+            )
+        };
+
+        // Some apps don't reliably send accessibility notifications when they
+        // are activated, so we also watch the frontmost app directly.
+        unsafe {
+            workspace.addObserver_forKeyPath_options_context(
+                &handler,
+                ns_string!("frontmostApplication"),
+                NSKeyValueObservingOptionNew,
+                ptr::null_mut(),
+            )
+        };
+        // There is no notification for switching between light and dark mode.
+        unsafe {
+            shared_app.addObserver_forKeyPath_options_context(
+                &handler,
+                ns_string!("effectiveAppearance"),
+                NSKeyValueObservingOptionNew,
+                ptr::null_mut(),
+            )
+        };
 
         NotificationCenter { inner: handler }
     }
@@ -225,6 +297,7 @@ impl NotificationCenter {
         let workspace = &unsafe { NSWorkspace::sharedWorkspace() };
 
         self.inner.send_screen_parameters();
+        self.inner.send_appearance();
         self.inner.send_wm_event(WmEvent::AppEventsRegistered);
         if let Some(app) = unsafe { workspace.frontmostApplication() } {
             self.inner.send_event(Event::ApplicationGloballyActivated(app.pid()));
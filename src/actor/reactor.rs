@@ -3,70 +3,866 @@
 //! It takes events from the rest of the system and builds a coherent picture of
 //! what is going on. It shares this with the layout actor, and reacts to layout
 //! changes by sending requests out to the other actors in the system.
+//!
+//! There is a single reactor for all displays. Only the main screen is laid
+//! out, so there is no per-display layout work that could move to a reactor
+//! of its own. Running one reactor per display would first need layouts for
+//! every screen, then splitting `windows`, `screens`, and the layout manager
+//! by display, and a coordinator that hands a window from one reactor to
+//! another when it moves between displays. Until then, the work that can
+//! delay commands is animation, which runs on the reactor thread and is left
+//! out of the timings in [`crate::metrics`].
 
 mod animation;
+mod drag;
+#[cfg(debug_assertions)]
+mod inject;
+mod interner;
+mod offscreen;
+mod pin;
+mod recent;
+#[cfg(any(test, feature = "bench"))]
+pub mod testing;
 
-use std::{collections::HashMap, mem, sync, thread};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, mem,
+    sync::{
+        self,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{RecvTimeoutError, TrySendError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use icrate::Foundation::CGRect;
+use icrate::Foundation::{CGPoint, CGRect, CGSize};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, info, instrument, trace, warn, Span};
 
 use crate::{
     actor::app::{pid_t, AppInfo, AppThreadHandle, RaiseToken, Request, WindowId, WindowInfo},
     actor::layout::{self, LayoutCommand, LayoutEvent, LayoutManager},
-    metrics::{self, MetricsCommand},
-    sys::geometry::{Round, SameAs},
-    sys::screen::SpaceId,
+    actor::{
+        borders,
+        mouse::{WindowFrame, WindowFrames},
+        split_handles,
+        wm_controller::{self, WmEvent},
+    },
+    config::{self, FocusModeConfig, PipConfig, RaiseConfig},
+    metrics::{self, MetricsCommand, Timing},
+    model::{Direction, LayoutKind, LayoutSpec, NodeId, Orientation, Place, Split, WindowMatcher},
+    sys::announce,
+    sys::app::open_app,
+    sys::clock::{Clock, SystemClock},
+    sys::event::{self, DragEvent},
+    sys::geometry::{CGRectDef, Contains, Inset, Rescale, Round, SameAs},
+    sys::power::PowerSource,
+    sys::screen::{self, DisplayUuid, SpaceId, SpaceInfo, SpaceUuid},
+    sys::window_managers,
+    sys::window_server::{
+        SystemWindowServer, WindowAppearance, WindowServer, WindowServerId, WindowServerInfo,
+    },
 };
-use animation::Animation;
+use animation::{Animation, FULL_FPS, REDUCED_FPS};
+use drag::{SplitDrag, WindowDrag};
+#[cfg(debug_assertions)]
+pub use inject::InjectedEvent;
+use interner::Interner;
+pub use pin::Pin;
+use recent::RecentCycle;
+
+/// The most events that can wait for the reactor, which bounds the memory
+/// they use when apps send events faster than we can handle them.
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// The most events that can be waiting for [`Sender::try_send`] to send
+/// another. Past this, the rest of the channel is left for events that
+/// can't be dropped, so their senders, like the main thread, don't wait
+/// behind a flood of frame changes.
+const TRY_SEND_LIMIT: usize = CHANNEL_CAPACITY / 2;
+
+/// The lowest opacity [`Command::SetOpacity`] sets.
+const MIN_OPACITY: f64 = 0.1;
+
+/// Sends events to the reactor.
+///
+/// When the channel is full, [`Sender::send`] waits for room so that no event
+/// is lost. App threads send frame changes with [`Sender::try_send`] instead,
+/// and hold on to the latest frame of each window until there is room.
+#[derive(Clone, Debug)]
+pub struct Sender {
+    tx: sync::mpsc::SyncSender<(Span, Event)>,
+    /// The number of events waiting in the channel.
+    queued: Arc<AtomicUsize>,
+}
+
+impl Sender {
+    pub fn send(&self, msg: (Span, Event)) -> Result<(), sync::mpsc::SendError<(Span, Event)>> {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let result = self.tx.send(msg);
+        if result.is_err() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Sends an event only if fewer than [`TRY_SEND_LIMIT`] events are
+    /// waiting, which keeps the rest of the channel free for [`Self::send`].
+    pub fn try_send(&self, msg: (Span, Event)) -> Result<(), TrySendError<(Span, Event)>> {
+        if self.queued.load(Ordering::Relaxed) >= TRY_SEND_LIMIT {
+            return Err(TrySendError::Full(msg));
+        }
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let result = self.tx.try_send(msg);
+        if result.is_err() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// Receives the events sent with a [`Sender`].
+struct Receiver {
+    rx: sync::mpsc::Receiver<(Span, Event)>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl Receiver {
+    /// Waits up to `timeout` for an event, or for as long as it takes if
+    /// there is no timeout.
+    fn recv(&self, timeout: Option<Duration>) -> Result<(Span, Event), RecvTimeoutError> {
+        let received = match timeout {
+            Some(timeout) => self.rx.recv_timeout(timeout),
+            None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        if received.is_ok() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        received
+    }
+}
 
-pub type Sender = std::sync::mpsc::Sender<(Span, Event)>;
+fn channel() -> (Sender, Receiver) {
+    let (tx, rx) = sync::mpsc::sync_channel(CHANNEL_CAPACITY);
+    let queued = Arc::new(AtomicUsize::new(0));
+    (
+        Sender { tx, queued: queued.clone() },
+        Receiver { rx, queued },
+    )
+}
 
 #[derive(Debug)]
 pub enum Event {
     ApplicationLaunched(pid_t, AppState),
+    /// The app could not be watched when it launched, but can be now. Sent
+    /// right after its ApplicationLaunched event.
+    ApplicationObservable(pid_t),
     ApplicationTerminated(pid_t),
     ApplicationActivated(pid_t, Option<WindowId>),
     ApplicationGloballyActivated(pid_t),
     ApplicationGloballyDeactivated(pid_t),
     ApplicationDeactivated(pid_t),
     ApplicationMainWindowChanged(pid_t, Option<WindowId>),
+    /// The frontmost app changed according to NSWorkspace. This duplicates
+    /// the other activation events, but is used to detect when we missed one.
+    FrontmostAppChanged(Option<pid_t>),
+    /// The app is asking for the user's attention by bouncing its Dock
+    /// icon.
+    ApplicationRequestedAttention(pid_t),
 
     WindowsDiscovered {
         pid: pid_t,
         new: Vec<(WindowId, WindowInfo)>,
         known_visible: Vec<WindowId>,
     },
+    /// The windows of the app that appeared or disappeared since it last
+    /// reported its visible windows.
+    VisibleWindowsChanged {
+        pid: pid_t,
+        /// Windows we haven't seen before.
+        new: Vec<(WindowId, WindowInfo)>,
+        /// Known windows that became visible.
+        shown: Vec<WindowId>,
+        hidden: Vec<WindowId>,
+    },
     WindowCreated(WindowId, WindowInfo),
     WindowDestroyed(WindowId),
     WindowFrameChanged(WindowId, CGRect, TransactionId, Requested),
+    WindowTitleChanged(WindowId, String),
 
     // None in the SpaceId vec disables managing windows on that screen until the next space change.
     ScreenParametersChanged(Vec<CGRect>, Vec<Option<SpaceId>>),
     SpaceChanged(Vec<Option<SpaceId>>),
+    /// The spaces on every display, in Mission Control order. Sent when we
+    /// start, and again when desktops are added, removed, or reordered.
+    SpacesChanged(Vec<SpaceInfo>),
+    /// The spaces the user turned off, which are saved with the layout.
+    DisabledSpacesChanged(HashSet<SpaceUuid>),
+    /// Settings for each screen, in the same order as the frames of
+    /// [`Event::ScreenParametersChanged`]. Sent before that event when the
+    /// screens change.
+    ScreenSettingsChanged(Vec<ScreenSettings>),
+    /// Screen capture started or stopped. Only sent if tiling should pause
+    /// during screen sharing.
+    ScreenCaptureChanged(bool),
+    /// The user revoked our Accessibility permission, or granted it again.
+    AccessibilityChanged(bool),
+    /// The power source changed, or how animations should run on it.
+    PowerStateChanged(PowerState),
+    /// The window limits changed because a different profile became active.
+    /// They apply to windows added from then on.
+    WindowLimitsChanged(HashMap<String, usize>),
+    /// A macOS Focus started or ended. Only sent if `focus_mode` is
+    /// configured.
+    FocusModeChanged(bool),
+    /// Sent every few minutes to check our model of the windows against the
+    /// window server, in case we missed an event.
+    Audit,
+    /// The user dragged the boundary after `node` by `delta` points.
+    SplitDragged {
+        node: NodeId,
+        orientation: Orientation,
+        delta: f64,
+    },
+    /// The user let go of the boundary they were dragging.
+    SplitDragEnded,
+    /// The user is dragging with the window drag modifiers held.
+    WindowDrag(DragEvent),
+    /// Keeps the frames up to date with the visible windows, whose drags and
+    /// title bar double-clicks the mouse actor handles.
+    WatchWindowFrames(WindowFrames),
+    /// The user double-clicked the title bar of a window.
+    TitleBarDoubleClicked(WindowId, TitleBarAction),
 
     Command(Command),
+    /// Runs a command and reports whether it did anything.
+    CommandWithResult(Command, sync::mpsc::Sender<CommandResult>),
+    /// Runs commands in order as a single event, so the layout is only
+    /// updated after the last one. Stops at the first command that fails, and
+    /// reports the results of the commands that ran.
+    CommandBatch(Vec<Command>, sync::mpsc::Sender<Vec<CommandResult>>),
+    Query(Query, sync::mpsc::Sender<QueryResponse>),
+    /// Sends the spaces, as in [`Query::Spaces`], now and whenever they
+    /// change, until the receiver is dropped.
+    SubscribeSpaces(sync::mpsc::Sender<Vec<SpaceData>>),
+    /// Sends the stacks, as in [`Query::Stacks`], now and whenever they
+    /// change, until the receiver is dropped.
+    SubscribeStacks(sync::mpsc::Sender<Vec<StackData>>),
+    /// Sent by the WM controller when it starts, so that we can ask it to
+    /// show notices, like the one about another window manager.
+    WmControllerStarted(wm_controller::WeakSender),
+    /// Handles made-up events in order, for reproducing bugs, and reports
+    /// why if one could not be handled.
+    #[cfg(debug_assertions)]
+    Inject(Vec<InjectedEvent>, sync::mpsc::Sender<Result<(), String>>),
+}
+
+impl Event {
+    /// The name of the event variant, for structured logging.
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::ApplicationLaunched(..) => "ApplicationLaunched",
+            Event::ApplicationObservable(..) => "ApplicationObservable",
+            Event::ApplicationTerminated(..) => "ApplicationTerminated",
+            Event::ApplicationActivated(..) => "ApplicationActivated",
+            Event::ApplicationGloballyActivated(..) => "ApplicationGloballyActivated",
+            Event::ApplicationGloballyDeactivated(..) => "ApplicationGloballyDeactivated",
+            Event::ApplicationDeactivated(..) => "ApplicationDeactivated",
+            Event::ApplicationMainWindowChanged(..) => "ApplicationMainWindowChanged",
+            Event::FrontmostAppChanged(..) => "FrontmostAppChanged",
+            Event::ApplicationRequestedAttention(..) => "ApplicationRequestedAttention",
+            Event::WindowsDiscovered { .. } => "WindowsDiscovered",
+            Event::VisibleWindowsChanged { .. } => "VisibleWindowsChanged",
+            Event::WindowCreated(..) => "WindowCreated",
+            Event::WindowDestroyed(..) => "WindowDestroyed",
+            Event::WindowFrameChanged(..) => "WindowFrameChanged",
+            Event::WindowTitleChanged(..) => "WindowTitleChanged",
+            Event::ScreenParametersChanged(..) => "ScreenParametersChanged",
+            Event::SpaceChanged(..) => "SpaceChanged",
+            Event::SpacesChanged(..) => "SpacesChanged",
+            Event::DisabledSpacesChanged(..) => "DisabledSpacesChanged",
+            Event::ScreenSettingsChanged(..) => "ScreenSettingsChanged",
+            Event::ScreenCaptureChanged(..) => "ScreenCaptureChanged",
+            Event::AccessibilityChanged(..) => "AccessibilityChanged",
+            Event::PowerStateChanged(..) => "PowerStateChanged",
+            Event::WindowLimitsChanged(..) => "WindowLimitsChanged",
+            Event::FocusModeChanged(..) => "FocusModeChanged",
+            Event::Audit => "Audit",
+            Event::SplitDragged { .. } => "SplitDragged",
+            Event::SplitDragEnded => "SplitDragEnded",
+            Event::WindowDrag(..) => "WindowDrag",
+            Event::WatchWindowFrames(..) => "WatchWindowFrames",
+            Event::TitleBarDoubleClicked(..) => "TitleBarDoubleClicked",
+            Event::Command(..) => "Command",
+            Event::CommandWithResult(..) => "CommandWithResult",
+            Event::CommandBatch(..) => "CommandBatch",
+            Event::Query(..) => "Query",
+            Event::SubscribeSpaces(..) => "SubscribeSpaces",
+            Event::SubscribeStacks(..) => "SubscribeStacks",
+            Event::WmControllerStarted(..) => "WmControllerStarted",
+            #[cfg(debug_assertions)]
+            Event::Inject(..) => "Inject",
+        }
+    }
+
+    /// The app this event is about, if any.
+    fn pid(&self) -> Option<pid_t> {
+        match self {
+            Event::ApplicationLaunched(pid, _)
+            | Event::ApplicationObservable(pid)
+            | Event::ApplicationTerminated(pid)
+            | Event::ApplicationActivated(pid, _)
+            | Event::ApplicationGloballyActivated(pid)
+            | Event::ApplicationGloballyDeactivated(pid)
+            | Event::ApplicationDeactivated(pid)
+            | Event::ApplicationMainWindowChanged(pid, _)
+            | Event::ApplicationRequestedAttention(pid)
+            | Event::WindowsDiscovered { pid, .. }
+            | Event::VisibleWindowsChanged { pid, .. } => Some(*pid),
+            Event::FrontmostAppChanged(pid) => *pid,
+            _ => self.window_id().map(|wid| wid.pid),
+        }
+    }
+
+    /// The window this event is about, if any.
+    fn window_id(&self) -> Option<WindowId> {
+        match self {
+            Event::WindowCreated(wid, _)
+            | Event::WindowDestroyed(wid)
+            | Event::WindowFrameChanged(wid, ..)
+            | Event::WindowTitleChanged(wid, _)
+            | Event::TitleBarDoubleClicked(wid, _) => Some(*wid),
+            Event::ApplicationActivated(_, wid) | Event::ApplicationMainWindowChanged(_, wid) => {
+                *wid
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Requested(pub bool);
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Command {
     Hello,
+    /// Raises the topmost managed window under the mouse cursor, without
+    /// activating its app, for focus that follows the mouse.
+    FocusWindowUnderCursor,
+    /// Raises the window that most recently asked for the user's attention.
+    FocusUrgent,
+    /// Moves the focused window to the nth desktop (starting at 1) on its
+    /// display, then switches to that desktop and focuses the window there.
+    FollowWindowToSpace(usize),
+    /// Moves the focused window to the nth desktop (starting at 1) on its
+    /// display. Focus follows it if `follow_moved_windows` is set, and
+    /// otherwise goes to the next window in the layout.
+    MoveWindowToSpace(usize),
+    /// Moves a window from any space to the space on the display with the
+    /// focused window and raises it. The window is the frontmost one whose title, app name, or
+    /// bundle id contains the text, ignoring case.
+    ///
+    /// Only windows we have seen since starting can be found.
+    PullWindow(String),
+    /// Like [`Command::PullWindow`], but switches to the window's space
+    /// instead of moving the window.
+    GoToWindow(String),
+    /// Raises the next window of the focused app, switching to its space if
+    /// it is on another one. Unlike Cmd+`, this includes windows on other
+    /// spaces.
+    FocusNextWindowOfApp,
+    FocusPrevWindowOfApp,
+    /// Raises the window focused before the current one. Repeating this
+    /// before [`Command::EndRecentCycle`] goes further back, through every
+    /// standard window in the order they were last focused, like Cmd+Tab
+    /// does for apps.
+    CycleRecentWindows,
+    /// Like [`Command::CycleRecentWindows`], but goes the other way.
+    CycleRecentWindowsBack,
+    /// Ends the cycle started by [`Command::CycleRecentWindows`], making the
+    /// window it ended on the most recently used. Key bindings send this when
+    /// their modifiers are released.
+    EndRecentCycle,
+    /// Reopens the most recently closed window. Its app is asked to reopen
+    /// it, or launched if it quit, and the window it opens takes the place of
+    /// the closed one.
+    ReopenClosedWindow,
+    /// Raises the frontmost window on the nth display (starting at 1).
+    /// Displays are numbered from left to right.
+    FocusDisplay(usize),
+    FocusNextDisplay,
+    FocusPrevDisplay,
+    /// Moves the focused window to the nth display (starting at 1).
+    ///
+    /// Only the main display is tiled. On other displays the window keeps its
+    /// size and is centered.
+    MoveWindowToDisplay(usize),
+    MoveWindowToNextDisplay,
+    MoveWindowToPrevDisplay,
+    /// Moves the windows that are completely off screen onto the display of
+    /// the focused window, shrinking them if they don't fit.
+    RescueOffscreenWindows,
+    /// Moves the focused window out of view, taking it out of the layout.
+    /// Unlike minimizing there is no animation, so bringing it back with
+    /// [`Command::ShowHiddenWindows`] is instant.
+    HideWindow,
+    /// Brings back the windows hidden with [`Command::HideWindow`]: tiled
+    /// windows to the layout, and the others to where they were.
+    ShowHiddenWindows,
+    /// Makes the focused window fill the screen in front of the other windows
+    /// in the layout, or puts it back in its tile.
+    ToggleZoom,
+    /// Takes the focused window out of the layout, leaving it where it is, or
+    /// puts it back in.
+    ToggleFloat,
+    /// Keeps the focused window in a corner of the main screen at the given
+    /// size, taking it out of the layout if it is tiled. It is moved back
+    /// there whenever it or the screen changes, and follows the main screen
+    /// to other spaces.
+    PinWindow(Pin),
+    /// Lets the focused window be moved again. It stays floating.
+    UnpinWindow,
+    /// Sets the opacity of the focused window, from 0.1 to 1. Lower values
+    /// are raised to 0.1, so the window can still be found.
+    ///
+    /// This and [`Command::ToggleShadow`] last until the window closes or we
+    /// quit, and only work where the window server lets us change other apps'
+    /// windows.
+    SetOpacity(f64),
+    /// Turns the focused window's shadow off, or back on.
+    ToggleShadow,
+    /// Stops moving windows until [`Command::ResumeTiling`]. Windows are still
+    /// tracked, and are moved back into place on resume.
+    PauseTiling,
+    ResumeTiling,
+    /// Shows where the layout would put each window on the main screen,
+    /// without moving them, or hides the preview. Tiling is paused while the
+    /// preview is shown.
+    ToggleLayoutPreview,
+    /// Sends every window on the main screen its frame again, even if we
+    /// think it is already there. Useful when an app moved or resized a window
+    /// without telling us.
+    ForceRelayout,
+    /// Rearranges the windows on the main screen into a layout from
+    /// [`Query::SpaceLayout`], matching windows by app and title.
+    ImportLayout(LayoutSpec),
+    /// Rebuilds the layout of the main screen to match where its windows are
+    /// now, and resumes tiling. Windows side by side or one above another
+    /// are split that way, and overlapping windows are stacked.
+    ///
+    /// To arrange windows by hand, pause tiling, move them into place, and
+    /// then run this.
+    CaptureLayout,
+    /// Stops moving the windows of the app with the given bundle id until
+    /// [`Command::ResumeApp`].
+    PauseApp(String),
+    ResumeApp(String),
     Layout(LayoutCommand),
     Metrics(MetricsCommand),
+    /// Runs a command up to the given number of times, stopping when it does
+    /// nothing, like a count before a command in vi. Only the last window a
+    /// repeated layout command focuses is raised.
+    Repeat(usize, Box<Command>),
+}
+
+/// Why a command did nothing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CommandError {
+    /// There is no focused window for the command to act on.
+    NoFocusedWindow,
+    /// The main screen is showing a space we don't manage.
+    NoManagedSpace,
+    NoWindowUnderCursor,
+    NoUrgentWindow,
+    NoWindowInDirection(Direction),
+    /// The container holding the focused window has fewer children.
+    NoSuchChild(usize),
+    NoSuchSpace(usize),
+    NoMatchingWindow(String),
+    NoClosedWindow,
+    /// The app of the closed window is not running and can't be launched.
+    AppNotFound(String),
+    NoSuchDisplay,
+    NoWindowsOnDisplay,
+    NoOffscreenWindows,
+    NoHiddenWindows,
+    /// The window is not in the layout, so it can't be zoomed or floated.
+    WindowNotTiled,
+    /// The selected tile has no neighbor that could take up its space.
+    NothingToShade,
+    WindowNotPinned,
+    /// The pinned window would have no size, or be pushed off the screen.
+    InvalidPin,
+    /// The window server only lets us change how some apps' windows look.
+    AppearanceRefused,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::NoFocusedWindow => write!(f, "no window is focused"),
+            CommandError::NoManagedSpace => write!(f, "the current space is not managed"),
+            CommandError::NoWindowUnderCursor => write!(f, "no window under the cursor"),
+            CommandError::NoUrgentWindow => write!(f, "no window is asking for attention"),
+            CommandError::NoWindowInDirection(direction) => {
+                write!(f, "no window in that direction ({direction:?})")
+            }
+            CommandError::NoSuchChild(index) => write!(f, "no window {index} in this container"),
+            CommandError::NoSuchSpace(index) => write!(f, "no space {index} on this display"),
+            CommandError::NoMatchingWindow(query) => write!(f, "no window matches {query:?}"),
+            CommandError::NoClosedWindow => write!(f, "no window was closed recently"),
+            CommandError::AppNotFound(app_id) => write!(f, "can't find the app {app_id:?}"),
+            CommandError::NoSuchDisplay => write!(f, "no such display"),
+            CommandError::NoWindowsOnDisplay => write!(f, "no windows on that display"),
+            CommandError::NoOffscreenWindows => write!(f, "no windows are off screen"),
+            CommandError::NoHiddenWindows => write!(f, "no windows are hidden"),
+            CommandError::WindowNotTiled => write!(f, "the window is not tiled"),
+            CommandError::NothingToShade => write!(f, "no other tile could take up the space"),
+            CommandError::WindowNotPinned => write!(f, "the window is not pinned"),
+            CommandError::InvalidPin => {
+                write!(
+                    f,
+                    "the width and height must be positive and the margin not negative"
+                )
+            }
+            CommandError::AppearanceRefused => {
+                write!(f, "the window server refused to change the window")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+pub type CommandResult = Result<(), CommandError>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Query {
+    /// Lists all known windows, starting with the frontmost.
+    Windows,
+    /// Dumps the layout state in the same format used for restoring it.
+    Layout,
+    /// Reports the power source and how it affects animations.
+    Power,
+    /// Describes the focused window, if any.
+    Focused,
+    /// Describes the layout of the space on the main screen, with windows
+    /// identified by app and title, if we manage the space.
+    SpaceLayout,
+    /// Lists running apps, starting with the one whose thread has used the
+    /// most CPU time.
+    Apps,
+    /// Lists the desktops and the spaces we have a layout for, with how many
+    /// windows each has, for status bars to show like workspaces in i3.
+    Spaces,
+    /// Lists the stacked and tabbed containers on the main screen, with the
+    /// windows in each, for status bars to show as tabs.
+    Stacks,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum QueryResponse {
+    Windows(Vec<WindowData>),
+    Layout(String),
+    Power(PowerState),
+    Focused(Option<FocusedWindow>),
+    SpaceLayout(Option<LayoutSpec>),
+    Apps(Vec<AppData>),
+    Spaces(Vec<SpaceData>),
+    Stacks(Vec<StackData>),
+}
+
+/// What double-clicking the title bar of a window does, instead of what is
+/// set in System Settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleBarAction {
+    /// Like [`Command::ToggleZoom`].
+    ToggleZoom,
+    /// Like [`Command::ToggleFloat`].
+    ToggleFloat,
+}
+
+/// How windows are animated into place.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnimationMode {
+    #[default]
+    Full,
+    /// Animate at a lower frame rate, to save power.
+    Reduced,
+    /// Move windows into place immediately.
+    Disabled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct PowerState {
+    pub source: PowerSource,
+    pub animations: AnimationMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WindowData {
+    pub id: WindowId,
+    /// The window's `CGWindowID`, to match it up with what other tools
+    /// report. Missing if the window server has no id for it.
+    pub window_server_id: Option<u32>,
+    pub title: String,
+    /// The localized name of the window's app.
+    pub app_name: Option<String>,
+    /// The path of the app's icon file, for switchers to show.
+    pub app_icon: Option<String>,
+    #[serde(with = "CGRectDef")]
+    pub frame: CGRect,
+    /// Whether the window is completely covered by a window in front of it.
+    pub is_occluded: bool,
+    pub subrole: String,
+    pub level: i32,
+    pub is_resizable: bool,
+    pub is_minimized: bool,
+    pub is_fullscreen: bool,
+    /// Whether the window is asking for the user's attention.
+    pub is_urgent: bool,
+    /// The opacity set with [`Command::SetOpacity`], or 1.
+    pub opacity: f64,
+    /// False if the shadow was turned off with [`Command::ToggleShadow`].
+    pub has_shadow: bool,
+    /// Where the window is kept with [`Command::PinWindow`].
+    pub pin: Option<Pin>,
+}
+
+/// A running app, and how busy its thread is.
+///
+/// An app that sends many notifications, such as one that keeps resizing a
+/// window, keeps its thread busy and shows up with a high CPU time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AppData {
+    pub pid: pid_t,
+    pub bundle_id: Option<String>,
+    /// The localized name, as shown in the Dock.
+    pub name: Option<String>,
+    pub bundle_path: Option<String>,
+    /// The path of the app's `.icns` icon file, if it has one. Apps that only
+    /// have an icon in an asset catalog have none.
+    pub icon_path: Option<String>,
+    /// For helper processes, the bundle id of the app they belong to.
+    pub group: Option<String>,
+    /// The number of windows we know about.
+    pub windows: usize,
+    /// The CPU time used by the app's thread, in seconds, if it can be read.
+    pub cpu_seconds: Option<f64>,
+}
+
+/// A desktop, or another space we have a layout for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpaceData {
+    pub id: SpaceId,
+    /// The number of the desktop on its display, starting at 1, as used by
+    /// [`Command::MoveWindowToSpace`]. Desktops are renumbered when one
+    /// before them is added or removed.
+    pub number: Option<usize>,
+    /// The number of windows in the layout, not counting floating windows.
+    pub windows: usize,
+    /// The kind of the top-level container, if the space has a layout yet.
+    pub layout: Option<LayoutKind>,
+    /// The screen showing the space, starting at 0 for the main screen, if it
+    /// is visible.
+    pub screen: Option<usize>,
+    /// Whether the space is on the main screen, which has keyboard focus.
+    pub is_focused: bool,
+}
+
+/// A stacked or tabbed container on the main screen.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StackData {
+    pub space: SpaceId,
+    pub kind: LayoutKind,
+    #[serde(with = "CGRectDef")]
+    pub frame: CGRect,
+    /// The window shown for each child of the container, in order.
+    pub windows: Vec<StackWindow>,
+    /// The index in `windows` of the selected child.
+    pub selected: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StackWindow {
+    pub id: WindowId,
+    pub title: String,
+    pub app_name: Option<String>,
+    pub app_icon: Option<String>,
+}
+
+/// The focused window, with what scripts and status bars usually want to
+/// show about it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FocusedWindow {
+    pub id: WindowId,
+    pub bundle_id: Option<String>,
+    pub app_name: Option<String>,
+    pub app_icon: Option<String>,
+    pub title: String,
+    #[serde(with = "CGRectDef")]
+    pub frame: CGRect,
+    /// The space of the screen the window is on, if we manage it.
+    pub space: Option<SpaceId>,
+    /// Whether the window is outside of the layout.
+    pub is_floating: bool,
 }
 
 pub struct Reactor {
     apps: HashMap<pid_t, AppState>,
     layout: LayoutManager,
     windows: HashMap<WindowId, WindowState>,
+    /// The id we use for each window the window server knows about.
+    ///
+    /// App threads can report a window we already know about under a new id,
+    /// for example after they restart. This lets us recognize those windows.
+    window_ids: HashMap<(pid_t, WindowServerId), WindowId>,
+    /// The stacking order of known windows, starting with the frontmost.
+    window_order: Vec<WindowId>,
+    /// Bundle ids, titles, and subroles shared between windows.
+    strings: Interner,
     main_screen: Option<Screen>,
+    /// All screens, starting with the main screen.
+    screens: Vec<Screen>,
+    /// The display showing each screen, in the same order as `screens`.
+    screen_displays: Vec<Option<DisplayUuid>>,
+    screen_settings: Vec<ScreenSettings>,
     global_frontmost_app_pid: Option<pid_t>,
     raise_token: RaiseToken,
+    clock: Arc<dyn Clock>,
+    window_server: Arc<dyn WindowServer>,
+    /// While set, we keep tracking windows but don't move any of them.
+    tiling_paused: bool,
+    /// Whether the screen is being shared; this pauses tiling like
+    /// `tiling_paused`.
+    screen_captured: bool,
+    /// Whether the user revoked our Accessibility permission. Nothing we ask
+    /// of the apps works without it, so this pauses tiling too.
+    accessibility_lost: bool,
+    power: PowerState,
+    /// Bundle ids of apps whose windows we don't move.
+    paused_apps: HashSet<String>,
+    split_handles: Option<split_handles::Sender>,
+    /// The boundaries between tiles last sent to `split_handles`.
+    splits: Vec<Split>,
+    borders: Option<borders::Sender>,
+    /// The windows last sent to `borders`.
+    bordered_windows: Vec<borders::Window>,
+    /// Whether to show where the layout would put each window instead of
+    /// moving them.
+    show_layout_preview: bool,
+    /// Set by [`Command::ForceRelayout`] until the next layout update.
+    force_relayout: bool,
+    /// The window being moved or resized by dragging it.
+    window_drag: Option<WindowDrag>,
+    /// Whether resizing by dragging a window or a boundary between tiles
+    /// shows an outline of the result, and resizes only when the drag ends.
+    preview_resize: bool,
+    /// The boundary being dragged while resizes are previewed.
+    split_drag: Option<SplitDrag>,
+    /// The tiled window filling the screen, if any.
+    zoomed: Option<WindowId>,
+    /// Where the mouse actor looks for windows that were dragged or had their
+    /// title bars double-clicked.
+    window_frames: Option<WindowFrames>,
+    /// Time spent animating while handling the current event. Animations
+    /// wait for their frames rather than doing work, so this is left out of
+    /// the event's timing.
+    animation_time: Duration,
+    /// The preview tiles last sent to `borders`.
+    preview_tiles: Vec<borders::PreviewTile>,
+    pip: PipConfig,
+    follow_moved_windows: bool,
+    focus_mode: FocusModeConfig,
+    /// How long apps get to come to the front when we raise their windows.
+    raise: RaiseConfig,
+    /// The app we last activated to raise one of its windows, and when, until
+    /// it comes to the front.
+    activating: Option<(pid_t, Instant)>,
+    /// Whether to announce each window we raise to screen readers.
+    announce_focus: bool,
+    /// Whether to list the windows being cycled through in an overlay.
+    show_switcher: bool,
+    /// Standard windows that have been focused, starting with the most
+    /// recent.
+    recent_windows: Vec<WindowId>,
+    /// The cycle started by [`Command::CycleRecentWindows`], until it ends.
+    recent_cycle: Option<RecentCycle>,
+    /// Whether a macOS Focus is on.
+    focus_active: bool,
+    /// Windows moved to the background space when the current Focus started,
+    /// and the space each was moved from.
+    focus_moved: Vec<(WindowId, SpaceId)>,
+    /// Windows asking for the user's attention, starting with the oldest
+    /// request. Cleared for an app when it is activated.
+    urgent: Vec<WindowId>,
+    /// The visible windows of each app, as last reported by its app thread.
+    visible_windows: HashMap<pid_t, Vec<WindowId>>,
+    /// Clients to tell when the spaces change.
+    space_subscribers: Vec<sync::mpsc::Sender<Vec<SpaceData>>>,
+    /// The spaces last sent to `space_subscribers`.
+    spaces_sent: Vec<SpaceData>,
+    /// Clients to tell when the stacks change.
+    stack_subscribers: Vec<sync::mpsc::Sender<Vec<StackData>>>,
+    /// The stacks last sent to `stack_subscribers`.
+    stacks_sent: Vec<StackData>,
+    /// Shows notices for us.
+    wm_controller: Option<wm_controller::WeakSender>,
+    /// The spaces on every display, in Mission Control order.
+    known_spaces: Vec<SpaceInfo>,
+    /// Spaces that have been on the main screen since we started.
+    visited_spaces: HashSet<SpaceId>,
+    /// Windows on a space we just visited for the first time that the apps
+    /// have not reported yet.
+    adoption: Option<Adoption>,
+    /// Windows closed recently, starting with the most recent.
+    closed_windows: VecDeque<ClosedWindow>,
+    /// The closed window we asked its app to reopen, and when we asked.
+    reopening: Option<(ClosedWindow, Instant)>,
 }
 
+/// Windows the window server shows on a newly visited space but that we
+/// don't know about yet.
+///
+/// Apps only report the windows on other spaces once those spaces are shown,
+/// and right after a space switch they can still report the old list, so we
+/// ask the apps that own these windows again until they show up. Until then,
+/// the layout of the space is rearranged by the adoption strategy as windows
+/// are found.
+#[derive(Debug)]
+struct Adoption {
+    space: SpaceId,
+    pending: HashMap<WindowServerId, pid_t>,
+    attempts: u32,
+    next_retry: Instant,
+}
+
+const ADOPTION_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Some windows, like those of apps the accessibility API can't see into,
+/// never show up, so we stop asking after this many tries.
+const MAX_ADOPTION_ATTEMPTS: u32 = 8;
+
+/// A window that was closed, remembered so it can be reopened in its place.
+#[derive(Debug)]
+struct ClosedWindow {
+    /// The bundle id of its app, or of the app its process belongs to.
+    app_id: String,
+    bundle_path: Option<String>,
+    title: Arc<str>,
+    frame: CGRect,
+    space: Option<SpaceId>,
+    place: Option<Place>,
+}
+
+const MAX_CLOSED_WINDOWS: usize = 20;
+
+/// Windows an app opens this long after we asked it to reopen one are placed
+/// like any other new window.
+const REOPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug)]
 pub struct AppState {
     pub info: AppInfo,
@@ -82,6 +878,28 @@ struct Screen {
     space: Option<SpaceId>,
 }
 
+/// How windows are laid out on a screen, from the config for its display.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScreenSettings {
+    /// Space between tiles and around the edges of the screen, in points.
+    pub gaps: f64,
+    /// The kind of layout new spaces start with.
+    pub default_layout: LayoutKind,
+    /// The display showing the screen, if we know it.
+    pub display: Option<DisplayUuid>,
+}
+
+/// A display named by a command. Next and previous are relative to the
+/// display of the main window, and wrap around.
+#[derive(Copy, Clone, Debug)]
+enum DisplayTarget {
+    Index(usize),
+    /// The display of the main window.
+    Current,
+    Next,
+    Prev,
+}
+
 /// A per-window counter that tracks the last time the reactor sent a request to
 /// change the window frame.
 #[derive(Default, Debug, Copy, Clone, PartialEq)]
@@ -89,18 +907,62 @@ pub struct TransactionId(u32);
 
 #[derive(Debug)]
 pub struct WindowState {
-    #[allow(unused)]
-    title: String,
+    title: Arc<str>,
     /// The last known frame of the window. Always includes the last write.
     ///
     /// This value only updates monotonically with respect to writes; in other
     /// words, we only accept reads when we know they come after the last write.
     frame_monotonic: CGRect,
     is_standard: bool,
+    subrole: Arc<str>,
+    level: i32,
+    is_resizable: bool,
+    is_minimized: bool,
+    is_fullscreen: bool,
+    /// Whether the window was moved out of view with [`Request::HideWindow`].
+    /// Hidden windows are restored the next time the layout is applied, but
+    /// are not put back in the layout when their app reports its windows.
+    is_hidden: bool,
+    window_server_id: WindowServerId,
     last_sent_txid: TransactionId,
+    /// For dialogs and sheets, the window they belong to. They float centered
+    /// over it, and follow it when its tile moves.
+    dialog_parent: Option<WindowId>,
+    /// Whether the dialog was centered over its parent yet.
+    is_dialog_placed: bool,
+    /// Picture-in-Picture windows are never tiled and are kept in front.
+    is_pip: bool,
+    /// Whether the user took the window out of the layout with
+    /// [`Command::ToggleFloat`].
+    is_floating: bool,
+    /// Where the window is kept with [`Command::PinWindow`].
+    pin: Option<Pin>,
+    /// The opacity and shadow set with [`Command::SetOpacity`] and
+    /// [`Command::ToggleShadow`].
+    appearance: WindowAppearance,
+    /// When we last finished moving the window, and the frame it had before.
+    last_write: Option<(Instant, CGRect)>,
+    /// How many times in a row something moved the window back right after
+    /// we moved it.
+    reverts: u32,
+    /// Whether we gave up moving the window because something keeps moving
+    /// it back. Cleared by [`Command::ForceRelayout`].
+    is_contested: bool,
 }
 
+/// A move back to the old frame this soon after we moved a window is not from
+/// the user.
+const REVERT_WINDOW: Duration = Duration::from_millis(500);
+
+/// How many reverts in a row mean another program is fighting over a window.
+const MAX_REVERTS: u32 = 3;
+
 impl WindowState {
+    /// Whether the window belongs in the layout.
+    fn is_tileable(&self) -> bool {
+        self.is_standard && !self.is_pip && !self.is_floating
+    }
+
     #[must_use]
     fn next_txid(&mut self) -> TransactionId {
         self.last_sent_txid.0 += 1;
@@ -108,25 +970,103 @@ impl WindowState {
     }
 }
 
-impl From<WindowInfo> for WindowState {
-    fn from(info: WindowInfo) -> Self {
+impl WindowState {
+    /// Makes the state for a new window, sharing its strings with other
+    /// windows through `strings`.
+    fn new(info: WindowInfo, strings: &mut Interner) -> Self {
         WindowState {
-            title: info.title,
+            title: strings.intern(&info.title),
             frame_monotonic: info.frame,
             is_standard: info.is_standard,
+            subrole: strings.intern(&info.subrole),
+            level: info.level,
+            is_resizable: info.is_resizable,
+            is_minimized: info.is_minimized,
+            is_fullscreen: info.is_fullscreen,
+            is_hidden: false,
+            window_server_id: info.sys_id,
             last_sent_txid: TransactionId::default(),
+            dialog_parent: None,
+            is_dialog_placed: false,
+            is_pip: info.is_pip,
+            is_floating: false,
+            pin: None,
+            appearance: WindowAppearance::default(),
+            last_write: None,
+            reverts: 0,
+            is_contested: false,
+        }
+    }
+}
+
+/// The parts of the config the reactor uses.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub pip: PipConfig,
+    pub follow_moved_windows: bool,
+    pub focus_mode: FocusModeConfig,
+    pub raise: RaiseConfig,
+    pub announce_focus: bool,
+    pub show_switcher: bool,
+    pub preview_resize: bool,
+}
+
+impl Settings {
+    pub fn new(config: &config::Config) -> Settings {
+        Settings {
+            pip: config.pip,
+            follow_moved_windows: config.tiling.follow_moved_windows,
+            focus_mode: config.focus_mode.clone(),
+            raise: config.raise.clone(),
+            announce_focus: config.accessibility.announce_focus,
+            show_switcher: config.key_bindings.show_switcher,
+            preview_resize: config.window_drag.preview_resize,
         }
     }
 }
 
 impl Reactor {
-    pub fn spawn(layout: LayoutManager) -> Sender {
-        let (events_tx, events) = sync::mpsc::channel::<(Span, Event)>();
+    /// Starts the reactor on its own thread.
+    pub fn spawn(
+        layout: LayoutManager,
+        split_handles: split_handles::Sender,
+        borders: borders::Sender,
+        settings: Settings,
+    ) -> Sender {
+        let (events_tx, events) = channel();
         thread::spawn(move || {
             let mut this = Reactor::new(layout);
-            for (span, event) in events {
-                let _guard = span.enter();
-                this.handle_event(event);
+            this.split_handles = Some(split_handles);
+            this.borders = Some(borders);
+            this.pip = settings.pip;
+            this.follow_moved_windows = settings.follow_moved_windows;
+            this.focus_mode = settings.focus_mode;
+            this.raise = settings.raise;
+            this.announce_focus = settings.announce_focus;
+            this.show_switcher = settings.show_switcher;
+            this.preview_resize = settings.preview_resize;
+            loop {
+                let timeout = this.adoption.as_ref().map(|adoption| {
+                    adoption.next_retry.saturating_duration_since(this.clock.now())
+                });
+                let received = events.recv(timeout);
+                match received {
+                    Ok((span, event)) => {
+                        let _guard = span.enter();
+                        let start = Instant::now();
+                        let kind = event.kind();
+                        this.handle_event(event);
+                        let animation_time = mem::take(&mut this.animation_time);
+                        metrics::record_handling(
+                            Timing::EventHandling,
+                            Some(kind),
+                            start.elapsed().saturating_sub(animation_time),
+                        );
+                    }
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                this.retry_adoption();
             }
         });
         events_tx
@@ -138,40 +1078,135 @@ impl Reactor {
             apps: HashMap::new(),
             layout,
             windows: HashMap::new(),
+            window_ids: HashMap::new(),
+            window_order: Vec::new(),
+            strings: Interner::default(),
             main_screen: None,
+            screens: Vec::new(),
+            screen_displays: Vec::new(),
+            screen_settings: Vec::new(),
             global_frontmost_app_pid: None,
             raise_token: RaiseToken::default(),
+            clock: Arc::new(SystemClock),
+            window_server: Arc::new(SystemWindowServer),
+            tiling_paused: false,
+            screen_captured: false,
+            accessibility_lost: false,
+            power: PowerState::default(),
+            paused_apps: HashSet::new(),
+            split_handles: None,
+            splits: Vec::new(),
+            borders: None,
+            bordered_windows: Vec::new(),
+            show_layout_preview: false,
+            force_relayout: false,
+            window_drag: None,
+            preview_resize: false,
+            split_drag: None,
+            zoomed: None,
+            window_frames: None,
+            animation_time: Duration::ZERO,
+            preview_tiles: Vec::new(),
+            pip: PipConfig::default(),
+            follow_moved_windows: false,
+            focus_mode: FocusModeConfig::default(),
+            raise: RaiseConfig::default(),
+            activating: None,
+            announce_focus: false,
+            show_switcher: false,
+            recent_windows: Vec::new(),
+            recent_cycle: None,
+            focus_active: false,
+            focus_moved: Vec::new(),
+            urgent: Vec::new(),
+            visible_windows: HashMap::new(),
+            space_subscribers: Vec::new(),
+            spaces_sent: Vec::new(),
+            stack_subscribers: Vec::new(),
+            wm_controller: None,
+            stacks_sent: Vec::new(),
+            known_spaces: Vec::new(),
+            visited_spaces: HashSet::new(),
+            adoption: None,
+            closed_windows: VecDeque::new(),
+            reopening: None,
+        }
+    }
+
+    /// Creates a reactor whose animations run on a virtual clock, so tests
+    /// and benchmarks don't have to wait for them. It sees no windows in the
+    /// window server until it is given a [`testing::MockWindows`] that does.
+    #[cfg(any(test, feature = "bench"))]
+    pub fn new_for_test() -> Reactor {
+        let mut reactor = Reactor::new(LayoutManager::new());
+        reactor.clock = Arc::new(crate::sys::clock::VirtualClock::new());
+        reactor.window_server = Arc::new(sync::Mutex::new(testing::MockWindows::default()));
+        reactor
+    }
+
+    #[cfg(any(test, feature = "bench"))]
+    pub fn handle_events(&mut self, events: Vec<Event>) {
+        for event in events {
+            self.handle_event(event);
         }
     }
 
     fn handle_event(&mut self, event: Event) {
-        debug!(?event, "Event");
+        debug!(
+            kind = event.kind(),
+            pid = event.pid(),
+            wid = event.window_id().map(tracing::field::debug),
+            ?event,
+            "Event"
+        );
         let main_window_orig = self.main_window();
         let mut animation_focus_wid = None;
         let mut is_resize = false;
         match event {
             Event::ApplicationLaunched(pid, state) => {
+                let app_id = state.info.app_id().map(ToString::to_string);
                 self.apps.insert(pid, state);
+                self.send_layout_event(LayoutEvent::AppLaunched(pid, app_id));
+            }
+            Event::ApplicationObservable(pid) => {
+                info!(?pid, "App can be watched after retrying");
+                // It may have been activated while we could not watch it.
+                if let Some(app) = self.apps.get(&pid) {
+                    _ = app.handle.send(Request::GetActivationState);
+                }
             }
             Event::ApplicationTerminated(pid) => {
-                // FIXME: This isn't ordered wrt other events from the app;
-                // reroute the event through the app thread so it's the last
-                // event for this app.
+                // This isn't ordered wrt other events from the app, so events
+                // for the app can still arrive after it. They are ignored.
                 self.apps.remove(&pid);
+                // If the app crashed we won't get destroyed events for its
+                // windows.
+                self.windows.retain(|wid, _| wid.pid != pid);
+                self.window_ids.retain(|&(window_pid, _), _| window_pid != pid);
+                self.window_order.retain(|wid| wid.pid != pid);
+                self.urgent.retain(|wid| wid.pid != pid);
+                self.visible_windows.remove(&pid);
                 self.send_layout_event(LayoutEvent::AppClosed(pid));
             }
             Event::ApplicationActivated(pid, main_window) => {
-                let state = self.apps.get_mut(&pid).unwrap();
+                let Some(state) = self.apps.get_mut(&pid) else {
+                    // The app already terminated.
+                    return;
+                };
                 state.is_frontmost = true;
                 state.main_window = main_window;
+                self.record_activation(pid);
             }
             Event::ApplicationGloballyActivated(pid) => {
                 // See the comment in main_window() for the difference between
                 // this and the ApplicationActivated event.
                 self.global_frontmost_app_pid = Some(pid);
+                self.urgent.retain(|wid| wid.pid != pid);
             }
             Event::ApplicationDeactivated(pid) => {
-                self.apps.get_mut(&pid).unwrap().is_frontmost = false;
+                if let Some(app) = self.apps.get_mut(&pid) {
+                    app.is_frontmost = false;
+                }
             }
             Event::ApplicationGloballyDeactivated(pid) => {
                 if self.global_frontmost_app_pid == Some(pid) {
@@ -179,45 +1214,87 @@ impl Reactor {
                 }
             }
             Event::ApplicationMainWindowChanged(pid, main_window) => {
-                self.apps.get_mut(&pid).unwrap().main_window = main_window;
+                if let Some(app) = self.apps.get_mut(&pid) {
+                    app.main_window = main_window;
+                }
             }
-            Event::WindowsDiscovered { pid, new, known_visible } => {
-                // FIXME: There is no synchronization ensuring that these windows
-                // are for the current space. The only way I've found to do that
-                // is to take a "snapshot" using CGWindowListCopyWindowInfo.
-                let mut app_windows = known_visible;
-                app_windows.retain(|wid| self.windows[wid].is_standard);
-                app_windows
-                    .extend(new.iter().filter_map(|(wid, info)| info.is_standard.then_some(wid)));
-                self.windows.extend(new.into_iter().map(|(wid, info)| (wid, info.into())));
-                // FIXME: We assume all windows are on the main screen.
-                if let Some(space) = self.main_screen_space() {
-                    self.send_layout_event(LayoutEvent::WindowsOnScreenUpdated(
-                        space,
-                        pid,
-                        app_windows,
-                    ));
+            Event::FrontmostAppChanged(frontmost) => {
+                self.global_frontmost_app_pid = frontmost;
+                for (&pid, app) in &self.apps {
+                    if app.is_frontmost != (Some(pid) == frontmost) {
+                        // Either the notification from the app is still on
+                        // its way or it was dropped. Ask the app for its state
+                        // in case it was dropped.
+                        debug!(?pid, app.is_frontmost, "Rechecking activation state");
+                        _ = app.handle.send(Request::GetActivationState);
+                    }
+                }
+            }
+            Event::ApplicationRequestedAttention(pid) => {
+                if self.global_frontmost_app_pid == Some(pid) {
+                    // The user is already looking at it.
+                    return;
                 }
+                let wid = self
+                    .apps
+                    .get(&pid)
+                    .and_then(|app| app.main_window)
+                    .or_else(|| self.window_order.iter().copied().find(|wid| wid.pid == pid));
+                if let Some(wid) = wid {
+                    self.mark_urgent(wid);
+                }
+            }
+            Event::WindowsDiscovered { pid, new, known_visible } => {
+                self.update_visible_windows(pid, new, known_visible);
+            }
+            Event::VisibleWindowsChanged { pid, new, shown, hidden } => {
+                let mut visible = self.visible_windows.remove(&pid).unwrap_or_default();
+                visible.retain(|wid| !hidden.contains(wid));
+                visible.extend(shown);
+                self.update_visible_windows(pid, new, visible);
             }
             Event::WindowCreated(wid, window) => {
+                let is_tileable = window.is_standard && !window.is_pip;
+                let is_dialog = matches!(&*window.subrole, "AXDialog" | "AXSystemDialog");
+                if !self.register_window(wid, window) {
+                    return;
+                }
+                // A dialog from an app in the background usually needs an
+                // answer before the app can continue.
+                if is_dialog && self.global_frontmost_app_pid != Some(wid.pid) {
+                    self.mark_urgent(wid);
+                }
                 // TODO: It's possible for a window to be on multiple spaces
                 // or move spaces. (Add a test)
                 // FIXME: We assume all windows are on the main screen.
                 if let Some(space) = self.main_screen_space() {
-                    if window.is_standard {
+                    if is_tileable {
                         animation_focus_wid = Some(wid);
-                        self.send_layout_event(LayoutEvent::WindowAdded(space, wid));
+                        let event = match self.take_reopened_place(wid, space) {
+                            Some(place) => LayoutEvent::WindowAddedAt(space, wid, place),
+                            None => LayoutEvent::WindowAdded(space, wid),
+                        };
+                        self.send_layout_event(event);
                     }
                 }
-                self.windows.insert(wid, window.into());
+                // New windows open in front of other windows.
+                self.move_to_front(wid);
             }
             Event::WindowDestroyed(wid) => {
-                self.windows.remove(&wid).unwrap();
+                self.remember_closed(wid);
+                self.remove_window(wid);
                 //animation_focus_wid = self.window_order.last().cloned();
-                self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+            }
+            Event::WindowTitleChanged(wid, title) => {
+                if let Some(window) = self.windows.get_mut(&wid) {
+                    window.title = self.strings.intern(&title);
+                }
             }
             Event::WindowFrameChanged(wid, new_frame, last_seen, requested) => {
-                let window = self.windows.get_mut(&wid).unwrap();
+                let Some(window) = self.windows.get_mut(&wid) else {
+                    // The app already terminated.
+                    return;
+                };
                 if last_seen != window.last_sent_txid {
                     // Ignore events that happened before the last time we
                     // changed the size or position of this window. Otherwise
@@ -225,14 +1302,28 @@ impl Reactor {
                     debug!(?last_seen, ?window.last_sent_txid, "Ignoring resize");
                     return;
                 }
+                if window.is_hidden {
+                    // We moved the window out of view ourselves; this is not
+                    // a change the layout should respond to.
+                    return;
+                }
                 if requested.0 {
                     // TODO: If the size is different from requested, applying a
                     // correction to the model can result in weird feedback
                     // loops, so we ignore these for now.
                     return;
                 }
+                let reverted = window.last_write.take().is_some_and(|(at, before)| {
+                    self.clock.now().duration_since(at) < REVERT_WINDOW && new_frame.same_as(before)
+                });
+                window.reverts = if reverted { window.reverts + 1 } else { 0 };
                 let old_frame = mem::replace(&mut window.frame_monotonic, new_frame);
-                if old_frame == new_frame {
+                if old_frame == new_frame || window.is_contested {
+                    return;
+                }
+                if window.reverts >= MAX_REVERTS {
+                    window.is_contested = true;
+                    self.report_conflict(wid);
                     return;
                 }
                 let Some(screen) = self.main_screen else { return };
@@ -248,20 +1339,66 @@ impl Reactor {
                 is_resize = true;
             }
             Event::ScreenParametersChanged(frames, spaces) => {
-                self.main_screen = frames
-                    .into_iter()
-                    .zip(spaces)
-                    .map(|(frame, space)| Screen { frame, space })
-                    .next();
+                let old_screens = mem::replace(
+                    &mut self.screens,
+                    frames
+                        .into_iter()
+                        .zip(spaces)
+                        .map(|(frame, space)| Screen { frame, space })
+                        .collect(),
+                );
+                // Settings for the new screens are sent first.
+                let displays = (0..self.screens.len())
+                    .map(|idx| self.screen_settings.get(idx).and_then(|s| s.display.clone()))
+                    .collect();
+                let old_displays = mem::replace(&mut self.screen_displays, displays);
+                self.main_screen = self.screens.first().copied();
+                self.migrate_windows(&old_screens, &old_displays);
                 if let Some(space) = self.main_screen_space() {
                     self.send_layout_event(LayoutEvent::SpaceExposed(
                         space,
                         self.main_screen.unwrap().frame.size,
+                        self.main_screen_settings().default_layout,
                     ));
+                    if self.visited_spaces.insert(space) {
+                        // The windows on screen when we start are discovered
+                        // as their apps launch.
+                        let on_screen = self.window_server.visible_windows();
+                        self.start_adoption(space, &on_screen, true);
+                    }
                 }
                 // FIXME: Update visible windows if space changed
             }
+            Event::ScreenSettingsChanged(settings) => self.screen_settings = settings,
+            Event::DisabledSpacesChanged(spaces) => self.layout.set_disabled_spaces(spaces),
+            Event::SpacesChanged(spaces) => {
+                let removed: Vec<SpaceId> = self
+                    .known_spaces
+                    .iter()
+                    .map(|info| info.id)
+                    .filter(|&space| !spaces.iter().any(|info| info.id == space))
+                    .collect();
+                self.known_spaces = spaces;
+                for &space in &removed {
+                    debug!(?space, "Space removed");
+                    self.visited_spaces.remove(&space);
+                    self.send_layout_event(LayoutEvent::SpaceRemoved(space));
+                }
+                if self.adoption.as_ref().is_some_and(|a| removed.contains(&a.space)) {
+                    self.adoption = None;
+                }
+                if !removed.is_empty() && self.main_screen_space().is_some() {
+                    // The windows on removed spaces were moved to the space
+                    // next to them, which may be on screen.
+                    for app in self.apps.values_mut() {
+                        _ = app.handle.send(Request::GetVisibleWindows);
+                    }
+                }
+            }
             Event::SpaceChanged(spaces) => {
+                for (screen, space) in self.screens.iter_mut().zip(&spaces) {
+                    screen.space = *space;
+                }
                 let Some(screen) = self.main_screen.as_mut() else {
                     return;
                 };
@@ -271,7 +1408,18 @@ impl Reactor {
                     self.send_layout_event(LayoutEvent::SpaceExposed(
                         space,
                         self.main_screen.unwrap().frame.size,
+                        self.main_screen_settings().default_layout,
                     ));
+                    self.move_pinned_windows_to(space);
+                    // Windows may have moved while the space was hidden.
+                    self.validate_frames();
+                    self.refresh_window_order();
+                    if self.visited_spaces.insert(space) {
+                        let on_screen = self.window_server.visible_windows();
+                        self.start_adoption(space, &on_screen, false);
+                    } else if self.adoption.as_ref().is_some_and(|a| a.space != space) {
+                        self.end_adoption();
+                    }
                 }
                 if self.main_screen_space().is_some() {
                     // TODO: Do this correctly/more optimally using CGWindowListCopyWindowInfo
@@ -283,18 +1431,139 @@ impl Reactor {
                     }
                 }
             }
-            Event::Command(Command::Hello) => {
-                println!("Hello, world!");
+            Event::Command(cmd) => {
+                if let Err(e) = self.handle_command(cmd) {
+                    info!("Command did nothing: {e}");
+                }
             }
-            Event::Command(Command::Layout(cmd)) => {
-                info!(?cmd);
-                let Some(space) = self.main_screen_space() else { return };
-                let response = self.layout.handle_command(space, cmd);
-                self.handle_layout_response(response);
+            Event::CommandWithResult(cmd, result_tx) => {
+                // Errors mean the client went away; ignore.
+                _ = result_tx.send(self.handle_command(cmd));
+            }
+            Event::CommandBatch(cmds, results_tx) => {
+                let mut results = vec![];
+                for cmd in cmds {
+                    let result = self.handle_command(cmd);
+                    let failed = result.is_err();
+                    results.push(result);
+                    if failed {
+                        break;
+                    }
+                }
+                // Errors mean the client went away; ignore.
+                _ = results_tx.send(results);
+            }
+            Event::ScreenCaptureChanged(captured) => self.screen_captured = captured,
+            Event::AccessibilityChanged(granted) => {
+                let lost = !granted;
+                if lost == self.accessibility_lost {
+                    return;
+                }
+                self.accessibility_lost = lost;
+                if granted {
+                    // Notifications stopped while access was gone, so watch
+                    // every app again and catch up on what we missed.
+                    for app in self.apps.values() {
+                        _ = app.handle.send(Request::Rewatch);
+                        _ = app.handle.send(Request::GetVisibleWindows);
+                    }
+                }
+            }
+            Event::PowerStateChanged(power) => self.power = power,
+            Event::WindowLimitsChanged(limits) => self.layout.set_window_limits(limits),
+            Event::Audit => {
+                let ids: Vec<_> = self
+                    .windows
+                    .values()
+                    .map(|w| w.window_server_id)
+                    .filter(|id| id.is_valid())
+                    .collect();
+                let existing = self.window_server.windows(&ids);
+                let on_screen = self.window_server.visible_windows();
+                self.audit(&existing, &on_screen);
+            }
+            Event::FocusModeChanged(active) => {
+                if active == self.focus_active {
+                    return;
+                }
+                self.focus_active = active;
+                if active {
+                    self.move_focus_apps_to_background();
+                } else {
+                    self.restore_focus_apps();
+                }
+            }
+            Event::SplitDragged { node, orientation, delta } => {
+                if self.preview_resize {
+                    self.preview_split_drag(node, orientation, delta);
+                    return;
+                }
+                if !self.move_split(node, orientation, delta) {
+                    return;
+                }
+                // Follow the mouse instead of animating.
+                is_resize = true;
+            }
+            Event::SplitDragEnded => {
+                let Some(drag) = self.split_drag.take() else { return };
+                self.show_resize_preview(None);
+                debug!(?drag, "Moving the boundary to the preview");
+                if !self.move_split(drag.node, drag.orientation, drag.delta) {
+                    return;
+                }
+                is_resize = true;
+            }
+            Event::WindowDrag(event) => {
+                self.handle_window_drag(event);
+                is_resize = true;
+            }
+            Event::WatchWindowFrames(frames) => self.window_frames = Some(frames),
+            Event::TitleBarDoubleClicked(wid, action) => {
+                let result = match action {
+                    TitleBarAction::ToggleZoom => self.toggle_zoom(wid),
+                    TitleBarAction::ToggleFloat => self.toggle_float(wid),
+                };
+                if let Err(e) = result {
+                    debug!(?wid, ?action, "Title bar action did nothing: {e}");
+                }
+            }
+            Event::Query(query, response_tx) => {
+                // Errors mean the client went away; ignore.
+                _ = response_tx.send(self.handle_query(query));
+                return;
+            }
+            #[cfg(debug_assertions)]
+            Event::Inject(events, result_tx) => {
+                // The injected events update the layout themselves.
+                _ = result_tx.send(self.inject(events));
+                return;
+            }
+            Event::SubscribeSpaces(spaces_tx) => {
+                let spaces = self.spaces();
+                if spaces_tx.send(spaces.clone()).is_ok() {
+                    self.space_subscribers.push(spaces_tx);
+                    self.spaces_sent = spaces;
+                }
+                return;
+            }
+            Event::SubscribeStacks(stacks_tx) => {
+                let stacks = self.stacks();
+                if stacks_tx.send(stacks.clone()).is_ok() {
+                    self.stack_subscribers.push(stacks_tx);
+                    self.stacks_sent = stacks;
+                }
+                return;
+            }
+            Event::WmControllerStarted(wm_controller) => {
+                self.wm_controller = Some(wm_controller);
+                return;
             }
-            Event::Command(Command::Metrics(cmd)) => metrics::handle_command(cmd),
         }
         if self.main_window() != main_window_orig {
+            if let Some(wid) = self.main_window() {
+                self.move_to_front(wid);
+                self.note_recent(wid);
+            }
             // TODO: There's an edge case where the space updates and the main
             // window does not (because it is on multiple spaces). Update the
             // layout in that case too.
@@ -303,6 +1572,180 @@ impl Reactor {
             }
         }
         self.update_layout(animation_focus_wid, is_resize);
+        self.update_borders();
+        self.update_window_frames();
+        self.update_space_subscribers();
+        self.update_stack_subscribers();
+    }
+
+    fn handle_command(&mut self, cmd: Command) -> CommandResult {
+        match cmd {
+            Command::Hello => println!("Hello, world!"),
+            Command::FocusWindowUnderCursor => {
+                let point = event::cursor_position().ok_or(CommandError::NoWindowUnderCursor)?;
+                self.refresh_window_order();
+                let wid = self.window_at_point(point).ok_or(CommandError::NoWindowUnderCursor)?;
+                // Focus follows the mouse without taking keyboard focus from
+                // the app that has it.
+                self.raise_window_only(wid);
+            }
+            Command::FocusUrgent => {
+                let wid = self.urgent.pop().ok_or(CommandError::NoUrgentWindow)?;
+                self.raise_window(wid);
+            }
+            Command::FollowWindowToSpace(index) => self.move_window_to_space(index, true)?,
+            Command::MoveWindowToSpace(index) => {
+                self.move_window_to_space(index, self.follow_moved_windows)?;
+            }
+            Command::PullWindow(query) => self.pull_window(&query, false)?,
+            Command::GoToWindow(query) => self.pull_window(&query, true)?,
+            Command::FocusNextWindowOfApp => self.cycle_app_windows(true)?,
+            Command::FocusPrevWindowOfApp => self.cycle_app_windows(false)?,
+            Command::CycleRecentWindows => self.cycle_recent_windows(true)?,
+            Command::CycleRecentWindowsBack => self.cycle_recent_windows(false)?,
+            Command::EndRecentCycle => self.end_recent_cycle(),
+            Command::ReopenClosedWindow => self.reopen_closed_window()?,
+            Command::FocusDisplay(index) => self.focus_display(DisplayTarget::Index(index))?,
+            Command::FocusNextDisplay => self.focus_display(DisplayTarget::Next)?,
+            Command::FocusPrevDisplay => self.focus_display(DisplayTarget::Prev)?,
+            Command::MoveWindowToDisplay(index) => {
+                self.move_window_to_display(DisplayTarget::Index(index))?;
+            }
+            Command::MoveWindowToNextDisplay => {
+                self.move_window_to_display(DisplayTarget::Next)?;
+            }
+            Command::MoveWindowToPrevDisplay => {
+                self.move_window_to_display(DisplayTarget::Prev)?;
+            }
+            Command::RescueOffscreenWindows => self.rescue_offscreen_windows()?,
+            Command::HideWindow => {
+                let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+                self.hide_window(wid);
+                if self.windows.get(&wid).is_some_and(|w| w.is_hidden && w.is_tileable()) {
+                    self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+                }
+            }
+            Command::ShowHiddenWindows => self.show_hidden_windows()?,
+            Command::ToggleZoom => {
+                let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+                self.toggle_zoom(wid)?;
+            }
+            Command::ToggleFloat => {
+                let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+                self.toggle_float(wid)?;
+            }
+            Command::PinWindow(pin) => {
+                let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+                self.pin_window(wid, pin)?;
+            }
+            Command::UnpinWindow => {
+                let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+                self.unpin_window(wid)?;
+            }
+            Command::SetOpacity(opacity) => {
+                let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+                self.set_appearance(wid, |appearance| {
+                    appearance.opacity = opacity.clamp(MIN_OPACITY, 1.0);
+                })?;
+            }
+            Command::ToggleShadow => {
+                let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+                self.set_appearance(wid, |appearance| {
+                    appearance.has_shadow = !appearance.has_shadow;
+                })?;
+            }
+            Command::ToggleLayoutPreview => {
+                if !self.show_layout_preview && self.main_screen_space().is_none() {
+                    return Err(CommandError::NoManagedSpace);
+                }
+                self.show_layout_preview = !self.show_layout_preview;
+            }
+            Command::ForceRelayout => {
+                if self.main_screen_space().is_none() {
+                    return Err(CommandError::NoManagedSpace);
+                }
+                self.force_relayout = true;
+                for window in self.windows.values_mut() {
+                    window.is_contested = false;
+                    window.reverts = 0;
+                }
+            }
+            Command::ImportLayout(spec) => {
+                let space = self.main_screen_space().ok_or(CommandError::NoManagedSpace)?;
+                let apps = &self.apps;
+                let windows = &self.windows;
+                self.layout.import(space, &spec, |matcher, wid| {
+                    let app = apps.get(&wid.pid).and_then(|app| app.info.app_id());
+                    let title = windows.get(&wid).map(|window| &*window.title);
+                    matcher.app.as_deref().map_or(true, |want| app == Some(want))
+                        && matcher
+                            .title
+                            .as_deref()
+                            .map_or(true, |want| title.is_some_and(|title| title.contains(want)))
+                });
+            }
+            Command::CaptureLayout => {
+                let space = self.main_screen_space().ok_or(CommandError::NoManagedSpace)?;
+                let windows = &self.windows;
+                self.layout.capture(space, |wid| windows.get(&wid).map(|w| w.frame_monotonic));
+                self.tiling_paused = false;
+            }
+            Command::PauseTiling => self.tiling_paused = true,
+            Command::ResumeTiling => self.tiling_paused = false,
+            Command::PauseApp(bundle_id) => {
+                self.paused_apps.insert(bundle_id);
+            }
+            Command::ResumeApp(bundle_id) => {
+                self.paused_apps.remove(&bundle_id);
+            }
+            Command::Layout(cmd) => {
+                info!(?cmd);
+                let space = self.main_screen_space().ok_or(CommandError::NoManagedSpace)?;
+                let response = self.layout.handle_command(space, cmd)?;
+                if let Some(wid) = response.raise_window {
+                    info!(raise_window = ?wid);
+                    self.raise_selected_window(wid);
+                }
+            }
+            Command::Metrics(cmd) => metrics::handle_command(cmd),
+            Command::Repeat(count, cmd) => self.repeat_command(count, *cmd)?,
+        }
+        Ok(())
+    }
+
+    /// Runs `cmd` up to `count` times, stopping early if it does nothing. It
+    /// is only an error if the first run does nothing.
+    fn repeat_command(&mut self, count: usize, cmd: Command) -> CommandResult {
+        let Command::Layout(cmd) = cmd else {
+            for i in 0..count {
+                match self.handle_command(cmd.clone()) {
+                    Err(e) if i == 0 => return Err(e),
+                    Err(_) => break,
+                    Ok(()) => {}
+                }
+            }
+            return Ok(());
+        };
+        let space = self.main_screen_space().ok_or(CommandError::NoManagedSpace)?;
+        let mut raise = None;
+        for i in 0..count {
+            match self.layout.handle_command(space, cmd.clone()) {
+                Ok(response) => {
+                    if let Some(wid) = response.raise_window {
+                        // Select the window now so that the next run starts
+                        // from it, instead of waiting for the app to raise it.
+                        _ = self.layout.handle_event(LayoutEvent::WindowRaised(space, Some(wid)));
+                        raise = Some(wid);
+                    }
+                }
+                Err(e) if i == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        if let Some(wid) = raise {
+            self.raise_selected_window(wid);
+        }
+        Ok(())
     }
 
     fn send_layout_event(&mut self, event: LayoutEvent) {
@@ -317,294 +1760,2955 @@ impl Reactor {
         }
     }
 
-    fn raise_window(&mut self, wid: WindowId) {
-        self.raise_token.set_pid(wid.pid);
-        self.apps
-            .get_mut(&wid.pid)
-            .unwrap()
-            .handle
-            .send(Request::Raise(wid, self.raise_token.clone()))
-            .unwrap();
+    /// Records a window reported by an app. Returns false if we already knew
+    /// about the window under the same id, or the app terminated.
+    ///
+    /// If we knew about the window under a different id, the old id is
+    /// forgotten and removed from the layout.
+    fn register_window(&mut self, wid: WindowId, info: WindowInfo) -> bool {
+        if !self.apps.contains_key(&wid.pid) {
+            debug!(?wid, "Window was reported after its app terminated");
+            return false;
+        }
+        if self.windows.contains_key(&wid) {
+            debug!(?wid, "Window was reported twice");
+            return false;
+        }
+        // Windows without a window server id can't be told apart this way.
+        let old = info
+            .sys_id
+            .is_valid()
+            .then(|| self.window_ids.insert((wid.pid, info.sys_id), wid))
+            .flatten();
+        if let Some(old) = old {
+            debug!(?old, ?wid, "Window was reported under a new id");
+            self.windows.remove(&old);
+            self.window_order.retain(|w| *w != old);
+            self.send_layout_event(LayoutEvent::WindowRemoved(old));
+        }
+        if let Some(adoption) = &mut self.adoption {
+            adoption.pending.remove(&info.sys_id);
+        }
+        let dialog_parent = self.find_dialog_parent(wid, &info);
+        let mut window = WindowState::new(info, &mut self.strings);
+        window.dialog_parent = dialog_parent;
+        self.windows.insert(wid, window);
+        true
     }
 
-    /// The main window of the active app, if any.
-    fn main_window(&self) -> Option<WindowId> {
-        // Because apps self-report this event from their respective
-        // threads, they can appear out of order. To mitigate this, we
-        // require that the "global" view from NSNotificationCenter
-        // agrees with the app about which is frontmost. This guarantees
-        // eventual consistency.
-        //
-        // Since the global events provide an authoritative ordering, why
-        // care about this event at all? The reason is that we want to
-        // know what the main window of the app is upon activation. This
-        // is important when the user clicks on a window of the app
-        // that was not previously the main window: The frontmost app
-        // and its main window can switch at the same time. In that case
-        // we don't want to record the old main window as having focus,
-        // since it never did. So we wait until both events are received.
-        let Some(pid) = self.global_frontmost_app_pid else {
-            return None;
+    fn remove_window(&mut self, wid: WindowId) {
+        let Some(window) = self.windows.remove(&wid) else {
+            // The app already terminated.
+            return;
         };
-        match self.apps.get(&pid) {
-            Some(AppState {
-                is_frontmost: true,
-                main_window: Some(window),
-                ..
-            }) if self.windows.contains_key(window) => Some(*window),
-            _ => None,
+        let key = (wid.pid, window.window_server_id);
+        if self.window_ids.get(&key) == Some(&wid) {
+            self.window_ids.remove(&key);
+        }
+        self.window_order.retain(|w| *w != wid);
+        self.urgent.retain(|w| *w != wid);
+        self.recent_windows.retain(|w| *w != wid);
+        if self.zoomed == Some(wid) {
+            self.zoomed = None;
         }
+        drop(window);
+        self.strings.collect();
+        if let Some(visible) = self.visible_windows.get_mut(&wid.pid) {
+            visible.retain(|w| *w != wid);
+        }
+        self.send_layout_event(LayoutEvent::WindowRemoved(wid));
     }
 
-    fn main_screen_space(&self) -> Option<SpaceId> {
-        self.main_screen?.space
+    /// Remembers a window that is being removed because it was closed, so
+    /// [`Command::ReopenClosedWindow`] can bring it back.
+    fn remember_closed(&mut self, wid: WindowId) {
+        let Some(window) = self.windows.get(&wid) else {
+            return;
+        };
+        let Some(app_id) = self.app_id(wid.pid) else {
+            return;
+        };
+        if !window.is_tileable() {
+            return;
+        }
+        // FIXME: We assume all windows are on the main screen.
+        let space = self.main_screen_space();
+        let closed = ClosedWindow {
+            app_id: app_id.to_string(),
+            bundle_path: self.display_app(wid.pid).and_then(|app| app.bundle_path.clone()),
+            title: window.title.clone(),
+            frame: window.frame_monotonic,
+            space,
+            place: space.and_then(|space| self.layout.window_place(space, wid)),
+        };
+        self.closed_windows.push_front(closed);
+        self.closed_windows.truncate(MAX_CLOSED_WINDOWS);
     }
 
-    #[instrument(skip(self), fields(?self.main_screen))]
-    pub fn update_layout(&mut self, new_wid: Option<WindowId>, is_resize: bool) {
-        let Some(main_screen) = self.main_screen else { return };
-        let Some(space) = main_screen.space else { return };
+    fn reopen_closed_window(&mut self) -> CommandResult {
+        let closed = self.closed_windows.front().ok_or(CommandError::NoClosedWindow)?;
+        let pid = self
+            .apps
+            .iter()
+            .find(|(_, app)| app.info.bundle_id.as_deref() == Some(&*closed.app_id))
+            .map(|(&pid, _)| pid);
+        if pid.is_none() && closed.bundle_path.is_none() {
+            // Keep the window, so it can be reopened once its app is running.
+            return Err(CommandError::AppNotFound(closed.app_id.clone()));
+        }
+        if closed.space.is_some() && closed.space != self.main_screen_space() {
+            switch_to_window_space(closed.space);
+        }
+        match (pid, &closed.bundle_path) {
+            (Some(pid), _) => {
+                self.raise_token.set_pid(pid);
+                let request =
+                    Request::ReopenWindow(closed.title.to_string(), self.raise_token.clone());
+                _ = self.apps[&pid].handle.send(request);
+            }
+            (None, Some(bundle_path)) => open_app(bundle_path),
+            (None, None) => (),
+        }
+        let closed = self.closed_windows.pop_front();
+        self.reopening = closed.map(|closed| (closed, self.clock.now()));
+        Ok(())
+    }
 
-        trace!(?main_screen);
-        let main_window = self.main_window();
-        trace!(?main_window);
-        let layout = self.layout.calculate_layout(space, main_screen.frame.clone());
-        trace!(?layout, "Layout");
+    /// If `wid` is the window an app opened after we asked it to reopen a
+    /// closed one, returns the place in the layout of `space` it takes.
+    fn take_reopened_place(&mut self, wid: WindowId, space: SpaceId) -> Option<Place> {
+        let (closed, asked) = self.reopening.as_ref()?;
+        let expired = self.clock.now().duration_since(*asked) > REOPEN_TIMEOUT;
+        let same_app = self.app_id(wid.pid) == Some(&*closed.app_id);
+        if expired {
+            self.reopening = None;
+        }
+        if expired || !same_app {
+            return None;
+        }
+        let (closed, _) = self.reopening.take()?;
+        debug!(?wid, title = ?closed.title, "Placing reopened window");
+        closed.place.or_else(|| {
+            // The window was alone in its container, so take the tile that
+            // is now where it was.
+            let screen = self.main_screen?.frame;
+            let mid = closed.frame.mid();
+            self.layout
+                .calculate_layout(space, screen)
+                .into_iter()
+                .find(|(_, frame)| frame.contains(mid))
+                .map(|(wid, _)| Place::After(wid))
+        })
+    }
 
-        let mut anim = Animation::new();
-        for &(wid, target_frame) in &layout {
-            let Some(window) = self.windows.get_mut(&wid) else {
-                // If we restored a saved state the window may not be available yet.
+    /// Warns that something keeps moving a window back after we move it,
+    /// naming the programs that might be doing it.
+    fn report_conflict(&self, wid: WindowId) {
+        let window = &self.windows[&wid];
+        let app = self.apps.get(&wid.pid).and_then(|app| app.info.bundle_id.as_deref());
+        let suspects = window_managers::other_window_managers();
+        let suspects = if suspects.is_empty() {
+            "unknown".to_string()
+        } else {
+            suspects.join(", ")
+        };
+        warn!(
+            ?wid,
+            title = ?window.title,
+            ?app,
+            "Something keeps moving this window back, so we will stop moving it. \
+             Possibly conflicting window managers: {suspects}. \
+             Quit them or run ForceRelayout to try again."
+        );
+        let notice = format!(
+            "Something keeps moving {:?} back, so Nimbus stopped arranging it.\n\n\
+             Possibly conflicting window managers: {suspects}. \
+             Quit them or run ForceRelayout to try again.",
+            window.title,
+        );
+        if let Some(wm_controller) = self.wm_controller.as_ref().and_then(|tx| tx.upgrade()) {
+            _ = wm_controller.send((Span::current().clone(), WmEvent::ShowNotice(notice)));
+        }
+    }
+
+    /// Compares the known windows with what the window server reports and
+    /// repairs any drift, which means we missed an event.
+    ///
+    /// Apps whose windows drifted are watched again with a new observer, in
+    /// case their accessibility connection broke and we are missing every
+    /// notification from them.
+    ///
+    /// `existing` describes each known window that still exists, and
+    /// `on_screen` each window currently on screen. Windows without a window
+    /// server id can't be looked up, so they are left alone.
+    fn audit(&mut self, existing: &[WindowServerInfo], on_screen: &[WindowServerInfo]) {
+        let existing: HashMap<(pid_t, WindowServerId), &WindowServerInfo> =
+            existing.iter().map(|info| ((info.pid, info.id), info)).collect();
+        let mut destroyed = vec![];
+        let mut silent_apps = HashSet::new();
+        for (wid, window) in &mut self.windows {
+            if !window.window_server_id.is_valid() {
+                continue;
+            }
+            let Some(info) = existing.get(&(wid.pid, window.window_server_id)) else {
+                destroyed.push(*wid);
+                silent_apps.insert(wid.pid);
                 continue;
             };
-            let target_frame = target_frame.round();
-            let current_frame = window.frame_monotonic;
-            if target_frame.same_as(current_frame) {
+            if window.is_hidden || info.frame.same_as(window.frame_monotonic) {
                 continue;
             }
-            info!(?wid, ?current_frame, ?target_frame);
-            let handle = &self.apps.get(&wid.pid).unwrap().handle;
-            let is_new = Some(wid) == new_wid;
-            let txid = window.next_txid();
-            anim.add_window(handle, wid, current_frame, target_frame, is_new, txid);
-            window.frame_monotonic = target_frame;
+            silent_apps.insert(wid.pid);
+            warn!(
+                ?wid,
+                title = ?window.title,
+                expected = ?window.frame_monotonic,
+                actual = ?info.frame,
+                last_sent_txid = ?window.last_sent_txid,
+                "Audit: frame out of sync"
+            );
+            window.frame_monotonic = info.frame;
         }
-        if is_resize {
-            // If the user is doing something with the mouse we don't want to
-            // animate on top of that.
-            anim.skip_to_end();
-        } else {
-            anim.run();
+        for wid in destroyed {
+            warn!(
+                ?wid,
+                title = ?self.windows[&wid].title,
+                "Audit: window no longer exists"
+            );
+            self.remove_window(wid);
+        }
+
+        let mut unknown_apps = HashSet::new();
+        for info in on_screen {
+            if !self.window_ids.contains_key(&(info.pid, info.id))
+                && self.apps.contains_key(&info.pid)
+            {
+                unknown_apps.insert(info.pid);
+            }
+        }
+        for pid in unknown_apps {
+            // Some apps have windows that the accessibility API never reports,
+            // so this is expected and only worth a debug log.
+            let app = &self.apps[&pid];
+            debug!(pid, bundle_id = ?app.info.bundle_id, "Audit: app has unknown windows");
+            _ = app.handle.send(Request::GetVisibleWindows);
+        }
+        for pid in silent_apps {
+            let Some(app) = self.apps.get(&pid) else { continue };
+            warn!(
+                pid,
+                bundle_id = ?app.info.bundle_id,
+                "Audit: app missed notifications; watching it again"
+            );
+            _ = app.handle.send(Request::Rewatch);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::{
-        collections::BTreeMap,
-        sync::mpsc::{channel, Receiver, Sender},
-    };
+    /// Starts watching for the windows in `on_screen` that we don't know about,
+    /// which are on `space` and were there before we first saw it.
+    ///
+    /// Windows of processes we don't know are only waited for if
+    /// `any_process` is set, as when we start and the apps have not launched
+    /// yet.
+    fn start_adoption(
+        &mut self,
+        space: SpaceId,
+        on_screen: &[WindowServerInfo],
+        any_process: bool,
+    ) {
+        self.end_adoption();
+        let pending: HashMap<WindowServerId, pid_t> = on_screen
+            .iter()
+            .filter(|info| !self.window_ids.contains_key(&(info.pid, info.id)))
+            .filter(|info| any_process || self.apps.contains_key(&info.pid))
+            .map(|info| (info.id, info.pid))
+            .collect();
+        debug!(
+            ?space,
+            count = pending.len(),
+            "Adopting windows on a newly visited space"
+        );
+        self.adoption = Some(Adoption {
+            space,
+            pending,
+            attempts: 0,
+            next_retry: self.clock.now() + ADOPTION_RETRY_INTERVAL,
+        });
+    }
 
-    use icrate::Foundation::{CGPoint, CGSize};
+    /// Asks the apps with windows still waiting for adoption for their
+    /// windows again, if it is time to.
+    fn retry_adoption(&mut self) {
+        let now = self.clock.now();
+        let Some(adoption) = &mut self.adoption else { return };
+        if now < adoption.next_retry {
+            return;
+        }
+        adoption.attempts += 1;
+        if adoption.attempts > MAX_ADOPTION_ATTEMPTS {
+            debug!(pending = ?adoption.pending, "Giving up on adopting windows");
+            self.end_adoption();
+            return;
+        }
+        adoption.next_retry = now + ADOPTION_RETRY_INTERVAL;
+        let pids: HashSet<pid_t> = adoption.pending.values().copied().collect();
+        for pid in pids {
+            if let Some(app) = self.apps.get(&pid) {
+                // Errors mean the app terminated; ignore.
+                _ = app.handle.send(Request::GetVisibleWindows);
+            }
+        }
+    }
 
-    use super::*;
-    use crate::{
-        actor::{app::Request, layout::LayoutManager},
-        sys::window_server::WindowServerId,
-    };
+    /// Registers the newly discovered windows of an app and updates the layout
+    /// with its windows that are visible, given the known ones that are.
+    fn update_visible_windows(
+        &mut self,
+        pid: pid_t,
+        new: Vec<(WindowId, WindowInfo)>,
+        mut visible: Vec<WindowId>,
+    ) {
+        if !self.apps.contains_key(&pid) {
+            // The app already terminated.
+            return;
+        }
+        // FIXME: There is no synchronization ensuring that these windows
+        // are for the current space. The only way I've found to do that
+        // is to take a "snapshot" using CGWindowListCopyWindowInfo.
+        for (wid, info) in new {
+            if self.register_window(wid, info) {
+                // We don't know where new windows are in the stacking
+                // order until the next refresh.
+                self.window_order.push(wid);
+            }
+            if !visible.contains(&wid) {
+                visible.push(wid);
+            }
+        }
+        let app_windows = visible
+            .iter()
+            .copied()
+            .filter(|wid| self.windows.get(wid).is_some_and(|w| w.is_tileable() && !w.is_hidden))
+            .collect();
+        self.visible_windows.insert(pid, visible);
+        // FIXME: We assume all windows are on the main screen.
+        if let Some(space) = self.main_screen_space() {
+            self.send_layout_event(LayoutEvent::WindowsOnScreenUpdated(space, pid, app_windows));
+            let windows = &self.windows;
+            self.layout.adopt(space, |wid| windows.get(&wid).map(|w| w.frame_monotonic));
+            if self.adoption.as_ref().is_some_and(|a| a.space == space && a.pending.is_empty()) {
+                debug!(?space, "Adopted every window on the space");
+                self.end_adoption();
+            }
+        }
+    }
+
+    /// Stops waiting for windows on the space being adopted. After this its
+    /// layout only changes as windows come and go or the user changes it.
+    fn end_adoption(&mut self) {
+        if let Some(adoption) = self.adoption.take() {
+            self.layout.finish_adoption(adoption.space);
+        }
+    }
+
+    /// Returns the window a dialog or sheet belongs to: the parent reported by
+    /// the app, or for dialogs without one, the app's main window.
+    fn find_dialog_parent(&self, wid: WindowId, info: &WindowInfo) -> Option<WindowId> {
+        if info.is_standard {
+            return None;
+        }
+        if let Some(parent) = info.parent {
+            return self.window_ids.get(&(wid.pid, parent)).copied();
+        }
+        if !matches!(info.subrole.as_str(), "AXDialog" | "AXSystemDialog") {
+            return None;
+        }
+        self.apps.get(&wid.pid)?.main_window.filter(|&main| main != wid)
+    }
+
+    /// Records that the window is asking for attention, as the most recent
+    /// request.
+    fn mark_urgent(&mut self, wid: WindowId) {
+        debug!(?wid, "Window requested attention");
+        self.urgent.retain(|w| *w != wid);
+        self.urgent.push(wid);
+    }
+
+    fn hide_window(&mut self, wid: WindowId) {
+        if self.is_paused(wid.pid) {
+            return;
+        }
+        let Some(window) = self.windows.get_mut(&wid) else {
+            return;
+        };
+        window.is_hidden = true;
+        // Errors mean the app terminated (and a termination event is coming);
+        // ignore.
+        _ = self.apps[&wid.pid].handle.send(Request::HideWindow(wid));
+    }
+
+    fn show_hidden_windows(&mut self) -> CommandResult {
+        let space = self.main_screen_space().ok_or(CommandError::NoManagedSpace)?;
+        let hidden: Vec<WindowId> =
+            self.windows.iter().filter(|(_, w)| w.is_hidden).map(|(&wid, _)| wid).collect();
+        if hidden.is_empty() {
+            return Err(CommandError::NoHiddenWindows);
+        }
+        for wid in hidden {
+            let window = self.windows.get_mut(&wid).unwrap();
+            if window.is_tileable() {
+                // The window stays hidden until the layout gives it a frame.
+                if self.layout.window_place(space, wid).is_none() {
+                    self.send_layout_event(LayoutEvent::WindowAdded(space, wid));
+                }
+                continue;
+            }
+            // Moving a hidden window is ignored, so this is still the frame
+            // it had before.
+            let frame = window.frame_monotonic;
+            self.set_window_frame(wid, frame);
+        }
+        Ok(())
+    }
+
+    fn is_tiling_paused(&self) -> bool {
+        self.tiling_paused
+            || self.screen_captured
+            || self.accessibility_lost
+            || self.show_layout_preview
+    }
+
+    /// Whether we should leave the windows of this app where they are.
+    fn is_paused(&self, pid: pid_t) -> bool {
+        self.is_tiling_paused()
+            || self.app_id(pid).is_some_and(|app_id| self.paused_apps.contains(app_id))
+    }
 
-    impl Reactor {
-        fn handle_events(&mut self, events: Vec<Event>) {
-            for event in events {
-                self.handle_event(event);
+    /// The bundle id that rules match the app by. For helper processes this
+    /// is the bundle id of the app they belong to.
+    fn app_id(&self, pid: pid_t) -> Option<&str> {
+        self.apps.get(&pid).and_then(|app| app.info.app_id())
+    }
+
+    /// The app to name and show for a process's windows: for helper
+    /// processes, the app they belong to, if it is running.
+    fn display_app(&self, pid: pid_t) -> Option<&AppInfo> {
+        let info = &self.apps.get(&pid)?.info;
+        let Some(group) = &info.group else {
+            return Some(info);
+        };
+        let leader = self.apps.values().find(|app| app.info.bundle_id.as_ref() == Some(group));
+        Some(leader.map_or(info, |app| &app.info))
+    }
+
+    /// Whether two processes belong to the same app.
+    fn is_same_app(&self, a: pid_t, b: pid_t) -> bool {
+        a == b || self.app_id(a).is_some_and(|app_id| self.app_id(b) == Some(app_id))
+    }
+
+    /// Raises `wid` and gives it keyboard focus.
+    fn raise_window(&mut self, wid: WindowId) {
+        // If the app already has keyboard focus we don't need to activate it.
+        // Activating would bring all of its windows forward.
+        let activate = self.global_frontmost_app_pid != Some(wid.pid);
+        self.send_raise(wid, activate);
+    }
+
+    /// Brings `wid` forward without activating its app, for focus that
+    /// follows the mouse or moves within a stack.
+    fn raise_window_only(&mut self, wid: WindowId) {
+        self.send_raise(wid, false);
+    }
+
+    /// Raises the window a layout command selected. Moving between the
+    /// windows of a stack or tabs only brings the window forward.
+    fn raise_selected_window(&mut self, wid: WindowId) {
+        let in_same_stack = match (self.main_screen, self.main_window()) {
+            (Some(Screen { frame, space: Some(space) }), Some(cur)) => self
+                .layout
+                .groups(space, frame)
+                .iter()
+                .any(|group| group.windows.contains(&wid) && group.windows.contains(&cur)),
+            _ => false,
+        };
+        if in_same_stack {
+            self.raise_window_only(wid);
+        } else {
+            self.raise_window(wid);
+        }
+    }
+
+    fn send_raise(&mut self, wid: WindowId, activate: bool) {
+        self.raise_token.set_pid(wid.pid);
+        // A raise of another window cancels this one if the app hasn't
+        // handled it yet, so only the latest activation is timed.
+        self.activating = activate.then(|| (wid.pid, self.clock.now()));
+        let request = if activate {
+            Request::Raise(wid, self.raise_token.clone())
+        } else {
+            Request::RaiseWindowOnly(wid, self.raise_token.clone())
+        };
+        self.apps.get_mut(&wid.pid).unwrap().handle.send(request).unwrap();
+        if self.announce_focus {
+            let app = self.apps[&wid.pid].info.localized_name.as_deref();
+            let title = self.windows.get(&wid).map(|window| &*window.title);
+            announce::announce(focus_announcement(title, app));
+        }
+
+        // Picture-in-Picture windows at the normal level would be covered by
+        // the raised window.
+        // FIXME: This can be handled before the app finishes raising.
+        for (&pip, window) in &self.windows {
+            if window.is_pip && window.level == 0 && pip != wid {
+                _ = self.apps[&pip.pid].handle.send(Request::OrderFront(pip));
             }
         }
     }
 
-    struct Apps(Sender<(Span, Request)>, Receiver<(Span, Request)>);
-    impl Apps {
-        fn new() -> Apps {
-            let (tx, rx) = channel();
-            Apps(tx, rx)
+    /// Records how long `pid` took to come to the front if we activated it,
+    /// and warns if that was longer than its raise timeout.
+    fn record_activation(&mut self, pid: pid_t) {
+        let Some((_, asked)) = self.activating.filter(|&(activating, _)| activating == pid) else {
+            return;
+        };
+        self.activating = None;
+        let elapsed = self.clock.now().duration_since(asked);
+        let app_id = self.app_id(pid);
+        let timeout = self.raise.timeout(app_id);
+        if elapsed > timeout {
+            warn!(
+                ?app_id,
+                "App took {elapsed:.1?} to come to the front, over its raise timeout of \
+                 {timeout:?}; give it a longer timeout in raise.app_timeouts_ms if it is \
+                 often this slow"
+            );
         }
+        let app = app_id.map_or_else(|| format!("pid {pid}"), str::to_owned);
+        metrics::record(Timing::Activation(app), elapsed);
+    }
+
+    /// Returns the space on the main screen and the nth desktop (starting at
+    /// 1) on the same display.
+    fn space_on_main_display(&self, index: usize) -> Result<(SpaceId, SpaceInfo), CommandError> {
+        let cur_space = self.main_screen_space().ok_or(CommandError::NoManagedSpace)?;
+        let idx = index.checked_sub(1).ok_or(CommandError::NoSuchSpace(index))?;
+        let spaces = screen::spaces();
+        let display = spaces
+            .iter()
+            .find(|s| s.id == cur_space)
+            .map(|s| &s.display)
+            .ok_or(CommandError::NoManagedSpace)?;
+        let target = spaces
+            .iter()
+            .filter(|s| s.display == *display && s.is_user_space)
+            .nth(idx)
+            .ok_or(CommandError::NoSuchSpace(index))?;
+        Ok((cur_space, target.clone()))
+    }
 
-        fn make_app(&mut self, pid: pid_t, windows: Vec<WindowInfo>) -> Vec<Event> {
-            self.make_app_with_opts(pid, windows, None, false)
+    /// Moves the main window to the nth space on its display. If `follow` is
+    /// set we switch to that space, and otherwise focus the next window in
+    /// the layout of this one.
+    fn move_window_to_space(&mut self, index: usize, follow: bool) -> CommandResult {
+        let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+        let (cur_space, target) = self.space_on_main_display(index)?;
+        if target.id == cur_space {
+            return Ok(());
+        }
+        // Decide before the window leaves the layout, so the choice doesn't
+        // depend on the order of the events that follow.
+        let next = self.layout.window_after(cur_space, wid);
+        screen::move_window_to_space(self.windows[&wid].window_server_id, target.id);
+        // The window will be added to the layout for the new space when that
+        // space is exposed and we discover the windows on it.
+        self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+        if follow {
+            screen::switch_to_space(&target);
+            self.raise_window(wid);
+        } else if let Some(next) = next {
+            self.send_layout_event(LayoutEvent::WindowRaised(cur_space, Some(next)));
+            self.raise_window(next);
         }
+        Ok(())
+    }
 
-        fn make_app_with_opts(
-            &mut self,
-            pid: pid_t,
-            windows: Vec<WindowInfo>,
-            main_window: Option<WindowId>,
-            is_frontmost: bool,
-        ) -> Vec<Event> {
-            let handle = AppThreadHandle::new_for_test(self.0.clone());
-            vec![
-                Event::ApplicationLaunched(
-                    pid,
-                    AppState {
-                        info: AppInfo {
-                            bundle_id: Some(format!("com.testapp{pid}")),
-                            localized_name: Some(format!("TestApp{pid}")),
-                        },
-                        handle,
-                        main_window,
-                        is_frontmost,
-                    },
-                ),
-                Event::WindowsDiscovered {
-                    pid,
-                    new: (1..).map(|idx| WindowId::new(pid, idx)).zip(windows).collect(),
-                    known_visible: vec![],
-                },
+    /// Returns the window whose title, app name, or bundle id contains
+    /// `query`, ignoring case. Windows in front are preferred.
+    fn find_window(&self, query: &str) -> Option<WindowId> {
+        let query = query.to_lowercase();
+        let matches = |wid: &WindowId| {
+            let app = self.apps.get(&wid.pid).map(|app| &app.info);
+            [
+                Some(&*self.windows[wid].title),
+                app.and_then(|app| app.localized_name.as_deref()),
+                app.and_then(|app| app.bundle_id.as_deref()),
             ]
+            .into_iter()
+            .flatten()
+            .any(|text| text.to_lowercase().contains(&query))
+        };
+        // Windows on other spaces are not in the stacking order.
+        self.window_order
+            .iter()
+            .copied()
+            .find(&matches)
+            .or_else(|| self.windows.keys().copied().filter(matches).min())
+    }
+
+    /// Raises the window matching `query`, first moving it to the space on
+    /// the display with the focused window or, if `switch` is set, switching
+    /// to its space.
+    fn pull_window(&mut self, query: &str, switch: bool) -> CommandResult {
+        let wid = self
+            .find_window(query)
+            .ok_or_else(|| CommandError::NoMatchingWindow(query.to_string()))?;
+        let window = &self.windows[&wid];
+        let (window_server_id, is_tileable) = (window.window_server_id, window.is_tileable());
+        let space = screen::window_space(window_server_id);
+        if switch {
+            switch_to_window_space(space);
+        } else {
+            let cur_space = self.focused_space().ok_or(CommandError::NoManagedSpace)?;
+            if space != Some(cur_space) {
+                screen::move_window_to_space(window_server_id, cur_space);
+                self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+                if is_tileable {
+                    self.send_layout_event(LayoutEvent::WindowAdded(cur_space, wid));
+                }
+            }
         }
+        self.raise_window(wid);
+        Ok(())
+    }
 
-        fn requests(&mut self) -> Vec<Request> {
-            self.1.try_iter().map(|(_span, rq)| rq).collect()
+    /// Raises the window of the focused app after the main window, or before
+    /// it if `forward` is not set, switching to its space.
+    ///
+    /// Windows are cycled in the order they were created, so the order stays
+    /// the same as focus moves.
+    fn cycle_app_windows(&mut self, forward: bool) -> CommandResult {
+        let current = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+        let mut wids: Vec<WindowId> = self
+            .windows
+            .iter()
+            .filter(|(wid, window)| {
+                self.is_same_app(wid.pid, current.pid) && window.is_standard && !window.is_minimized
+            })
+            .map(|(&wid, _)| wid)
+            .collect();
+        wids.sort();
+        let len = wids.len();
+        let idx = match wids.iter().position(|&wid| wid == current) {
+            Some(idx) if forward => (idx + 1) % len,
+            Some(idx) => (idx + len - 1) % len,
+            None if forward => 0,
+            None => len.checked_sub(1).ok_or(CommandError::NoFocusedWindow)?,
+        };
+        let wid = wids[idx];
+        if wid == current {
+            return Ok(());
         }
+        switch_to_window_space(screen::window_space(self.windows[&wid].window_server_id));
+        self.raise_window(wid);
+        Ok(())
     }
 
-    fn make_window(idx: usize) -> WindowInfo {
-        WindowInfo {
-            is_standard: true,
-            title: format!("Window{idx}"),
-            frame: CGRect::new(
-                CGPoint::new(100.0 * f64::from(idx as u32), 100.0),
-                CGSize::new(50.0, 50.0),
-            ),
-            sys_id: WindowServerId::new(0),
+    /// Moves the visible windows of the apps in `focus_mode` to its background
+    /// space, remembering where they were.
+    fn move_focus_apps_to_background(&mut self) {
+        if self.focus_mode.background_apps.is_empty() {
+            return;
+        }
+        let (cur_space, target) = match self.space_on_main_display(self.focus_mode.background_space)
+        {
+            Ok(spaces) => spaces,
+            Err(e) => {
+                warn!("Could not move apps for focus mode: {e}");
+                return;
+            }
+        };
+        if target.id == cur_space {
+            return;
+        }
+        let visible: HashSet<(pid_t, WindowServerId)> = self
+            .window_server
+            .visible_windows()
+            .iter()
+            .map(|info| (info.pid, info.id))
+            .collect();
+        let wids: Vec<WindowId> = self
+            .window_order
+            .iter()
+            .copied()
+            .filter(|wid| visible.contains(&(wid.pid, self.windows[wid].window_server_id)))
+            .filter(|wid| {
+                self.app_id(wid.pid).is_some_and(|app_id| {
+                    self.focus_mode.background_apps.iter().any(|app| app == app_id)
+                })
+            })
+            .collect();
+        for wid in wids {
+            let window_server_id = self.windows[&wid].window_server_id;
+            // Visible windows can be on the current space of another display.
+            let space = screen::window_space(window_server_id).unwrap_or(cur_space);
+            if space == target.id {
+                continue;
+            }
+            debug!(
+                ?wid,
+                ?space,
+                "Moving window to the background for focus mode"
+            );
+            screen::move_window_to_space(window_server_id, target.id);
+            self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+            self.focus_moved.push((wid, space));
         }
     }
 
-    fn make_windows(count: usize) -> Vec<WindowInfo> {
-        (1..=count).map(make_window).collect()
+    /// Moves the windows moved by [`Self::move_focus_apps_to_background`] back
+    /// to their spaces.
+    fn restore_focus_apps(&mut self) {
+        for (wid, space) in mem::take(&mut self.focus_moved) {
+            let Some(window) = self.windows.get(&wid) else {
+                continue;
+            };
+            screen::move_window_to_space(window.window_server_id, space);
+            // Windows moved to another space are added back when that space
+            // is exposed and we discover them.
+            let is_visible = self.screens.iter().any(|screen| screen.space == Some(space));
+            if window.is_tileable() && is_visible {
+                self.send_layout_event(LayoutEvent::WindowAdded(space, wid));
+            }
+        }
     }
 
-    #[test]
-    fn it_tracks_frontmost_app_and_main_window_correctly() {
-        use Event::*;
-        let mut apps = Apps::new();
-        let mut reactor = Reactor::new(LayoutManager::new());
-        let space = SpaceId::new(1);
-        reactor.handle_event(ScreenParametersChanged(
-            vec![CGRect::ZERO],
-            vec![Some(space)],
-        ));
-        assert_eq!(None, reactor.main_window());
+    /// Returns the frame of the display named by `target`.
+    fn display_frame(&self, target: DisplayTarget) -> Option<CGRect> {
+        let frames: Vec<CGRect> = self.screens.iter().map(|screen| screen.frame).collect();
+        let frames: Vec<CGRect> =
+            screen::arrangement_order(&frames).into_iter().map(|idx| frames[idx]).collect();
+        let current = || {
+            let point = match self.main_window().and_then(|wid| self.windows.get(&wid)) {
+                Some(window) => window.frame_monotonic.mid(),
+                None => self.main_screen?.frame.mid(),
+            };
+            frames.iter().position(|frame| frame.contains(point))
+        };
+        let idx = match target {
+            DisplayTarget::Index(index) => index.checked_sub(1),
+            DisplayTarget::Current => current(),
+            DisplayTarget::Next => current().map(|idx| (idx + 1) % frames.len()),
+            DisplayTarget::Prev => current().map(|idx| (idx + frames.len() - 1) % frames.len()),
+        };
+        idx.and_then(|idx| frames.get(idx).copied())
+    }
 
-        reactor.handle_event(ApplicationGloballyActivated(1));
-        reactor.handle_events(apps.make_app_with_opts(
-            1,
-            make_windows(2),
-            Some(WindowId::new(1, 1)),
-            true,
-        ));
-        reactor.handle_events(apps.make_app(2, make_windows(2)));
-        assert_eq!(Some(WindowId::new(1, 1)), reactor.main_window());
-        assert_eq!(
-            reactor.layout.selected_window(space),
-            Some(WindowId::new(1, 1))
-        );
-        reactor.handle_event(ApplicationGloballyDeactivated(1));
-        assert_eq!(None, reactor.main_window());
-        reactor.handle_event(ApplicationActivated(2, None));
-        reactor.handle_event(ApplicationGloballyActivated(2));
-        assert_eq!(None, reactor.main_window());
-        reactor.handle_event(ApplicationMainWindowChanged(2, Some(WindowId::new(2, 2))));
-        assert_eq!(Some(WindowId::new(2, 2)), reactor.main_window());
+    fn focus_display(&mut self, target: DisplayTarget) -> CommandResult {
+        let display = self.display_frame(target).ok_or(CommandError::NoSuchDisplay)?;
+        self.refresh_window_order();
+        let wid = self.window_order.iter().copied().find(|wid| {
+            let window = &self.windows[wid];
+            !window.is_hidden && display.contains(window.frame_monotonic.mid())
+        });
+        let wid = wid.ok_or(CommandError::NoWindowsOnDisplay)?;
+        self.raise_window(wid);
+        Ok(())
+    }
+
+    fn move_window_to_display(&mut self, target: DisplayTarget) -> CommandResult {
+        let wid = self.main_window().ok_or(CommandError::NoFocusedWindow)?;
+        let display = self.display_frame(target).ok_or(CommandError::NoSuchDisplay)?;
+        let window = self.windows.get_mut(&wid).ok_or(CommandError::NoFocusedWindow)?;
+        if display.contains(window.frame_monotonic.mid()) {
+            return Ok(());
+        }
+        match self.main_screen {
+            Some(Screen { frame, space: Some(space) }) if frame == display => {
+                // The next layout update moves the window into place.
+                let layout = self.layout.calculate_layout(space, frame);
+                let is_tiled = layout.iter().any(|&(tiled, _)| tiled == wid);
+                if !is_tiled {
+                    self.send_layout_event(LayoutEvent::WindowAdded(space, wid));
+                }
+            }
+            _ => {
+                let mid = window.frame_monotonic.mid();
+                let frame = match self.screens.iter().find(|screen| screen.frame.contains(mid)) {
+                    // Keep the part of the display the window covers, even if
+                    // the displays are different sizes.
+                    Some(screen) => window.frame_monotonic.rescale(screen.frame, display),
+                    None => {
+                        let size = CGSize::new(
+                            window.frame_monotonic.size.width.min(display.size.width),
+                            window.frame_monotonic.size.height.min(display.size.height),
+                        );
+                        let center = display.mid();
+                        CGRect::new(
+                            CGPoint::new(center.x - size.width / 2.0, center.y - size.height / 2.0),
+                            size,
+                        )
+                    }
+                };
+                self.set_window_frame(wid, frame);
+                self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+            }
+        }
+        self.raise_window(wid);
+        Ok(())
+    }
+
+    /// Moves the windows outside the layout that were on screens that are
+    /// gone to the screens that replaced them, keeping the part of the screen
+    /// each covered. A screen is replaced by the new screen of the same
+    /// display if there is one, as when its resolution changed or it was
+    /// rearranged, and otherwise by the main screen.
+    ///
+    /// `old_displays` has the display of each of `old_screens`. Screens whose
+    /// display we don't know are only kept if one is in the same place.
+    fn migrate_windows(&mut self, old_screens: &[Screen], old_displays: &[Option<DisplayUuid>]) {
+        let Some(main_screen) = self.main_screen else { return };
+        let tiled: Vec<WindowId> = match main_screen.space {
+            Some(space) => self
+                .layout
+                .calculate_layout(space, main_screen.frame)
+                .into_iter()
+                .map(|(wid, _)| wid)
+                .collect(),
+            None => vec![],
+        };
+        // Work out every move before making any, so that a window moved onto
+        // the frame of another old screen isn't moved again.
+        let mut moves = vec![];
+        for (idx, old) in old_screens.iter().enumerate() {
+            let replacement = match old_displays.get(idx).and_then(Option::as_ref) {
+                Some(display) => self
+                    .screen_displays
+                    .iter()
+                    .position(|new| new.as_ref() == Some(display))
+                    .map(|new| self.screens[new].frame),
+                None => self.screens.iter().map(|s| s.frame).find(|&frame| frame == old.frame),
+            };
+            let new = match replacement {
+                Some(frame) if frame == old.frame => continue,
+                Some(frame) => frame,
+                None => main_screen.frame,
+            };
+            for (&wid, window) in &self.windows {
+                let mid = window.frame_monotonic.mid();
+                if window.is_hidden
+                    || window.is_minimized
+                    || window.is_fullscreen
+                    || window.dialog_parent.is_some()
+                    || !old.frame.contains(mid)
+                    || tiled.contains(&wid)
+                {
+                    continue;
+                }
+                let frame = window.frame_monotonic.rescale(old.frame, new);
+                debug!(?wid, ?frame, "Moving window off a removed screen");
+                moves.push((wid, frame));
+            }
+        }
+        for (wid, frame) in moves {
+            self.set_window_frame(wid, frame);
+        }
+    }
+
+    /// Checks our view of window frames in the current layout against the
+    /// window server, so that windows moved behind our back are corrected by
+    /// the next layout update.
+    ///
+    /// The window server reports every frame in a single call, which is much
+    /// cheaper than a round trip to each app over the accessibility API.
+    fn validate_frames(&mut self) {
+        let Some(screen) = self.main_screen else { return };
+        let Some(space) = screen.space else { return };
+        let in_layout: HashSet<WindowId> = self
+            .layout
+            .calculate_layout(space, screen.frame)
+            .into_iter()
+            .map(|(wid, _)| wid)
+            .collect();
+        let ids: Vec<_> = in_layout
+            .iter()
+            .filter_map(|wid| Some(self.windows.get(wid)?.window_server_id))
+            .filter(|id| id.is_valid())
+            .collect();
+        for info in self.window_server.windows(&ids) {
+            // Checking the pid makes sure the id wasn't reused by another
+            // app's window.
+            let Some(wid) = self.window_ids.get(&(info.pid, info.id)) else {
+                continue;
+            };
+            if !in_layout.contains(wid) {
+                continue;
+            }
+            let window = self.windows.get_mut(wid).unwrap();
+            if window.is_hidden || info.frame.same_as(window.frame_monotonic) {
+                continue;
+            }
+            debug!(
+                ?wid,
+                expected = ?window.frame_monotonic,
+                actual = ?info.frame,
+                "Frame out of sync"
+            );
+            window.frame_monotonic = info.frame;
+        }
+    }
+
+    fn handle_query(&mut self, query: Query) -> QueryResponse {
+        match query {
+            Query::Windows => {
+                self.refresh_window_order();
+                let windows = self
+                    .window_order
+                    .iter()
+                    .map(|wid| {
+                        let window = &self.windows[wid];
+                        let app = self.display_app(wid.pid);
+                        WindowData {
+                            id: *wid,
+                            window_server_id: window
+                                .window_server_id
+                                .is_valid()
+                                .then(|| window.window_server_id.as_u32()),
+                            title: window.title.to_string(),
+                            app_name: app.and_then(|app| app.localized_name.clone()),
+                            app_icon: app.and_then(|app| app.icon_path.clone()),
+                            frame: window.frame_monotonic,
+                            is_occluded: self.is_occluded(*wid),
+                            subrole: window.subrole.to_string(),
+                            level: window.level,
+                            is_resizable: window.is_resizable,
+                            is_minimized: window.is_minimized,
+                            is_fullscreen: window.is_fullscreen,
+                            is_urgent: self.urgent.contains(wid),
+                            opacity: window.appearance.opacity,
+                            has_shadow: window.appearance.has_shadow,
+                            pin: window.pin,
+                        }
+                    })
+                    .collect();
+                QueryResponse::Windows(windows)
+            }
+            Query::Layout => QueryResponse::Layout(self.layout.serialize_to_string()),
+            Query::Power => QueryResponse::Power(self.power),
+            Query::Focused => QueryResponse::Focused(self.focused_window()),
+            Query::SpaceLayout => {
+                let spec = self.main_screen_space().map(|space| {
+                    self.layout.export(space, |wid| WindowMatcher {
+                        app: self.app_id(wid.pid).map(ToString::to_string),
+                        title: self.windows.get(&wid).map(|window| window.title.to_string()),
+                    })
+                });
+                QueryResponse::SpaceLayout(spec)
+            }
+            Query::Apps => {
+                let mut apps: Vec<_> = self
+                    .apps
+                    .iter()
+                    .map(|(&pid, app)| AppData {
+                        pid,
+                        bundle_id: app.info.bundle_id.clone(),
+                        name: app.info.localized_name.clone(),
+                        bundle_path: app.info.bundle_path.clone(),
+                        icon_path: app.info.icon_path.clone(),
+                        group: app.info.group.clone(),
+                        windows: self.windows.keys().filter(|wid| wid.pid == pid).count(),
+                        cpu_seconds: app.handle.cpu_time().map(|time| time.as_secs_f64()),
+                    })
+                    .collect();
+                apps.sort_by(|a, b| {
+                    let cpu = |app: &AppData| app.cpu_seconds.unwrap_or(0.0);
+                    cpu(b).total_cmp(&cpu(a)).then(a.pid.cmp(&b.pid))
+                });
+                QueryResponse::Apps(apps)
+            }
+            Query::Spaces => QueryResponse::Spaces(self.spaces()),
+            Query::Stacks => QueryResponse::Stacks(self.stacks()),
+        }
+    }
+
+    fn spaces(&self) -> Vec<SpaceData> {
+        let mut summaries: Vec<_> = self
+            .layout
+            .space_summaries()
+            .into_iter()
+            .map(|(id, windows, layout)| (id, windows, Some(layout)))
+            .collect();
+        // List new desktops before we lay them out, so status bars can show
+        // them.
+        let unvisited: Vec<_> = self
+            .known_spaces
+            .iter()
+            .filter(|info| info.is_user_space && !summaries.iter().any(|s| s.0 == info.id))
+            .map(|info| (info.id, 0, None))
+            .collect();
+        summaries.extend(unvisited);
+        let mut spaces: Vec<_> = summaries
+            .into_iter()
+            .map(|(id, windows, layout)| {
+                let screen = self.screens.iter().position(|screen| screen.space == Some(id));
+                SpaceData {
+                    id,
+                    number: self.space_number(id),
+                    windows,
+                    layout,
+                    screen,
+                    is_focused: screen == Some(0),
+                }
+            })
+            .collect();
+        let order = |id| self.known_spaces.iter().position(|info| info.id == id);
+        spaces.sort_by_key(|space| (order(space.id).unwrap_or(usize::MAX), space.id));
+        spaces
+    }
+
+    /// Returns the number of the desktop on its display, starting at 1.
+    fn space_number(&self, space: SpaceId) -> Option<usize> {
+        let info = self.known_spaces.iter().find(|info| info.id == space)?;
+        let position = self
+            .known_spaces
+            .iter()
+            .filter(|other| other.display == info.display && other.is_user_space)
+            .position(|other| other.id == space)?;
+        Some(position + 1)
+    }
+
+    /// Sends the spaces to subscribers if they changed since the last time.
+    fn update_space_subscribers(&mut self) {
+        if self.space_subscribers.is_empty() {
+            return;
+        }
+        let spaces = self.spaces();
+        if spaces == self.spaces_sent {
+            return;
+        }
+        // Errors mean the client went away.
+        self.space_subscribers.retain(|tx| tx.send(spaces.clone()).is_ok());
+        self.spaces_sent = spaces;
+    }
+
+    fn stacks(&self) -> Vec<StackData> {
+        let Some(Screen { frame, space: Some(space) }) = self.main_screen else {
+            return vec![];
+        };
+        // Inset as in `calculate_layout`, so the frames match the windows'.
+        let half_gap = self.main_screen_settings().gaps / 2.0;
+        self.layout
+            .groups(space, frame.inset(half_gap))
+            .into_iter()
+            .map(|group| StackData {
+                space,
+                kind: group.kind,
+                frame: group.frame.inset(half_gap),
+                windows: group
+                    .windows
+                    .into_iter()
+                    .map(|wid| {
+                        let app = self.display_app(wid.pid);
+                        StackWindow {
+                            id: wid,
+                            title: self
+                                .windows
+                                .get(&wid)
+                                .map(|window| window.title.to_string())
+                                .unwrap_or_default(),
+                            app_name: app.and_then(|app| app.localized_name.clone()),
+                            app_icon: app.and_then(|app| app.icon_path.clone()),
+                        }
+                    })
+                    .collect(),
+                selected: group.selected,
+            })
+            .collect()
+    }
+
+    /// Sends the stacks to subscribers if they changed since the last time,
+    /// for example because another window in one was selected.
+    fn update_stack_subscribers(&mut self) {
+        if self.stack_subscribers.is_empty() {
+            return;
+        }
+        let stacks = self.stacks();
+        if stacks == self.stacks_sent {
+            return;
+        }
+        // Errors mean the client went away.
+        self.stack_subscribers.retain(|tx| tx.send(stacks.clone()).is_ok());
+        self.stacks_sent = stacks;
+    }
+
+    fn focused_window(&self) -> Option<FocusedWindow> {
+        let wid = self.main_window()?;
+        let window = self.windows.get(&wid)?;
+        let mid = window.frame_monotonic.mid();
+        let screen = self.screens.iter().find(|screen| screen.frame.contains(mid));
+        let is_tiled = match self.main_screen {
+            Some(Screen { frame, space: Some(space) }) => self
+                .layout
+                .calculate_layout(space, frame)
+                .iter()
+                .any(|&(tiled, _)| tiled == wid),
+            _ => false,
+        };
+        let app = self.display_app(wid.pid);
+        Some(FocusedWindow {
+            id: wid,
+            bundle_id: app.and_then(|app| app.bundle_id.clone()),
+            app_name: app.and_then(|app| app.localized_name.clone()),
+            app_icon: app.and_then(|app| app.icon_path.clone()),
+            title: window.title.to_string(),
+            frame: window.frame_monotonic,
+            space: screen.and_then(|screen| screen.space),
+            is_floating: !is_tiled,
+        })
+    }
+
+    /// Updates the stacking order from the window server.
+    ///
+    /// We don't get notified when windows are reordered, so this should be
+    /// called before anything that depends on the order.
+    fn refresh_window_order(&mut self) {
+        let order: Vec<WindowId> = self
+            .window_server
+            .visible_windows()
+            .into_iter()
+            .filter_map(|info| self.window_ids.get(&(info.pid, info.id)).copied())
+            .collect();
+        self.apply_window_order(&order);
+    }
+
+    /// Moves windows in `order`, which starts with the frontmost window, to the
+    /// front in the same order. Windows the window server did not report (for
+    /// example, because they are on another space) keep their relative order
+    /// behind them.
+    fn apply_window_order(&mut self, order: &[WindowId]) {
+        let mut front: Vec<WindowId> =
+            order.iter().copied().filter(|wid| self.window_order.contains(wid)).collect();
+        self.window_order.retain(|wid| !front.contains(wid));
+        front.append(&mut self.window_order);
+        self.window_order = front;
+    }
+
+    fn move_to_front(&mut self, wid: WindowId) {
+        if !self.windows.contains_key(&wid) {
+            // Activation events can arrive after the window was destroyed.
+            return;
+        }
+        self.window_order.retain(|w| *w != wid);
+        self.window_order.insert(0, wid);
+    }
+
+    /// Returns the frontmost visible window containing `point`.
+    fn window_at_point(&self, point: CGPoint) -> Option<WindowId> {
+        self.window_order.iter().copied().find(|wid| {
+            let window = &self.windows[wid];
+            !window.is_hidden && window.frame_monotonic.contains(point)
+        })
+    }
+
+    /// Whether the window is completely covered by a window in front of it.
+    fn is_occluded(&self, wid: WindowId) -> bool {
+        let frame = self.windows[&wid].frame_monotonic;
+        self.window_order
+            .iter()
+            .take_while(|w| **w != wid)
+            .any(|w| !self.windows[w].is_hidden && self.windows[w].frame_monotonic.contains(frame))
+    }
+
+    /// The main window of the active app, if any.
+    fn main_window(&self) -> Option<WindowId> {
+        // Because apps self-report this event from their respective
+        // threads, they can appear out of order. To mitigate this, we
+        // require that the "global" view from NSNotificationCenter
+        // agrees with the app about which is frontmost. This guarantees
+        // eventual consistency.
+        //
+        // Since the global events provide an authoritative ordering, why
+        // care about this event at all? The reason is that we want to
+        // know what the main window of the app is upon activation. This
+        // is important when the user clicks on a window of the app
+        // that was not previously the main window: The frontmost app
+        // and its main window can switch at the same time. In that case
+        // we don't want to record the old main window as having focus,
+        // since it never did. So we wait until both events are received.
+        let Some(pid) = self.global_frontmost_app_pid else {
+            return None;
+        };
+        match self.apps.get(&pid) {
+            Some(AppState {
+                is_frontmost: true,
+                main_window: Some(window),
+                ..
+            }) if self.windows.contains_key(window) => Some(*window),
+            // Web app shims are frontmost while their windows, which belong
+            // to the browser's process, have focus.
+            Some(AppState {
+                is_frontmost: true,
+                main_window: None,
+                ..
+            }) => self
+                .apps
+                .iter()
+                .filter(|(&other, _)| other != pid && self.is_same_app(other, pid))
+                .filter_map(|(_, app)| app.main_window)
+                .filter(|window| self.windows.contains_key(window))
+                .min(),
+            _ => None,
+        }
+    }
+
+    fn main_screen_space(&self) -> Option<SpaceId> {
+        self.main_screen?.space
+    }
+
+    /// Returns the space on the display with the focused window, or on the
+    /// main screen if no window is focused.
+    fn focused_space(&self) -> Option<SpaceId> {
+        let window = self.main_window().and_then(|wid| self.windows.get(&wid));
+        let screen = window.and_then(|window| {
+            let mid = window.frame_monotonic.mid();
+            self.screens.iter().find(|screen| screen.frame.contains(mid))
+        });
+        screen.or(self.main_screen.as_ref())?.space
+    }
+
+    #[instrument(skip(self), fields(?self.main_screen))]
+    pub fn update_layout(&mut self, new_wid: Option<WindowId>, is_resize: bool) {
+        self.update_split_handles();
+        self.update_layout_preview();
+        let Some(main_screen) = self.main_screen else { return };
+        let Some(space) = main_screen.space else { return };
+        if self.is_tiling_paused() {
+            return;
+        }
+        // A forced update sends every frame without animating, so repeating
+        // it leaves windows where they are.
+        let force = mem::take(&mut self.force_relayout);
+        let start = Instant::now();
+
+        trace!(?main_screen);
+        let main_window = self.main_window();
+        trace!(?main_window);
+        let mut layout = self.calculate_layout(space, main_screen.frame);
+        let dialogs = self.place_dialogs(&layout);
+        layout.extend(dialogs);
+        layout.extend(self.place_pip_windows(main_screen.frame));
+        layout.extend(self.place_pinned_windows(main_screen.frame));
+        trace!(?layout, "Layout");
+
+        let fps = match self.animation_mode() {
+            AnimationMode::Reduced => REDUCED_FPS,
+            AnimationMode::Full | AnimationMode::Disabled => FULL_FPS,
+        };
+        let mut anim = Animation::new(&*self.clock, fps);
+        let mut moved = vec![];
+        for &(wid, target_frame) in &layout {
+            if self.is_paused(wid.pid) {
+                continue;
+            }
+            let target_frame = self.clamp_to_screens(target_frame);
+            let Some(window) = self.windows.get_mut(&wid) else {
+                // If we restored a saved state the window may not be available yet.
+                continue;
+            };
+            if window.is_contested
+                || self.window_drag.as_ref().is_some_and(|drag| drag.is_moving(wid))
+            {
+                continue;
+            }
+            let target_frame = target_frame.round();
+            let current_frame = window.frame_monotonic;
+            if target_frame.same_as(current_frame) && !window.is_hidden && !force {
+                continue;
+            }
+            window.is_hidden = false;
+            info!(?wid, ?current_frame, ?target_frame);
+            let handle = &self.apps.get(&wid.pid).unwrap().handle;
+            let is_new = Some(wid) == new_wid;
+            let txid = window.next_txid();
+            anim.add_window(handle, wid, current_frame, target_frame, is_new, txid);
+            window.frame_monotonic = target_frame;
+            moved.push((wid, current_frame));
+        }
+        let mut animation_time = Duration::ZERO;
+        if is_resize || force || self.animation_mode() == AnimationMode::Disabled {
+            // If the user is doing something with the mouse we don't want to
+            // animate on top of that.
+            anim.skip_to_end();
+        } else {
+            let anim_start = Instant::now();
+            anim.run();
+            animation_time = anim_start.elapsed();
+        }
+        self.animation_time += animation_time;
+        metrics::record(Timing::Layout, start.elapsed() - animation_time);
+        let now = self.clock.now();
+        for (wid, before) in moved {
+            self.windows.get_mut(&wid).unwrap().last_write = Some((now, before));
+        }
+    }
+
+    /// How to animate windows, from the power state and any Focus.
+    fn animation_mode(&self) -> AnimationMode {
+        match self.focus_mode.animations {
+            Some(mode) if self.focus_active => mode,
+            _ => self.power.animations,
+        }
+    }
+
+    fn main_screen_settings(&self) -> ScreenSettings {
+        self.screen_settings.first().cloned().unwrap_or_default()
+    }
+
+    /// Returns frames for the dialogs that need to be centered over their
+    /// parent windows in `layout`: new dialogs, and those whose parent is
+    /// about to move. Other dialogs stay where the user put them.
+    fn place_dialogs(&mut self, layout: &[(WindowId, CGRect)]) -> Vec<(WindowId, CGRect)> {
+        let mut frames = vec![];
+        for (&wid, window) in &self.windows {
+            let Some(parent) = window.dialog_parent else { continue };
+            let parent_frame =
+                layout.iter().find_map(|&(other, frame)| (other == parent).then_some(frame));
+            let Some(parent_frame) = parent_frame.map(|frame| frame.round()) else {
+                continue;
+            };
+            let parent_moved = self
+                .windows
+                .get(&parent)
+                .is_some_and(|parent| !parent_frame.same_as(parent.frame_monotonic));
+            if window.is_dialog_placed && !parent_moved {
+                continue;
+            }
+            let size = window.frame_monotonic.size;
+            let center = parent_frame.mid();
+            let origin = CGPoint::new(center.x - size.width / 2.0, center.y - size.height / 2.0);
+            frames.push((wid, CGRect::new(origin, size)));
+        }
+        for &(wid, _) in &frames {
+            self.windows.get_mut(&wid).unwrap().is_dialog_placed = true;
+        }
+        frames
+    }
+
+    /// Returns frames that keep Picture-in-Picture windows in the configured
+    /// corner of `screen`, if there is one.
+    fn place_pip_windows(&self, screen: CGRect) -> Vec<(WindowId, CGRect)> {
+        let Some(corner) = self.pip.corner else { return vec![] };
+        let area = screen.inset(self.pip.margin);
+        self.windows
+            .iter()
+            .filter(|(_, window)| {
+                window.is_pip && !window.is_minimized && !window.is_hidden && window.pin.is_none()
+            })
+            .map(|(&wid, window)| (wid, corner.place(window.frame_monotonic.size, area)))
+            .collect()
+    }
+
+    /// Calculates the frames of the windows in the layout for `space`,
+    /// leaving gaps between them and around the edges of the screen.
+    fn calculate_layout(&self, space: SpaceId, screen: CGRect) -> Vec<(WindowId, CGRect)> {
+        // Half of the gap comes from each side of the boundary between two
+        // windows.
+        let half_gap = self.main_screen_settings().gaps / 2.0;
+        let mut layout = self.layout.calculate_layout(space, screen.inset(half_gap));
+        for (wid, frame) in &mut layout {
+            *frame = if Some(*wid) == self.zoomed {
+                screen.inset(half_gap * 2.0)
+            } else {
+                frame.inset(half_gap)
+            };
+        }
+        layout
+    }
+
+    /// Changes how the window server draws `wid`, and remembers the change if
+    /// the window server made it.
+    fn set_appearance(
+        &mut self,
+        wid: WindowId,
+        change: impl FnOnce(&mut WindowAppearance),
+    ) -> CommandResult {
+        let window = self.windows.get_mut(&wid).ok_or(CommandError::NoFocusedWindow)?;
+        let mut appearance = window.appearance;
+        change(&mut appearance);
+        if let Err(err) = self.window_server.set_appearance(window.window_server_id, appearance) {
+            debug!(?wid, ?err, "The window server refused to change the window");
+            return Err(CommandError::AppearanceRefused);
+        }
+        window.appearance = appearance;
+        Ok(())
+    }
+
+    fn toggle_zoom(&mut self, wid: WindowId) -> CommandResult {
+        if self.zoomed == Some(wid) {
+            self.zoomed = None;
+            return Ok(());
+        }
+        let Some(Screen { frame, space: Some(space) }) = self.main_screen else {
+            return Err(CommandError::NoManagedSpace);
+        };
+        if !self.calculate_layout(space, frame).iter().any(|&(tiled, _)| tiled == wid) {
+            return Err(CommandError::WindowNotTiled);
+        }
+        self.zoomed = Some(wid);
+        self.raise_window(wid);
+        Ok(())
+    }
+
+    fn toggle_float(&mut self, wid: WindowId) -> CommandResult {
+        let space = self.main_screen_space().ok_or(CommandError::NoManagedSpace)?;
+        let window = self.windows.get_mut(&wid).ok_or(CommandError::NoFocusedWindow)?;
+        if window.is_floating {
+            window.is_floating = false;
+            window.pin = None;
+            if window.is_tileable() {
+                self.send_layout_event(LayoutEvent::WindowAdded(space, wid));
+            }
+        } else if window.is_tileable() {
+            window.is_floating = true;
+            self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+        } else {
+            return Err(CommandError::WindowNotTiled);
+        }
+        Ok(())
+    }
+
+    /// Sends the boundaries between tiles to the split handles actor if they
+    /// changed. There are none while tiling is paused.
+    fn update_split_handles(&mut self) {
+        let Some(split_handles) = &self.split_handles else {
+            return;
+        };
+        let splits = match self.main_screen {
+            Some(Screen { frame, space: Some(space) }) if !self.is_tiling_paused() => {
+                // Put the boundaries in the middle of the gaps.
+                let half_gap = self.main_screen_settings().gaps / 2.0;
+                self.layout.calculate_splits(space, frame.inset(half_gap))
+            }
+            _ => vec![],
+        };
+        if splits == self.splits {
+            return;
+        }
+        self.splits = splits.clone();
+        _ = split_handles.send((Span::current(), split_handles::Event::SplitsChanged(splits)));
+    }
+
+    /// Sends the frames the layout would give each window to the borders actor
+    /// if they changed, or hides them if the preview is off.
+    fn update_layout_preview(&mut self) {
+        let Some(borders) = &self.borders else { return };
+        let tiles: Vec<_> = match self.main_screen {
+            Some(Screen { frame, space: Some(space) }) if self.show_layout_preview => self
+                .calculate_layout(space, frame)
+                .into_iter()
+                .filter_map(|(wid, frame)| {
+                    Some(borders::PreviewTile {
+                        title: self.windows.get(&wid)?.title.to_string(),
+                        frame: frame.round(),
+                    })
+                })
+                .collect(),
+            _ => vec![],
+        };
+        if tiles == self.preview_tiles {
+            return;
+        }
+        self.preview_tiles = tiles.clone();
+        _ = borders.send((Span::current(), borders::Event::PreviewChanged(tiles)));
+    }
+
+    /// Sends the tiled windows and the focused window to the borders actor if
+    /// they changed.
+    fn update_borders(&mut self) {
+        let Some(borders) = &self.borders else { return };
+        let main_window = self.main_window();
+        let tiled: Vec<WindowId> = match self.main_screen {
+            Some(Screen { frame, space: Some(space) }) if !self.is_tiling_paused() => self
+                .layout
+                .calculate_layout(space, frame)
+                .into_iter()
+                .map(|(wid, _)| wid)
+                .collect(),
+            _ => vec![],
+        };
+        let floating_main = main_window.filter(|wid| !tiled.contains(wid));
+        let windows: Vec<_> = tiled
+            .iter()
+            .map(|&wid| (wid, false))
+            .chain(floating_main.map(|wid| (wid, true)))
+            .filter_map(|(wid, is_floating)| {
+                let window = self.windows.get(&wid)?;
+                if window.is_hidden || window.is_minimized || window.is_fullscreen {
+                    return None;
+                }
+                Some(borders::Window {
+                    id: window.window_server_id,
+                    frame: window.frame_monotonic,
+                    app: self
+                        .apps
+                        .get(&wid.pid)
+                        .and_then(|app| app.info.app_id())
+                        .map(|app_id| self.strings.intern(app_id)),
+                    title: window.title.clone(),
+                    is_focused: Some(wid) == main_window,
+                    is_floating,
+                })
+            })
+            .collect();
+        if windows == self.bordered_windows {
+            return;
+        }
+        self.bordered_windows = windows.clone();
+        _ = borders.send((Span::current(), borders::Event::WindowsChanged(windows)));
+    }
+
+    /// Tells the mouse actor where the visible windows are, so it can tell
+    /// when they are dragged or their title bars are double-clicked.
+    fn update_window_frames(&self) {
+        let Some(window_frames) = &self.window_frames else {
+            return;
+        };
+        let windows = match self.main_screen_space() {
+            Some(_) => self
+                .window_order
+                .iter()
+                .filter(|wid| {
+                    self.visible_windows.get(&wid.pid).is_some_and(|visible| visible.contains(wid))
+                })
+                .filter_map(|wid| {
+                    let window = &self.windows[wid];
+                    let is_shown =
+                        !window.is_hidden && !window.is_minimized && !window.is_fullscreen;
+                    is_shown.then_some(WindowFrame {
+                        wid: *wid,
+                        frame: window.frame_monotonic,
+                        is_standard: window.is_standard,
+                    })
+                })
+                .collect(),
+            None => vec![],
+        };
+        window_frames.set(windows);
+    }
+}
+
+/// Switches to `space`, the space a window is on, if it is known.
+fn switch_to_window_space(space: Option<SpaceId>) {
+    let target = space.and_then(|space| screen::spaces().into_iter().find(|s| s.id == space));
+    if let Some(target) = target {
+        screen::switch_to_space(&target);
+    }
+}
+
+/// Returns what screen readers say when we raise a window, like "Inbox, Mail".
+fn focus_announcement(title: Option<&str>, app: Option<&str>) -> String {
+    let title = title.filter(|title| !title.is_empty());
+    match (title, app) {
+        (Some(title), Some(app)) => format!("{title}, {app}"),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Untitled window".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use icrate::Foundation::{CGPoint, CGSize};
+
+    use super::{testing::*, *};
+    use crate::{
+        actor::app::Request,
+        sys::{
+            clock::VirtualClock, geometry::Corner, screen::DisplayUuid,
+            window_server::WindowServerId,
+        },
+    };
+
+    #[test]
+    fn it_tracks_frontmost_app_and_main_window_correctly() {
+        use Event::*;
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        let space = SpaceId::new(1);
+        reactor.handle_event(ScreenParametersChanged(
+            vec![CGRect::ZERO],
+            vec![Some(space)],
+        ));
+        assert_eq!(None, reactor.main_window());
+
+        reactor.handle_event(ApplicationGloballyActivated(1));
+        reactor.handle_events(apps.make_app_with_opts(
+            1,
+            make_windows(2),
+            Some(WindowId::new(1, 1)),
+            true,
+        ));
+        reactor.handle_events(apps.make_app(2, make_windows(2)));
+        assert_eq!(Some(WindowId::new(1, 1)), reactor.main_window());
+        assert_eq!(
+            reactor.layout.selected_window(space),
+            Some(WindowId::new(1, 1))
+        );
+        reactor.handle_event(ApplicationGloballyDeactivated(1));
+        assert_eq!(None, reactor.main_window());
+        reactor.handle_event(ApplicationActivated(2, None));
+        reactor.handle_event(ApplicationGloballyActivated(2));
+        assert_eq!(None, reactor.main_window());
+        reactor.handle_event(ApplicationMainWindowChanged(2, Some(WindowId::new(2, 2))));
+        assert_eq!(Some(WindowId::new(2, 2)), reactor.main_window());
+        assert_eq!(
+            reactor.layout.selected_window(space),
+            Some(WindowId::new(2, 2))
+        );
+        reactor.handle_event(ApplicationMainWindowChanged(1, Some(WindowId::new(1, 2))));
+        assert_eq!(Some(WindowId::new(2, 2)), reactor.main_window());
+        reactor.handle_event(ApplicationDeactivated(1));
+        assert_eq!(Some(WindowId::new(2, 2)), reactor.main_window());
+        reactor.handle_event(ApplicationDeactivated(2));
+        assert_eq!(None, reactor.main_window());
+
+        reactor.handle_event(ApplicationGloballyActivated(3));
+        assert_eq!(None, reactor.main_window());
+
+        reactor.handle_events(apps.make_app_with_opts(
+            3,
+            make_windows(2),
+            Some(WindowId::new(3, 1)),
+            true,
+        ));
+        assert_eq!(Some(WindowId::new(3, 1)), reactor.main_window());
+        assert_eq!(
+            reactor.layout.selected_window(space),
+            Some(WindowId::new(3, 1))
+        );
+    }
+
+    #[test]
+    fn it_rechecks_activation_state_when_frontmost_app_disagrees() {
+        use Event::*;
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        reactor.handle_event(ScreenParametersChanged(
+            vec![CGRect::ZERO],
+            vec![Some(SpaceId::new(1))],
+        ));
+        reactor.handle_events(apps.make_app_with_opts(
+            1,
+            make_windows(1),
+            Some(WindowId::new(1, 1)),
+            true,
+        ));
+        reactor.handle_events(apps.make_app(2, make_windows(1)));
+        reactor.handle_event(ApplicationGloballyActivated(1));
+        _ = apps.requests();
+
+        reactor.handle_event(FrontmostAppChanged(Some(1)));
+        assert!(apps.requests().is_empty());
+
+        // App 2 was activated, but neither app told us.
+        reactor.handle_event(FrontmostAppChanged(Some(2)));
+        let requests = apps.requests();
+        assert_eq!(2, requests.len(), "{requests:?}");
+        assert!(requests.iter().all(|r| matches!(r, Request::GetActivationState)));
+        assert_eq!(None, reactor.main_window());
+
+        reactor.handle_event(ApplicationDeactivated(1));
+        reactor.handle_event(ApplicationActivated(2, Some(WindowId::new(2, 1))));
+        assert_eq!(Some(WindowId::new(2, 1)), reactor.main_window());
+    }
+
+    #[test]
+    fn it_checks_the_activation_of_apps_watched_after_retrying() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        reactor.handle_events(apps.make_app(1, make_windows(1)));
+        _ = apps.requests();
+
+        reactor.handle_event(Event::ApplicationObservable(1));
+        let requests = apps.requests();
+        assert!(
+            requests.iter().any(|request| matches!(request, Request::GetActivationState)),
+            "{requests:?}"
+        );
+    }
+
+    #[test]
+    fn it_does_not_move_windows_while_paused() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
+            vec![Some(SpaceId::new(1))],
+        ));
+
+        reactor.handle_event(Event::Command(Command::PauseTiling));
+        reactor.handle_events(apps.make_app(1, make_windows(2)));
+        assert!(apps.requests().is_empty());
+
+        reactor.handle_event(Event::Command(Command::ResumeTiling));
+        let (_events, state) = simulate_events_for_requests(apps.requests());
+        assert_eq!(state.len(), 2);
+
+        reactor.handle_event(Event::Command(Command::PauseApp("com.testapp2".into())));
+        reactor.handle_events(apps.make_app(2, make_windows(1)));
+        let (_events, state) = simulate_events_for_requests(apps.requests());
+        assert!(state.keys().all(|wid| wid.pid == 1), "{state:?}");
+
+        reactor.handle_event(Event::Command(Command::ResumeApp("com.testapp2".into())));
+        let (_events, state) = simulate_events_for_requests(apps.requests());
+        assert!(state.contains_key(&WindowId::new(2, 1)), "{state:?}");
+    }
+
+    #[test]
+    fn it_pauses_while_accessibility_access_is_lost() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
+            vec![Some(SpaceId::new(1))],
+        ));
+
+        reactor.handle_event(Event::AccessibilityChanged(false));
+        reactor.handle_events(apps.make_app(1, make_windows(2)));
+        assert!(apps.requests().is_empty());
+
+        reactor.handle_event(Event::AccessibilityChanged(true));
+        let requests = apps.requests();
+        assert!(requests.iter().any(|rq| matches!(rq, Request::Rewatch)));
+        let (_events, state) = simulate_events_for_requests(requests);
+        assert_eq!(state.len(), 2);
+
+        // Hearing that access is still there changes nothing.
+        reactor.handle_event(Event::AccessibilityChanged(true));
+        assert!(apps.requests().is_empty());
+    }
+
+    #[test]
+    fn it_handles_windows_destroyed_during_animation() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+
+        sim.app("Terminal").window("Shell").created();
+        // The windows start moving, but Shell closes before the apps report
+        // their new frames.
+        sim.respond();
+        sim.window("Shell").destroyed();
+        sim.deliver();
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+    }
+
+    #[test]
+    fn it_handles_windows_destroyed_during_raise() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").activated(Some("Doc1"));
+        sim.settle();
+
+        let doc2 = sim.wid("Doc2");
+        sim.reactor.raise_window(doc2);
+        // The app activates Doc2, but it closes before we hear about it.
+        sim.respond();
+        sim.window("Doc2").destroyed();
+        sim.deliver();
+        sim.settle();
+        assert_eq!(sim.reactor.window_order, vec![sim.wid("Doc1")]);
+        assert_frames!(sim, "Doc1" => rect(0, 0, 1200, 1000));
+    }
+
+    #[test]
+    fn it_moves_windows_back_after_resuming() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+
+        sim.command(Command::PauseTiling);
+        sim.window("Shell").moved_to(rect(600, 100, 600, 1000));
+        sim.settle();
+        assert_frames!(sim, "Shell" => rect(600, 100, 600, 1000));
+
+        sim.command(Command::ResumeTiling);
+        sim.settle();
+        assert_frames!(sim, "Shell" => rect(600, 0, 600, 1000));
+
+        sim.app("Terminal").terminated();
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 1200, 1000));
+        assert_eq!(Sim::space(), sim.reactor.main_screen_space().unwrap());
+    }
+
+    #[test]
+    fn it_ignores_events_from_terminated_apps() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        sim.app("Terminal").terminated();
+        sim.settle();
+
+        // Events sent before the app exited can arrive after we notice.
+        let pid = sim.wid("Shell").pid;
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.event(Event::ApplicationMainWindowChanged(pid, None));
+        sim.event(Event::ApplicationDeactivated(pid));
+        sim.app("Terminal").window("Shell2").created();
+        sim.event(Event::WindowsDiscovered {
+            pid,
+            new: vec![(WindowId::new(pid, 3), make_window(3))],
+            known_visible: vec![],
+        });
+        sim.settle();
+        assert!(sim.reactor.windows.keys().all(|wid| wid.pid != pid));
+        assert_frames!(sim, "Doc1" => rect(0, 0, 1200, 1000));
+    }
+
+    #[test]
+    fn it_moves_windows_between_displays() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").activated(Some("Doc2"));
+        sim.settle();
+        // Add a display to the left of the main display, which makes it
+        // display 1.
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000), rect(-1000, 100, 1000, 800)],
+            vec![Some(Sim::space()), None],
+        ));
+        sim.settle();
+
+        sim.command(Command::MoveWindowToDisplay(1));
+        sim.settle();
+        // Doc2 is over the same part of the new display as it was of the old,
+        // and keeps its shape.
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Doc2" => rect(-490, 100, 480, 800),
+        );
+
+        sim.command(Command::MoveWindowToNextDisplay);
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 600, 1000),
+            "Doc2" => rect(600, 0, 600, 1000),
+        );
+    }
+
+    #[test]
+    fn it_moves_floating_windows_off_removed_displays() {
+        let mut sim = Sim::new();
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000), rect(1200, 0, 600, 500)],
+            vec![Some(Sim::space()), None],
+        ));
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").activated(Some("Doc2"));
+        sim.command(Command::ToggleFloat);
+        sim.settle();
+        sim.window("Doc2").moved_to(rect(1300, 100, 300, 200));
+        sim.settle();
+
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000)],
+            vec![Some(Sim::space())],
+        ));
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Doc2" => rect(200, 200, 600, 400),
+        );
+    }
+
+    #[test]
+    fn it_keeps_floating_windows_on_displays_that_move() {
+        let mut sim = Sim::new();
+        let settings = |displays: &[&str]| {
+            Event::ScreenSettingsChanged(
+                displays
+                    .iter()
+                    .map(|display| ScreenSettings {
+                        display: Some(DisplayUuid::new(display)),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+        };
+        sim.event(settings(&["main", "side"]));
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000), rect(1200, 0, 600, 500)],
+            vec![Some(Sim::space()), None],
+        ));
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").activated(Some("Doc2"));
+        sim.command(Command::ToggleFloat);
+        sim.settle();
+        sim.window("Doc2").moved_to(rect(1300, 100, 300, 200));
+        sim.settle();
+
+        // The side display moves to the left of the main one and gets taller.
+        sim.event(settings(&["main", "side"]));
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000), rect(-600, 0, 600, 1000)],
+            vec![Some(Sim::space()), None],
+        ));
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Doc2" => rect(-500, 300, 300, 200),
+        );
+    }
+
+    #[test]
+    fn it_rescues_windows_from_off_screen() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Notes").window("Note").created();
+        sim.app("Notes").activated(Some("Note"));
+        sim.command(Command::ToggleFloat);
+        sim.settle();
+        sim.window("Note").moved_to(rect(3000, 200, 400, 300));
+        sim.settle();
+
+        sim.command(Command::RescueOffscreenWindows);
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Note" => rect(800, 200, 400, 300),
+        );
+        assert_eq!(
+            Err(CommandError::NoOffscreenWindows),
+            sim.command_result(Command::RescueOffscreenWindows)
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_frames_it_gives_windows_on_screen() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Notes").window("Note").created();
+        sim.app("Notes").activated(Some("Note"));
+        sim.command(Command::ToggleFloat);
+        sim.settle();
+        sim.window("Note").moved_to(rect(3000, 200, 400, 300));
+        sim.settle();
+
+        sim.command(Command::HideWindow);
+        sim.settle();
+        sim.command(Command::ShowHiddenWindows);
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Note" => rect(800, 200, 400, 300),
+        );
+    }
+
+    #[test]
+    fn it_rejects_space_zero() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").activated(Some("Doc1"));
+        sim.settle();
+        assert_eq!(
+            Err(CommandError::NoSuchSpace(0)),
+            sim.command_result(Command::FollowWindowToSpace(0))
+        );
+        assert_eq!(
+            Err(CommandError::NoSuchSpace(0)),
+            sim.command_result(Command::MoveWindowToSpace(0))
+        );
+    }
+
+    #[test]
+    fn it_hides_windows_until_shown_again() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Notes").window("Note").created();
+        sim.app("Notes").activated(Some("Note"));
+        sim.command(Command::ToggleFloat);
+        sim.settle();
+        sim.window("Note").moved_to(rect(100, 100, 400, 300));
+        sim.settle();
+
+        sim.command(Command::HideWindow);
+        sim.app("Safari").activated(Some("Doc2"));
+        sim.command(Command::HideWindow);
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Doc2" => rect(5000, 5000, 600, 1000),
+            "Note" => rect(5000, 5000, 400, 300),
+        );
+
+        // Apps reporting their windows don't bring them back.
+        sim.event(Event::WindowsDiscovered {
+            pid: sim.wid("Doc2").pid,
+            new: vec![],
+            known_visible: vec![sim.wid("Doc1"), sim.wid("Doc2")],
+        });
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 1200, 1000));
+
+        sim.command(Command::ShowHiddenWindows);
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 600, 1000),
+            "Doc2" => rect(600, 0, 600, 1000),
+            "Note" => rect(100, 100, 400, 300),
+        );
+        assert_eq!(
+            Err(CommandError::NoHiddenWindows),
+            sim.command_result(Command::ShowHiddenWindows)
+        );
+    }
+
+    #[test]
+    fn it_applies_screen_settings() {
+        let mut sim = Sim::new();
+        sim.event(Event::ScreenSettingsChanged(vec![ScreenSettings {
+            gaps: 10.0,
+            default_layout: LayoutKind::Vertical,
+            display: None,
+        }]));
+        // The default layout only applies to spaces we haven't seen before.
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000)],
+            vec![Some(SpaceId::new(2))],
+        ));
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(10, 10, 1180, 485),
+            "Doc2" => rect(10, 505, 1180, 485),
+        );
+    }
+
+    #[test]
+    fn it_centers_dialogs_over_their_parent() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        sim.app("Safari").window("Save").created_as_dialog_of("Doc1");
+        sim.settle();
+        assert_frames!(sim, "Save" => rect(275, 475, 50, 50));
+
+        // The user can move the dialog somewhere else.
+        sim.window("Save").moved_to(rect(100, 100, 50, 50));
+        sim.settle();
+        assert_frames!(sim, "Save" => rect(100, 100, 50, 50));
+
+        // It follows its parent when the parent's tile moves.
+        sim.window("Shell").destroyed();
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Save" => rect(575, 475, 50, 50),
+        );
+    }
+
+    #[test]
+    fn it_keeps_pip_windows_out_of_the_layout() {
+        let mut sim = Sim::new();
+        sim.reactor.pip = PipConfig {
+            corner: Some(Corner::BottomRight),
+            margin: 10.0,
+        };
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Video").created_as_pip();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 600, 1000),
+            "Shell" => rect(600, 0, 600, 1000),
+            "Video" => rect(1140, 940, 50, 50),
+        );
+
+        // Raising another window keeps the video in front.
+        let video = sim.wid("Video");
+        let shell = sim.wid("Shell");
+        sim.mock().ordered_front.clear();
+        sim.reactor.raise_window(shell);
+        sim.settle();
+        assert_eq!(vec![video], sim.mock().ordered_front);
+    }
+
+    #[test]
+    fn it_reports_commands_that_do_nothing() {
+        use crate::model::Direction::*;
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Safari").activated(Some("Doc1"));
+        sim.settle();
+
+        let move_focus = |direction| Command::Layout(LayoutCommand::MoveFocus(direction));
+        assert_eq!(
+            Err(CommandError::NoWindowInDirection(Left)),
+            sim.command_result(move_focus(Left))
+        );
+        assert_eq!(Ok(()), sim.command_result(move_focus(Right)));
+        assert_eq!(
+            Err(CommandError::NoSuchDisplay),
+            sim.command_result(Command::FocusDisplay(2))
+        );
+    }
+
+    #[test]
+    fn it_repeats_commands_up_to_a_count() {
+        use crate::model::Direction::*;
+        let mut sim = Sim::new();
+        sim.app("A").window("One").created();
+        sim.app("B").window("Two").created();
+        sim.app("C").window("Three").created();
+        sim.app("A").activated(Some("One"));
+        sim.settle();
+
+        let move_focus = |count, direction| {
+            Command::Repeat(
+                count,
+                Box::new(Command::Layout(LayoutCommand::MoveFocus(direction))),
+            )
+        };
+        assert_eq!(Ok(()), sim.command_result(move_focus(2, Right)));
+        sim.settle();
+        assert_eq!(Some(sim.wid("Three")), sim.reactor.main_window());
+
+        // Stops at the edge of the layout.
+        assert_eq!(Ok(()), sim.command_result(move_focus(5, Left)));
+        sim.settle();
+        assert_eq!(Some(sim.wid("One")), sim.reactor.main_window());
+        assert_eq!(
+            Err(CommandError::NoWindowInDirection(Left)),
+            sim.command_result(move_focus(2, Left))
+        );
+    }
+
+    #[test]
+    fn it_moves_focus_from_the_new_main_window_of_an_app() {
+        use crate::model::Direction::*;
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").window("Doc3").created();
+        sim.app("Safari").activated(Some("Doc1"));
+        sim.settle();
+
+        // Cycling windows with Cmd+` changes the main window without
+        // activating the app again.
+        sim.app("Safari").main_window_changed("Doc3");
+        sim.command(Command::Layout(LayoutCommand::MoveFocus(Left)));
+        sim.settle();
+        assert_eq!(Some(sim.wid("Doc2")), sim.reactor.main_window());
+
+        // A window the app never told us about is registered when it becomes
+        // the main window.
+        sim.app("Safari").main_window_changed("Doc4");
+        sim.settle();
+        assert_eq!(Some(sim.wid("Doc4")), sim.reactor.main_window());
+        sim.command(Command::Layout(LayoutCommand::MoveFocus(Left)));
+        sim.settle();
+        assert_eq!(Some(sim.wid("Doc3")), sim.reactor.main_window());
+    }
+
+    #[test]
+    fn it_stops_a_batch_at_the_first_failed_command() {
+        use crate::model::Direction::*;
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Safari").activated(Some("Doc1"));
+        sim.settle();
+
+        let (tx, rx) = sync::mpsc::channel();
+        sim.event(Event::CommandBatch(
+            vec![
+                Command::PauseTiling,
+                Command::Layout(LayoutCommand::MoveFocus(Left)),
+                Command::ResumeTiling,
+            ],
+            tx,
+        ));
+        assert_eq!(
+            vec![Ok(()), Err(CommandError::NoWindowInDirection(Left))],
+            rx.recv().unwrap()
+        );
+        assert!(sim.reactor.tiling_paused);
+    }
+
+    #[test]
+    fn it_describes_the_focused_window() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        let (tx, rx) = sync::mpsc::channel();
+        sim.event(Event::Query(Query::Focused, tx.clone()));
+        assert!(matches!(rx.recv().unwrap(), QueryResponse::Focused(None)));
+
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.settle();
+        sim.event(Event::Query(Query::Focused, tx));
+        let QueryResponse::Focused(Some(focused)) = rx.recv().unwrap() else {
+            panic!("Expected a focused window");
+        };
+        assert_eq!(sim.wid("Shell"), focused.id);
+        assert_eq!(Some("com.testapp2"), focused.bundle_id.as_deref());
+        assert_eq!("Shell", focused.title);
+        assert_eq!(rect(600, 0, 600, 1000), focused.frame);
+        assert_eq!(Some(Sim::space()), focused.space);
+        assert!(!focused.is_floating);
+    }
+
+    #[test]
+    fn it_lists_apps() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        let (tx, rx) = sync::mpsc::channel();
+        sim.event(Event::Query(Query::Apps, tx));
+        let QueryResponse::Apps(apps) = rx.recv().unwrap() else {
+            panic!("Expected apps");
+        };
+        let summary: Vec<_> = apps
+            .iter()
+            .map(|app| (app.bundle_id.as_deref(), app.name.as_deref(), app.windows))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some("com.testapp1"), Some("TestApp1"), 2),
+                (Some("com.testapp2"), Some("TestApp2"), 1)
+            ],
+            summary
+        );
+        // The fake apps in tests have no threads.
+        assert!(apps.iter().all(|app| app.cpu_seconds.is_none()));
+    }
+
+    #[test]
+    fn it_finds_windows_by_title_or_app() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        assert_eq!(Some(sim.wid("Doc2")), sim.reactor.find_window("doc2"));
+        assert_eq!(Some(sim.wid("Shell")), sim.reactor.find_window("TESTAPP2"));
+        assert_eq!(
+            Some(sim.wid("Shell")),
+            sim.reactor.find_window("com.testapp2")
+        );
+        assert_eq!(
+            Err(CommandError::NoMatchingWindow("Spotify".into())),
+            sim.command_result(Command::PullWindow("Spotify".into()))
+        );
+
+        sim.event(Event::WindowTitleChanged(sim.wid("Doc2"), "Inbox".into()));
+        assert_eq!(Some(sim.wid("Doc2")), sim.reactor.find_window("inbox"));
+        assert_eq!(None, sim.reactor.find_window("doc2"));
+    }
+
+    #[test]
+    fn it_pulls_windows_to_the_display_with_the_focused_window() {
+        let mut sim = Sim::new();
+        let other_space = SpaceId::new(2);
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000), rect(1200, 0, 1000, 800)],
+            vec![Some(Sim::space()), Some(other_space)],
+        ));
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").activated(Some("Doc1"));
+        sim.settle();
+        assert_eq!(Some(Sim::space()), sim.reactor.focused_space());
+
+        let doc1 = sim.wid("Doc1");
+        sim.reactor.windows.get_mut(&doc1).unwrap().frame_monotonic = rect(1300, 100, 400, 300);
+        assert_eq!(Some(other_space), sim.reactor.focused_space());
+    }
+
+    #[test]
+    fn it_cycles_through_the_windows_of_the_focused_app() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").window("Doc3").created();
+        sim.app("Safari").activated(Some("Doc1"));
+        sim.settle();
+
+        sim.command(Command::FocusNextWindowOfApp);
+        sim.settle();
+        assert_eq!(Some(sim.wid("Doc2")), sim.reactor.main_window());
+
+        // Wraps around, skipping the other app's windows.
+        sim.command(Command::FocusPrevWindowOfApp);
+        sim.settle();
+        sim.command(Command::FocusPrevWindowOfApp);
+        sim.settle();
+        assert_eq!(Some(sim.wid("Doc3")), sim.reactor.main_window());
+    }
+
+    #[test]
+    fn it_cycles_through_recently_used_windows() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Notes").window("Note").created();
+        for (app, window) in [("Safari", "Doc1"), ("Terminal", "Shell"), ("Notes", "Note")] {
+            sim.app(app).activated(Some(window));
+        }
+        sim.settle();
+
+        let cycle = |sim: &mut Sim, cmd: Command| {
+            assert_eq!(Ok(()), sim.command_result(cmd));
+            sim.settle();
+            sim.reactor.main_window()
+        };
+        // Windows raised along the way don't change the order.
+        assert_eq!(
+            Some(sim.wid("Shell")),
+            cycle(&mut sim, Command::CycleRecentWindows)
+        );
+        assert_eq!(
+            Some(sim.wid("Doc1")),
+            cycle(&mut sim, Command::CycleRecentWindows)
+        );
+        sim.command(Command::EndRecentCycle);
+
+        // The window the cycle ended on is now the most recently used.
+        assert_eq!(
+            Some(sim.wid("Note")),
+            cycle(&mut sim, Command::CycleRecentWindows)
+        );
+        sim.command(Command::EndRecentCycle);
+        assert_eq!(
+            Some(sim.wid("Shell")),
+            cycle(&mut sim, Command::CycleRecentWindowsBack)
+        );
+        assert_eq!(
+            Some(sim.wid("Doc1")),
+            cycle(&mut sim, Command::CycleRecentWindowsBack)
+        );
+        sim.command(Command::EndRecentCycle);
+        assert_eq!(
+            vec![sim.wid("Doc1"), sim.wid("Note"), sim.wid("Shell")],
+            sim.reactor.recent_windows
+        );
+    }
+
+    #[test]
+    fn it_sets_window_opacity_and_shadow() {
+        let mut sim = Sim::new();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.settle();
+        let shell = sim.wid("Shell");
+
+        sim.command(Command::SetOpacity(0.8));
+        sim.command(Command::ToggleShadow);
+        sim.settle();
+        let expected = WindowAppearance {
+            opacity: 0.8,
+            has_shadow: false,
+        };
+        assert_eq!(Some(&expected), sim.mock().appearances.get(&shell));
+
+        // The opacity is kept when the shadow is turned back on.
+        sim.command(Command::ToggleShadow);
+        sim.command(Command::SetOpacity(0.0));
+        sim.settle();
+        let QueryResponse::Windows(windows) = sim.reactor.handle_query(Query::Windows) else {
+            panic!("Expected windows");
+        };
+        let window = windows.iter().find(|window| window.id == shell).unwrap();
+        assert_eq!((0.1, true), (window.opacity, window.has_shadow));
+
+        // Nothing changes when the window server won't change the window.
+        sim.mock().refuses_appearance = true;
+        assert_eq!(
+            Err(CommandError::AppearanceRefused),
+            sim.command_result(Command::ToggleShadow)
+        );
+        let QueryResponse::Windows(windows) = sim.reactor.handle_query(Query::Windows) else {
+            panic!("Expected windows");
+        };
+        let window = windows.iter().find(|window| window.id == shell).unwrap();
+        assert!(window.has_shadow);
+    }
+
+    #[test]
+    fn it_keeps_pinned_windows_in_place() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").activated(Some("Doc2"));
+        sim.command(Command::PinWindow(Pin {
+            corner: Corner::TopRight,
+            width: 400.0,
+            height: 300.0,
+            margin: 0.0,
+        }));
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 1200, 1000),
+            "Doc2" => rect(800, 0, 400, 300),
+        );
+
+        // It goes back when moved.
+        sim.window("Doc2").moved_to(rect(100, 100, 400, 300));
+        sim.settle();
+        assert_frames!(sim, "Doc2" => rect(800, 0, 400, 300));
+
+        // And stays in the corner when the screen changes.
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1000, 800)],
+            vec![Some(Sim::space())],
+        ));
+        sim.settle();
+        assert_frames!(sim, "Doc2" => rect(600, 0, 400, 300));
+
+        sim.command(Command::UnpinWindow);
+        sim.window("Doc2").moved_to(rect(100, 100, 400, 300));
+        sim.settle();
+        assert_frames!(sim, "Doc2" => rect(100, 100, 400, 300));
+        assert_eq!(
+            Err(CommandError::WindowNotPinned),
+            sim.command_result(Command::UnpinWindow)
+        );
+
+        let pin = Pin {
+            corner: Corner::TopRight,
+            width: 400.0,
+            height: 300.0,
+            margin: 0.0,
+        };
+        for pin in [
+            Pin { width: 0.0, ..pin },
+            Pin { height: -300.0, ..pin },
+            Pin { margin: -10.0, ..pin },
+        ] {
+            assert_eq!(
+                Err(CommandError::InvalidPin),
+                sim.command_result(Command::PinWindow(pin))
+            );
+        }
+        assert_eq!(None, sim.reactor.windows[&sim.wid("Doc2")].pin);
+    }
+
+    #[test]
+    fn it_reopens_closed_windows_in_their_place() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").window("Doc3").created();
+        sim.settle();
+        sim.window("Doc2").destroyed();
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc3" => rect(600, 0, 600, 1000));
+
+        assert_eq!(Ok(()), sim.command_result(Command::ReopenClosedWindow));
+        sim.settle();
+        assert_eq!(vec!["Doc2".to_string()], sim.mock().reopened);
+        sim.app("Safari").window("Doc2 again").created();
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 400, 1000),
+            "Doc2 again" => rect(400, 0, 400, 1000),
+            "Doc3" => rect(800, 0, 400, 1000),
+        );
+
+        assert_eq!(
+            Err(CommandError::NoClosedWindow),
+            sim.command_result(Command::ReopenClosedWindow)
+        );
+    }
+
+    #[test]
+    fn it_keeps_closed_windows_whose_app_cant_be_found() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.settle();
+        sim.window("Doc1").destroyed();
+        sim.app("Safari").terminated();
+        sim.settle();
+        sim.reactor.closed_windows[0].bundle_path = None;
+
+        assert_eq!(
+            Err(CommandError::AppNotFound("com.testapp1".into())),
+            sim.command_result(Command::ReopenClosedWindow)
+        );
+        assert_eq!(1, sim.reactor.closed_windows.len());
+        assert!(sim.reactor.reopening.is_none());
+    }
+
+    #[test]
+    fn it_treats_helper_processes_as_part_of_their_app() {
+        let mut sim = Sim::new();
+        sim.app("Launcher").window("Main").created();
+        sim.app("Helper").window("Editor").created();
+        sim.settle();
+        let (launcher, helper) = (sim.wid("Main").pid, sim.wid("Editor").pid);
+        sim.reactor.apps.get_mut(&helper).unwrap().info.group = Some("com.testapp1".into());
+        assert!(sim.reactor.is_same_app(launcher, helper));
+        assert_eq!(
+            Some("TestApp1"),
+            sim.reactor.display_app(helper).and_then(|app| app.localized_name.as_deref())
+        );
+        sim.command(Command::PauseApp("com.testapp1".into()));
+        assert!(sim.reactor.is_paused(helper));
+    }
+
+    #[test]
+    fn it_sends_space_changes_to_subscribers() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.settle();
+        let (tx, rx) = sync::mpsc::channel();
+        sim.event(Event::SubscribeSpaces(tx));
+        let summary = |spaces: Vec<SpaceData>| -> Vec<_> {
+            spaces.iter().map(|space| (space.id, space.windows, space.is_focused)).collect()
+        };
+        assert_eq!(vec![(Sim::space(), 1, true)], summary(rx.recv().unwrap()));
+
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        let updates: Vec<_> = rx.try_iter().map(summary).collect();
+        assert_eq!(vec![vec![(Sim::space(), 2, true)]], updates);
+
+        // Nothing is sent when the spaces stay the same.
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.settle();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn it_updates_spaces_when_desktops_change() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.settle();
+        let desktop = |id| SpaceInfo {
+            id: SpaceId::new(id),
+            uuid: SpaceUuid::new(&format!("desktop{id}")),
+            display: DisplayUuid::new("main"),
+            is_user_space: true,
+        };
+        let spaces = |sim: &mut Sim| -> Vec<_> {
+            let QueryResponse::Spaces(spaces) = sim.reactor.handle_query(Query::Spaces) else {
+                panic!("Expected spaces");
+            };
+            spaces
+                .iter()
+                .map(|space| (space.id, space.number, space.layout.is_some()))
+                .collect()
+        };
+        let (space1, space2, space3) = (Sim::space(), SpaceId::new(2), SpaceId::new(3));
+
+        // A new desktop is listed before we lay it out.
+        sim.event(Event::SpacesChanged(vec![desktop(1), desktop(2)]));
+        sim.settle();
+        assert_eq!(
+            vec![(space1, Some(1), true), (space2, Some(2), false)],
+            spaces(&mut sim)
+        );
+
+        sim.event(Event::SpaceChanged(vec![Some(space2)]));
+        sim.event(Event::SpaceChanged(vec![Some(space1)]));
+        sim.settle();
+        assert!(sim.reactor.visited_spaces.contains(&space2));
+
+        // Removing it forgets its layout, and adding one before the first
+        // renumbers it.
+        sim.event(Event::SpacesChanged(vec![desktop(3), desktop(1)]));
+        sim.settle();
+        assert_eq!(
+            vec![(space3, Some(1), false), (space1, Some(2), true)],
+            spaces(&mut sim)
+        );
+        assert!(!sim.reactor.visited_spaces.contains(&space2));
+    }
+
+    #[test]
+    fn it_sends_stack_changes_to_subscribers() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.settle();
+        let (tx, rx) = sync::mpsc::channel();
+        sim.event(Event::SubscribeStacks(tx));
+        assert_eq!(Vec::<StackData>::new(), rx.recv().unwrap());
+
+        sim.command(Command::Layout(LayoutCommand::Group(Orientation::Vertical)));
+        sim.settle();
+        let stacks = rx.try_recv().unwrap();
+        assert_eq!(1, stacks.len());
+        let stack = &stacks[0];
+        assert_eq!(
+            (Sim::space(), LayoutKind::Stacked, rect(0, 0, 1200, 1000)),
+            (stack.space, stack.kind, stack.frame)
+        );
+        let titles: Vec<_> = stack.windows.iter().map(|window| window.title.as_str()).collect();
+        assert_eq!(vec!["Doc1", "Shell"], titles);
+        assert_eq!(Some("TestApp2"), stack.windows[1].app_name.as_deref());
+        assert_eq!(1, stack.selected);
+
+        sim.command(Command::Layout(LayoutCommand::MoveFocus(Direction::Up)));
+        sim.settle();
+        let updates: Vec<_> = rx.try_iter().map(|stacks| stacks[0].selected).collect();
+        assert_eq!(vec![0], updates);
+    }
+
+    #[test]
+    fn it_raises_windows_in_a_stack_without_activating_them() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.settle();
+
+        // Side by side, moving focus to another app activates it.
+        sim.command(Command::Layout(LayoutCommand::MoveFocus(Direction::Left)));
+        sim.settle();
+        assert!(sim.mock().raised_only.is_empty());
+
+        sim.command(Command::Layout(LayoutCommand::Group(Orientation::Vertical)));
+        sim.settle();
+        sim.command(Command::Layout(LayoutCommand::MoveFocus(Direction::Down)));
+        sim.settle();
+        assert_eq!(vec![sim.wid("Shell")], sim.mock().raised_only);
+    }
+
+    #[test]
+    fn it_times_apps_until_they_come_to_the_front() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.settle();
+
+        sim.command(Command::Layout(LayoutCommand::MoveFocus(Direction::Left)));
+        let safari = sim.wid("Doc1").pid;
+        let activating = |sim: &Sim| sim.reactor.activating.map(|(pid, _)| pid);
+        assert_eq!(Some(safari), activating(&sim));
+
+        // Another app coming to the front doesn't end the wait.
+        sim.app("Terminal").activated(Some("Shell"));
+        assert_eq!(Some(safari), activating(&sim));
+
+        sim.settle();
+        assert_eq!(None, activating(&sim));
+    }
+
+    #[test]
+    fn it_tracks_windows_asking_for_attention() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.app("Terminal").activated(Some("Shell"));
+        sim.settle();
+        assert_eq!(
+            Err(CommandError::NoUrgentWindow),
+            sim.command_result(Command::FocusUrgent)
+        );
+
+        let safari = sim.wid("Doc1").pid;
+        let terminal = sim.wid("Shell").pid;
+        sim.event(Event::ApplicationRequestedAttention(safari));
+        sim.event(Event::ApplicationRequestedAttention(terminal));
+        assert_eq!(vec![sim.wid("Doc1")], sim.reactor.urgent);
+
+        sim.app("Safari").window("Save").created_as_dialog_of("Doc1");
+        assert_eq!(vec![sim.wid("Doc1"), sim.wid("Save")], sim.reactor.urgent);
+
+        assert_eq!(Ok(()), sim.command_result(Command::FocusUrgent));
+        sim.settle();
+        assert_eq!(Some(safari), sim.reactor.global_frontmost_app_pid);
+        assert_eq!(Some(sim.wid("Save")), sim.reactor.main_window());
+        assert!(sim.reactor.urgent.is_empty());
+    }
+
+    #[test]
+    fn it_applies_focus_mode_animations() {
+        let mut sim = Sim::new();
+        sim.reactor.focus_mode.animations = Some(AnimationMode::Disabled);
+        sim.event(Event::FocusModeChanged(true));
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+        assert!(sim.mock().animated.is_empty());
+
+        sim.event(Event::FocusModeChanged(false));
+        sim.app("Safari").window("Doc3").created();
+        sim.settle();
+        assert!(!sim.mock().animated.is_empty());
+    }
+
+    #[test]
+    fn it_forces_a_relayout() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.settle();
+
+        // The app resizes a window without sending a notification.
+        let wid = sim.wid("Doc1");
+        sim.mock().windows.get_mut(&wid).unwrap().frame = rect(100, 100, 300, 300);
+        sim.mock().animated.clear();
+        for _ in 0..2 {
+            sim.command(Command::ForceRelayout);
+            sim.settle();
+            assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+        }
+        assert!(sim.mock().animated.is_empty());
+    }
+
+    #[test]
+    fn it_keeps_room_in_the_channel_for_events_that_cannot_be_dropped() {
+        let (tx, rx) = channel();
+        let event = || (Span::none(), Event::SplitDragEnded);
+        let mut sent = 0;
+        while tx.try_send(event()).is_ok() {
+            sent += 1;
+        }
+        assert_eq!(TRY_SEND_LIMIT, sent);
+
+        // Sending fills the rest of the channel without waiting.
+        for _ in TRY_SEND_LIMIT..CHANNEL_CAPACITY {
+            tx.send(event()).unwrap();
+        }
+        assert!(matches!(
+            tx.tx.try_send(event()),
+            Err(TrySendError::Full(_))
+        ));
+
+        // Trying again only works once the reactor is below the limit.
+        rx.recv(Some(Duration::ZERO)).unwrap();
+        assert!(tx.try_send(event()).is_err());
+        for _ in TRY_SEND_LIMIT..CHANNEL_CAPACITY {
+            rx.recv(Some(Duration::ZERO)).unwrap();
+        }
+        assert!(tx.try_send(event()).is_ok());
+    }
+
+    #[test]
+    fn it_stops_fighting_over_windows() {
+        let mut sim = Sim::new();
+        let (wm_tx, mut wm_rx) = tokio::sync::mpsc::unbounded_channel();
+        sim.event(Event::WmControllerStarted(wm_tx.downgrade()));
+        sim.app("Safari").window("Doc1").created();
+        sim.settle();
+        let tiled = rect(0, 0, 1200, 1000);
+        let original = rect(100, 100, 50, 50);
+        assert_frames!(sim, "Doc1" => tiled);
+
+        // Something moves the window back every time we move it.
+        for _ in 0..MAX_REVERTS - 1 {
+            sim.window("Doc1").moved_to(original);
+            sim.settle();
+            assert_frames!(sim, "Doc1" => tiled);
+        }
+        assert!(wm_rx.try_recv().is_err());
+        sim.window("Doc1").moved_to(original);
+        sim.settle();
+        assert_frames!(sim, "Doc1" => original);
+        // The user is told why the window stopped being arranged.
+        assert!(matches!(wm_rx.try_recv(), Ok((_, WmEvent::ShowNotice(_)))));
+
+        sim.command(Command::ForceRelayout);
+        sim.settle();
+        assert_frames!(sim, "Doc1" => tiled);
+    }
+
+    #[test]
+    fn it_previews_the_layout_without_moving_windows() {
+        let mut sim = Sim::new();
+        let (borders_tx, mut borders_rx) = borders::channel();
+        sim.reactor.borders = Some(borders_tx);
+        sim.command(Command::ToggleLayoutPreview);
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        assert!(sim.mock().windows.is_empty());
+
+        let mut preview = None;
+        while let Ok((_, event)) = borders_rx.try_recv() {
+            if let borders::Event::PreviewChanged(tiles) = event {
+                preview = Some(tiles);
+            }
+        }
+        let tiles: Vec<_> =
+            preview.unwrap().into_iter().map(|tile| (tile.title, tile.frame)).collect();
         assert_eq!(
-            reactor.layout.selected_window(space),
-            Some(WindowId::new(2, 2))
+            vec![
+                ("Doc1".to_string(), rect(0, 0, 600, 1000)),
+                ("Shell".to_string(), rect(600, 0, 600, 1000)),
+            ],
+            tiles
         );
-        reactor.handle_event(ApplicationMainWindowChanged(1, Some(WindowId::new(1, 2))));
-        assert_eq!(Some(WindowId::new(2, 2)), reactor.main_window());
-        reactor.handle_event(ApplicationDeactivated(1));
-        assert_eq!(Some(WindowId::new(2, 2)), reactor.main_window());
-        reactor.handle_event(ApplicationDeactivated(2));
-        assert_eq!(None, reactor.main_window());
 
-        reactor.handle_event(ApplicationGloballyActivated(3));
-        assert_eq!(None, reactor.main_window());
+        sim.command(Command::ToggleLayoutPreview);
+        sim.settle();
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 600, 1000),
+            "Shell" => rect(600, 0, 600, 1000),
+        );
+        let mut preview = None;
+        while let Ok((_, event)) = borders_rx.try_recv() {
+            if let borders::Event::PreviewChanged(tiles) = event {
+                preview = Some(tiles);
+            }
+        }
+        assert_eq!(Some(vec![]), preview);
+    }
 
-        reactor.handle_events(apps.make_app_with_opts(
-            3,
-            make_windows(2),
-            Some(WindowId::new(3, 1)),
-            true,
-        ));
-        assert_eq!(Some(WindowId::new(3, 1)), reactor.main_window());
+    #[test]
+    fn it_sends_tiled_windows_to_borders() {
+        let mut sim = Sim::new();
+        let (borders_tx, mut borders_rx) = borders::channel();
+        sim.reactor.borders = Some(borders_tx);
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.app("Safari").activated(Some("Doc2"));
+        sim.settle();
+
+        let mut windows = vec![];
+        while let Ok((_, event)) = borders_rx.try_recv() {
+            if let borders::Event::WindowsChanged(latest) = event {
+                windows = latest;
+            }
+        }
+        let windows: Vec<_> = windows
+            .into_iter()
+            .map(|window| (window.frame, window.is_focused, window.is_floating))
+            .collect();
         assert_eq!(
-            reactor.layout.selected_window(space),
-            Some(WindowId::new(3, 1))
+            vec![
+                (rect(0, 0, 600, 1000), false, false),
+                (rect(600, 0, 600, 1000), true, false),
+            ],
+            windows
         );
-    }
 
-    #[derive(Default, PartialEq, Debug)]
-    struct WindowState {
-        last_seen_txid: TransactionId,
-        animating: bool,
-        frame: CGRect,
+        let doc1 = sim.wid("Doc1");
+        sim.event(Event::WindowTitleChanged(doc1, "Renamed".to_string()));
+        let mut titles = vec![];
+        while let Ok((_, event)) = borders_rx.try_recv() {
+            if let borders::Event::WindowsChanged(latest) = event {
+                titles = latest.into_iter().map(|window| window.title.to_string()).collect();
+            }
+        }
+        assert_eq!(vec!["Renamed", "Doc2"], titles);
     }
 
-    fn simulate_events_for_requests(
-        requests: Vec<Request>,
-    ) -> (Vec<Event>, BTreeMap<WindowId, WindowState>) {
-        let mut events = vec![];
-        let mut windows: BTreeMap<WindowId, WindowState> = BTreeMap::new();
+    #[test]
+    fn it_resizes_windows_when_a_split_is_dragged() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
 
-        for request in requests {
-            match request {
-                Request::GetVisibleWindows => {}
-                Request::SetWindowFrame(wid, frame, txid) => {
-                    let window = windows.entry(wid).or_default();
-                    window.last_seen_txid = txid;
-                    let old_frame = window.frame;
-                    window.frame = frame;
-                    if !window.animating && !old_frame.same_as(frame) {
-                        events.push(Event::WindowFrameChanged(wid, frame, txid, Requested(true)));
-                    }
-                }
-                Request::SetWindowPos(wid, pos, txid) => {
-                    let window = windows.entry(wid).or_default();
-                    window.last_seen_txid = txid;
-                    let old_frame = window.frame;
-                    window.frame.origin = pos;
-                    if !window.animating && !old_frame.same_as(window.frame) {
-                        events.push(Event::WindowFrameChanged(
-                            wid,
-                            window.frame,
-                            txid,
-                            Requested(true),
-                        ));
-                    }
-                }
-                Request::BeginWindowAnimation(wid) => {
-                    windows.entry(wid).or_default().animating = true;
-                }
-                Request::EndWindowAnimation(wid) => {
-                    let window = windows.entry(wid).or_default();
-                    window.animating = false;
-                    events.push(Event::WindowFrameChanged(
-                        wid,
-                        window.frame,
-                        window.last_seen_txid,
-                        Requested(true),
-                    ));
+        let screen = rect(0, 0, 1200, 1000);
+        let splits = sim.reactor.layout.calculate_splits(Sim::space(), screen);
+        let [split] = splits[..] else {
+            panic!("Expected one split: {splits:?}")
+        };
+        assert_eq!(rect(600, 0, 0, 1000), split.frame);
+        for delta in [50.0, 50.0, -40.0] {
+            sim.event(Event::SplitDragged {
+                node: split.node,
+                orientation: split.orientation,
+                delta,
+            });
+            sim.settle();
+        }
+        assert_frames!(
+            sim,
+            "Doc1" => rect(0, 0, 660, 1000),
+            "Shell" => rect(660, 0, 540, 1000),
+        );
+    }
+
+    #[test]
+    fn it_previews_split_drags_until_released() {
+        let mut sim = Sim::new();
+        let (borders_tx, mut borders_rx) = borders::channel();
+        let (split_handles_tx, _split_handles_rx) = split_handles::channel();
+        sim.reactor.borders = Some(borders_tx);
+        sim.reactor.split_handles = Some(split_handles_tx);
+        sim.reactor.preview_resize = true;
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        let mut resize_previews = || {
+            let mut previews = vec![];
+            while let Ok((_, event)) = borders_rx.try_recv() {
+                if let borders::Event::ResizePreviewChanged(frame) = event {
+                    previews.push(frame);
                 }
-                Request::Raise(_, _) => todo!(),
             }
+            previews
+        };
+        resize_previews();
+
+        let [split] = sim.reactor.splits[..] else {
+            panic!("Expected one split: {:?}", sim.reactor.splits)
+        };
+        for delta in [50.0, 50.0, -40.0] {
+            sim.event(Event::SplitDragged {
+                node: split.node,
+                orientation: split.orientation,
+                delta,
+            });
+            sim.settle();
         }
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Shell" => rect(600, 0, 600, 1000));
+        assert_eq!(
+            vec![
+                Some(rect(648, 0, 4, 1000)),
+                Some(rect(698, 0, 4, 1000)),
+                Some(rect(658, 0, 4, 1000)),
+            ],
+            resize_previews()
+        );
 
-        (events, windows)
+        sim.event(Event::SplitDragEnded);
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 660, 1000), "Shell" => rect(660, 0, 540, 1000));
+        assert_eq!(vec![None], resize_previews());
     }
 
     #[test]
     fn it_ignores_stale_resize_events() {
         let mut apps = Apps::new();
-        let mut reactor = Reactor::new(LayoutManager::new());
+        let mut reactor = Reactor::new_for_test();
         reactor.handle_event(Event::ScreenParametersChanged(
             vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
             vec![Some(SpaceId::new(1))],
@@ -631,7 +4735,7 @@ mod tests {
     #[test]
     fn it_sends_writes_when_stale_read_state_looks_same_as_written_state() {
         let mut apps = Apps::new();
-        let mut reactor = Reactor::new(LayoutManager::new());
+        let mut reactor = Reactor::new_for_test();
         reactor.handle_event(Event::ScreenParametersChanged(
             vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
             vec![Some(SpaceId::new(1))],
@@ -665,7 +4769,7 @@ mod tests {
     #[test]
     fn sends_writes_same_as_last_written_state_if_changed_externally() {
         let mut apps = Apps::new();
-        let mut reactor = Reactor::new(LayoutManager::new());
+        let mut reactor = Reactor::new_for_test();
         reactor.handle_event(Event::ScreenParametersChanged(
             vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
             vec![Some(SpaceId::new(1))],
@@ -702,7 +4806,7 @@ mod tests {
     #[test]
     fn it_responds_to_resizes() {
         let mut apps = Apps::new();
-        let mut reactor = Reactor::new(LayoutManager::new());
+        let mut reactor = Reactor::new_for_test();
         reactor.handle_event(Event::ScreenParametersChanged(
             vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
             vec![Some(SpaceId::new(1))],
@@ -742,10 +4846,560 @@ mod tests {
         assert_ne!(old_frame, windows[&next].frame);
     }
 
+    #[test]
+    fn it_restores_hidden_windows_on_next_layout() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
+            vec![Some(SpaceId::new(1))],
+        ));
+
+        reactor.handle_events(apps.make_app(1, make_windows(2)));
+        let (events, windows) = simulate_events_for_requests(apps.requests());
+        for event in events {
+            reactor.handle_event(event);
+        }
+        assert!(apps.requests().is_empty());
+
+        let wid = WindowId::new(1, 1);
+        reactor.hide_window(wid);
+        let mut requests = apps.requests();
+        assert!(matches!(requests.as_slice(), [Request::HideWindow(w)] if *w == wid));
+        for (w, state) in &windows {
+            requests.insert(
+                0,
+                Request::SetWindowFrame(*w, state.frame, state.last_seen_txid),
+            );
+        }
+        let (events, _) = simulate_events_for_requests(requests);
+        for event in events {
+            reactor.handle_event(event);
+        }
+        assert!(
+            apps.requests().is_empty(),
+            "moving a hidden window should not affect the layout"
+        );
+
+        reactor.update_layout(None, false);
+        let (_events, state) = simulate_events_for_requests(apps.requests());
+        assert_eq!(windows[&wid].frame, state[&wid].frame);
+    }
+
+    #[test]
+    fn it_tracks_window_order() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
+            vec![None],
+        ));
+        reactor.handle_events(apps.make_app(1, make_windows(3)));
+        let wid = |idx| WindowId::new(1, idx);
+
+        reactor.apply_window_order(&[wid(3), wid(1)]);
+        assert_eq!(reactor.window_order, vec![wid(3), wid(1), wid(2)]);
+
+        let mut window = make_window(4);
+        window.frame = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
+        reactor.handle_event(Event::WindowCreated(wid(4), window));
+        assert_eq!(reactor.window_order, vec![wid(4), wid(3), wid(1), wid(2)]);
+        assert!(reactor.is_occluded(wid(1)));
+        assert!(!reactor.is_occluded(wid(4)));
+        assert_eq!(
+            reactor.window_at_point(CGPoint::new(225., 125.)),
+            Some(wid(4))
+        );
+
+        reactor.handle_event(Event::WindowDestroyed(wid(4)));
+        assert_eq!(reactor.window_order, vec![wid(3), wid(1), wid(2)]);
+        assert!(!reactor.is_occluded(wid(1)));
+        assert_eq!(
+            reactor.window_at_point(CGPoint::new(225., 125.)),
+            Some(wid(2))
+        );
+        assert_eq!(reactor.window_at_point(CGPoint::new(175., 125.)), None);
+    }
+
+    #[test]
+    fn it_checks_the_pid_of_windows_from_the_window_server() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Terminal").window("Shell").created();
+        sim.settle();
+        let (doc1, shell) = (sim.wid("Doc1"), sim.wid("Shell"));
+        assert_eq!(sim.reactor.window_order, vec![shell, doc1]);
+
+        {
+            let mut mock = sim.mock();
+            // Doc1 closed without us noticing, and another app's window got
+            // its id.
+            let reused = mock.server_windows.iter_mut().find(|(wid, _)| *wid == doc1).unwrap();
+            reused.1.pid = 99;
+            mock.windows.get_mut(&doc1).unwrap().frame = rect(10, 10, 100, 100);
+            mock.raise(doc1);
+            // Shell was resized without a notification.
+            mock.windows.get_mut(&shell).unwrap().frame = rect(600, 0, 300, 300);
+        }
+        sim.reactor.validate_frames();
+        sim.reactor.refresh_window_order();
+        assert!(sim.reactor.windows[&doc1].frame_monotonic.same_as(rect(0, 0, 600, 1000)));
+        assert!(sim.reactor.windows[&shell].frame_monotonic.same_as(rect(600, 0, 300, 300)));
+        assert_eq!(sim.reactor.window_order, vec![shell, doc1]);
+    }
+
+    #[test]
+    fn it_replaces_windows_reported_under_new_ids() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        let space = SpaceId::new(1);
+        let screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![screen],
+            vec![Some(space)],
+        ));
+        reactor.handle_events(apps.make_app(1, make_windows(2)));
+        let layout_windows = |reactor: &Reactor| {
+            let mut wids: Vec<_> = reactor
+                .layout
+                .calculate_layout(space, screen)
+                .into_iter()
+                .map(|(wid, _)| wid)
+                .collect();
+            wids.sort();
+            wids
+        };
+
+        // The same windows are discovered twice.
+        reactor.handle_event(Event::WindowsDiscovered {
+            pid: 1,
+            new: vec![(WindowId::new(1, 1), make_window(1))],
+            known_visible: vec![WindowId::new(1, 2)],
+        });
+        assert_eq!(
+            vec![WindowId::new(1, 1), WindowId::new(1, 2)],
+            layout_windows(&reactor)
+        );
+
+        // The app thread restarts and gives the windows new ids.
+        reactor.handle_event(Event::WindowsDiscovered {
+            pid: 1,
+            new: vec![
+                (WindowId::new(1, 11), make_window(1)),
+                (WindowId::new(1, 12), make_window(2)),
+            ],
+            known_visible: vec![],
+        });
+        assert_eq!(
+            vec![WindowId::new(1, 11), WindowId::new(1, 12)],
+            layout_windows(&reactor)
+        );
+        assert_eq!(2, reactor.windows.len());
+        assert_eq!(2, reactor.window_order.len());
+
+        reactor.handle_event(Event::WindowCreated(WindowId::new(1, 13), make_window(1)));
+        assert_eq!(
+            vec![WindowId::new(1, 12), WindowId::new(1, 13)],
+            layout_windows(&reactor)
+        );
+
+        // Windows without a window server id are all different windows.
+        let no_id = || WindowInfo {
+            sys_id: WindowServerId::new(0),
+            ..make_window(3)
+        };
+        reactor.handle_event(Event::WindowCreated(
+            WindowId::new(1, u32::MAX - 1),
+            no_id(),
+        ));
+        reactor.handle_event(Event::WindowCreated(
+            WindowId::new(1, u32::MAX - 2),
+            no_id(),
+        ));
+        assert_eq!(4, layout_windows(&reactor).len());
+        let QueryResponse::Windows(windows) = reactor.handle_query(Query::Windows) else {
+            panic!("Expected windows");
+        };
+        let ids: HashMap<_, _> =
+            windows.iter().map(|window| (window.id, window.window_server_id)).collect();
+        assert_eq!(Some(&Some(2)), ids.get(&WindowId::new(1, 12)));
+        assert_eq!(Some(&None), ids.get(&WindowId::new(1, u32::MAX - 1)));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn it_handles_injected_events() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.settle();
+        let injected = WindowId::new(sim.wid("Doc1").pid, 100);
+        let inject = |sim: &mut Sim, events| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            sim.event(Event::Inject(events, tx));
+            sim.settle();
+            rx.recv().unwrap()
+        };
+
+        let created = InjectedEvent::WindowCreated {
+            id: injected,
+            title: "Injected".into(),
+            frame: rect(0, 0, 100, 100),
+        };
+        assert_eq!(Ok(()), inject(&mut sim, vec![created]));
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000));
+        assert!(sim.mock().windows[&injected].frame.same_as(rect(600, 0, 600, 1000)));
+
+        assert_eq!(
+            Ok(()),
+            inject(&mut sim, vec![InjectedEvent::WindowDestroyed(injected)])
+        );
+        assert_frames!(sim, "Doc1" => rect(0, 0, 1200, 1000));
+
+        let unknown = InjectedEvent::WindowDestroyed(WindowId::new(999, 1));
+        assert!(inject(&mut sim, vec![unknown]).is_err());
+    }
+
+    #[test]
+    fn it_moves_and_resizes_windows_dragged_with_modifiers() {
+        use crate::sys::event::MouseButton;
+
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.settle();
+        let point = |x, y| CGPoint::new(x, y);
+
+        // The window follows the mouse instead of staying in its tile.
+        sim.event(Event::WindowDrag(DragEvent::Started(
+            MouseButton::Left,
+            point(100., 100.),
+        )));
+        sim.event(Event::WindowDrag(DragEvent::Moved(point(800., 150.))));
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(700, 50, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+
+        // Dropping it on another tile swaps the two.
+        sim.event(Event::WindowDrag(DragEvent::Ended(point(800., 150.))));
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(600, 0, 600, 1000), "Doc2" => rect(0, 0, 600, 1000));
+
+        // Dropping it outside of any other tile puts it back.
+        sim.event(Event::WindowDrag(DragEvent::Started(
+            MouseButton::Left,
+            point(700., 100.),
+        )));
+        sim.event(Event::WindowDrag(DragEvent::Moved(point(750., 100.))));
+        sim.event(Event::WindowDrag(DragEvent::Ended(point(750., 100.))));
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(600, 0, 600, 1000), "Doc2" => rect(0, 0, 600, 1000));
+
+        // Resizing a tiled window resizes its tile.
+        sim.event(Event::WindowDrag(DragEvent::Started(
+            MouseButton::Right,
+            point(100., 100.),
+        )));
+        sim.event(Event::WindowDrag(DragEvent::Moved(point(300., 120.))));
+        sim.event(Event::WindowDrag(DragEvent::Ended(point(300., 120.))));
+        sim.settle();
+        assert_frames!(sim, "Doc2" => rect(0, 0, 800, 1000), "Doc1" => rect(800, 0, 400, 1000));
+    }
+
+    #[test]
+    fn it_previews_resizes_until_the_drag_ends() {
+        use crate::sys::event::MouseButton;
+
+        let mut sim = Sim::new();
+        let (borders_tx, mut borders_rx) = borders::channel();
+        sim.reactor.borders = Some(borders_tx);
+        sim.reactor.preview_resize = true;
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.settle();
+        let mut resize_previews = || {
+            let mut previews = vec![];
+            while let Ok((_, event)) = borders_rx.try_recv() {
+                if let borders::Event::ResizePreviewChanged(frame) = event {
+                    previews.push(frame);
+                }
+            }
+            previews
+        };
+        resize_previews();
+
+        sim.event(Event::WindowDrag(DragEvent::Started(
+            MouseButton::Right,
+            CGPoint::new(100., 100.),
+        )));
+        sim.event(Event::WindowDrag(DragEvent::Moved(CGPoint::new(
+            200., 110.,
+        ))));
+        sim.event(Event::WindowDrag(DragEvent::Moved(CGPoint::new(
+            300., 120.,
+        ))));
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+        assert_eq!(
+            vec![Some(rect(0, 0, 700, 1010)), Some(rect(0, 0, 800, 1020))],
+            resize_previews()
+        );
+
+        sim.event(Event::WindowDrag(DragEvent::Ended(CGPoint::new(
+            300., 120.,
+        ))));
+        sim.settle();
+        assert_frames!(sim, "Doc1" => rect(0, 0, 800, 1000), "Doc2" => rect(800, 0, 400, 1000));
+        assert_eq!(vec![None], resize_previews());
+    }
+
+    #[test]
+    fn it_shares_the_frames_of_visible_windows_with_the_mouse_actor() {
+        let mut sim = Sim::new();
+        let frames = WindowFrames::default();
+        sim.event(Event::WatchWindowFrames(frames.clone()));
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        let (doc1, doc2) = (sim.wid("Doc1"), sim.wid("Doc2"));
+        sim.event(Event::VisibleWindowsChanged {
+            pid: doc1.pid,
+            new: vec![],
+            shown: vec![doc1, doc2],
+            hidden: vec![],
+        });
+        sim.settle();
+        let at = |x, y| frames.window_at(CGPoint::new(x, y));
+        assert_eq!(Some(sim.wid("Doc1")), at(100., 500.));
+        assert_eq!(Some(sim.wid("Doc2")), at(700., 500.));
+        assert_eq!(
+            Some(sim.wid("Doc1")),
+            frames.title_bar_at(CGPoint::new(100., 10.))
+        );
+        assert_eq!(None, frames.title_bar_at(CGPoint::new(100., 500.)));
+
+        sim.app("Safari").window("Doc2").destroyed();
+        sim.settle();
+        assert_eq!(Some(sim.wid("Doc1")), at(700., 500.));
+    }
+
+    #[test]
+    fn it_zooms_and_floats_windows_from_their_title_bars() {
+        let mut sim = Sim::new();
+        sim.app("Safari").window("Doc1").created();
+        sim.app("Safari").window("Doc2").created();
+        sim.settle();
+        let doc1 = sim.wid("Doc1");
+        let double_click = |sim: &mut Sim, action| {
+            sim.event(Event::TitleBarDoubleClicked(doc1, action));
+            sim.settle();
+        };
+
+        double_click(&mut sim, TitleBarAction::ToggleZoom);
+        assert_frames!(sim, "Doc1" => rect(0, 0, 1200, 1000), "Doc2" => rect(600, 0, 600, 1000));
+        double_click(&mut sim, TitleBarAction::ToggleZoom);
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+
+        // A floating window stays where it is, and can't be zoomed.
+        double_click(&mut sim, TitleBarAction::ToggleFloat);
+        assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(0, 0, 1200, 1000));
+        assert_eq!(
+            Err(CommandError::WindowNotTiled),
+            sim.reactor.toggle_zoom(doc1)
+        );
+        // It goes back in as if it were new.
+        double_click(&mut sim, TitleBarAction::ToggleFloat);
+        assert_frames!(sim, "Doc2" => rect(0, 0, 600, 1000), "Doc1" => rect(600, 0, 600, 1000));
+    }
+
+    #[test]
+    fn it_applies_visible_window_changes() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        let space = SpaceId::new(1);
+        let screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![screen],
+            vec![Some(space)],
+        ));
+        reactor.handle_events(apps.make_app(1, make_windows(3)));
+        let layout_windows = |reactor: &Reactor| {
+            let mut wids: Vec<_> = reactor
+                .layout
+                .calculate_layout(space, screen)
+                .into_iter()
+                .map(|(wid, _)| wid)
+                .collect();
+            wids.sort();
+            wids
+        };
+        let changed =
+            |new, shown, hidden| Event::VisibleWindowsChanged { pid: 1, new, shown, hidden };
+
+        reactor.handle_event(changed(vec![], vec![], vec![WindowId::new(1, 2)]));
+        assert_eq!(
+            vec![WindowId::new(1, 1), WindowId::new(1, 3)],
+            layout_windows(&reactor)
+        );
+
+        reactor.handle_event(changed(
+            vec![(WindowId::new(1, 4), make_window(4))],
+            vec![WindowId::new(1, 2)],
+            vec![],
+        ));
+        assert_eq!(
+            vec![
+                WindowId::new(1, 1),
+                WindowId::new(1, 2),
+                WindowId::new(1, 3),
+                WindowId::new(1, 4)
+            ],
+            layout_windows(&reactor)
+        );
+
+        // Nothing changed, but destroyed windows are forgotten.
+        reactor.handle_event(Event::WindowDestroyed(WindowId::new(1, 3)));
+        reactor.handle_event(changed(vec![], vec![], vec![]));
+        assert_eq!(
+            vec![
+                WindowId::new(1, 1),
+                WindowId::new(1, 2),
+                WindowId::new(1, 4)
+            ],
+            layout_windows(&reactor)
+        );
+    }
+
+    #[test]
+    fn it_repairs_drift_found_by_audit() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.))],
+            vec![Some(SpaceId::new(1))],
+        ));
+        let mut windows = make_windows(3);
+        // The window server has no id for window 4, so it can't be audited.
+        windows.push(WindowInfo {
+            sys_id: WindowServerId::NONE,
+            ..make_window(4)
+        });
+        reactor.handle_events(apps.make_app(1, windows));
+        _ = apps.requests();
+        let info = |id, frame| WindowServerInfo {
+            id: WindowServerId::new(id),
+            pid: 1,
+            frame,
+        };
+        let moved = CGRect::new(CGPoint::new(10., 10.), CGSize::new(300., 300.));
+        let frame_2 = reactor.windows[&WindowId::new(1, 2)].frame_monotonic;
+
+        // Window 3 was destroyed and window 99 created without us noticing.
+        // Another app's window now has window 3's id.
+        reactor.audit(
+            &[
+                info(1, moved),
+                info(2, frame_2),
+                WindowServerInfo { pid: 2, ..info(3, moved) },
+            ],
+            &[info(1, moved), info(2, frame_2), info(99, moved)],
+        );
+        assert_eq!(moved, reactor.windows[&WindowId::new(1, 1)].frame_monotonic);
+        assert!(!reactor.windows.contains_key(&WindowId::new(1, 3)));
+        assert!(!reactor.window_order.contains(&WindowId::new(1, 3)));
+        assert!(reactor.windows.contains_key(&WindowId::new(1, 4)));
+        let requests = apps.requests();
+        assert!(requests.iter().any(|rq| matches!(rq, Request::GetVisibleWindows)));
+        assert!(requests.iter().any(|rq| matches!(rq, Request::Rewatch)));
+
+        // Apps whose windows are in sync keep their observer.
+        let frame_1 = reactor.windows[&WindowId::new(1, 1)].frame_monotonic;
+        reactor.audit(&[info(1, frame_1), info(2, frame_2)], &[]);
+        let requests = apps.requests();
+        assert!(!requests.iter().any(|rq| matches!(rq, Request::Rewatch)));
+    }
+
+    #[test]
+    fn it_adopts_windows_found_on_a_newly_visited_space() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        let clock = VirtualClock::new();
+        reactor.clock = Arc::new(clock.clone());
+        let space = SpaceId::new(1);
+        let screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![screen],
+            vec![Some(space)],
+        ));
+        reactor.handle_events(apps.make_app(1, make_windows(1)));
+        _ = apps.requests();
+        let info = |id| WindowServerInfo {
+            id: WindowServerId::new(id),
+            pid: 1,
+            frame: CGRect::ZERO,
+        };
+
+        // Window 2 was on the space before we saw it, and the app has not
+        // reported it yet.
+        reactor.start_adoption(space, &[info(1), info(2)], false);
+        let pending = &reactor.adoption.as_ref().unwrap().pending;
+        assert_eq!(
+            vec![&WindowServerId::new(2)],
+            pending.keys().collect::<Vec<_>>()
+        );
+
+        reactor.retry_adoption();
+        assert!(apps.requests().is_empty());
+        clock.advance(ADOPTION_RETRY_INTERVAL);
+        reactor.retry_adoption();
+        let requests = apps.requests();
+        assert!(requests.iter().any(|rq| matches!(rq, Request::GetVisibleWindows)));
+
+        reactor.handle_event(Event::WindowsDiscovered {
+            pid: 1,
+            new: vec![(WindowId::new(1, 2), make_window(2))],
+            known_visible: vec![WindowId::new(1, 1)],
+        });
+        assert!(reactor.adoption.is_none());
+        let layout = reactor.layout.calculate_layout(space, screen);
+        assert!(layout.iter().any(|&(wid, _)| wid == WindowId::new(1, 2)));
+    }
+
+    #[test]
+    fn it_removes_windows_of_terminated_apps() {
+        let mut apps = Apps::new();
+        let mut reactor = Reactor::new_for_test();
+        let space = SpaceId::new(1);
+        let screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
+        reactor.handle_event(Event::ScreenParametersChanged(
+            vec![screen],
+            vec![Some(space)],
+        ));
+        reactor.handle_events(apps.make_app(1, make_windows(2)));
+        reactor.handle_events(apps.make_app(2, make_windows(1)));
+        _ = apps.requests();
+
+        reactor.handle_event(Event::ApplicationTerminated(1));
+        assert_eq!(
+            vec![WindowId::new(2, 1)],
+            reactor.windows.keys().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![WindowId::new(2, 1)],
+            reactor
+                .layout
+                .calculate_layout(space, screen)
+                .into_iter()
+                .map(|(wid, _)| wid)
+                .collect::<Vec<_>>()
+        );
+
+        // Late events from the app should be ignored.
+        reactor.handle_event(Event::WindowDestroyed(WindowId::new(1, 1)));
+        reactor.handle_event(Event::ApplicationTerminated(1));
+    }
+
     #[test]
     fn it_manages_windows_on_enabled_spaces() {
         let mut apps = Apps::new();
-        let mut reactor = Reactor::new(LayoutManager::new());
+        let mut reactor = Reactor::new_for_test();
         let full_screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
         reactor.handle_event(Event::ScreenParametersChanged(
             vec![full_screen],
@@ -764,7 +5418,7 @@ mod tests {
     #[test]
     fn it_ignores_windows_on_disabled_spaces() {
         let mut apps = Apps::new();
-        let mut reactor = Reactor::new(LayoutManager::new());
+        let mut reactor = Reactor::new_for_test();
         let full_screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
         reactor.handle_event(Event::ScreenParametersChanged(
             vec![full_screen],
@@ -784,4 +5438,15 @@ mod tests {
         reactor.handle_event(Event::WindowCreated(WindowId::new(1, 2), make_window(2)));
         reactor.handle_event(Event::WindowDestroyed(WindowId::new(1, 2)));
     }
+
+    #[test]
+    fn it_names_the_app_in_focus_announcements() {
+        assert_eq!(
+            "Inbox, Mail",
+            focus_announcement(Some("Inbox"), Some("Mail"))
+        );
+        assert_eq!("Mail", focus_announcement(Some(""), Some("Mail")));
+        assert_eq!("Inbox", focus_announcement(Some("Inbox"), None));
+        assert_eq!("Untitled window", focus_announcement(None, None));
+    }
 }
@@ -1,17 +1,24 @@
-use std::{
-    thread,
-    time::{Duration, Instant},
-};
+use std::time::{Duration, Instant};
 
 use icrate::Foundation::{CGPoint, CGRect, CGSize};
 
 use super::TransactionId;
-use crate::actor::app::{AppThreadHandle, Request, WindowId};
+use crate::{
+    actor::app::{AppThreadHandle, Request, WindowId},
+    metrics::{self, Timing},
+    sys::clock::Clock,
+};
+
+/// The normal frame rate of animations.
+pub const FULL_FPS: f64 = 100.0;
+/// The frame rate of animations while saving power.
+pub const REDUCED_FPS: f64 = 30.0;
 
 #[derive(Debug)]
 pub struct Animation<'a> {
     //start: CFAbsoluteTime,
     //interval: CFTimeInterval,
+    clock: &'a dyn Clock,
     start: Instant,
     interval: Duration,
     frames: u32,
@@ -27,16 +34,16 @@ pub struct Animation<'a> {
 }
 
 impl<'a> Animation<'a> {
-    pub fn new() -> Self {
-        const FPS: f64 = 100.0;
+    pub fn new(clock: &'a dyn Clock, fps: f64) -> Self {
         const DURATION: f64 = 0.30;
-        let interval = Duration::from_secs_f64(1.0 / FPS);
+        let interval = Duration::from_secs_f64(1.0 / fps);
         // let now = unsafe { CFAbsoluteTimeGetCurrent() };
-        let now = Instant::now();
+        let now = clock.now();
         Animation {
+            clock,
             start: now, // + interval, // not necessary, provide one extra frame to get things going
             interval,
-            frames: (DURATION * FPS).round() as u32,
+            frames: (DURATION * fps).round() as u32,
             windows: vec![],
         }
     }
@@ -71,6 +78,7 @@ impl<'a> Animation<'a> {
         }
 
         let mut next_frames = Vec::with_capacity(self.windows.len());
+        let mut last_frame = None;
         for frame in 1..=self.frames {
             let t: f64 = f64::from(frame) / f64::from(self.frames);
 
@@ -80,11 +88,10 @@ impl<'a> Animation<'a> {
             }
 
             let deadline = self.start + frame * self.interval;
-            let duration = deadline - Instant::now();
-            if duration < Duration::ZERO {
+            if deadline < self.clock.now() {
                 continue;
             }
-            thread::sleep(duration);
+            self.clock.sleep_until(deadline);
 
             for (&(handle, wid, _, to, _, txid), rect) in self.windows.iter().zip(&next_frames) {
                 let mut rect = *rect;
@@ -98,6 +105,11 @@ impl<'a> Animation<'a> {
                     handle.send(Request::SetWindowPos(wid, rect.origin, txid)).unwrap();
                 }
             }
+            let now = self.clock.now();
+            if let Some(last_frame) = last_frame {
+                metrics::record(Timing::AnimationFrame, now - last_frame);
+            }
+            last_frame = Some(now);
         }
 
         for &(handle, wid, _, _, _, _) in &self.windows {
@@ -138,3 +150,43 @@ fn ease(t: f64) -> f64 {
 fn blend(a: f64, b: f64, s: f64) -> f64 {
     (1.0 - s) * a + s * b
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use crate::sys::clock::VirtualClock;
+
+    #[test]
+    fn it_sends_every_frame_on_schedule() {
+        let clock = VirtualClock::new();
+        let start = clock.now();
+        let (tx, rx) = channel();
+        let handle = AppThreadHandle::new_for_test(tx);
+        let wid = WindowId::new(1, 1);
+        let from = CGRect::new(CGPoint::new(0., 0.), CGSize::new(100., 100.));
+        let to = CGRect::new(CGPoint::new(500., 0.), CGSize::new(100., 100.));
+
+        let mut anim = Animation::new(&clock, FULL_FPS);
+        let frames = anim.frames;
+        anim.add_window(&handle, wid, from, to, false, TransactionId::default());
+        anim.run();
+
+        let requests: Vec<_> = rx.try_iter().map(|(_span, rq)| rq).collect();
+        assert!(matches!(
+            requests.first(),
+            Some(Request::BeginWindowAnimation(_))
+        ));
+        assert!(matches!(
+            requests.last(),
+            Some(Request::EndWindowAnimation(_))
+        ));
+        assert_eq!(requests.len(), frames as usize + 2);
+        assert!(matches!(
+            requests[requests.len() - 2],
+            Request::SetWindowFrame(_, frame, _) if frame == to
+        ));
+        assert_eq!(clock.now() - start, frames * Duration::from_millis(10));
+    }
+}
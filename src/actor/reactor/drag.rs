@@ -0,0 +1,237 @@
+//! Moving and resizing windows by dragging anywhere inside them while holding
+//! the window drag modifiers.
+//!
+//! Floating windows follow the mouse. A tiled window follows it too while
+//! being moved, and swaps places with the tiled window it is dropped on, or
+//! goes back to its tile if there is none. Resizing a tiled window resizes its
+//! tile, as if the user had dragged its edge.
+//!
+//! Apps that are slow to resize fall behind the mouse, so resizing can instead
+//! show an outline of the new frame, and resize the window once when the
+//! button is released. The same goes for dragging the boundary between two
+//! tiles, which shows an outline of the boundary where it will be.
+
+use icrate::Foundation::{CGPoint, CGRect, CGSize};
+use tracing::{debug, Span};
+
+use super::{Reactor, Request, Screen};
+use crate::{
+    actor::{app::WindowId, borders, layout::LayoutEvent},
+    model::{NodeId, Orientation},
+    sys::{
+        event::{DragEvent, MouseButton},
+        geometry::Contains,
+    },
+};
+
+/// Windows are not resized to less than this in either direction.
+const MIN_SIZE: f64 = 50.0;
+
+/// The width of the outline shown where a dragged boundary will be.
+const SPLIT_PREVIEW_WIDTH: f64 = 4.0;
+
+/// A window being dragged.
+#[derive(Debug)]
+pub(super) struct WindowDrag {
+    pub wid: WindowId,
+    pub button: MouseButton,
+    start: CGPoint,
+    start_frame: CGRect,
+    is_tiled: bool,
+    /// The frame shown in the resize outline, if any.
+    preview: Option<CGRect>,
+}
+
+/// A boundary between tiles being dragged while resizes are previewed.
+#[derive(Debug)]
+pub(super) struct SplitDrag {
+    pub node: NodeId,
+    pub orientation: Orientation,
+    /// How far the boundary has been dragged so far, in points.
+    pub delta: f64,
+}
+
+impl WindowDrag {
+    /// Whether the window follows the mouse rather than its tile.
+    pub fn is_moving(&self, wid: WindowId) -> bool {
+        self.wid == wid && self.button == MouseButton::Left
+    }
+}
+
+impl Reactor {
+    pub(super) fn handle_window_drag(&mut self, event: DragEvent) {
+        match event {
+            DragEvent::Started(button, point) => {
+                self.refresh_window_order();
+                let Some(wid) = self.window_at_point(point) else { return };
+                let window = &self.windows[&wid];
+                if self.is_paused(wid.pid) || window.is_fullscreen {
+                    return;
+                }
+                if button == MouseButton::Right && !window.is_resizable {
+                    return;
+                }
+                let start_frame = window.frame_monotonic;
+                let is_tiled = self.tiled_windows().iter().any(|&(tiled, _)| tiled == wid);
+                debug!(?wid, ?button, is_tiled, "Started dragging window");
+                self.window_drag = Some(WindowDrag {
+                    wid,
+                    button,
+                    start: point,
+                    start_frame,
+                    is_tiled,
+                    preview: None,
+                });
+                self.raise_window(wid);
+            }
+            DragEvent::Moved(point) => {
+                let Some(drag) = &self.window_drag else { return };
+                let (dx, dy) = (point.x - drag.start.x, point.y - drag.start.y);
+                let start_frame = drag.start_frame;
+                let (wid, button, is_tiled) = (drag.wid, drag.button, drag.is_tiled);
+                let Some(window) = self.windows.get_mut(&wid) else {
+                    // The window closed while it was being dragged.
+                    if self.window_drag.take().is_some_and(|drag| drag.preview.is_some()) {
+                        self.show_resize_preview(None);
+                    }
+                    return;
+                };
+                let Some(app) = self.apps.get(&wid.pid) else { return };
+                match button {
+                    MouseButton::Left => {
+                        let origin =
+                            CGPoint::new(start_frame.origin.x + dx, start_frame.origin.y + dy);
+                        window.frame_monotonic.origin = origin;
+                        let txid = window.next_txid();
+                        _ = app.handle.send(Request::SetWindowPos(wid, origin, txid));
+                    }
+                    MouseButton::Right => {
+                        let size = CGSize::new(
+                            (start_frame.size.width + dx).max(MIN_SIZE),
+                            (start_frame.size.height + dy).max(MIN_SIZE),
+                        );
+                        let new_frame = CGRect::new(start_frame.origin, size);
+                        if self.preview_resize {
+                            self.window_drag.as_mut().unwrap().preview = Some(new_frame);
+                            self.show_resize_preview(Some(new_frame));
+                        } else {
+                            self.resize_dragged_window(wid, is_tiled, new_frame);
+                        }
+                    }
+                }
+            }
+            DragEvent::Ended(point) => {
+                let Some(drag) = self.window_drag.take() else { return };
+                if let Some(new_frame) = drag.preview {
+                    self.show_resize_preview(None);
+                    debug!(wid = ?drag.wid, ?new_frame, "Resizing window to the preview");
+                    self.resize_dragged_window(drag.wid, drag.is_tiled, new_frame);
+                    return;
+                }
+                if drag.button != MouseButton::Left || !drag.is_tiled {
+                    return;
+                }
+                let Some(space) = self.main_screen_space() else { return };
+                let target = self
+                    .tiled_windows()
+                    .into_iter()
+                    .find(|&(other, frame)| other != drag.wid && frame.contains(point));
+                if let Some((target, _)) = target {
+                    debug!(wid = ?drag.wid, ?target, "Swapping windows");
+                    self.send_layout_event(LayoutEvent::WindowsSwapped(space, drag.wid, target));
+                }
+                // Either way the window goes to its tile when the layout is
+                // updated.
+            }
+        }
+    }
+
+    /// Gives `wid` the frame it was dragged to, resizing its tile if it is
+    /// tiled.
+    fn resize_dragged_window(&mut self, wid: WindowId, is_tiled: bool, new_frame: CGRect) {
+        if !is_tiled {
+            self.set_window_frame(wid, new_frame);
+            return;
+        }
+        let Some(window) = self.windows.get(&wid) else {
+            return;
+        };
+        let old_frame = window.frame_monotonic;
+        let Some(Screen {
+            frame: screen,
+            space: Some(space),
+        }) = self.main_screen
+        else {
+            return;
+        };
+        self.send_layout_event(LayoutEvent::WindowResized {
+            space,
+            screen,
+            wid,
+            old_frame,
+            new_frame,
+        });
+    }
+
+    /// Moves the boundary after `node` on the main screen by `delta` points,
+    /// returning whether it moved.
+    pub(super) fn move_split(
+        &mut self,
+        node: NodeId,
+        orientation: Orientation,
+        delta: f64,
+    ) -> bool {
+        let Some(Screen { frame, space: Some(space) }) = self.main_screen else {
+            return false;
+        };
+        self.layout.move_split(space, node, orientation, delta, frame)
+    }
+
+    /// Adds `delta` to how far the boundary after `node` was dragged, and
+    /// shows an outline where it will be once the drag ends.
+    pub(super) fn preview_split_drag(
+        &mut self,
+        node: NodeId,
+        orientation: Orientation,
+        delta: f64,
+    ) {
+        let drag = self.split_drag.get_or_insert(SplitDrag { node, orientation, delta: 0.0 });
+        drag.delta += delta;
+        let total = drag.delta;
+        let Some(split) = self
+            .splits
+            .iter()
+            .find(|split| split.node == node && split.orientation == orientation)
+        else {
+            return;
+        };
+        let frame = split.frame;
+        let half = SPLIT_PREVIEW_WIDTH / 2.0;
+        let preview = match orientation {
+            Orientation::Horizontal => CGRect::new(
+                CGPoint::new(frame.origin.x + total - half, frame.origin.y),
+                CGSize::new(SPLIT_PREVIEW_WIDTH, frame.size.height),
+            ),
+            Orientation::Vertical => CGRect::new(
+                CGPoint::new(frame.origin.x, frame.origin.y + total - half),
+                CGSize::new(frame.size.width, SPLIT_PREVIEW_WIDTH),
+            ),
+        };
+        self.show_resize_preview(Some(preview));
+    }
+
+    /// Shows the outline of the frame a window is being resized to, or hides
+    /// it.
+    pub(super) fn show_resize_preview(&self, frame: Option<CGRect>) {
+        let Some(borders) = &self.borders else { return };
+        _ = borders.send((Span::current(), borders::Event::ResizePreviewChanged(frame)));
+    }
+
+    /// The windows in the layout of the main screen, and their frames.
+    fn tiled_windows(&self) -> Vec<(WindowId, CGRect)> {
+        match self.main_screen {
+            Some(Screen { frame, space: Some(space) }) => self.calculate_layout(space, frame),
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Replaying made-up events into a running reactor.
+//!
+//! Bugs reported by users often depend on the exact layout they had, which is
+//! hard to recreate by hand. Injecting a sequence of events into the running
+//! window manager reproduces them against the real layout state instead. This
+//! is only built into debug builds.
+
+use icrate::Foundation::CGRect;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::{Event, Reactor, Requested};
+use crate::{
+    actor::app::{pid_t, WindowId, WindowInfo},
+    sys::{geometry::CGRectDef, screen::SpaceId, window_server::WindowServerId},
+};
+
+/// The events that can be injected, in the same format as the config file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum InjectedEvent {
+    /// A standard window opened in a running app. The app knows nothing about
+    /// it, so requests to move it fail.
+    WindowCreated {
+        id: WindowId,
+        title: String,
+        #[serde(with = "CGRectDef")]
+        frame: CGRect,
+    },
+    WindowDestroyed(WindowId),
+    /// The user moved or resized a window.
+    WindowFrameChanged(WindowId, #[serde(with = "CGRectDef")] CGRect),
+    ApplicationActivated(pid_t, Option<WindowId>),
+    ApplicationMainWindowChanged(pid_t, Option<WindowId>),
+    /// The space on each screen changed, in the order of the screens.
+    SpaceChanged(Vec<Option<SpaceId>>),
+}
+
+impl Reactor {
+    /// Handles `events` as if they came from apps and the system, stopping at
+    /// the first one that names an app that is not running.
+    pub(super) fn inject(&mut self, events: Vec<InjectedEvent>) -> Result<(), String> {
+        for event in events {
+            info!(?event, "Injecting event");
+            let event = self.injected_event(event);
+            if let Some(pid) = event.pid().filter(|pid| !self.apps.contains_key(pid)) {
+                return Err(format!("no app with pid {pid} is running"));
+            }
+            self.handle_event(event);
+        }
+        Ok(())
+    }
+
+    fn injected_event(&self, event: InjectedEvent) -> Event {
+        match event {
+            InjectedEvent::WindowCreated { id, title, frame } => Event::WindowCreated(
+                id,
+                WindowInfo {
+                    is_standard: true,
+                    title,
+                    frame,
+                    sys_id: WindowServerId::NONE,
+                    subrole: "AXStandardWindow".to_string(),
+                    level: 0,
+                    is_resizable: true,
+                    is_minimized: false,
+                    is_fullscreen: false,
+                    parent: None,
+                    is_pip: false,
+                },
+            ),
+            InjectedEvent::WindowDestroyed(wid) => Event::WindowDestroyed(wid),
+            InjectedEvent::WindowFrameChanged(wid, frame) => {
+                // Frames the app reports are only applied if it has seen our
+                // last change to the window.
+                let txid = self.windows.get(&wid).map(|w| w.last_sent_txid).unwrap_or_default();
+                Event::WindowFrameChanged(wid, frame, txid, Requested(false))
+            }
+            InjectedEvent::ApplicationActivated(pid, main) => {
+                Event::ApplicationActivated(pid, main)
+            }
+            InjectedEvent::ApplicationMainWindowChanged(pid, main) => {
+                Event::ApplicationMainWindowChanged(pid, main)
+            }
+            InjectedEvent::SpaceChanged(spaces) => Event::SpaceChanged(spaces),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Sharing one copy of strings that many windows have in common.
+
+use std::{collections::HashSet, sync::Arc};
+
+/// A pool of shared strings, such as bundle ids and window titles.
+///
+/// Interning a string that is already in the pool returns the copy in the
+/// pool instead of allocating a new one.
+#[derive(Debug, Default)]
+pub struct Interner(HashSet<Arc<str>>);
+
+impl Interner {
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(existing) = self.0.get(s) {
+            return existing.clone();
+        }
+        let s: Arc<str> = s.into();
+        self.0.insert(s.clone());
+        s
+    }
+
+    /// Drops the strings that are only held by the pool.
+    pub fn collect(&mut self) {
+        self.0.retain(|s| Arc::strong_count(s) > 1);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Interner;
+
+    #[test]
+    fn it_shares_strings() {
+        let mut strings = Interner::default();
+        let a = strings.intern("AXStandardWindow");
+        let b = strings.intern("AXStandardWindow");
+        assert!(Arc::ptr_eq(&a, &b));
+        let c = strings.intern("AXDialog");
+        assert_eq!(2, strings.len());
+
+        drop((a, b));
+        strings.collect();
+        assert_eq!(1, strings.len());
+        assert!(Arc::ptr_eq(&c, &strings.intern("AXDialog")));
+    }
+}
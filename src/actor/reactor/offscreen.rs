@@ -0,0 +1,85 @@
+//! Keeping windows where the user can see them.
+//!
+//! Bad math or a display that disappeared at the wrong moment can send a
+//! window somewhere no screen shows, where the user has no way to get it
+//! back. Every frame we give a window is checked against the screens before
+//! it is applied, and [`Command::RescueOffscreenWindows`] brings back windows
+//! that ended up off screen anyway, whoever moved them there.
+//!
+//! [`Command::RescueOffscreenWindows`]: super::Command::RescueOffscreenWindows
+
+use icrate::Foundation::CGRect;
+use tracing::debug;
+
+use super::{CommandError, CommandResult, DisplayTarget, Reactor, Request};
+use crate::{
+    actor::app::WindowId,
+    sys::geometry::{ClampTo, Round},
+};
+
+impl Reactor {
+    /// Returns `frame` moved onto the nearest screen if its center is off all
+    /// of them.
+    pub(super) fn clamp_to_screens(&self, frame: CGRect) -> CGRect {
+        let clamped = frame.clamp_to(self.screens.iter().map(|screen| screen.frame));
+        if clamped != frame {
+            debug!(?frame, ?clamped, "Moved a frame back on screen");
+        }
+        clamped
+    }
+
+    /// Moves `wid` to `frame`, kept on the screens, and shows it if it was
+    /// hidden.
+    ///
+    /// Every frame we give a window outside of a layout update goes through
+    /// here; layout updates clamp their frames before animating them.
+    pub(super) fn set_window_frame(&mut self, wid: WindowId, frame: CGRect) {
+        let frame = self.clamp_to_screens(frame).round();
+        let Some(app) = self.apps.get(&wid.pid) else { return };
+        let Some(window) = self.windows.get_mut(&wid) else {
+            return;
+        };
+        window.frame_monotonic = frame;
+        window.is_hidden = false;
+        let txid = window.next_txid();
+        // Errors mean the app terminated (and a termination event is coming);
+        // ignore.
+        _ = app.handle.send(Request::SetWindowFrame(wid, frame, txid));
+    }
+
+    /// Moves the windows that no screen shows any part of onto the display of
+    /// the focused window.
+    pub(super) fn rescue_offscreen_windows(&mut self) -> CommandResult {
+        let display = self
+            .display_frame(DisplayTarget::Current)
+            .or(self.main_screen.map(|screen| screen.frame))
+            .ok_or(CommandError::NoSuchDisplay)?;
+        let offscreen: Vec<(WindowId, CGRect)> = self
+            .windows
+            .iter()
+            .filter(|&(wid, window)| {
+                let frame = window.frame_monotonic;
+                !window.is_hidden
+                    && !window.is_minimized
+                    && !window.is_fullscreen
+                    && self.apps.contains_key(&wid.pid)
+                    && !self.screens.iter().any(|screen| overlaps(screen.frame, frame))
+            })
+            .map(|(&wid, window)| (wid, window.frame_monotonic))
+            .collect();
+        if offscreen.is_empty() {
+            return Err(CommandError::NoOffscreenWindows);
+        }
+        for (wid, frame) in offscreen {
+            let frame = frame.clamp_to([display]);
+            debug!(?wid, ?frame, "Rescuing window from off screen");
+            self.set_window_frame(wid, frame);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the rects share any area, not just an edge.
+fn overlaps(a: CGRect, b: CGRect) -> bool {
+    a.min().x < b.max().x && b.min().x < a.max().x && a.min().y < b.max().y && b.min().y < a.max().y
+}
@@ -0,0 +1,98 @@
+//! Pinning floating windows to a corner of the main screen, like a picture
+//! in a frame.
+//!
+//! A pinned window is put back in its corner whenever the layout is applied,
+//! so it returns there after being moved and follows the main screen when
+//! displays change. When another space is shown on the main screen, pinned
+//! windows are moved to it.
+
+use icrate::Foundation::{CGRect, CGSize};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::{CommandError, CommandResult, Reactor};
+use crate::{
+    actor::{app::WindowId, layout::LayoutEvent},
+    sys::{
+        geometry::{Corner, Inset},
+        screen::{self, SpaceId},
+    },
+};
+
+/// Where a pinned window is kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Pin {
+    pub corner: Corner,
+    pub width: f64,
+    pub height: f64,
+    /// Space between the window and the edges of the screen, in points.
+    #[serde(default)]
+    pub margin: f64,
+}
+
+impl Pin {
+    /// Whether the window would have a size and stay on the screen.
+    fn is_valid(&self) -> bool {
+        self.width > 0.0 && self.height > 0.0 && self.margin >= 0.0
+    }
+
+    /// Returns the frame of the window on a screen with the given frame. The
+    /// window is shrunk to fit if the screen is too small.
+    fn frame(&self, screen: CGRect) -> CGRect {
+        let area = screen.inset(self.margin);
+        let size = CGSize::new(
+            self.width.min(area.size.width),
+            self.height.min(area.size.height),
+        );
+        self.corner.place(size, area)
+    }
+}
+
+impl Reactor {
+    /// Pins `wid`, taking it out of the layout if it is tiled.
+    pub(super) fn pin_window(&mut self, wid: WindowId, pin: Pin) -> CommandResult {
+        if !pin.is_valid() {
+            return Err(CommandError::InvalidPin);
+        }
+        let window = self.windows.get_mut(&wid).ok_or(CommandError::NoFocusedWindow)?;
+        if window.is_tileable() {
+            window.is_floating = true;
+            self.send_layout_event(LayoutEvent::WindowRemoved(wid));
+        }
+        debug!(?wid, ?pin, "Pinning window");
+        self.windows.get_mut(&wid).unwrap().pin = Some(pin);
+        Ok(())
+    }
+
+    /// Lets `wid` be moved again. It stays floating where it is.
+    pub(super) fn unpin_window(&mut self, wid: WindowId) -> CommandResult {
+        let window = self.windows.get_mut(&wid).ok_or(CommandError::NoFocusedWindow)?;
+        window.pin.take().map(|_| ()).ok_or(CommandError::WindowNotPinned)
+    }
+
+    /// Returns the frames of the pinned windows on a main screen with the
+    /// given frame.
+    pub(super) fn place_pinned_windows(&self, screen: CGRect) -> Vec<(WindowId, CGRect)> {
+        self.windows
+            .iter()
+            .filter(|(_, window)| {
+                !window.is_minimized && !window.is_fullscreen && !window.is_hidden
+            })
+            .filter_map(|(&wid, window)| Some((wid, window.pin?.frame(screen))))
+            .collect()
+    }
+
+    /// Moves the pinned windows to `space` if they are on another one.
+    pub(super) fn move_pinned_windows_to(&self, space: SpaceId) {
+        for (wid, window) in &self.windows {
+            if window.pin.is_none() || !window.window_server_id.is_valid() {
+                continue;
+            }
+            if screen::window_space(window.window_server_id) != Some(space) {
+                debug!(?wid, ?space, "Moving pinned window to the new space");
+                screen::move_window_to_space(window.window_server_id, space);
+            }
+        }
+    }
+}
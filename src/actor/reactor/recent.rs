@@ -0,0 +1,123 @@
+//! Cycling through windows in the order they were last focused, like Cmd+Tab
+//! does for apps.
+//!
+//! Each [`Command::CycleRecentWindows`] raises the next window in the cycle.
+//! While a cycle is open the order is frozen, so that raising windows along
+//! the way does not change it; [`Command::EndRecentCycle`], sent when the
+//! binding's modifiers are released, closes the cycle and makes the window
+//! it ended on the most recently used.
+//!
+//! [`Command::CycleRecentWindows`]: super::Command::CycleRecentWindows
+//! [`Command::EndRecentCycle`]: super::Command::EndRecentCycle
+
+use tracing::{debug, Span};
+
+use super::{switch_to_window_space, CommandError, CommandResult, Reactor};
+use crate::{
+    actor::{app::WindowId, borders},
+    sys::screen,
+};
+
+/// The windows being cycled through, and the one raised last.
+#[derive(Debug)]
+pub(super) struct RecentCycle {
+    windows: Vec<WindowId>,
+    selected: usize,
+}
+
+impl Reactor {
+    /// Records that `wid` was focused, unless a cycle is open.
+    pub(super) fn note_recent(&mut self, wid: WindowId) {
+        if self.recent_cycle.is_some() || !self.windows.contains_key(&wid) {
+            return;
+        }
+        self.recent_windows.retain(|&w| w != wid);
+        self.recent_windows.insert(0, wid);
+    }
+
+    /// Raises the next window in the cycle, or the previous one if `forward`
+    /// is false, opening a cycle if there is none.
+    pub(super) fn cycle_recent_windows(&mut self, forward: bool) -> CommandResult {
+        let (windows, current) = match self.recent_cycle.take() {
+            Some(cycle) => {
+                let current = cycle.windows[cycle.selected];
+                (cycle.windows, Some(current))
+            }
+            None => (self.recent_order(), self.main_window()),
+        };
+        // Windows can close or be minimized while the cycle is open.
+        let windows: Vec<WindowId> = windows
+            .into_iter()
+            .filter(|wid| self.windows.get(wid).is_some_and(|window| !window.is_minimized))
+            .collect();
+        if windows.is_empty() {
+            self.update_switcher();
+            return Err(CommandError::NoFocusedWindow);
+        }
+        let len = windows.len();
+        let current = current.and_then(|current| windows.iter().position(|&w| w == current));
+        let selected = match current {
+            Some(idx) if forward => (idx + 1) % len,
+            Some(idx) => (idx + len - 1) % len,
+            None if forward => 0,
+            None => len - 1,
+        };
+        let wid = windows[selected];
+        debug!(?wid, selected, "Cycling to recent window");
+        self.recent_cycle = Some(RecentCycle { windows, selected });
+        if Some(wid) != self.main_window() {
+            switch_to_window_space(screen::window_space(self.windows[&wid].window_server_id));
+            self.raise_window(wid);
+        }
+        self.update_switcher();
+        Ok(())
+    }
+
+    /// Closes the cycle, making the window it ended on the most recently
+    /// used.
+    pub(super) fn end_recent_cycle(&mut self) {
+        let Some(cycle) = self.recent_cycle.take() else { return };
+        self.note_recent(cycle.windows[cycle.selected]);
+        self.update_switcher();
+    }
+
+    /// Returns the standard windows we know of, starting with the most
+    /// recently focused. Windows that were never focused since we started
+    /// come last, in stacking order.
+    fn recent_order(&self) -> Vec<WindowId> {
+        let never_focused =
+            self.window_order.iter().filter(|wid| !self.recent_windows.contains(wid));
+        self.recent_windows
+            .iter()
+            .chain(never_focused)
+            .copied()
+            .filter(|wid| self.windows.get(wid).is_some_and(|window| window.is_standard))
+            .collect()
+    }
+
+    /// Shows the windows in the open cycle, or hides the switcher if there is
+    /// none.
+    fn update_switcher(&self) {
+        let Some(borders) = &self.borders else { return };
+        if !self.show_switcher {
+            return;
+        }
+        let switcher = self.recent_cycle.as_ref().map(|cycle| borders::Switcher {
+            entries: cycle
+                .windows
+                .iter()
+                .map(|wid| {
+                    let app = self.display_app(wid.pid).and_then(|app| app.localized_name.clone());
+                    let title = self.windows.get(wid).map(|window| &*window.title).unwrap_or("");
+                    match app {
+                        Some(app) if !title.is_empty() => format!("{app} – {title}"),
+                        Some(app) => app,
+                        None => title.to_string(),
+                    }
+                })
+                .collect(),
+            selected: cycle.selected,
+        });
+        _ = borders.send((Span::current(), borders::Event::SwitcherChanged(switcher)));
+    }
+}
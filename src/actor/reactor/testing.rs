@@ -0,0 +1,592 @@
+//! Helpers for reactor tests.
+//!
+//! Scenario tests are written against [`Sim`], which drives a reactor and a
+//! set of mock apps that respond to its requests. Apps and windows are
+//! referred to by name:
+//!
+//! ```ignore
+//! let mut sim = Sim::new();
+//! sim.app("Safari").window("Doc1").created();
+//! sim.app("Safari").window("Doc2").created();
+//! sim.settle();
+//! assert_frames!(sim, "Doc1" => rect(0, 0, 600, 1000), "Doc2" => rect(600, 0, 600, 1000));
+//! ```
+//!
+//! Tests for race conditions can call [`Sim::respond`] and [`Sim::deliver`]
+//! separately to interleave other events with the apps' responses.
+//!
+//! With the `bench` feature this is also used by the `nimbus-bench` binary.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    mem,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use core_graphics_types::base::{kCGErrorFailure, kCGErrorIllegalArgument, CGError};
+use icrate::Foundation::{CGPoint, CGSize};
+
+use super::*;
+use crate::{
+    actor::app::{MainWindow, Request},
+    sys::window_server::{WindowServer, WindowServerId, WindowServerInfo},
+};
+
+pub struct Apps(Sender<(Span, Request)>, Receiver<(Span, Request)>);
+impl Apps {
+    pub fn new() -> Apps {
+        let (tx, rx) = channel();
+        Apps(tx, rx)
+    }
+
+    pub fn make_app(&mut self, pid: pid_t, windows: Vec<WindowInfo>) -> Vec<Event> {
+        self.make_app_with_opts(pid, windows, None, false)
+    }
+
+    pub fn make_app_with_opts(
+        &mut self,
+        pid: pid_t,
+        windows: Vec<WindowInfo>,
+        main_window: Option<WindowId>,
+        is_frontmost: bool,
+    ) -> Vec<Event> {
+        let handle = AppThreadHandle::new_for_test(self.0.clone());
+        vec![
+            Event::ApplicationLaunched(
+                pid,
+                AppState {
+                    info: AppInfo {
+                        bundle_id: Some(format!("com.testapp{pid}")),
+                        localized_name: Some(format!("TestApp{pid}")),
+                        bundle_path: Some(format!("/Applications/TestApp{pid}.app")),
+                        icon_path: None,
+                        group: None,
+                    },
+                    handle,
+                    main_window,
+                    is_frontmost,
+                },
+            ),
+            Event::WindowsDiscovered {
+                pid,
+                new: (1..).map(|idx| WindowId::new(pid, idx)).zip(windows).collect(),
+                known_visible: vec![],
+            },
+        ]
+    }
+
+    pub fn requests(&mut self) -> Vec<Request> {
+        self.1.try_iter().map(|(_span, rq)| rq).collect()
+    }
+}
+
+pub fn make_window(idx: usize) -> WindowInfo {
+    WindowInfo {
+        is_standard: true,
+        title: format!("Window{idx}"),
+        frame: CGRect::new(
+            CGPoint::new(100.0 * f64::from(idx as u32), 100.0),
+            CGSize::new(50.0, 50.0),
+        ),
+        sys_id: WindowServerId::new(idx as u32),
+        subrole: "AXStandardWindow".to_string(),
+        level: 0,
+        is_resizable: true,
+        is_minimized: false,
+        is_fullscreen: false,
+        parent: None,
+        is_pip: false,
+    }
+}
+
+pub fn make_windows(count: usize) -> Vec<WindowInfo> {
+    (1..=count).map(make_window).collect()
+}
+
+/// The state of a window in a mock app.
+#[derive(Default, PartialEq, Debug)]
+pub struct WindowState {
+    pub last_seen_txid: TransactionId,
+    pub animating: bool,
+    pub frame: CGRect,
+}
+
+/// Simulates how apps respond to requests from the reactor, and what the
+/// window server reports about their windows.
+///
+/// Window state is kept across calls to [`MockWindows::handle_requests`].
+#[derive(Default, Debug)]
+pub struct MockWindows {
+    pub windows: BTreeMap<WindowId, WindowState>,
+    /// The windows added with [`MockWindows::add_window`] as the window
+    /// server sees them, starting with the frontmost. Frames are those the
+    /// windows were created with; later ones are in `windows`.
+    pub server_windows: Vec<(WindowId, WindowServerInfo)>,
+    /// Windows that were closed; requests for them are ignored.
+    pub destroyed: HashSet<WindowId>,
+    /// Windows moved to the front of their level, in order.
+    pub ordered_front: Vec<WindowId>,
+    /// Windows that were animated into place, in order.
+    pub animated: Vec<WindowId>,
+    /// Windows raised without activating their app, in order.
+    pub raised_only: Vec<WindowId>,
+    /// Titles of the closed windows apps were asked to reopen, in order.
+    pub reopened: Vec<String>,
+    /// The appearance last set for each window.
+    pub appearances: BTreeMap<WindowId, WindowAppearance>,
+    /// Whether the window server refuses to change how windows look, as it
+    /// does for other apps' windows on many systems.
+    pub refuses_appearance: bool,
+}
+
+impl MockWindows {
+    /// Adds a window the app just created in front of the others.
+    pub fn add_window(&mut self, wid: WindowId, info: &WindowInfo) {
+        let info = WindowServerInfo {
+            id: info.sys_id,
+            pid: wid.pid,
+            frame: info.frame,
+        };
+        self.server_windows.insert(0, (wid, info));
+    }
+
+    /// Moves a window to the front of the others.
+    pub fn raise(&mut self, wid: WindowId) {
+        let Some(idx) = self.server_windows.iter().position(|(w, _)| *w == wid) else {
+            return;
+        };
+        let window = self.server_windows.remove(idx);
+        self.server_windows.insert(0, window);
+    }
+
+    /// Applies `requests` and returns the events the apps would send in
+    /// response.
+    pub fn handle_requests(&mut self, requests: Vec<Request>) -> Vec<Event> {
+        let mut events = vec![];
+        for request in requests {
+            if request_window(&request).is_some_and(|wid| self.destroyed.contains(&wid)) {
+                // The app would fail to find the window.
+                continue;
+            }
+            match request {
+                Request::GetVisibleWindows | Request::GetActivationState | Request::Rewatch => {}
+                Request::SetWindowFrame(wid, frame, txid) => {
+                    let window = self.windows.entry(wid).or_default();
+                    window.last_seen_txid = txid;
+                    let old_frame = window.frame;
+                    window.frame = frame;
+                    if !window.animating && !old_frame.same_as(frame) {
+                        events.push(Event::WindowFrameChanged(wid, frame, txid, Requested(true)));
+                    }
+                }
+                Request::SetWindowPos(wid, pos, txid) => {
+                    let window = self.windows.entry(wid).or_default();
+                    window.last_seen_txid = txid;
+                    let old_frame = window.frame;
+                    window.frame.origin = pos;
+                    if !window.animating && !old_frame.same_as(window.frame) {
+                        events.push(Event::WindowFrameChanged(
+                            wid,
+                            window.frame,
+                            txid,
+                            Requested(true),
+                        ));
+                    }
+                }
+                Request::HideWindow(wid) => {
+                    // Simulate the move notification sent by the app.
+                    let window = self.windows.entry(wid).or_default();
+                    window.frame.origin = CGPoint::new(5000., 5000.);
+                    events.push(Event::WindowFrameChanged(
+                        wid,
+                        window.frame,
+                        window.last_seen_txid,
+                        Requested(false),
+                    ));
+                }
+                Request::BeginWindowAnimation(wid) => {
+                    self.windows.entry(wid).or_default().animating = true;
+                    self.animated.push(wid);
+                }
+                Request::EndWindowAnimation(wid) => {
+                    let window = self.windows.entry(wid).or_default();
+                    window.animating = false;
+                    events.push(Event::WindowFrameChanged(
+                        wid,
+                        window.frame,
+                        window.last_seen_txid,
+                        Requested(true),
+                    ));
+                }
+                Request::Raise(wid, _) | Request::RaiseWindowOnly(wid, _) => {
+                    if matches!(request, Request::RaiseWindowOnly(..)) {
+                        self.raised_only.push(wid);
+                    }
+                    self.raise(wid);
+                    events.push(Event::ApplicationGloballyActivated(wid.pid));
+                    events.push(Event::ApplicationActivated(wid.pid, Some(wid)));
+                }
+                Request::OrderFront(wid) => {
+                    self.raise(wid);
+                    self.ordered_front.push(wid);
+                }
+                Request::ReopenWindow(title, _) => self.reopened.push(title),
+            }
+        }
+        events
+    }
+}
+
+impl MockWindows {
+    fn visible_windows(&self) -> Vec<WindowServerInfo> {
+        self.server_windows
+            .iter()
+            .filter(|(wid, _)| !self.destroyed.contains(wid))
+            .map(|(wid, info)| WindowServerInfo {
+                frame: self.windows.get(wid).map_or(info.frame, |window| window.frame),
+                ..info.clone()
+            })
+            .collect()
+    }
+
+    fn windows(&self, ids: &[WindowServerId]) -> Vec<WindowServerInfo> {
+        let mut windows = self.visible_windows();
+        windows.retain(|info| ids.contains(&info.id));
+        windows
+    }
+
+    fn set_appearance(
+        &mut self,
+        id: WindowServerId,
+        appearance: WindowAppearance,
+    ) -> Result<(), CGError> {
+        if self.refuses_appearance {
+            return Err(kCGErrorFailure);
+        }
+        let &(wid, _) = self
+            .server_windows
+            .iter()
+            .find(|(_, info)| info.id == id)
+            .ok_or(kCGErrorIllegalArgument)?;
+        self.appearances.insert(wid, appearance);
+        Ok(())
+    }
+}
+
+/// Lets the reactor query mock windows that the test keeps changing.
+impl WindowServer for Mutex<MockWindows> {
+    fn visible_windows(&self) -> Vec<WindowServerInfo> {
+        self.lock().unwrap().visible_windows()
+    }
+
+    fn windows(&self, ids: &[WindowServerId]) -> Vec<WindowServerInfo> {
+        self.lock().unwrap().windows(ids)
+    }
+
+    fn set_appearance(
+        &self,
+        id: WindowServerId,
+        appearance: WindowAppearance,
+    ) -> Result<(), CGError> {
+        self.lock().unwrap().set_appearance(id, appearance)
+    }
+}
+
+fn request_window(request: &Request) -> Option<WindowId> {
+    match request {
+        Request::GetVisibleWindows
+        | Request::GetActivationState
+        | Request::Rewatch
+        | Request::ReopenWindow(..) => None,
+        Request::SetWindowFrame(wid, ..)
+        | Request::SetWindowPos(wid, ..)
+        | Request::HideWindow(wid)
+        | Request::BeginWindowAnimation(wid)
+        | Request::EndWindowAnimation(wid)
+        | Request::Raise(wid, _)
+        | Request::RaiseWindowOnly(wid, _)
+        | Request::OrderFront(wid) => Some(*wid),
+    }
+}
+
+pub fn simulate_events_for_requests(
+    requests: Vec<Request>,
+) -> (Vec<Event>, BTreeMap<WindowId, WindowState>) {
+    let mut mock = MockWindows::default();
+    let events = mock.handle_requests(requests);
+    (events, mock.windows)
+}
+
+pub fn rect(x: i32, y: i32, width: i32, height: i32) -> CGRect {
+    CGRect::new(
+        CGPoint::new(x.into(), y.into()),
+        CGSize::new(width.into(), height.into()),
+    )
+}
+
+/// A reactor with a single 1200x1000 screen, connected to mock apps and a
+/// mock window server.
+pub struct Sim {
+    pub reactor: Reactor,
+    mock: Arc<Mutex<MockWindows>>,
+    apps: Apps,
+    /// Events sent by the mock apps that the reactor has not seen yet.
+    pending: Vec<Event>,
+    pids: HashMap<String, pid_t>,
+    windows: HashMap<String, WindowId>,
+    next_window_idx: HashMap<pid_t, u32>,
+}
+
+impl Sim {
+    pub fn new() -> Sim {
+        let mock = Arc::new(Mutex::new(MockWindows::default()));
+        let mut reactor = Reactor::new_for_test();
+        reactor.window_server = mock.clone();
+        let mut sim = Sim {
+            reactor,
+            mock,
+            apps: Apps::new(),
+            pending: vec![],
+            pids: HashMap::new(),
+            windows: HashMap::new(),
+            next_window_idx: HashMap::new(),
+        };
+        sim.event(Event::ScreenParametersChanged(
+            vec![rect(0, 0, 1200, 1000)],
+            vec![Some(Sim::space())],
+        ));
+        sim
+    }
+
+    pub fn space() -> SpaceId {
+        SpaceId::new(1)
+    }
+
+    /// Returns the app with the given name, launching it if needed.
+    pub fn app(&mut self, name: &str) -> AppSim<'_> {
+        let pid = match self.pids.get(name) {
+            Some(&pid) => pid,
+            None => {
+                let pid = self.pids.len() as pid_t + 1;
+                self.pids.insert(name.to_string(), pid);
+                let events = self.apps.make_app(pid, vec![]);
+                self.reactor.handle_events(events);
+                pid
+            }
+        };
+        AppSim { sim: self, pid }
+    }
+
+    /// Returns a window that was created earlier.
+    pub fn window(&mut self, title: &str) -> WindowSim<'_> {
+        let wid = self.wid(title);
+        WindowSim {
+            sim: self,
+            wid,
+            title: title.to_string(),
+        }
+    }
+
+    pub fn wid(&self, title: &str) -> WindowId {
+        *self.windows.get(title).unwrap_or_else(|| panic!("No window named {title:?}"))
+    }
+
+    /// Returns the mock apps and window server. The reactor can't query the
+    /// window server while this is held.
+    pub fn mock(&self) -> MutexGuard<'_, MockWindows> {
+        self.mock.lock().unwrap()
+    }
+
+    /// Returns the frame of the window in its mock app.
+    pub fn frame(&self, title: &str) -> CGRect {
+        match self.mock().windows.get(&self.wid(title)) {
+            Some(window) => window.frame,
+            None => panic!("Window {title:?} was never moved"),
+        }
+    }
+
+    pub fn event(&mut self, event: Event) {
+        self.reactor.handle_event(event);
+    }
+
+    pub fn command(&mut self, command: Command) {
+        self.event(Event::Command(command));
+    }
+
+    pub fn command_result(&mut self, command: Command) -> CommandResult {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.event(Event::CommandWithResult(command, tx));
+        rx.recv().unwrap()
+    }
+
+    /// Lets the mock apps handle the reactor's requests. The events they
+    /// send back are held until [`Sim::deliver`].
+    pub fn respond(&mut self) {
+        let requests = self.apps.requests();
+        let events = self.mock().handle_requests(requests);
+        self.pending.extend(events);
+    }
+
+    /// Delivers the events held by [`Sim::respond`] to the reactor.
+    pub fn deliver(&mut self) {
+        for event in mem::take(&mut self.pending) {
+            self.reactor.handle_event(event);
+        }
+    }
+
+    /// Responds to requests and delivers the results until the reactor stops
+    /// sending requests.
+    pub fn settle(&mut self) {
+        for _ in 0..100 {
+            self.respond();
+            if self.pending.is_empty() {
+                return;
+            }
+            self.deliver();
+        }
+        panic!("Reactor did not settle");
+    }
+}
+
+pub struct AppSim<'a> {
+    sim: &'a mut Sim,
+    pid: pid_t,
+}
+
+impl<'a> AppSim<'a> {
+    /// Returns the window of this app with the given title. The window does
+    /// not exist until [`WindowSim::created`] is called.
+    pub fn window(self, title: &str) -> WindowSim<'a> {
+        let wid = match self.sim.windows.get(title) {
+            Some(&wid) => wid,
+            None => {
+                let idx = self.sim.next_window_idx.entry(self.pid).or_insert(0);
+                *idx += 1;
+                let wid = WindowId::new(self.pid, *idx);
+                self.sim.windows.insert(title.to_string(), wid);
+                wid
+            }
+        };
+        WindowSim {
+            sim: self.sim,
+            wid,
+            title: title.to_string(),
+        }
+    }
+
+    /// The app becomes frontmost with the given main window.
+    pub fn activated(self, main_window: Option<&str>) {
+        let main_window = main_window.map(|title| self.sim.wid(title));
+        if let Some(wid) = main_window {
+            self.sim.mock().raise(wid);
+        }
+        self.sim.event(Event::ApplicationGloballyActivated(self.pid));
+        self.sim.event(Event::ApplicationActivated(self.pid, main_window));
+    }
+
+    /// The main window of the app changes without the app being activated
+    /// again, as when cycling its windows with Cmd+`. A window that was never
+    /// created is reported the way the app actor finds it: as a new window.
+    pub fn main_window_changed(self, title: &str) {
+        let pid = self.pid;
+        let WindowSim { sim, wid, title } = self.window(title);
+        let main = if sim.reactor.windows.contains_key(&wid) {
+            MainWindow::Known(wid)
+        } else {
+            let mut info = make_window(sim.windows.len());
+            info.title = title;
+            sim.mock().add_window(wid, &info);
+            MainWindow::New(wid, info)
+        };
+        sim.mock().raise(wid);
+        for event in main.into_events(pid) {
+            sim.event(event);
+        }
+    }
+
+    pub fn terminated(self) {
+        self.sim.event(Event::ApplicationTerminated(self.pid));
+    }
+}
+
+pub struct WindowSim<'a> {
+    sim: &'a mut Sim,
+    wid: WindowId,
+    title: String,
+}
+
+impl<'a> WindowSim<'a> {
+    pub fn created(self) -> WindowId {
+        // Window server ids are unique across apps.
+        let mut info = make_window(self.sim.windows.len());
+        info.title = self.title;
+        self.sim.mock().add_window(self.wid, &info);
+        self.sim.event(Event::WindowCreated(self.wid, info));
+        self.wid
+    }
+
+    /// Creates the window as a dialog belonging to `parent`.
+    pub fn created_as_dialog_of(self, parent: &str) -> WindowId {
+        let parent = self.sim.reactor.windows[&self.sim.wid(parent)].window_server_id;
+        let mut info = make_window(self.sim.windows.len());
+        info.title = self.title;
+        info.is_standard = false;
+        info.subrole = "AXDialog".to_string();
+        info.parent = Some(parent);
+        self.sim.mock().add_window(self.wid, &info);
+        self.sim.event(Event::WindowCreated(self.wid, info));
+        self.wid
+    }
+
+    /// Creates the window as a Picture-in-Picture window.
+    pub fn created_as_pip(self) -> WindowId {
+        let mut info = make_window(self.sim.windows.len());
+        info.title = self.title;
+        info.is_pip = true;
+        self.sim.mock().add_window(self.wid, &info);
+        self.sim.event(Event::WindowCreated(self.wid, info));
+        self.wid
+    }
+
+    pub fn destroyed(self) {
+        self.sim.mock().destroyed.insert(self.wid);
+        self.sim.event(Event::WindowDestroyed(self.wid));
+    }
+
+    /// The user moves or resizes the window.
+    pub fn moved_to(self, frame: CGRect) {
+        let txid = {
+            let mut mock = self.sim.mock();
+            let window = mock.windows.entry(self.wid).or_default();
+            window.frame = frame;
+            window.last_seen_txid
+        };
+        self.sim.event(Event::WindowFrameChanged(
+            self.wid,
+            frame,
+            txid,
+            Requested(false),
+        ));
+    }
+}
+
+/// Asserts that each named window has the given frame in its mock app.
+#[cfg(test)]
+macro_rules! assert_frames {
+    ($sim:expr, $($title:expr => $frame:expr),+ $(,)?) => {{
+        $(
+            let actual = $sim.frame($title);
+            let expected: CGRect = $frame;
+            assert!(
+                actual.same_as(expected),
+                "frame of {:?} was {actual:?}, expected {expected:?}",
+                $title,
+            );
+        )+
+    }};
+}
+#[cfg(test)]
+pub(crate) use assert_frames;
@@ -0,0 +1,166 @@
+//! This actor shows invisible handles on the boundaries between tiles, which
+//! can be dragged with the mouse to resize the tiles on either side.
+//!
+//! The reactor tells us where the boundaries are whenever the layout changes.
+//! Drags are sent back to the reactor as they happen, so the windows follow
+//! the mouse, or an outline of the boundary does if resizes are previewed.
+
+use icrate::Foundation::{CGPoint, CGRect, CGSize, MainThreadMarker};
+use tracing::{info_span, warn, Span};
+
+use crate::{
+    actor::reactor,
+    model::{Orientation, Split},
+    sys::{
+        event::{MouseEvent, MouseTap},
+        geometry::Contains,
+        overlay::{Color, OverlayWindow},
+    },
+};
+
+pub type Sender = tokio::sync::mpsc::UnboundedSender<(Span, Event)>;
+type WeakSender = tokio::sync::mpsc::WeakUnboundedSender<(Span, Event)>;
+pub type Receiver = tokio::sync::mpsc::UnboundedReceiver<(Span, Event)>;
+
+/// Creates the channel for the actor. The reactor is created with the sender
+/// before the actor itself, which needs the reactor's sender.
+pub fn channel() -> (Sender, Receiver) {
+    tokio::sync::mpsc::unbounded_channel()
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// The boundaries between tiles on the main screen changed.
+    SplitsChanged(Vec<Split>),
+    Mouse(MouseEvent),
+}
+
+/// How far the handles extend on each side of a boundary, in points.
+const HANDLE_RADIUS: f64 = 4.0;
+
+pub struct SplitHandles {
+    events_tx: reactor::Sender,
+    receiver: Receiver,
+    sender: WeakSender,
+    splits: Vec<Split>,
+    /// One window for each split, reused as the splits change.
+    windows: Vec<OverlayWindow>,
+    mouse_tap: Option<MouseTap>,
+    drag: Option<Drag>,
+}
+
+struct Drag {
+    split: Split,
+    last_position: CGPoint,
+}
+
+impl SplitHandles {
+    pub fn new(events_tx: reactor::Sender, sender: &Sender, receiver: Receiver) -> Self {
+        SplitHandles {
+            events_tx,
+            receiver,
+            sender: sender.downgrade(),
+            splits: vec![],
+            windows: vec![],
+            mouse_tap: None,
+            drag: None,
+        }
+    }
+
+    pub async fn run(mut self) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            warn!("Split handles must run on the main thread");
+            return;
+        };
+        let sender = self.sender.clone();
+        self.mouse_tap = MouseTap::new(move |event| {
+            // Moves are frequent, and only drags matter here.
+            if let MouseEvent::Moved(_) = event {
+                return;
+            }
+            let Some(sender) = sender.upgrade() else { return };
+            _ = sender.send((Span::none(), Event::Mouse(event)));
+        });
+        if self.mouse_tap.is_none() {
+            // Without the tap the handles would only get in the way.
+            warn!("Could not watch the mouse; split handles are disabled");
+            return;
+        }
+        while let Some((span, event)) = self.receiver.recv().await {
+            let _guard = span.enter();
+            self.handle_event(event, mtm);
+        }
+    }
+
+    fn handle_event(&mut self, event: Event, mtm: MainThreadMarker) {
+        match event {
+            Event::SplitsChanged(splits) => self.show_handles(splits, mtm),
+            Event::Mouse(MouseEvent::Down(position)) => {
+                self.drag = self
+                    .splits
+                    .iter()
+                    .find(|split| handle_frame(split).contains(position))
+                    .map(|&split| Drag { split, last_position: position });
+            }
+            Event::Mouse(MouseEvent::Dragged(position)) => {
+                let Some(drag) = &mut self.drag else { return };
+                let delta = match drag.split.orientation {
+                    Orientation::Horizontal => position.x - drag.last_position.x,
+                    Orientation::Vertical => position.y - drag.last_position.y,
+                };
+                if delta == 0.0 {
+                    return;
+                }
+                drag.last_position = position;
+                let span = info_span!("split_handles::dragged", ?delta);
+                _ = self.events_tx.send((
+                    span,
+                    reactor::Event::SplitDragged {
+                        node: drag.split.node,
+                        orientation: drag.split.orientation,
+                        delta,
+                    },
+                ));
+            }
+            Event::Mouse(MouseEvent::Up(_)) => {
+                if self.drag.take().is_some() {
+                    let span = info_span!("split_handles::drag_ended");
+                    _ = self.events_tx.send((span, reactor::Event::SplitDragEnded));
+                }
+            }
+            Event::Mouse(MouseEvent::Moved(_)) => (),
+        }
+    }
+
+    fn show_handles(&mut self, splits: Vec<Split>, mtm: MainThreadMarker) {
+        while self.windows.len() < splits.len() {
+            let window = OverlayWindow::new(mtm);
+            window.set_background(Color::CLEAR);
+            window.set_ignores_mouse(false);
+            self.windows.push(window);
+        }
+        for (window, split) in self.windows.iter().zip(&splits) {
+            window.set_frame(handle_frame(split));
+            window.show();
+        }
+        for window in &self.windows[splits.len()..] {
+            window.hide();
+        }
+        self.splits = splits;
+    }
+}
+
+/// The area around a split that can be grabbed.
+fn handle_frame(split: &Split) -> CGRect {
+    let frame = split.frame;
+    match split.orientation {
+        Orientation::Horizontal => CGRect::new(
+            CGPoint::new(frame.origin.x - HANDLE_RADIUS, frame.origin.y),
+            CGSize::new(2.0 * HANDLE_RADIUS, frame.size.height),
+        ),
+        Orientation::Vertical => CGRect::new(
+            CGPoint::new(frame.origin.x, frame.origin.y - HANDLE_RADIUS),
+            CGSize::new(frame.size.width, 2.0 * HANDLE_RADIUS),
+        ),
+    }
+}
@@ -2,63 +2,381 @@
 //! window manager on certain spaces and launching app threads. It also
 //! controls hotkey registration.
 
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+    thread,
+    time::{Duration, Instant},
+};
 
 use accessibility_sys::pid_t;
-use tracing::{debug, instrument, Span};
+use icrate::Foundation::{CGPoint, CGRect, CGSize, MainThreadMarker};
+use tracing::{debug, info, info_span, instrument, warn, Span};
 
 pub type Sender = tokio::sync::mpsc::UnboundedSender<(Span, WmEvent)>;
-type WeakSender = tokio::sync::mpsc::WeakUnboundedSender<(Span, WmEvent)>;
+pub type WeakSender = tokio::sync::mpsc::WeakUnboundedSender<(Span, WmEvent)>;
 type Receiver = tokio::sync::mpsc::UnboundedReceiver<(Span, WmEvent)>;
 
 use crate::{
-    actor::{self, app::AppInfo, reactor},
-    sys::{hotkey::HotkeyManager, screen::SpaceId},
+    actor::{self, app::AppInfo, borders, mouse, reactor},
+    config::{
+        self, BindKeysBy, BorderRule, DefaultSpaceActivation, DisplayConfig, OverlayConfig,
+        PowerConfig, ProfileConfig, SpaceActivationConfig,
+    },
+    crash_loop,
+    metrics::{self, MetricsCommand},
+    sys::{
+        app::running_apps,
+        appearance::Appearance,
+        clock, dock,
+        event::{InputTap, KeyPressTap},
+        focus,
+        hotkey::{describe_hotkey, parse_hotkey, Hotkey, HotkeyManager, KeyRepeat, Modifiers},
+        idle::IdleMonitor,
+        keyboard::KeyboardLayout,
+        overlay::{OverlayWindow, Palette},
+        permissions::{self, Permission},
+        power,
+        process::ExitWatcher,
+        screen::{self, DisplayInfo, DisplayUuid, SpaceId, SpaceInfo, SpaceUuid},
+    },
+    update::{self, Release},
 };
 
+/// Whether to manage `space`, going by the config and the spaces the user
+/// turned off. Safe mode is up to the caller.
+///
+/// `is_starting` is whether the space was active on its display when we
+/// started or the display was connected, and `is_new` whether it was created
+/// while we were running.
+pub fn is_space_activated(
+    config: &SpaceActivationConfig,
+    display: Option<&DisplayConfig>,
+    disabled_spaces: &HashSet<SpaceUuid>,
+    space: &SpaceInfo,
+    is_starting: bool,
+    is_new: bool,
+) -> bool {
+    // Full screen apps and Split View manage their own windows.
+    if !space.is_user_space
+        || disabled_spaces.contains(&space.uuid)
+        || display.is_some_and(|c| c.disabled)
+    {
+        return false;
+    }
+    is_enabled_by_config(config, space, is_starting, is_new)
+}
+
+/// Whether the space activation config alone turns on `space`.
+fn is_enabled_by_config(
+    config: &SpaceActivationConfig,
+    space: &SpaceInfo,
+    is_starting: bool,
+    is_new: bool,
+) -> bool {
+    let is_default = match config.default {
+        DefaultSpaceActivation::All => true,
+        DefaultSpaceActivation::StartingSpaces => is_starting,
+        DefaultSpaceActivation::None => false,
+    };
+    is_default
+        || (config.new_spaces && is_new)
+        || config.spaces.contains(&space.uuid)
+        || config.displays.contains(&space.display)
+}
+
 #[derive(Debug)]
 pub enum WmEvent {
     AppEventsRegistered,
     AppLaunch(pid_t, AppInfo),
+    /// The connected displays, in the same order as the screens in the next
+    /// [`reactor::Event::ScreenParametersChanged`].
+    DisplaysChanged(Vec<DisplayInfo>),
+    AppearanceChanged(Appearance),
+    /// The user chose another keyboard layout.
+    KeyboardLayoutChanged,
     ReactorEvent(reactor::Event),
     Command(WmCommand),
+    /// A newer release than the one running was found.
+    UpdateAvailable(Release),
+    /// Shows a notice from another actor, until a key is pressed or it times
+    /// out.
+    ShowNotice(String),
+    /// The user revoked our Accessibility permission, or granted it again.
+    AccessibilityChanged(bool),
+    /// Desktops were added, removed, or reordered in Mission Control. Has the
+    /// spaces on every display, in order.
+    SpacesChanged(Vec<SpaceInfo>),
+    /// Sent every minute while any profile has a schedule, to switch
+    /// profiles when a schedule starts or ends.
+    ScheduleTick,
+    /// Sent every few minutes while the user is active, to check for events
+    /// we missed.
+    AuditTick,
+    /// Sent every second while the timing overlay is shown, to redraw it.
+    TimingTick,
+    /// Sent when a notice shown with a timeout has been up for that long.
+    /// Has the token of the notice, which is ignored if another notice
+    /// replaced it since.
+    NoticeTimedOut(Weak<()>),
+    /// Selects the named profile over any schedule, or goes back to the
+    /// schedule if `None`. Replies with the profile that is then active, or an
+    /// error if there is no profile with that name.
+    UseProfile(
+        Option<String>,
+        std::sync::mpsc::Sender<Result<Option<String>, String>>,
+    ),
 }
 
 #[derive(Debug, Clone)]
 pub enum WmCommand {
     ToggleSpaceActivated,
+    /// Show or hide an overlay listing the registered key bindings.
+    ToggleKeyBindings,
+    HideKeyBindings,
+    HideNotice,
+    /// Show or hide an overlay with live timings of event handling,
+    /// accessibility calls, and animation frames.
+    ToggleTiming,
+    /// Adds a digit to the count for the next reactor command, like in vi:
+    /// 3 followed by MoveFocus(Right) moves focus three windows right.
+    Count(usize),
     ReactorCommand(reactor::Command),
 }
 
 pub struct Config {
-    pub one_space: bool,
+    pub space_activation: SpaceActivationConfig,
+    pub pause_while_screen_sharing: bool,
+    pub bind_keys_by: BindKeysBy,
+    pub key_repeat: Option<KeyRepeat>,
     pub restore_file: PathBuf,
+    pub displays: Vec<DisplayConfig>,
+    pub power: PowerConfig,
+    pub overlays: OverlayConfig,
+    pub check_for_updates: bool,
+    pub watch_focus_mode: bool,
+    /// The border rules outside of any profile.
+    pub border_rules: Vec<BorderRule>,
+    /// The window limits outside of any profile.
+    pub window_limits: HashMap<String, usize>,
+    pub profiles: Vec<ProfileConfig>,
+    /// The profile chosen with `nimbus profile use`, which takes precedence
+    /// over schedules.
+    pub selected_profile: Option<String>,
+    /// Start with every space disabled, whatever the space activation config
+    /// says, so that spaces can be turned on one at a time.
+    pub safe_mode: bool,
+    /// The spaces the user turned off before the last restart.
+    pub disabled_spaces: HashSet<SpaceUuid>,
+    /// If safe mode was entered because nimbus kept restarting, how often it
+    /// restarted, which the user is told about.
+    pub crash_loop: Option<crash_loop::Threshold>,
+}
+
+/// How often to check whether a profile's schedule started or ended.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often to compare what we know about spaces and windows with the
+/// system.
+const AUDIT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The highest count for a command, which keeps a mistyped count from
+/// running a command for a long time.
+const MAX_COUNT: usize = 99;
+
+/// How long a typed count waits for a command before it is dropped.
+const COUNT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the timing overlay is redrawn.
+const TIMING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long notices that don't need action, like the update notice, stay up
+/// if no key is pressed. Without Input Monitoring access this is the only way
+/// they go away.
+const NOTICE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a notice is about, so it can be hidden when that is resolved without
+/// hiding an unrelated notice shown since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NoticeKind {
+    Update,
+    SafeMode,
+    AccessibilityLost,
+    /// A notice sent by another actor with [`WmEvent::ShowNotice`].
+    Other,
+}
+
+/// A notice shown with [`WmController::show_notice`].
+struct Notice {
+    kind: NoticeKind,
+    overlay: OverlayWindow,
+    _tap: Option<KeyPressTap>,
+    /// Identifies the notice to the thread that hides it after a timeout.
+    token: Arc<()>,
 }
 
 pub struct WmController {
     config: Config,
     events_tx: reactor::Sender,
+    mouse_tx: mouse::Sender,
+    borders_tx: borders::Sender,
     receiver: Receiver,
     sender: WeakSender,
-    starting_space: Option<SpaceId>,
+    /// The first space we saw on each display.
+    starting_spaces: HashMap<DisplayUuid, SpaceId>,
+    /// Spaces that existed when we first saw the space configuration.
+    initial_spaces: Option<HashSet<SpaceId>>,
+    /// The spaces on every display, in Mission Control order.
+    known_spaces: Vec<SpaceInfo>,
     cur_space: Vec<Option<SpaceId>>,
-    disabled_spaces: HashSet<SpaceId>,
+    /// The display showing each screen, starting with the main screen.
+    displays: Vec<DisplayInfo>,
+    /// The spaces the user turned off.
+    disabled_spaces: HashSet<SpaceUuid>,
+    /// In safe mode, the spaces the user turned back on. No other spaces are
+    /// managed.
+    safe_mode_spaces: Option<HashSet<SpaceId>>,
     hotkeys: Option<HotkeyManager>,
+    exit_watcher: Option<ExitWatcher>,
+    /// Wakes background threads paused by the idle monitor.
+    _input_tap: Option<InputTap>,
+    key_bindings_overlay: Option<(OverlayWindow, Option<KeyPressTap>)>,
+    /// An update, safe mode, lost permission, or forwarded notice.
+    notice: Option<Notice>,
+    /// The timing overlay, and a token that keeps the thread redrawing it
+    /// running until the overlay is hidden.
+    timing_overlay: Option<(OverlayWindow, Arc<()>)>,
+    /// The colors for overlays in the current appearance.
+    palette: Palette,
+    /// The name of the active profile, if any.
+    profile: Option<String>,
+    /// The frames of the screens before room is left for external bars.
+    screen_frames: Vec<CGRect>,
+    /// The count typed for the next reactor command, if any, and when its
+    /// last digit was typed.
+    count: Option<(usize, Instant)>,
 }
 
 impl WmController {
-    pub fn new(config: Config, events_tx: reactor::Sender) -> (Self, Sender) {
+    pub fn new(
+        config: Config,
+        events_tx: reactor::Sender,
+        mouse_tx: mouse::Sender,
+        borders_tx: borders::Sender,
+    ) -> (Self, Sender) {
         let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
-        let this = Self {
+        // Apps that crash don't send any notifications, so watch for their
+        // processes to exit.
+        let exit_tx = events_tx.clone();
+        let exit_watcher = ExitWatcher::spawn(move |pid| {
+            let span = info_span!("wm_controller::process_exited", ?pid);
+            _ = exit_tx.send((span, reactor::Event::ApplicationTerminated(pid)));
+        })
+        .map_err(|e| warn!("Could not watch for process exits: {e}"))
+        .ok();
+        let idle = IdleMonitor::new(config.power.idle_after());
+        let input_tap = idle.watch_input();
+        if config.pause_while_screen_sharing {
+            let capture_tx = events_tx.clone();
+            screen::watch_screen_capture(Duration::from_secs(2), idle.clone(), move |captured| {
+                let span = info_span!("wm_controller::screen_capture_changed", ?captured);
+                _ = capture_tx.send((span, reactor::Event::ScreenCaptureChanged(captured)));
+            });
+        }
+        let power_tx = events_tx.clone();
+        let power_config = config.power.clone();
+        power::watch_power_source(Duration::from_secs(30), idle.clone(), move |source| {
+            let animations = power_config.animation_mode(source);
+            let span = info_span!("wm_controller::power_source_changed", ?source, ?animations);
+            let power = reactor::PowerState { source, animations };
+            _ = power_tx.send((span, reactor::Event::PowerStateChanged(power)));
+        });
+        if config.watch_focus_mode {
+            let focus_tx = events_tx.clone();
+            focus::watch_focus(Duration::from_secs(5), idle.clone(), move |active| {
+                let span = info_span!("wm_controller::focus_mode_changed", ?active);
+                _ = focus_tx.send((span, reactor::Event::FocusModeChanged(active)));
+            });
+        }
+        let attention_tx = events_tx.clone();
+        dock::watch_for_attention(Duration::from_millis(500), idle.clone(), move |pid| {
+            let span = info_span!("wm_controller::app_requested_attention", ?pid);
+            _ = attention_tx.send((span, reactor::Event::ApplicationRequestedAttention(pid)));
+        });
+        let permission_tx = sender.clone();
+        permissions::watch_permission(
+            Permission::Accessibility,
+            Duration::from_secs(2),
+            idle.clone(),
+            move |granted| {
+                let span = info_span!("wm_controller::accessibility_changed", ?granted);
+                _ = permission_tx.send((span, WmEvent::AccessibilityChanged(granted)));
+            },
+        );
+        let spaces_tx = sender.clone();
+        screen::watch_spaces(Duration::from_secs(2), idle.clone(), move |spaces| {
+            let span = info_span!("wm_controller::spaces_changed");
+            _ = spaces_tx.send((span, WmEvent::SpacesChanged(spaces)));
+        });
+        let audit_tx = sender.clone();
+        thread::spawn(move || loop {
+            thread::sleep(AUDIT_INTERVAL);
+            idle.wait_until_active();
+            let span = info_span!("wm_controller::audit_tick");
+            if audit_tx.send((span, WmEvent::AuditTick)).is_err() {
+                break;
+            }
+        });
+        if config.profiles.iter().any(|profile| profile.schedule.is_some()) {
+            let tick_tx = sender.clone();
+            thread::spawn(move || loop {
+                thread::sleep(SCHEDULE_INTERVAL);
+                let span = info_span!("wm_controller::schedule_tick");
+                if tick_tx.send((span, WmEvent::ScheduleTick)).is_err() {
+                    break;
+                }
+            });
+        }
+        if config.check_for_updates {
+            let update_tx = sender.clone();
+            update::watch_for_updates(move |release| {
+                let span = info_span!("wm_controller::update_available", ?release);
+                _ = update_tx.send((span, WmEvent::UpdateAvailable(release)));
+            });
+        }
+        let safe_mode_spaces = config.safe_mode.then(HashSet::new);
+        let disabled_spaces = config.disabled_spaces.clone();
+        let mut this = Self {
             config,
             events_tx,
+            mouse_tx,
+            borders_tx,
             receiver,
             sender: sender.downgrade(),
-            starting_space: None,
+            starting_spaces: HashMap::new(),
+            initial_spaces: None,
+            known_spaces: Vec::new(),
             cur_space: Vec::new(),
-            disabled_spaces: HashSet::new(),
+            displays: Vec::new(),
+            disabled_spaces,
+            safe_mode_spaces,
             hotkeys: None,
+            exit_watcher,
+            _input_tap: input_tap,
+            key_bindings_overlay: None,
+            notice: None,
+            timing_overlay: None,
+            palette: Palette::default_for(Appearance::default()),
+            profile: None,
+            screen_frames: Vec::new(),
+            count: None,
         };
+        this.update_profile();
+        this.send_event(reactor::Event::WmControllerStarted(sender.downgrade()));
+        if let Some(threshold) = this.config.crash_loop {
+            this.show_crash_loop_notice(threshold);
+        }
         (this, sender)
     }
 
@@ -75,14 +393,48 @@ impl WmController {
         use self::WmCommand::*;
         use self::WmEvent::*;
         use reactor::Event;
+        if let Command(cmd) = &event {
+            if !matches!(cmd, Count(_) | ReactorCommand(_)) {
+                // A count only applies to the command bound right after it.
+                self.count = None;
+            }
+        }
         match event {
             AppEventsRegistered => {
-                actor::app::spawn_initial_app_threads(self.events_tx.clone());
+                for (pid, info) in running_apps(None) {
+                    self.launch_app(pid, info);
+                }
             }
-            AppLaunch(pid, info) => {
-                actor::app::spawn_app_thread(pid, info, self.events_tx.clone());
+            AppLaunch(pid, info) => self.launch_app(pid, info),
+            KeyboardLayoutChanged => {
+                if self.config.bind_keys_by == BindKeysBy::Character && self.hotkeys.is_some() {
+                    self.unregister_hotkeys();
+                    self.register_hotkeys();
+                }
+            }
+            DisplaysChanged(displays) => self.displays = displays,
+            AppearanceChanged(appearance) => {
+                self.palette = self.config.overlays.palette(appearance);
+                let event = borders::Event::PaletteChanged(self.palette);
+                _ = self.borders_tx.send((Span::current().clone(), event));
+                if self.key_bindings_overlay.is_some() {
+                    // Redraw the overlay in the new colors.
+                    self.hide_key_bindings();
+                    self.show_key_bindings();
+                }
+                if let Some((overlay, _)) = &self.timing_overlay {
+                    overlay.set_background(self.palette.background);
+                }
             }
             ReactorEvent(mut event) => {
+                if let Event::ScreenParametersChanged(frames, _) = &mut event {
+                    self.screen_frames = frames.clone();
+                    // Hot corners are at the real corners of the screen,
+                    // regardless of any space left for bars.
+                    let mouse_event = mouse::Event::ScreenParametersChanged(frames.clone());
+                    _ = self.mouse_tx.send((Span::current().clone(), mouse_event));
+                    self.apply_display_config(frames);
+                }
                 if let Event::SpaceChanged(spaces) | Event::ScreenParametersChanged(_, spaces) =
                     &mut event
                 {
@@ -94,95 +446,523 @@ impl WmController {
             Command(ToggleSpaceActivated) => {
                 for space in &self.cur_space {
                     let Some(space) = space else { return };
-                    if !self.disabled_spaces.remove(space) {
-                        self.disabled_spaces.insert(*space);
+                    if let Some(enabled) = &mut self.safe_mode_spaces {
+                        if !enabled.remove(space) {
+                            enabled.insert(*space);
+                        }
+                    } else if let Some(info) = self.space_info(*space) {
+                        let uuid = info.uuid.clone();
+                        if !self.disabled_spaces.remove(&uuid) {
+                            self.disabled_spaces.insert(uuid);
+                        }
                     }
                 }
+                let disabled = self.disabled_spaces.clone();
+                self.send_event(Event::DisabledSpacesChanged(disabled));
                 let mut spaces = self.cur_space.clone();
                 self.apply_space_activation(&mut spaces);
                 self.send_event(Event::SpaceChanged(spaces));
             }
+            Command(ToggleKeyBindings) => {
+                if self.key_bindings_overlay.is_some() {
+                    self.hide_key_bindings();
+                } else {
+                    self.show_key_bindings();
+                }
+            }
+            Command(HideKeyBindings) => self.hide_key_bindings(),
+            UpdateAvailable(release) => self.show_update_notice(&release),
+            ShowNotice(text) => self.show_notice(NoticeKind::Other, &text, Some(NOTICE_TIMEOUT)),
+            AccessibilityChanged(granted) => {
+                self.send_event(Event::AccessibilityChanged(granted));
+                if granted {
+                    self.hide_notice_of(NoticeKind::AccessibilityLost);
+                } else {
+                    self.show_notice(
+                        NoticeKind::AccessibilityLost,
+                        "Nimbus lost Accessibility access, so it stopped arranging windows.\n\n\
+                         Turn Nimbus back on in System Settings under Privacy & Security > \
+                         Accessibility, and it will pick up where it left off.",
+                        None,
+                    );
+                }
+            }
+            SpacesChanged(spaces) => self.update_known_spaces(spaces),
+            ScheduleTick => self.update_profile(),
+            AuditTick => self.audit(),
+            TimingTick => {
+                if let Some((overlay, _)) = &self.timing_overlay {
+                    overlay.set_text(&metrics::live_report(), self.palette.text);
+                }
+            }
+            UseProfile(name, reply_tx) => {
+                let exists = |name: &String| self.config.profiles.iter().any(|p| &p.name == name);
+                let result = match name {
+                    Some(name) if !exists(&name) => Err(format!("no profile named {name:?}")),
+                    name => {
+                        self.config.selected_profile = name;
+                        self.update_profile();
+                        Ok(self.profile.clone())
+                    }
+                };
+                // Errors mean the client went away; ignore.
+                _ = reply_tx.send(result);
+            }
+            Command(HideNotice) => self.hide_notice(),
+            NoticeTimedOut(token) => {
+                let current = self.notice.as_ref().map(|notice| Arc::downgrade(&notice.token));
+                if current.is_some_and(|current| current.ptr_eq(&token)) {
+                    self.hide_notice();
+                }
+            }
+            Command(ToggleTiming) => {
+                if self.timing_overlay.is_some() {
+                    self.hide_timing();
+                } else {
+                    self.show_timing();
+                }
+            }
+            Command(Count(digit)) => {
+                let count = (self.take_count().unwrap_or(0) * 10 + digit).min(MAX_COUNT);
+                self.count = (count > 0).then(|| (count, Instant::now()));
+            }
+            // Sent when the keys of a cycling binding are released rather than
+            // by a binding of its own, so it leaves the count alone.
+            Command(ReactorCommand(cmd @ reactor::Command::EndRecentCycle)) => {
+                self.send_event(Event::Command(cmd));
+            }
             Command(ReactorCommand(cmd)) => {
+                let cmd = match self.take_count() {
+                    Some(count) if count > 1 => reactor::Command::Repeat(count, Box::new(cmd)),
+                    _ => cmd,
+                };
                 self.send_event(Event::Command(cmd));
             }
         }
     }
 
+    /// Clears the typed count, returning it unless it timed out.
+    fn take_count(&mut self) -> Option<usize> {
+        let (count, typed_at) = self.count.take()?;
+        (typed_at.elapsed() < COUNT_TIMEOUT).then_some(count)
+    }
+
+    fn show_key_bindings(&mut self) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            warn!("Cannot show key bindings off the main thread");
+            return;
+        };
+        let Some(hotkeys) = &self.hotkeys else { return };
+        let bindings: Vec<_> = hotkeys
+            .bindings()
+            .iter()
+            .map(|(hotkey, cmd)| (describe_hotkey(hotkey), describe_command(cmd)))
+            .collect();
+        let width = bindings.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+        let text = bindings
+            .iter()
+            .map(|(key, cmd)| format!("{key:width$}  {cmd}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let (overlay, tap) = self.show_text_overlay(mtm, &text, WmCommand::HideKeyBindings);
+        if tap.is_none() {
+            warn!("Could not watch key presses; press the hotkey again to dismiss");
+        }
+        self.key_bindings_overlay = Some((overlay, tap));
+    }
+
+    fn show_update_notice(&mut self, release: &Release) {
+        let text = format!(
+            "Nimbus {} is available (you have {}).\n\nSee what's new at {}",
+            release.version,
+            update::CURRENT_VERSION,
+            release.url,
+        );
+        self.show_notice(NoticeKind::Update, &text, Some(NOTICE_TIMEOUT));
+    }
+
+    fn show_crash_loop_notice(&mut self, threshold: crash_loop::Threshold) {
+        let toggle = key_bindings(&self.config.restore_file)
+            .into_iter()
+            .find(|(_, cmd)| matches!(cmd, WmCommand::ToggleSpaceActivated))
+            .map(|(hotkey, _)| describe_hotkey(&hotkey));
+        let secs = threshold.period.as_secs();
+        let period = if secs % 60 == 0 {
+            format!("{} minutes", secs / 60)
+        } else {
+            format!("{secs} seconds")
+        };
+        let mut text = format!(
+            "Nimbus restarted more than {} times in {period}, so it started in safe \
+             mode and is not managing any space.",
+            threshold.max_starts,
+        );
+        if let Some(toggle) = toggle {
+            text += &format!("\n\nPress {toggle} to manage the current space again.");
+        }
+        self.show_notice(NoticeKind::SafeMode, &text, None);
+    }
+
+    /// Shows `text` in place of any other notice until a key is pressed, or
+    /// until `timeout` has passed.
+    fn show_notice(&mut self, kind: NoticeKind, text: &str, timeout: Option<Duration>) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            warn!("Cannot show a notice off the main thread");
+            return;
+        };
+        self.hide_notice();
+        let (overlay, tap) = self.show_text_overlay(mtm, text, WmCommand::HideNotice);
+        let token = Arc::new(());
+        if let Some(timeout) = timeout {
+            let sender = self.sender.clone();
+            let notice = Arc::downgrade(&token);
+            thread::spawn(move || {
+                thread::sleep(timeout);
+                let Some(sender) = sender.upgrade() else { return };
+                let span = info_span!("wm_controller::notice_timed_out");
+                _ = sender.send((span, WmEvent::NoticeTimedOut(notice)));
+            });
+        }
+        self.notice = Some(Notice {
+            kind,
+            overlay,
+            _tap: tap,
+            token,
+        });
+    }
+
+    fn hide_notice(&mut self) {
+        if let Some(notice) = self.notice.take() {
+            notice.overlay.hide();
+        }
+    }
+
+    /// Hides the notice if it is of `kind`, leaving any other notice up.
+    fn hide_notice_of(&mut self, kind: NoticeKind) {
+        if self.notice.as_ref().is_some_and(|notice| notice.kind == kind) {
+            self.hide_notice();
+        }
+    }
+
+    /// Shows `text` in an overlay that is dismissed on the next key press by
+    /// sending `dismiss`, if we can watch key presses.
+    fn show_text_overlay(
+        &self,
+        mtm: MainThreadMarker,
+        text: &str,
+        dismiss: WmCommand,
+    ) -> (OverlayWindow, Option<KeyPressTap>) {
+        let overlay = OverlayWindow::new(mtm);
+        overlay.set_background(self.palette.background);
+        overlay.set_text(text, self.palette.text);
+        overlay.show();
+        let sender = self.sender.clone();
+        let tap = KeyPressTap::new(move || {
+            let Some(sender) = sender.upgrade() else { return };
+            let span = info_span!("wm_controller::key_pressed");
+            _ = sender.send((span, WmEvent::Command(dismiss.clone())));
+        });
+        (overlay, tap)
+    }
+
+    fn show_timing(&mut self) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            warn!("Cannot show timing off the main thread");
+            return;
+        };
+        // Timings are still logged to the console, which has the spans the
+        // overlay leaves out.
+        self.send_event(reactor::Event::Command(reactor::Command::Metrics(
+            MetricsCommand::ShowTiming,
+        )));
+        metrics::set_live(true);
+        let overlay = OverlayWindow::new(mtm);
+        overlay.set_background(self.palette.background);
+        overlay.set_text(&metrics::live_report(), self.palette.text);
+        overlay.show();
+        let token = Arc::new(());
+        let alive = Arc::downgrade(&token);
+        let sender = self.sender.clone();
+        thread::spawn(move || loop {
+            thread::sleep(TIMING_INTERVAL);
+            let Some(sender) = sender.upgrade() else { break };
+            if alive.upgrade().is_none() {
+                break;
+            }
+            let span = info_span!("wm_controller::timing_tick");
+            if sender.send((span, WmEvent::TimingTick)).is_err() {
+                break;
+            }
+        });
+        self.timing_overlay = Some((overlay, token));
+    }
+
+    fn hide_timing(&mut self) {
+        if let Some((overlay, _token)) = self.timing_overlay.take() {
+            overlay.hide();
+            metrics::set_live(false);
+        }
+    }
+
+    fn hide_key_bindings(&mut self) {
+        if let Some((overlay, _tap)) = self.key_bindings_overlay.take() {
+            overlay.hide();
+        }
+    }
+
+    fn launch_app(&mut self, pid: pid_t, info: AppInfo) {
+        if let Some(watcher) = &self.exit_watcher {
+            if let Err(e) = watcher.watch(pid) {
+                debug!(?pid, "Could not watch process: {e}");
+            }
+        }
+        actor::app::spawn_app_thread(pid, info, self.events_tx.clone());
+    }
+
     fn handle_space_changed(&mut self, spaces: &[Option<SpaceId>]) {
         self.cur_space = spaces.iter().copied().collect();
-        let Some(&Some(space)) = spaces.first() else { return };
-        if self.starting_space.is_none() {
-            self.starting_space = Some(space);
-            self.register_hotkeys();
-        } else if self.config.one_space {
-            let Some(&Some(space)) = spaces.first() else { return };
-            if Some(space) == self.starting_space {
+        self.update_known_spaces(screen::spaces());
+        if self.initial_spaces.is_none() {
+            self.initial_spaces = Some(self.known_spaces.iter().map(|info| info.id).collect());
+        }
+        for space in spaces.iter().flatten() {
+            if let Some(info) = self.space_info(*space) {
+                self.starting_spaces.entry(info.display.clone()).or_insert(*space);
+            }
+        }
+        // In safe mode the key bindings are needed to turn spaces back on.
+        if self.safe_mode_spaces.is_some()
+            || spaces.iter().flatten().any(|&space| self.is_enabled_by_config(space))
+        {
+            if self.hotkeys.is_none() {
                 self.register_hotkeys();
-            } else {
-                self.unregister_hotkeys();
+            }
+        } else {
+            self.unregister_hotkeys();
+        }
+    }
+
+    /// Forgets what we know about spaces that were removed, and tells the
+    /// reactor about the new list if it changed.
+    fn update_known_spaces(&mut self, spaces: Vec<SpaceInfo>) {
+        if spaces == self.known_spaces {
+            return;
+        }
+        let exists = |space: &SpaceId| spaces.iter().any(|info| info.id == *space);
+        let disabled = self.disabled_spaces.len();
+        self.disabled_spaces.retain(|uuid| spaces.iter().any(|info| info.uuid == *uuid));
+        if self.disabled_spaces.len() != disabled {
+            let disabled = self.disabled_spaces.clone();
+            self.send_event(reactor::Event::DisabledSpacesChanged(disabled));
+        }
+        if let Some(enabled) = &mut self.safe_mode_spaces {
+            enabled.retain(exists);
+        }
+        self.starting_spaces.retain(|_, space| exists(space));
+        info!(count = spaces.len(), "Spaces changed");
+        self.known_spaces = spaces.clone();
+        self.send_event(reactor::Event::SpacesChanged(spaces));
+    }
+
+    fn space_info(&self, space: SpaceId) -> Option<&SpaceInfo> {
+        self.known_spaces.iter().find(|info| info.id == space)
+    }
+
+    /// Checks that we know the current space of each screen, then has the
+    /// reactor check its windows.
+    fn audit(&mut self) {
+        let spaces: Vec<Option<SpaceId>> = self
+            .displays
+            .iter()
+            .map(|display| screen::current_space(&display.uuid))
+            .collect();
+        if spaces.len() == self.cur_space.len() && spaces != self.cur_space {
+            warn!(expected = ?self.cur_space, actual = ?spaces, "Audit: missed a space change");
+            self.handle_event(WmEvent::ReactorEvent(reactor::Event::SpaceChanged(spaces)));
+        }
+        self.send_event(reactor::Event::Audit);
+    }
+
+    /// Returns the config for the display showing the screen at `idx`, if any.
+    fn display_config(&self, idx: usize) -> Option<&DisplayConfig> {
+        let display = self.displays.get(idx)?;
+        let displays = self
+            .active_profile()
+            .and_then(|profile| profile.displays.as_ref())
+            .unwrap_or(&self.config.displays);
+        displays.iter().find(|config| config.display.matches(display))
+    }
+
+    fn active_profile(&self) -> Option<&ProfileConfig> {
+        let name = self.profile.as_ref()?;
+        self.config.profiles.iter().find(|profile| &profile.name == name)
+    }
+
+    /// Switches to the selected profile, or if there is none, the profile
+    /// whose schedule contains the current time, if that changed.
+    fn update_profile(&mut self) {
+        let name = self.config.selected_profile.clone().or_else(|| {
+            let now = clock::local_time();
+            config::scheduled_profile(&self.config.profiles, now)
+                .map(|profile| profile.name.clone())
+        });
+        if name != self.profile {
+            self.set_profile(name);
+        }
+    }
+
+    /// Makes the named profile active, or the top-level settings if `None`,
+    /// and applies its settings to the other actors.
+    fn set_profile(&mut self, name: Option<String>) {
+        info!(profile = ?name, "Switching profile");
+        self.profile = name;
+        let profile = self.active_profile();
+        let rules = profile
+            .and_then(|profile| profile.border_rules.clone())
+            .unwrap_or_else(|| self.config.border_rules.clone());
+        let limits = profile
+            .and_then(|profile| profile.window_limits.clone())
+            .unwrap_or_else(|| self.config.window_limits.clone());
+        _ = self
+            .borders_tx
+            .send((Span::current().clone(), borders::Event::RulesChanged(rules)));
+        self.send_event(reactor::Event::WindowLimitsChanged(limits));
+        if self.hotkeys.is_some() {
+            self.unregister_hotkeys();
+            self.register_hotkeys();
+            if self.key_bindings_overlay.is_some() {
+                self.hide_key_bindings();
+                self.show_key_bindings();
             }
         }
+        if self.screen_frames.is_empty() {
+            // The display settings are applied with the first screen
+            // parameters.
+            return;
+        }
+        let mut frames = self.screen_frames.clone();
+        self.apply_display_config(&mut frames);
+        let mut spaces = self.cur_space.clone();
+        self.apply_space_activation(&mut spaces);
+        self.send_event(reactor::Event::ScreenParametersChanged(frames, spaces));
+    }
+
+    /// Leaves room for external bars and tells the reactor how to lay out
+    /// each screen.
+    fn apply_display_config(&mut self, frames: &mut [CGRect]) {
+        let mut settings = vec![];
+        for (idx, frame) in frames.iter_mut().enumerate() {
+            let display = self.displays.get(idx).map(|display| display.uuid.clone());
+            let Some(config) = self.display_config(idx) else {
+                settings.push(reactor::ScreenSettings { display, ..Default::default() });
+                continue;
+            };
+            let bar = config.external_bar;
+            let height = (frame.size.height - bar.top - bar.bottom).max(0.0);
+            *frame = CGRect::new(
+                CGPoint::new(frame.origin.x, frame.origin.y + bar.top),
+                CGSize::new(frame.size.width, height),
+            );
+            settings.push(reactor::ScreenSettings {
+                gaps: config.gaps,
+                default_layout: config.default_layout,
+                display,
+            });
+        }
+        self.send_event(reactor::Event::ScreenSettingsChanged(settings));
     }
 
     fn apply_space_activation(&self, spaces: &mut [Option<SpaceId>]) {
-        for space in spaces {
+        for (idx, space) in spaces.iter_mut().enumerate() {
             match space {
-                Some(_) if self.config.one_space && *space != self.starting_space => *space = None,
-                Some(sp) if self.disabled_spaces.contains(sp) => *space = None,
+                // Full screen apps and Split View manage their own windows,
+                // and refuse most attempts to move them.
+                Some(sp) if self.is_fullscreen_space(*sp) => *space = None,
+                Some(sp) if self.safe_mode_spaces.as_ref().is_some_and(|s| !s.contains(sp)) => {
+                    *space = None
+                }
+                Some(_) if self.safe_mode_spaces.is_some() => (),
+                Some(sp) if !self.is_activated(idx, *sp) => *space = None,
                 _ => (),
             }
         }
     }
 
+    fn is_fullscreen_space(&self, space: SpaceId) -> bool {
+        self.space_info(space).is_some_and(|info| !info.is_user_space)
+    }
+
+    fn is_activated(&self, idx: usize, space: SpaceId) -> bool {
+        let display = self.display_config(idx);
+        let Some(info) = self.space_info(space) else {
+            return self.is_enabled_by_config(space) && !display.is_some_and(|c| c.disabled);
+        };
+        is_space_activated(
+            &self.config.space_activation,
+            display,
+            &self.disabled_spaces,
+            info,
+            self.is_starting_space(info),
+            self.is_new_space(space),
+        )
+    }
+
+    /// Whether the config turns on `space`, even if the user turned it off.
+    fn is_enabled_by_config(&self, space: SpaceId) -> bool {
+        let config = &self.config.space_activation;
+        let Some(info) = self.space_info(space) else {
+            // Mission Control hasn't told us about the space yet, so only the
+            // settings that don't depend on which space it is apply.
+            return config.default == DefaultSpaceActivation::All
+                || (config.new_spaces && self.is_new_space(space));
+        };
+        is_enabled_by_config(
+            config,
+            info,
+            self.is_starting_space(info),
+            self.is_new_space(space),
+        )
+    }
+
+    fn is_starting_space(&self, info: &SpaceInfo) -> bool {
+        self.starting_spaces.get(&info.display) == Some(&info.id)
+    }
+
+    fn is_new_space(&self, space: SpaceId) -> bool {
+        self.initial_spaces.as_ref().is_some_and(|initial| !initial.contains(&space))
+    }
+
     fn send_event(&mut self, event: reactor::Event) {
         _ = self.events_tx.send((Span::current().clone(), event));
     }
 
     fn register_hotkeys(&mut self) {
         debug!("register_hotkeys");
-        use crate::metrics::MetricsCommand::*;
-        use crate::model::Direction::*;
-        use crate::model::Orientation;
-        use crate::sys::hotkey::{KeyCode, Modifiers};
-        use actor::layout::LayoutCommand::*;
-        use actor::reactor::Command;
-
-        use KeyCode::*;
-        const ALT: Modifiers = Modifiers::ALT;
-        const SHIFT: Modifiers = Modifiers::SHIFT;
-
-        let mgr = HotkeyManager::new(self.sender.upgrade().unwrap());
-        mgr.register(ALT, KeyW, Command::Hello);
-        //mgr.register(ALT, KeyS, Command::Layout(Shuffle));
-        mgr.register(ALT, KeyA, Command::Layout(Ascend));
-        mgr.register(ALT, KeyD, Command::Layout(Descend));
-        mgr.register(ALT, KeyH, Command::Layout(MoveFocus(Left)));
-        mgr.register(ALT, KeyJ, Command::Layout(MoveFocus(Down)));
-        mgr.register(ALT, KeyK, Command::Layout(MoveFocus(Up)));
-        mgr.register(ALT, KeyL, Command::Layout(MoveFocus(Right)));
-        mgr.register(ALT | SHIFT, KeyH, Command::Layout(MoveNode(Left)));
-        mgr.register(ALT | SHIFT, KeyJ, Command::Layout(MoveNode(Down)));
-        mgr.register(ALT | SHIFT, KeyK, Command::Layout(MoveNode(Up)));
-        mgr.register(ALT | SHIFT, KeyL, Command::Layout(MoveNode(Right)));
-        mgr.register(ALT, Equal, Command::Layout(Split(Orientation::Vertical)));
-        mgr.register(
-            ALT,
-            Backslash,
-            Command::Layout(Split(Orientation::Horizontal)),
-        );
-        mgr.register(ALT, KeyS, Command::Layout(Group(Orientation::Vertical)));
-        mgr.register(ALT, KeyT, Command::Layout(Group(Orientation::Horizontal)));
-        mgr.register(ALT, KeyE, Command::Layout(Ungroup));
-        mgr.register(ALT, KeyM, Command::Metrics(ShowTiming));
-        mgr.register(ALT | SHIFT, KeyD, Command::Layout(Debug));
-        mgr.register(ALT | SHIFT, KeyS, Command::Layout(Serialize));
-        mgr.register(
-            ALT | SHIFT,
-            KeyE,
-            Command::Layout(SaveAndExit(self.config.restore_file.clone())),
-        );
-        mgr.register_wm(ALT, KeyZ, WmCommand::ToggleSpaceActivated);
-
+        let mut mgr = HotkeyManager::new(self.sender.upgrade().unwrap(), self.config.key_repeat);
+        let layout = match self.config.bind_keys_by {
+            BindKeysBy::Position => None,
+            BindKeysBy::Character => {
+                let layout = KeyboardLayout::current();
+                if layout.is_none() {
+                    warn!("Could not read the keyboard layout; binding keys by position");
+                }
+                layout
+            }
+        };
+        for (mut hotkey, cmd) in self.active_key_bindings() {
+            if let Some(layout) = &layout {
+                hotkey.key_code = layout.key_for_us_char(hotkey.key_code);
+            }
+            if is_repeatable(&cmd) {
+                mgr.register_repeating(hotkey, cmd);
+            } else if is_cycling(&cmd) {
+                let end = WmCommand::ReactorCommand(reactor::Command::EndRecentCycle);
+                mgr.register_cycling(hotkey, cmd, end);
+            } else {
+                mgr.register(hotkey, cmd);
+            }
+        }
         self.hotkeys = Some(mgr);
     }
 
@@ -190,4 +970,211 @@ impl WmController {
         debug!("unregister_hotkeys");
         self.hotkeys = None;
     }
+
+    /// Returns the default key bindings with the active profile's bindings
+    /// in place of the ones for the same keys.
+    fn active_key_bindings(&self) -> Vec<(Hotkey, WmCommand)> {
+        let mut bindings = key_bindings(&self.config.restore_file);
+        let Some(profile) = self.active_profile() else {
+            return bindings;
+        };
+        for (key, cmd) in &profile.key_bindings {
+            // The keys were checked when the config was loaded.
+            let Some(hotkey) = parse_hotkey(key) else { continue };
+            bindings.retain(|(bound, _)| *bound != hotkey);
+            bindings.push((hotkey, WmCommand::ReactorCommand(cmd.clone())));
+        }
+        bindings
+    }
+}
+
+/// Returns the hotkeys the window manager registers and the commands they run.
+pub fn key_bindings(restore_file: &Path) -> Vec<(Hotkey, WmCommand)> {
+    use crate::model::Direction::*;
+    use crate::model::{Orientation, SplitOrientation};
+    use crate::sys::hotkey::KeyCode;
+    use actor::layout::LayoutCommand::*;
+    use actor::reactor::Command;
+
+    use KeyCode::*;
+    const ALT: Modifiers = Modifiers::ALT;
+    const SHIFT: Modifiers = Modifiers::SHIFT;
+    const CTRL: Modifiers = Modifiers::CONTROL;
+
+    let mut bindings = vec![];
+    let mut bind = |modifiers, key_code, cmd| {
+        bindings.push((
+            Hotkey { modifiers, key_code },
+            WmCommand::ReactorCommand(cmd),
+        ))
+    };
+    bind(ALT, KeyW, Command::Hello);
+    //bind(ALT, KeyS, Command::Layout(Shuffle));
+    bind(ALT, KeyA, Command::Layout(Ascend));
+    bind(ALT, KeyD, Command::Layout(Descend));
+    bind(ALT, KeyH, Command::Layout(MoveFocus(Left)));
+    bind(ALT, KeyJ, Command::Layout(MoveFocus(Down)));
+    bind(ALT, KeyK, Command::Layout(MoveFocus(Up)));
+    bind(ALT, KeyL, Command::Layout(MoveFocus(Right)));
+    bind(ALT | SHIFT, KeyH, Command::Layout(MoveNode(Left)));
+    bind(ALT | SHIFT, KeyJ, Command::Layout(MoveNode(Down)));
+    bind(ALT | SHIFT, KeyK, Command::Layout(MoveNode(Up)));
+    bind(ALT | SHIFT, KeyL, Command::Layout(MoveNode(Right)));
+    bind(
+        ALT,
+        Equal,
+        Command::Layout(Split(SplitOrientation::Vertical)),
+    );
+    bind(
+        ALT,
+        Backslash,
+        Command::Layout(Split(SplitOrientation::Horizontal)),
+    );
+    bind(ALT, KeyS, Command::Layout(Group(Orientation::Vertical)));
+    bind(ALT, KeyT, Command::Layout(Group(Orientation::Horizontal)));
+    bind(ALT, KeyE, Command::Layout(Ungroup));
+    bind(ALT, Tab, Command::CycleRecentWindows);
+    bind(ALT | SHIFT, Tab, Command::CycleRecentWindowsBack);
+    bind(ALT, Comma, Command::FocusPrevDisplay);
+    bind(ALT, Period, Command::FocusNextDisplay);
+    bind(ALT | SHIFT, Comma, Command::MoveWindowToPrevDisplay);
+    bind(ALT | SHIFT, Period, Command::MoveWindowToNextDisplay);
+    bind(ALT, KeyP, Command::PauseTiling);
+    bind(ALT | SHIFT, KeyP, Command::ResumeTiling);
+    bind(ALT | SHIFT, KeyR, Command::ForceRelayout);
+    bind(ALT | SHIFT, KeyD, Command::Layout(Debug));
+    bind(ALT | SHIFT, KeyS, Command::Layout(Serialize));
+    bind(
+        ALT | SHIFT,
+        KeyE,
+        Command::Layout(SaveAndExit(restore_file.to_owned())),
+    );
+    let digits = [
+        Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+    ];
+    for (idx, key) in digits.into_iter().enumerate() {
+        bind(ALT, key, Command::Layout(FocusChildIndex(idx + 1)));
+        bind(ALT | SHIFT, key, Command::FollowWindowToSpace(idx + 1));
+    }
+    for (idx, key) in [Digit0].into_iter().chain(digits).enumerate() {
+        bindings.push((
+            Hotkey {
+                modifiers: CTRL | ALT,
+                key_code: key,
+            },
+            WmCommand::Count(idx),
+        ));
+    }
+    bindings.push((
+        Hotkey { modifiers: ALT, key_code: KeyZ },
+        WmCommand::ToggleSpaceActivated,
+    ));
+    bindings.push((
+        Hotkey { modifiers: ALT, key_code: KeyM },
+        WmCommand::ToggleTiming,
+    ));
+    bindings.push((
+        Hotkey {
+            modifiers: ALT,
+            key_code: Slash,
+        },
+        WmCommand::ToggleKeyBindings,
+    ));
+    bindings
+}
+
+/// Returns whether holding down the keys for `cmd` runs it again and again,
+/// when key repeat is turned on.
+fn is_repeatable(cmd: &WmCommand) -> bool {
+    use actor::layout::LayoutCommand::*;
+    matches!(
+        cmd,
+        WmCommand::ReactorCommand(reactor::Command::Layout(MoveFocus(_) | MoveNode(_)))
+    )
+}
+
+/// Returns whether any key binding, by default or in a profile, steps through
+/// a cycle. Telling when its keys are released needs Input Monitoring.
+pub fn binds_cycling(restore_file: &Path, profiles: &[ProfileConfig]) -> bool {
+    let defaults = key_bindings(restore_file).into_iter().map(|(_, cmd)| cmd);
+    let profiles = profiles.iter().flat_map(|profile| {
+        profile.key_bindings.values().map(|cmd| WmCommand::ReactorCommand(cmd.clone()))
+    });
+    defaults.chain(profiles).any(|cmd| is_cycling(&cmd))
+}
+
+/// Returns whether `cmd` steps through a cycle that stays open until the keys
+/// for it are released.
+fn is_cycling(cmd: &WmCommand) -> bool {
+    matches!(
+        cmd,
+        WmCommand::ReactorCommand(
+            reactor::Command::CycleRecentWindows | reactor::Command::CycleRecentWindowsBack
+        )
+    )
+}
+
+/// Describes a command for display next to its key binding.
+pub fn describe_command(cmd: &WmCommand) -> String {
+    match cmd {
+        WmCommand::ReactorCommand(cmd) => format!("{cmd:?}"),
+        cmd => format!("{cmd:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::is_space_activated;
+    use crate::{
+        config::{DefaultSpaceActivation, SpaceActivationConfig},
+        sys::screen::{DisplayUuid, SpaceId, SpaceInfo, SpaceUuid},
+    };
+
+    #[test]
+    fn it_activates_spaces_by_config_and_user_choice() {
+        let space = SpaceInfo {
+            id: SpaceId::new(1),
+            uuid: SpaceUuid::new("space"),
+            display: DisplayUuid::new("display"),
+            is_user_space: true,
+        };
+        let none = HashSet::new();
+        let disabled = HashSet::from([space.uuid.clone()]);
+        let config = |default, new_spaces| SpaceActivationConfig {
+            default,
+            new_spaces,
+            ..Default::default()
+        };
+        let activated = |config, disabled, is_starting, is_new| {
+            is_space_activated(&config, None, disabled, &space, is_starting, is_new)
+        };
+
+        let off = config(DefaultSpaceActivation::None, false);
+        assert!(!activated(off, &none, true, true));
+        let new_spaces = config(DefaultSpaceActivation::None, true);
+        assert!(activated(new_spaces.clone(), &none, false, true));
+        assert!(!activated(new_spaces, &none, false, false));
+        let starting = config(DefaultSpaceActivation::StartingSpaces, false);
+        assert!(activated(starting.clone(), &none, true, false));
+        assert!(!activated(starting, &none, false, false));
+
+        // Spaces the user turned off stay off.
+        let all = config(DefaultSpaceActivation::All, false);
+        assert!(!activated(all.clone(), &disabled, true, false));
+
+        let full_screen = SpaceInfo {
+            is_user_space: false,
+            ..space.clone()
+        };
+        assert!(!is_space_activated(
+            &all,
+            None,
+            &none,
+            &full_screen,
+            true,
+            false
+        ));
+    }
 }
@@ -0,0 +1,171 @@
+//! Measures how long the reactor takes to handle common operations when many
+//! apps and windows are open.
+//!
+//! This runs the reactor against the same mock apps the reactor tests use, so
+//! it needs no permissions and moves no real windows:
+//!
+//! ```text
+//! cargo run --release --features bench --bin nimbus-bench -- --apps 300 --windows 3000
+//! ```
+//!
+//! The apps sometimes answer the reactor's requests only after the next
+//! operation has started, like real apps that are slow to respond.
+
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use nimbus_wm::{
+    actor::{
+        layout::LayoutCommand,
+        reactor::{testing::Sim, Command},
+    },
+    model::Direction,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+#[derive(Parser)]
+struct Opt {
+    /// Number of apps to launch.
+    #[arg(long, default_value_t = 200)]
+    apps: usize,
+
+    /// Number of windows to open, spread randomly across the apps.
+    #[arg(long, default_value_t = 2000)]
+    windows: usize,
+
+    /// Number of operations to time, each picked at random.
+    #[arg(long, default_value_t = 3000)]
+    ops: usize,
+
+    /// Chance that the apps answer an operation late, from 0 to 1.
+    #[arg(long, default_value_t = 0.3)]
+    late: f64,
+
+    /// Seed for the random choices, to repeat an earlier run.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    /// Moving focus to a neighboring window.
+    Focus,
+    /// Moving the selected window in the layout.
+    Move,
+    /// Opening or closing a window, which lays out the space again.
+    Layout,
+}
+
+const OPS: [Op; 3] = [Op::Focus, Op::Move, Op::Layout];
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Left,
+    Direction::Right,
+    Direction::Up,
+    Direction::Down,
+];
+
+/// The apps and windows the benchmark has opened.
+struct Bench {
+    sim: Sim,
+    rng: StdRng,
+    apps: Vec<String>,
+    windows: Vec<String>,
+    next_window: usize,
+}
+
+impl Bench {
+    fn open_window(&mut self) {
+        let app = self.apps.choose(&mut self.rng).expect("at least one app");
+        let title = format!("Window{}", self.next_window);
+        self.next_window += 1;
+        self.sim.app(app).window(&title).created();
+        self.windows.push(title);
+    }
+
+    fn close_window(&mut self) {
+        let idx = self.rng.gen_range(0..self.windows.len());
+        let title = self.windows.swap_remove(idx);
+        self.sim.window(&title).destroyed();
+    }
+
+    /// Runs `op` and returns how long the reactor took to handle it.
+    fn run(&mut self, op: Op) -> Duration {
+        let direction = *DIRECTIONS.choose(&mut self.rng).unwrap();
+        let start = Instant::now();
+        match op {
+            Op::Focus => self.sim.command(Command::Layout(LayoutCommand::MoveFocus(direction))),
+            Op::Move => self.sim.command(Command::Layout(LayoutCommand::MoveNode(direction))),
+            Op::Layout if self.windows.is_empty() || self.rng.gen_bool(0.5) => self.open_window(),
+            Op::Layout => self.close_window(),
+        }
+        start.elapsed()
+    }
+}
+
+fn main() {
+    let opt = Opt::parse();
+    assert!(opt.apps > 0, "--apps must be at least 1");
+    let seed = opt.seed.unwrap_or_else(rand::random);
+    println!("seed: {seed}");
+
+    let mut bench = Bench {
+        sim: Sim::new(),
+        rng: StdRng::seed_from_u64(seed),
+        apps: (0..opt.apps).map(|idx| format!("App{idx}")).collect(),
+        windows: vec![],
+        next_window: 0,
+    };
+    let start = Instant::now();
+    for app in &bench.apps {
+        bench.sim.app(app);
+    }
+    for _ in 0..opt.windows {
+        bench.open_window();
+    }
+    bench.sim.settle();
+    println!(
+        "opened {} windows in {} apps in {:.1?}\n",
+        opt.windows,
+        opt.apps,
+        start.elapsed()
+    );
+
+    let mut samples: [Vec<Duration>; 3] = Default::default();
+    for _ in 0..opt.ops {
+        let op = *OPS.choose(&mut bench.rng).unwrap();
+        samples[op as usize].push(bench.run(op));
+        if bench.rng.gen_bool(opt.late) {
+            // Hold the apps' answers until after the next operation.
+            bench.sim.respond();
+        } else {
+            bench.sim.settle();
+        }
+    }
+    bench.sim.settle();
+
+    println!(
+        "{:<10}{:>8}{:>12}{:>12}{:>12}{:>12}",
+        "operation", "count", "p50", "p90", "p99", "max"
+    );
+    for (op, samples) in OPS.iter().zip(&mut samples) {
+        if samples.is_empty() {
+            continue;
+        }
+        samples.sort();
+        println!(
+            "{:<10}{:>8}{:>12.1?}{:>12.1?}{:>12.1?}{:>12.1?}",
+            format!("{op:?}").to_lowercase(),
+            samples.len(),
+            percentile(samples, 0.5),
+            percentile(samples, 0.9),
+            percentile(samples, 0.99),
+            samples[samples.len() - 1],
+        );
+    }
+}
+
+/// Returns the `p`th percentile of `sorted`, which must not be empty.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[idx]
+}
@@ -0,0 +1,1503 @@
+//! User configuration, read from `~/.nimbus/config.ron`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actor::{
+        borders,
+        reactor::{AnimationMode, Command, TitleBarAction},
+    },
+    crash_loop::Threshold,
+    model::{AdoptStrategy, LayoutKind},
+    sys::{
+        appearance::Appearance,
+        clock::{LocalTime, TimeOfDay, Weekday},
+        event::ModifierKey,
+        geometry::Corner,
+        hotkey::{parse_hotkey, KeyRepeat},
+        overlay::{Color, Palette},
+        power::PowerSource,
+        screen::{DisplayInfo, DisplayUuid, SpaceUuid},
+        window_server::WindowServerId,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub spaces: SpaceActivationConfig,
+    pub tiling: TilingConfig,
+    /// Settings for particular displays. The first entry that matches a
+    /// display is used.
+    pub displays: Vec<DisplayConfig>,
+    pub hot_corners: HotCornersConfig,
+    pub window_drag: WindowDragConfig,
+    pub title_bar: TitleBarConfig,
+    pub key_bindings: KeyBindingsConfig,
+    pub power: PowerConfig,
+    pub overlays: OverlayConfig,
+    pub borders: BorderConfig,
+    pub pip: PipConfig,
+    pub focus_mode: FocusModeConfig,
+    pub raise: RaiseConfig,
+    pub accessibility: AccessibilityConfig,
+    /// Named sets of settings that replace the top-level ones, on a schedule.
+    pub profiles: Vec<ProfileConfig>,
+    pub crash_loop: CrashLoopConfig,
+    pub updates: UpdateConfig,
+    pub logging: LoggingConfig,
+}
+
+impl Config {
+    /// Reads the config file at `path`, or returns the default config if there
+    /// is none.
+    ///
+    /// Parse errors point to the line and column of the problem, so they can
+    /// be shown to the user as-is.
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        Self::read(path, true)
+    }
+
+    /// Like [`Config::load`], but a missing file is an error, for files the
+    /// user named.
+    pub fn load_existing(path: &Path) -> anyhow::Result<Config> {
+        Self::read(path, false)
+    }
+
+    fn read(path: &Path, missing_ok: bool) -> anyhow::Result<Config> {
+        let buf = match fs::read_to_string(path) {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && missing_ok => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(anyhow!("{}: {e}", path.display())),
+        };
+        Self::parse(&buf).map_err(|e| anyhow!("{}:{e}", path.display()))
+    }
+
+    fn parse(buf: &str) -> Result<Config, ParseError> {
+        let config: Config = ron::from_str(buf).map_err(|e| ParseError::new(buf, e))?;
+        config
+            .validate()
+            .map_err(|message| ParseError { message, ..Default::default() })?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(dup) = find_duplicate(&self.spaces.spaces) {
+            return Err(format!("space {dup:?} is listed more than once"));
+        }
+        if let Some(dup) = find_duplicate(&self.spaces.displays) {
+            return Err(format!("display {dup:?} is listed more than once"));
+        }
+        let selectors: Vec<_> = self.displays.iter().map(|config| &config.display).collect();
+        if let Some(dup) = find_duplicate(&selectors) {
+            return Err(format!(
+                "display {dup:?} has more than one entry in displays"
+            ));
+        }
+        if let Some(repeat) = &self.key_bindings.repeat {
+            if repeat.interval_ms == 0 {
+                return Err("the key repeat interval must be at least 1 ms".to_string());
+            }
+            if repeat.acceleration < 1.0 {
+                return Err("key repeat acceleration must be at least 1".to_string());
+            }
+        }
+        if self.pip.margin < 0.0 {
+            return Err("the pip margin must not be negative".to_string());
+        }
+        if self.borders.width < 0.0
+            || self.borders.rules.iter().any(|rule| rule.width.is_some_and(|w| w < 0.0))
+        {
+            return Err("border widths must not be negative".to_string());
+        }
+        if !self.focus_mode.background_apps.is_empty() && self.focus_mode.background_space == 0 {
+            return Err("focus_mode needs a background_space to move apps to".to_string());
+        }
+        let names: Vec<_> = self.profiles.iter().map(|profile| &profile.name).collect();
+        if let Some(dup) = find_duplicate(&names) {
+            return Err(format!("profile {dup:?} is defined more than once"));
+        }
+        for profile in &self.profiles {
+            if profile.schedule.as_ref().is_some_and(|s| s.start == s.end) {
+                return Err(format!(
+                    "the schedule for profile {:?} starts and ends at the same time",
+                    profile.name
+                ));
+            }
+            if let Some(key) = profile.key_bindings.keys().find(|key| parse_hotkey(key).is_none()) {
+                return Err(format!(
+                    "profile {:?} binds {key:?}, which is not a hotkey like \"Alt + KeyW\"",
+                    profile.name
+                ));
+            }
+        }
+        let window_limits = self
+            .profiles
+            .iter()
+            .filter_map(|profile| profile.window_limits.as_ref())
+            .chain([&self.tiling.window_limits]);
+        for limits in window_limits {
+            if let Some((app, _)) = limits.iter().find(|(_, &limit)| limit == 0) {
+                return Err(format!("the window limit for {app:?} must be at least 1"));
+            }
+        }
+        if self.crash_loop.max_starts == 0 || self.crash_loop.period_secs == 0 {
+            return Err("the crash loop max_starts and period_secs must be at least 1".to_string());
+        }
+        let raise = &self.raise;
+        let mut raise_timeouts = raise.app_timeouts_ms.values().chain([&raise.timeout_ms]);
+        if raise_timeouts.any(|&ms| ms > MAX_RAISE_TIMEOUT_MS) {
+            return Err(format!(
+                "raise timeouts must be at most {MAX_RAISE_TIMEOUT_MS} ms"
+            ));
+        }
+        for config in &self.displays {
+            if config.gaps < 0.0
+                || config.external_bar.top < 0.0
+                || config.external_bar.bottom < 0.0
+            {
+                return Err(format!("display {:?} has negative spacing", config.display));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn find_duplicate<T: PartialEq>(items: &[T]) -> Option<&T> {
+    items
+        .iter()
+        .enumerate()
+        .find_map(|(i, item)| items[..i].contains(item).then_some(item))
+}
+
+/// An error in the config file, with the offending line if there is one.
+#[derive(Debug, Default)]
+struct ParseError {
+    line: usize,
+    col: usize,
+    source_line: String,
+    message: String,
+}
+
+impl ParseError {
+    fn new(buf: &str, err: ron::error::SpannedError) -> ParseError {
+        let ron::error::Position { line, col } = err.position;
+        let source_line = buf.lines().nth(line.saturating_sub(1)).unwrap_or_default();
+        ParseError {
+            line,
+            col,
+            source_line: source_line.to_string(),
+            message: err.code.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, " {}", self.message);
+        }
+        writeln!(f, "{}:{}: {}", self.line, self.col, self.message)?;
+        writeln!(f, "  | {}", self.source_line)?;
+        write!(f, "  | {:>width$}", "^", width = self.col.max(1))
+    }
+}
+
+/// Controls which spaces the window manager is active on.
+///
+/// A space is managed if it is selected by `default` or by any of the other
+/// options. Spaces can still be toggled at runtime.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpaceActivationConfig {
+    pub default: DefaultSpaceActivation,
+    /// Whether to manage spaces created while the window manager is running.
+    pub new_spaces: bool,
+    /// Spaces to manage, by uuid.
+    pub spaces: Vec<SpaceUuid>,
+    /// Displays on which to manage every space, by uuid.
+    pub displays: Vec<DisplayUuid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DefaultSpaceActivation {
+    /// Manage every space.
+    #[default]
+    All,
+    /// Manage the space that was active on each display when the window
+    /// manager started, or when the display was connected.
+    StartingSpaces,
+    /// Only manage the spaces selected by the other options.
+    None,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TilingConfig {
+    /// Whether to stop moving windows while the screen is being shared or
+    /// recorded.
+    pub pause_while_screen_sharing: bool,
+    /// Whether new windows split the selected window, side by side if it is
+    /// wider than it is tall and one above the other otherwise. Otherwise
+    /// they are added to the top level of the layout.
+    pub split_new_windows: bool,
+    /// Whether closing a window gives the windows next to it equal shares of
+    /// its container. By default each grows in proportion to its size.
+    pub balance_on_close: bool,
+    /// The most tiles the windows of an app can take up, by bundle id. Any
+    /// more windows are stacked with the app's other windows.
+    pub window_limits: HashMap<String, usize>,
+    /// Whether focus follows a window moved to another space with
+    /// `MoveWindowToSpace`. By default it stays on the current space and
+    /// moves to the next window in the layout.
+    pub follow_moved_windows: bool,
+    /// How to arrange the windows already open on a space when we start
+    /// tiling it.
+    pub adopt: AdoptStrategy,
+}
+
+impl Default for TilingConfig {
+    fn default() -> Self {
+        TilingConfig {
+            pause_while_screen_sharing: false,
+            split_new_windows: true,
+            balance_on_close: false,
+            window_limits: HashMap::new(),
+            follow_moved_windows: false,
+            adopt: AdoptStrategy::default(),
+        }
+    }
+}
+
+/// Settings for one display, applied whenever it is connected.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub display: DisplaySelector,
+    /// Space between tiled windows and around the edges of the screen, in
+    /// points.
+    #[serde(default)]
+    pub gaps: f64,
+    /// Space to leave for a status bar that is not part of macOS.
+    #[serde(default)]
+    pub external_bar: ExternalBarConfig,
+    /// The layout new spaces on this display start with.
+    #[serde(default)]
+    pub default_layout: LayoutKind,
+    /// Whether to leave every space on this display unmanaged.
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+/// Which display a [`DisplayConfig`] applies to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum DisplaySelector {
+    Uuid(DisplayUuid),
+    /// The name of the display as shown in System Settings, like
+    /// `"Built-in Retina Display"`.
+    Name(String),
+}
+
+impl DisplaySelector {
+    pub fn matches(&self, display: &DisplayInfo) -> bool {
+        match self {
+            DisplaySelector::Uuid(uuid) => *uuid == display.uuid,
+            DisplaySelector::Name(name) => *name == display.name,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExternalBarConfig {
+    /// Height to leave free at the top of the screen, in points.
+    pub top: f64,
+    /// Height to leave free at the bottom of the screen, in points.
+    pub bottom: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindingsConfig {
+    pub bind_by: BindKeysBy,
+    /// How bindings that move focus or windows repeat while held down, or
+    /// `None` to run them once per press.
+    pub repeat: Option<KeyRepeatConfig>,
+    /// Whether to list the windows in an overlay while cycling through them
+    /// in the order they were last used.
+    pub show_switcher: bool,
+}
+
+impl KeyBindingsConfig {
+    pub fn key_repeat(&self) -> Option<KeyRepeat> {
+        self.repeat.as_ref().map(|repeat| KeyRepeat {
+            delay: Duration::from_millis(repeat.delay_ms),
+            interval: Duration::from_millis(repeat.interval_ms),
+            acceleration: repeat.acceleration,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyRepeatConfig {
+    /// Milliseconds a binding is held before it first repeats.
+    pub delay_ms: u64,
+    /// Milliseconds between the first and second repeats.
+    pub interval_ms: u64,
+    /// How much faster each repeat comes than the one before, or 1 to repeat
+    /// at a steady rate.
+    pub acceleration: f64,
+}
+
+impl Default for KeyRepeatConfig {
+    fn default() -> Self {
+        KeyRepeatConfig {
+            delay_ms: 300,
+            interval_ms: 100,
+            acceleration: 1.0,
+        }
+    }
+}
+
+/// Which key a key binding, named after a key on a US keyboard, refers to in
+/// other keyboard layouts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BindKeysBy {
+    /// The key in the same position, whatever it types.
+    #[default]
+    Position,
+    /// The key that types the same character, looked up again whenever the
+    /// keyboard layout changes. Keys whose character the layout doesn't have
+    /// keep their position.
+    Character,
+}
+
+/// Commands to run when the mouse is moved into a corner of any screen.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotCornersConfig {
+    pub top_left: Option<Command>,
+    pub top_right: Option<Command>,
+    pub bottom_left: Option<Command>,
+    pub bottom_right: Option<Command>,
+}
+
+impl HotCornersConfig {
+    pub fn is_empty(&self) -> bool {
+        self.top_left.is_none()
+            && self.top_right.is_none()
+            && self.bottom_left.is_none()
+            && self.bottom_right.is_none()
+    }
+}
+
+/// Moving and resizing windows by dragging anywhere inside them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowDragConfig {
+    /// The keys to hold while dragging with the left button to move a window,
+    /// or the right button to resize it. Empty to turn this off.
+    pub modifiers: Vec<ModifierKey>,
+    /// Show an outline of the new frame while resizing, and resize the
+    /// window only when the button is released. Apps that are slow to
+    /// resize then don't have to keep up with the mouse. This also applies
+    /// to dragging the boundary between two tiles.
+    pub preview_resize: bool,
+}
+
+impl WindowDragConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.modifiers.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TitleBarConfig {
+    /// What double-clicking the title bar of a window does, or `None` to
+    /// leave it to macOS.
+    pub double_click: Option<TitleBarAction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    /// Seconds without keyboard or mouse input after which background polling
+    /// pauses until the next input, or 0 to never pause. Watching for input
+    /// needs Input Monitoring access, so this is off unless set.
+    pub idle_after_secs: u64,
+    /// Battery charge in percent below which `low_battery_animations` applies
+    /// while running on battery.
+    pub low_battery_percent: f64,
+    pub low_battery_animations: AnimationMode,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        PowerConfig {
+            idle_after_secs: 0,
+            low_battery_percent: 20.0,
+            low_battery_animations: AnimationMode::Reduced,
+        }
+    }
+}
+
+impl PowerConfig {
+    pub fn idle_after(&self) -> Option<Duration> {
+        (self.idle_after_secs > 0).then(|| Duration::from_secs(self.idle_after_secs))
+    }
+
+    /// Returns how to animate windows when running on `source`.
+    pub fn animation_mode(&self, source: PowerSource) -> AnimationMode {
+        let low = source.battery_percent.is_some_and(|p| p < self.low_battery_percent);
+        if source.on_battery && low {
+            self.low_battery_animations
+        } else {
+            AnimationMode::Full
+        }
+    }
+}
+
+/// Colors for overlays, replacing the defaults for the light and dark
+/// appearances.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayConfig {
+    pub light: OverlayColors,
+    pub dark: OverlayColors,
+}
+
+impl OverlayConfig {
+    /// Returns the colors to use in `appearance`.
+    pub fn palette(&self, appearance: Appearance) -> Palette {
+        let colors = match appearance {
+            Appearance::Light => &self.light,
+            Appearance::Dark => &self.dark,
+        };
+        let default = Palette::default_for(appearance);
+        Palette {
+            background: colors.background.unwrap_or(default.background),
+            text: colors.text.unwrap_or(default.text),
+            focused_border: colors.focused_border.unwrap_or(default.focused_border),
+            unfocused_border: colors.unfocused_border.unwrap_or(default.unfocused_border),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayColors {
+    pub background: Option<Color>,
+    pub text: Option<Color>,
+    /// The border around the focused window.
+    pub focused_border: Option<Color>,
+    /// The border around other windows.
+    pub unfocused_border: Option<Color>,
+}
+
+/// Borders drawn around tiled windows and the focused window.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BorderConfig {
+    pub enabled: bool,
+    /// Width of the borders in points.
+    pub width: f64,
+    /// Changes to the border of matching windows. Every matching rule
+    /// applies in order, so later rules override earlier ones.
+    pub rules: Vec<BorderRule>,
+}
+
+impl Default for BorderConfig {
+    fn default() -> Self {
+        BorderConfig {
+            enabled: false,
+            width: 4.0,
+            rules: vec![],
+        }
+    }
+}
+
+impl BorderConfig {
+    /// Returns the border for `window`, or `None` if it shouldn't have one.
+    pub fn style(
+        &self,
+        rules: &mut BorderRules,
+        window: &borders::Window,
+        palette: &Palette,
+    ) -> Option<BorderStyle> {
+        let mut style = BorderStyle {
+            width: self.width,
+            color: if window.is_focused {
+                palette.focused_border
+            } else {
+                palette.unfocused_border
+            },
+        };
+        for rule in rules.matching(window) {
+            style.width = rule.width.unwrap_or(style.width);
+            style.color = rule.color.unwrap_or(style.color);
+        }
+        (style.width > 0.0).then_some(style)
+    }
+}
+
+/// A change to the border of windows that match every condition given.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BorderRule {
+    /// Matches windows of the app with this bundle id.
+    pub app: Option<String>,
+    /// Matches windows whose title contains this.
+    pub title: Option<String>,
+    /// Matches the focused window if true, or other windows if false.
+    pub focused: Option<bool>,
+    /// Matches windows outside of the layout if true, or tiled windows if
+    /// false.
+    pub floating: Option<bool>,
+    pub color: Option<Color>,
+    /// Width of the border in points, or 0 for none.
+    pub width: Option<f64>,
+}
+
+impl BorderRule {
+    pub fn matches(&self, window: &borders::Window) -> bool {
+        self.matches_window(window) && self.matches_state(window)
+    }
+
+    /// Whether the app and title of `window` match, which only changes when
+    /// its title does.
+    fn matches_window(&self, window: &borders::Window) -> bool {
+        self.app
+            .as_ref()
+            .map_or(true, |app| window.app.as_deref() == Some(app.as_str()))
+            && self.title.as_ref().map_or(true, |title| window.title.contains(title.as_str()))
+    }
+
+    /// Whether the focus and floating state of `window` match.
+    fn matches_state(&self, window: &borders::Window) -> bool {
+        self.focused.map_or(true, |focused| focused == window.is_focused)
+            && self.floating.map_or(true, |floating| floating == window.is_floating)
+    }
+}
+
+/// Border rules, indexed by the app they match and with the rules that match
+/// each window remembered until its title changes, so that styling a window
+/// doesn't look at every rule.
+#[derive(Debug, Default)]
+pub struct BorderRules {
+    rules: Vec<BorderRule>,
+    index: BorderRuleIndex,
+    matches: HashMap<WindowServerId, WindowMatches>,
+}
+
+/// The rules whose app and title match a window, with the app and title they
+/// were matched against.
+#[derive(Debug)]
+struct WindowMatches {
+    app: Option<Arc<str>>,
+    title: Arc<str>,
+    rules: Vec<usize>,
+}
+
+impl BorderRules {
+    pub fn new(rules: Vec<BorderRule>) -> BorderRules {
+        BorderRules {
+            index: BorderRuleIndex::new(&rules),
+            rules,
+            matches: HashMap::default(),
+        }
+    }
+
+    /// Returns the rules that match `window`, in config order.
+    pub fn matching<'a>(
+        &'a mut self,
+        window: &'a borders::Window,
+    ) -> impl Iterator<Item = &'a BorderRule> + 'a {
+        let is_stale = self.matches.get(&window.id).map_or(true, |matches| {
+            matches.app != window.app || matches.title != window.title
+        });
+        if is_stale {
+            let candidates = self.index.candidates(window.app.as_deref());
+            let rules = candidates
+                .iter()
+                .copied()
+                .filter(|&idx| self.rules[idx].matches_window(window))
+                .collect();
+            let matches = WindowMatches {
+                app: window.app.clone(),
+                title: window.title.clone(),
+                rules,
+            };
+            self.matches.insert(window.id, matches);
+        }
+        let rules = &self.rules;
+        self.matches[&window.id]
+            .rules
+            .iter()
+            .map(move |&idx| &rules[idx])
+            .filter(move |rule| rule.matches_state(window))
+    }
+
+    /// Forgets the matches of windows other than `windows`.
+    pub fn retain(&mut self, windows: &[borders::Window]) {
+        let ids: HashSet<_> = windows.iter().map(|window| window.id).collect();
+        self.matches.retain(|id, _| ids.contains(id));
+    }
+}
+
+/// Border rules grouped by the app they match, so that matching a window only
+/// looks at the rules that can apply to it.
+#[derive(Debug, Default)]
+struct BorderRuleIndex {
+    /// For each app named by a rule, the rules for that app and the rules for
+    /// any app, in config order.
+    by_app: HashMap<String, Vec<usize>>,
+    /// The rules for any app.
+    any_app: Vec<usize>,
+}
+
+impl BorderRuleIndex {
+    fn new(rules: &[BorderRule]) -> BorderRuleIndex {
+        let mut index = BorderRuleIndex::default();
+        for rule in rules {
+            if let Some(app) = &rule.app {
+                index.by_app.entry(app.clone()).or_default();
+            }
+        }
+        for (idx, rule) in rules.iter().enumerate() {
+            match &rule.app {
+                Some(app) => index.by_app.get_mut(app).unwrap().push(idx),
+                None => {
+                    index.any_app.push(idx);
+                    for rules in index.by_app.values_mut() {
+                        rules.push(idx);
+                    }
+                }
+            }
+        }
+        index
+    }
+
+    /// Returns the indices of the rules that can match windows of `app`, in
+    /// config order.
+    fn candidates(&self, app: Option<&str>) -> &[usize] {
+        app.and_then(|app| self.by_app.get(app)).unwrap_or(&self.any_app)
+    }
+}
+
+/// How to place Picture-in-Picture windows, which are never tiled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipConfig {
+    /// The corner of the screen to keep them in, or `None` to leave them
+    /// where the app puts them.
+    pub corner: Option<Corner>,
+    /// Space between the window and the edges of the screen, in points.
+    pub margin: f64,
+}
+
+impl Default for PipConfig {
+    fn default() -> Self {
+        PipConfig { corner: None, margin: 16.0 }
+    }
+}
+
+/// Settings that replace the top-level ones while the profile is active.
+/// Settings that are left out keep their top-level values.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub name: String,
+    /// When the profile is active. Profiles without a schedule are only
+    /// active when chosen with `nimbus profile use`.
+    pub schedule: Option<Schedule>,
+    pub displays: Option<Vec<DisplayConfig>>,
+    pub border_rules: Option<Vec<BorderRule>>,
+    pub window_limits: Option<HashMap<String, usize>>,
+    /// Key bindings for the profile, written like `"Alt + Shift + KeyH"`.
+    /// Each replaces the default binding for the same keys, if there is one.
+    pub key_bindings: HashMap<String, Command>,
+}
+
+/// A daily period of time, on some days of the week.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// The days the period starts on, or every day if empty.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub start: TimeOfDay,
+    /// The end of the period. If this is before `start`, the period ends the
+    /// next day.
+    pub end: TimeOfDay,
+}
+
+impl Schedule {
+    pub fn contains(&self, now: LocalTime) -> bool {
+        let starts_on = |day| self.days.is_empty() || self.days.contains(&day);
+        if self.start < self.end {
+            starts_on(now.weekday) && self.start <= now.time && now.time < self.end
+        } else {
+            (starts_on(now.weekday) && now.time >= self.start)
+                || (starts_on(now.weekday.prev()) && now.time < self.end)
+        }
+    }
+}
+
+/// Returns the first profile whose schedule contains `now`.
+pub fn scheduled_profile(profiles: &[ProfileConfig], now: LocalTime) -> Option<&ProfileConfig> {
+    profiles
+        .iter()
+        .find(|profile| profile.schedule.as_ref().is_some_and(|s| s.contains(now)))
+}
+
+/// Changes applied while a macOS Focus, like Do Not Disturb, is on. They are
+/// reverted when the Focus ends.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FocusModeConfig {
+    /// How to animate windows during a Focus, or `None` to follow the power
+    /// settings.
+    pub animations: Option<AnimationMode>,
+    /// Bundle ids of apps whose visible windows are moved to
+    /// `background_space` when a Focus starts, and moved back when it ends.
+    pub background_apps: Vec<String>,
+    /// The desktop (starting at 1) on the same display to move them to.
+    pub background_space: usize,
+}
+
+impl FocusModeConfig {
+    /// Whether any setting depends on the Focus, so we need to watch it.
+    pub fn is_enabled(&self) -> bool {
+        self.animations.is_some() || !self.background_apps.is_empty()
+    }
+}
+
+/// How long an app may take to come to the front when we raise one of its
+/// windows before we warn that it is slow.
+///
+/// The timing overlay lists how long each app took to come to the front, to
+/// help pick timeouts for apps that are always slow.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RaiseConfig {
+    /// The timeout in milliseconds.
+    pub timeout_ms: u64,
+    /// Timeouts for apps that need more or less time, by bundle id.
+    pub app_timeouts_ms: HashMap<String, u64>,
+}
+
+/// Raise timeouts longer than this would leave focus stuck for too long.
+const MAX_RAISE_TIMEOUT_MS: u64 = 10_000;
+
+impl Default for RaiseConfig {
+    fn default() -> Self {
+        RaiseConfig {
+            timeout_ms: 500,
+            app_timeouts_ms: HashMap::new(),
+        }
+    }
+}
+
+impl RaiseConfig {
+    /// Returns the timeout for the app with `bundle_id`.
+    pub fn timeout(&self, bundle_id: Option<&str>) -> Duration {
+        let ms = bundle_id
+            .and_then(|id| self.app_timeouts_ms.get(id))
+            .copied()
+            .unwrap_or(self.timeout_ms);
+        Duration::from_millis(ms)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BorderStyle {
+    pub width: f64,
+    pub color: Color,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessibilityConfig {
+    /// Whether to have screen readers like VoiceOver read the title of each
+    /// window we focus, for example with a key binding.
+    pub announce_focus: bool,
+}
+
+/// When to start in safe mode because nimbus keeps restarting.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrashLoopConfig {
+    /// Starting more than this many times within `period_secs` is a crash
+    /// loop.
+    pub max_starts: usize,
+    pub period_secs: u64,
+}
+
+impl CrashLoopConfig {
+    pub fn threshold(&self) -> Threshold {
+        Threshold {
+            max_starts: self.max_starts,
+            period: Duration::from_secs(self.period_secs),
+        }
+    }
+}
+
+impl Default for CrashLoopConfig {
+    fn default() -> Self {
+        let threshold = Threshold::default();
+        CrashLoopConfig {
+            max_starts: threshold.max_starts,
+            period_secs: threshold.period.as_secs(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    /// Check GitHub for new releases at launch and once a day, and show a
+    /// notice when there is one.
+    pub check: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable output, indented by span.
+    #[default]
+    Tree,
+    /// One JSON object per line, including the fields of the current spans.
+    Json,
+}
+
+/// Returns the contents of a config file with every option set to its
+/// default and documented, for `nimbus config init`.
+///
+/// `key_bindings` is a list of hotkeys and the commands they run, which are
+/// listed for reference.
+pub fn default_config_file(key_bindings: &[(String, String)]) -> String {
+    let mut buf = String::from(
+        r#"// Configuration for nimbus.
+//
+// Every option is shown here with its default value. Options can be removed
+// to use the default.
+(
+    spaces: (
+        // Which spaces to manage by default. One of:
+        //   All             Manage every space.
+        //   StartingSpaces  Manage the space that was active on each display
+        //                   when nimbus started or the display was connected.
+        //   None            Only manage the spaces selected below.
+        // Spaces can always be toggled at runtime.
+        default: All,
+
+        // Whether to manage spaces created while nimbus is running.
+        new_spaces: false,
+
+        // Additional spaces to manage, by uuid.
+        spaces: [],
+
+        // Displays on which to manage every space, by uuid.
+        displays: [],
+    ),
+
+    tiling: (
+        // Whether to stop moving windows while the screen is being shared or
+        // recorded. Windows are moved back into place when sharing stops.
+        pause_while_screen_sharing: false,
+
+        // Whether new windows split the selected window instead of being
+        // added to the top level of the layout. The split is side by side if
+        // the selected window is wider than it is tall, and one above the
+        // other otherwise.
+        split_new_windows: true,
+
+        // Whether closing a window resizes the windows next to it to equal
+        // sizes. Otherwise each keeps its share of the remaining space.
+        balance_on_close: false,
+
+        // The most tiles the windows of an app can take up, by bundle id.
+        // Windows beyond the limit are stacked with the app's other windows
+        // instead of splitting the screen further. For example:
+        //
+        //   window_limits: {"com.google.Chrome": 2},
+        window_limits: {},
+
+        // Whether focus follows a window moved to another space with
+        // MoveWindowToSpace. Otherwise it stays on the current space and goes
+        // to the next window in the layout. FollowWindowToSpace always
+        // follows.
+        follow_moved_windows: false,
+
+        // How to arrange the windows already open on a space when nimbus
+        // starts tiling it:
+        //   Order   Side by side, in the order they are found.
+        //   Grid    In columns, keeping each window near where it was.
+        //   Stack   All in one stack.
+        //   Master  The largest window on the left and the rest on the right.
+        adopt: Order,
+    ),
+
+    // Settings for particular displays, which apply whenever the display is
+    // connected. For example:
+    //
+    //   displays: [
+    //       (
+    //           // Either Uuid("...") or Name("..."), as shown in System
+    //           // Settings.
+    //           display: Name("Built-in Retina Display"),
+    //           // Space between tiled windows and around the screen edges.
+    //           gaps: 8,
+    //           // Space to leave for a status bar like SketchyBar.
+    //           external_bar: (top: 32, bottom: 0),
+    //           // The layout new spaces start with: Horizontal, Vertical,
+    //           // Tabbed, or Stacked.
+    //           default_layout: Vertical,
+    //           // Leave every space on the display unmanaged.
+    //           disabled: false,
+    //       ),
+    //   ],
+    displays: [],
+
+    // Commands to run when the mouse is moved into a corner of a screen, as
+    // an alternative to the hot corners in System Settings. For example:
+    //
+    //   top_right: Some(PauseTiling),
+    hot_corners: (
+        top_left: None,
+        top_right: None,
+        bottom_left: None,
+        bottom_right: None,
+    ),
+
+    // Hold these keys and drag anywhere inside a window with the left mouse
+    // button to move it, or with the right button to resize it. Dropping a
+    // tiled window onto another swaps the two. Any of Shift, Ctrl, Alt, and
+    // Cmd; for example:
+    //
+    //   modifiers: [Ctrl, Alt],
+    //
+    // With preview_resize, resizing shows an outline of the new frame, and
+    // the window is resized once when the button is released. Dragging the
+    // boundary between tiles shows an outline of the boundary the same way.
+    window_drag: (
+        modifiers: [],
+        preview_resize: false,
+    ),
+
+    // What double-clicking the title bar of a window does instead of what is
+    // set in System Settings: Some(ToggleZoom) to make a tiled window fill
+    // the screen, Some(ToggleFloat) to take it out of the layout, or None to
+    // leave it to macOS.
+    title_bar: (
+        double_click: None,
+    ),
+
+    key_bindings: (
+        // Key bindings are named after keys on a US keyboard. In other
+        // keyboard layouts, they use either:
+        //   Position   The key in the same place, whatever it types.
+        //   Character  The key that types the same character, so Alt + KeyA
+        //              is Alt + Q on a French keyboard. Keys whose character
+        //              the layout lacks, like digits on a French keyboard,
+        //              stay in place.
+        bind_by: Position,
+
+        // Whether bindings that move focus or windows repeat while held down,
+        // like keys do when typing. Noticing when they are released needs
+        // Input Monitoring access. For example:
+        //
+        //   repeat: Some((
+        //       // Milliseconds before the first repeat.
+        //       delay_ms: 300,
+        //       // Milliseconds between the first and second repeats.
+        //       interval_ms: 100,
+        //       // How much faster each repeat comes than the last, or 1 for
+        //       // a steady rate.
+        //       acceleration: 1.2,
+        //   )),
+        repeat: None,
+
+        // Whether to list the windows in an overlay while Alt + Tab cycles
+        // through them, most recently used first. Telling when Alt is
+        // released needs Input Monitoring access.
+        show_switcher: false,
+    ),
+
+    power: (
+        // Seconds without keyboard or mouse input after which background
+        // checks, like the one for screen sharing, pause until the next input.
+        // 0 never pauses. Watching for input needs Input Monitoring access,
+        // which nimbus asks for when this is set; 300 is a good value.
+        idle_after_secs: 0,
+
+        // How to animate windows when running on battery below
+        // low_battery_percent. One of:
+        //   Full      Animate normally.
+        //   Reduced   Animate at a lower frame rate.
+        //   Disabled  Move windows into place immediately.
+        low_battery_percent: 20,
+        low_battery_animations: Reduced,
+    ),
+
+    // Colors for overlays in the light and dark appearances, as
+    // (red, green, blue, alpha) from 0 to 1. Colors that are left out follow
+    // the system appearance. For example:
+    //
+    //   dark: (focused_border: Some((1.0, 0.6, 0.0, 1.0))),
+    overlays: (
+        light: (),
+        dark: (),
+    ),
+
+    borders: (
+        // Whether to draw borders around tiled windows and the focused
+        // window, in the border colors from overlays.
+        enabled: false,
+        width: 4,
+
+        // Changes to the borders of windows that match every condition
+        // given: app (a bundle id), title (part of the window title),
+        // focused, and floating. Every matching
+        // rule applies, with later rules taking precedence. For example:
+        //
+        //   rules: [
+        //       (floating: Some(true), color: Some((1.0, 0.0, 0.0, 1.0))),
+        //       (app: Some("com.apple.Terminal"), width: Some(0)),
+        //   ],
+        rules: [],
+    ),
+
+    // Picture-in-Picture windows are never tiled and are kept above other
+    // windows.
+    pip: (
+        // The corner of the screen to keep them in, like Some(BottomRight),
+        // or None to leave them where the app puts them.
+        corner: None,
+        // Space between the window and the edges of the screen.
+        margin: 16,
+    ),
+
+    // Changes while a Focus, like Do Not Disturb, is on, reverted when it
+    // ends. macOS asks whether nimbus may see your Focus status the first
+    // time this is used. For example:
+    //
+    //   focus_mode: (
+    //       animations: Some(Disabled),
+    //       background_apps: ["com.tinyspeck.slackmacgap"],
+    //       background_space: 2,
+    //   ),
+    focus_mode: (
+        // How to animate windows, or None to follow the power settings.
+        animations: None,
+        // Bundle ids of apps whose visible windows move to the desktop
+        // background_space (starting at 1) on the same display.
+        background_apps: [],
+        background_space: 0,
+    ),
+
+    // How long an app may take to come to the front when raising one of its
+    // windows before a warning is logged. The timing overlay shown by
+    // ToggleTiming lists how long each app takes. For example:
+    //
+    //   raise: (app_timeouts_ms: {"com.microsoft.Word": 2000}),
+    raise: (
+        timeout_ms: 500,
+        // Timeouts for particular apps, by bundle id.
+        app_timeouts_ms: {},
+    ),
+
+    accessibility: (
+        // Whether screen readers like VoiceOver read the title and app of
+        // each window focused by nimbus, for example with a key binding.
+        announce_focus: false,
+    ),
+
+    // Named sets of settings that replace the top-level displays,
+    // border_rules (borders.rules), and window_limits (tiling.window_limits)
+    // while active, and key bindings that replace the ones listed at the end
+    // of this file. The first profile whose schedule contains the current
+    // time is active; the top-level settings apply outside of every schedule.
+    // Days are Mon, Tue, Wed, Thu, Fri, Sat, and Sun, or every day if left
+    // out. `nimbus profile use <name>` chooses a profile on this machine
+    // instead of the schedules, whether or not it has one. For example:
+    //
+    //   profiles: [
+    //       (
+    //           name: "work",
+    //           schedule: Some((
+    //               days: [Mon, Tue, Wed, Thu, Fri],
+    //               start: "09:00",
+    //               end: "17:30",
+    //           )),
+    //           displays: Some([(display: Name("DELL U2720Q"), gaps: 0)]),
+    //           window_limits: Some({"com.google.Chrome": 1}),
+    //           key_bindings: {"Alt + KeyF": FocusNextDisplay},
+    //       ),
+    //   ],
+    profiles: [],
+
+    crash_loop: (
+        // Starting more than max_starts times within period_secs seconds
+        // means something makes nimbus crash, so it starts in safe mode
+        // instead and leaves every space alone until one is turned back on.
+        max_starts: 4,
+        period_secs: 300,
+    ),
+
+    updates: (
+        // Check for new releases at launch and once a day, and show a notice
+        // with a link to the changelog when there is one.
+        check: false,
+    ),
+
+    logging: (
+        // How to format log output. One of:
+        //   Tree  Human-readable output, indented by span.
+        //   Json  One JSON object per line, for log viewers.
+        format: Tree,
+    ),
+)
+
+// Key bindings:
+"#,
+    );
+    let width = key_bindings.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, command) in key_bindings {
+        buf.push_str(&format!("//   {key:width$}  {command}\n"));
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use icrate::Foundation::CGRect;
+
+    use super::*;
+    use crate::sys::hotkey::{KeyCode, Modifiers};
+
+    #[test]
+    fn it_only_defaults_missing_files_it_was_not_asked_for() {
+        let path = Path::new("/nonexistent/nimbus/config.ron");
+        assert!(Config::load(path).is_ok());
+        let err = Config::load_existing(path).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent"), "{err}");
+    }
+
+    #[test]
+    fn it_parses_partial_configs() {
+        let config: Config = ron::from_str("(spaces: (default: None, new_spaces: true))").unwrap();
+        assert_eq!(config.spaces.default, DefaultSpaceActivation::None);
+        assert!(config.spaces.new_spaces);
+        assert!(config.spaces.displays.is_empty());
+
+        let config: Config = ron::from_str("()").unwrap();
+        assert_eq!(config.spaces.default, DefaultSpaceActivation::All);
+    }
+
+    #[test]
+    fn it_reports_where_errors_are() {
+        let err = Config::parse("(\n  spaces: (\n    defualt: None,\n  ),\n)").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("defualt"), "{}", err.message);
+        assert_eq!(err.source_line, "    defualt: None,");
+
+        let err = Config::parse(r#"(spaces: (spaces: ["a", "b", "a"]))"#).unwrap_err();
+        assert!(err.message.contains("\"a\""), "{}", err.message);
+    }
+
+    #[test]
+    fn it_parses_display_configs() {
+        let config = Config::parse(
+            r#"(displays: [
+                (display: Name("Built-in Retina Display"), gaps: 8, default_layout: Vertical),
+                (display: Uuid("1234"), external_bar: (top: 32), disabled: true),
+            ])"#,
+        )
+        .unwrap();
+        let [laptop, external] = &config.displays[..] else {
+            panic!()
+        };
+        assert_eq!(laptop.gaps, 8.0);
+        assert_eq!(laptop.default_layout, LayoutKind::Vertical);
+        assert_eq!(laptop.external_bar.top, 0.0);
+        assert!(!laptop.disabled);
+        assert_eq!(external.external_bar.top, 32.0);
+        assert!(external.disabled);
+
+        let err = Config::parse(r#"(displays: [(display: Uuid("a")), (display: Uuid("a"))])"#)
+            .unwrap_err();
+        assert!(err.message.contains("more than one"), "{}", err.message);
+    }
+
+    #[test]
+    fn it_parses_hot_corners() {
+        let config = Config::parse("(hot_corners: (bottom_left: Some(PauseTiling)))").unwrap();
+        assert!(matches!(
+            config.hot_corners.bottom_left,
+            Some(Command::PauseTiling)
+        ));
+        assert!(config.hot_corners.top_left.is_none());
+        assert!(!config.hot_corners.is_empty());
+        assert!(Config::default().hot_corners.is_empty());
+    }
+
+    #[test]
+    fn it_parses_title_bar_actions() {
+        let config = Config::parse("(title_bar: (double_click: Some(ToggleFloat)))").unwrap();
+        assert_eq!(
+            Some(TitleBarAction::ToggleFloat),
+            config.title_bar.double_click
+        );
+        assert_eq!(None, Config::default().title_bar.double_click);
+    }
+
+    #[test]
+    fn it_parses_window_drag_modifiers() {
+        let config = Config::parse("(window_drag: (modifiers: [Ctrl, Alt]))").unwrap();
+        assert_eq!(
+            vec![ModifierKey::Ctrl, ModifierKey::Alt],
+            config.window_drag.modifiers
+        );
+        assert!(config.window_drag.is_enabled());
+        assert!(!Config::default().window_drag.is_enabled());
+        assert!(Config::parse("(window_drag: (modifiers: [Hyper]))").is_err());
+    }
+
+    #[test]
+    fn it_parses_window_limits() {
+        let config =
+            Config::parse(r#"(tiling: (window_limits: {"com.google.Chrome": 2}))"#).unwrap();
+        assert_eq!(
+            config.tiling.window_limits.get("com.google.Chrome"),
+            Some(&2)
+        );
+
+        let err =
+            Config::parse(r#"(tiling: (window_limits: {"com.google.Chrome": 0}))"#).unwrap_err();
+        assert!(err.message.contains("at least 1"), "{}", err.message);
+    }
+
+    #[test]
+    fn it_parses_raise_timeouts() {
+        let config =
+            Config::parse(r#"(raise: (app_timeouts_ms: {"com.microsoft.Word": 2000}))"#).unwrap();
+        assert_eq!(
+            config.raise.timeout(Some("com.microsoft.Word")),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            config.raise.timeout(Some("com.apple.Safari")),
+            Duration::from_millis(500)
+        );
+        assert_eq!(config.raise.timeout(None), Duration::from_millis(500));
+
+        let err = Config::parse("(raise: (timeout_ms: 60000))").unwrap_err();
+        assert!(err.message.contains("at most"), "{}", err.message);
+    }
+
+    #[test]
+    fn it_parses_profiles() {
+        let config = Config::parse(
+            r#"(profiles: [
+                (
+                    name: "work",
+                    schedule: Some((days: [Mon, Fri], start: "09:00", end: "17:30")),
+                    window_limits: Some({"com.google.Chrome": 1}),
+                ),
+                (name: "night", schedule: Some((start: "22:00", end: "06:00"))),
+                (name: "manual", displays: Some([])),
+            ])"#,
+        )
+        .unwrap();
+        let at = |weekday, hour, minute| LocalTime {
+            weekday,
+            time: TimeOfDay::new(hour, minute).unwrap(),
+        };
+        let active = |now| scheduled_profile(&config.profiles, now).map(|p| p.name.as_str());
+        assert_eq!(active(at(Weekday::Mon, 9, 0)), Some("work"));
+        assert_eq!(active(at(Weekday::Fri, 17, 29)), Some("work"));
+        assert_eq!(active(at(Weekday::Fri, 17, 30)), None);
+        assert_eq!(active(at(Weekday::Tue, 12, 0)), None);
+        assert_eq!(active(at(Weekday::Sun, 23, 0)), Some("night"));
+        assert_eq!(active(at(Weekday::Mon, 5, 59)), Some("night"));
+        assert_eq!(active(at(Weekday::Mon, 6, 0)), None);
+
+        let err = Config::parse(
+            r#"(profiles: [(name: "a", schedule: Some((start: "9am", end: "17:00")))])"#,
+        )
+        .unwrap_err();
+        assert!(err.message.contains("9am"), "{}", err.message);
+        let err = Config::parse(r#"(profiles: [(name: "a"), (name: "a")])"#).unwrap_err();
+        assert!(err.message.contains("more than once"), "{}", err.message);
+    }
+
+    #[test]
+    fn it_parses_profile_key_bindings() {
+        let config = Config::parse(
+            r#"(profiles: [(name: "a", key_bindings: {"Ctrl + Alt + KeyH": FocusNextDisplay})])"#,
+        )
+        .unwrap();
+        let (key, cmd) = config.profiles[0].key_bindings.iter().next().unwrap();
+        let hotkey = parse_hotkey(key).unwrap();
+        assert_eq!(hotkey.modifiers, Modifiers::CONTROL | Modifiers::ALT);
+        assert_eq!(hotkey.key_code, KeyCode::KeyH);
+        assert!(matches!(cmd, Command::FocusNextDisplay));
+
+        let err =
+            Config::parse(r#"(profiles: [(name: "a", key_bindings: {"Hyper + KeyH": Hello})])"#)
+                .unwrap_err();
+        assert!(err.message.contains("Hyper"), "{}", err.message);
+    }
+
+    #[test]
+    fn default_config_file_is_valid() {
+        let bindings = [("Alt + KeyW".to_string(), "Hello".to_string())];
+        let config = Config::parse(&default_config_file(&bindings)).unwrap();
+        assert_eq!(config.spaces.default, DefaultSpaceActivation::All);
+        assert!(!config.spaces.new_spaces);
+        assert_eq!(config.logging.format, LogFormat::Tree);
+        assert_eq!(config.power.idle_after(), None);
+        assert!(config.tiling.split_new_windows);
+        assert_eq!(config.crash_loop.threshold(), Threshold::default());
+    }
+
+    #[test]
+    fn it_overrides_overlay_colors() {
+        let config =
+            Config::parse("(overlays: (dark: (text: Some((1.0, 0.0, 0.0, 1.0)))))").unwrap();
+        let dark = config.overlays.palette(Appearance::Dark);
+        assert_eq!(dark.text, Color(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(
+            dark.background,
+            Palette::default_for(Appearance::Dark).background
+        );
+        assert_eq!(
+            config.overlays.palette(Appearance::Light),
+            Palette::default_for(Appearance::Light)
+        );
+    }
+
+    #[test]
+    fn it_applies_border_rules_in_order() {
+        let config = Config::parse(
+            r#"(borders: (enabled: true, width: 2, rules: [
+                (floating: Some(true), color: Some((1.0, 0.0, 0.0, 1.0)), width: Some(6)),
+                (app: Some("com.apple.Terminal"), focused: Some(false), width: Some(0)),
+                (app: Some("com.apple.Terminal"), floating: Some(true), width: Some(3)),
+            ]))"#,
+        )
+        .unwrap();
+        let palette = Palette::default_for(Appearance::Dark);
+        let mut rules = BorderRules::new(config.borders.rules.clone());
+        let mut style = |app: &str, is_focused, is_floating| {
+            let window = borders::Window {
+                id: WindowServerId::new(1),
+                frame: CGRect::ZERO,
+                app: Some(app.into()),
+                title: "".into(),
+                is_focused,
+                is_floating,
+            };
+            config.borders.style(&mut rules, &window, &palette)
+        };
+        let red = Color(1.0, 0.0, 0.0, 1.0);
+        assert_eq!(
+            style("com.apple.Safari", true, false),
+            Some(BorderStyle {
+                width: 2.0,
+                color: palette.focused_border
+            })
+        );
+        assert_eq!(
+            style("com.apple.Safari", false, true),
+            Some(BorderStyle { width: 6.0, color: red })
+        );
+        assert_eq!(style("com.apple.Terminal", false, false), None);
+        assert_eq!(
+            style("com.apple.Terminal", false, true),
+            Some(BorderStyle { width: 3.0, color: red })
+        );
+
+        let err = Config::parse("(borders: (width: -1))").unwrap_err();
+        assert!(err.message.contains("negative"), "{}", err.message);
+    }
+
+    #[test]
+    fn it_matches_border_rules_again_when_the_title_changes() {
+        let config = Config::parse(
+            r#"(borders: (enabled: true, width: 2, rules: [
+                (title: Some("Private"), width: Some(0)),
+            ]))"#,
+        )
+        .unwrap();
+        let palette = Palette::default_for(Appearance::Dark);
+        let mut rules = BorderRules::new(config.borders.rules.clone());
+        let mut window = borders::Window {
+            id: WindowServerId::new(1),
+            frame: CGRect::ZERO,
+            app: Some("com.apple.Safari".into()),
+            title: "Start Page".into(),
+            is_focused: true,
+            is_floating: false,
+        };
+        assert!(config.borders.style(&mut rules, &window, &palette).is_some());
+        window.title = "Private Browsing".into();
+        assert_eq!(config.borders.style(&mut rules, &window, &palette), None);
+        window.title = "Start Page".into();
+        assert!(config.borders.style(&mut rules, &window, &palette).is_some());
+
+        rules.retain(&[]);
+        assert!(rules.matches.is_empty());
+    }
+
+    #[test]
+    fn it_indexes_border_rules_by_app() {
+        let rule = |app: Option<&str>| BorderRule {
+            app: app.map(str::to_string),
+            ..Default::default()
+        };
+        let rules = [
+            rule(None),
+            rule(Some("com.apple.Terminal")),
+            rule(Some("com.apple.Safari")),
+            rule(None),
+        ];
+        let index = BorderRuleIndex::new(&rules);
+        assert_eq!(index.candidates(Some("com.apple.Terminal")), &[0, 1, 3]);
+        assert_eq!(index.candidates(Some("com.apple.Safari")), &[0, 2, 3]);
+        assert_eq!(index.candidates(Some("com.apple.Notes")), &[0, 3]);
+        assert_eq!(index.candidates(None), &[0, 3]);
+    }
+
+    #[test]
+    fn it_throttles_animations_on_low_battery() {
+        let power = PowerConfig::default();
+        let mode = |on_battery, battery_percent| {
+            power.animation_mode(PowerSource { on_battery, battery_percent })
+        };
+        assert_eq!(AnimationMode::Full, mode(false, None));
+        assert_eq!(AnimationMode::Full, mode(false, Some(5.0)));
+        assert_eq!(AnimationMode::Full, mode(true, Some(50.0)));
+        assert_eq!(AnimationMode::Reduced, mode(true, Some(5.0)));
+    }
+}
@@ -0,0 +1,152 @@
+//! Detecting when nimbus keeps restarting.
+//!
+//! A rule or an app that makes us crash soon after starting would otherwise
+//! rearrange the user's windows on every restart, so after too many starts in
+//! a short time we start in safe mode instead.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::{debug, warn};
+
+/// How many starts in how long make a crash loop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    /// Starting more than this many times within `period` is a crash loop.
+    pub max_starts: usize,
+    pub period: Duration,
+}
+
+impl Default for Threshold {
+    fn default() -> Self {
+        Threshold {
+            max_starts: 4,
+            period: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Records that nimbus started in `file`, and returns whether it has started
+/// more often than `threshold` allows.
+///
+/// The record is cleared once a crash loop is detected, so the start after
+/// that counts from zero.
+pub fn record_start(file: &Path, threshold: Threshold) -> io::Result<bool> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let contents = match fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut starts = recent_starts(&contents, now, threshold.period);
+    starts.push(now);
+    if starts.len() > threshold.max_starts {
+        fs::remove_file(file)?;
+        return Ok(true);
+    }
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents: String = starts.iter().map(|start| format!("{start}\n")).collect();
+    fs::write(file, contents)?;
+    Ok(false)
+}
+
+/// Clears the starts recorded in `file` once nimbus has run for `period`, or
+/// when it exits before then. Starts that ended either way were not crashes,
+/// so they shouldn't count towards a crash loop.
+///
+/// Exiting means calling `exit`, which is how nimbus quits. Crashes abort
+/// instead, and leave the record in place.
+pub fn clear_starts_on_success(file: PathBuf, period: Duration) {
+    if STARTS_FILE.set(file.clone()).is_ok() {
+        unsafe { libc::atexit(clear_starts_at_exit) };
+    }
+    thread::spawn(move || {
+        thread::sleep(period);
+        debug!("Ran for {period:?}; clearing recorded starts");
+        if let Err(e) = clear_starts(&file) {
+            warn!("Could not clear recorded starts: {e}");
+        }
+    });
+}
+
+/// The file [`clear_starts_on_success`] clears at exit.
+static STARTS_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+extern "C" fn clear_starts_at_exit() {
+    if let Some(file) = STARTS_FILE.get() {
+        _ = clear_starts(file);
+    }
+}
+
+fn clear_starts(file: &Path) -> io::Result<()> {
+    match fs::remove_file(file) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Returns the starts recorded in `contents` that were within `period` of
+/// `now`. Each line is a time in seconds since the Unix epoch.
+fn recent_starts(contents: &str, now: u64, period: Duration) -> Vec<u64> {
+    contents
+        .lines()
+        .filter_map(|line| line.trim().parse::<u64>().ok())
+        .filter(|&start| now.saturating_sub(start) < period.as_secs())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, time::Duration};
+
+    use super::{clear_starts, recent_starts, record_start, Threshold};
+
+    #[test]
+    fn it_keeps_recent_starts() {
+        let now = 10_000;
+        let period = Duration::from_secs(300);
+        let old = now - period.as_secs();
+        let contents = format!("{old}\n{}\nnot a time\n{}\n", now - 60, now - 1);
+        assert_eq!(
+            vec![now - 60, now - 1],
+            recent_starts(&contents, now, period)
+        );
+        assert!(recent_starts("", now, period).is_empty());
+        assert_eq!(
+            vec![now - 1],
+            recent_starts(&contents, now, Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn it_detects_too_many_starts() {
+        let dir = env::temp_dir().join(format!("nimbus-crash-loop-{}", process::id()));
+        let file = dir.join("starts");
+        let threshold = Threshold {
+            max_starts: 2,
+            period: Duration::from_secs(300),
+        };
+        assert!(!record_start(&file, threshold).unwrap());
+        assert!(!record_start(&file, threshold).unwrap());
+        assert!(record_start(&file, threshold).unwrap());
+        // The record starts over after a crash loop.
+        assert!(!file.exists());
+        assert!(!record_start(&file, threshold).unwrap());
+
+        // And after a successful run.
+        clear_starts(&file).unwrap();
+        assert!(!file.exists());
+        clear_starts(&file).unwrap();
+        assert!(!record_start(&file, threshold).unwrap());
+        assert!(!record_start(&file, threshold).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
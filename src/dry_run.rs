@@ -0,0 +1,192 @@
+//! The `--validate` dry run, which reports what the window manager would do
+//! with the current config without moving anything.
+
+use std::{collections::HashSet, path::Path, sync::Arc};
+
+use accessibility::{AXUIElement, AXUIElementAttributes};
+use icrate::Foundation::{CGRect, MainThreadMarker};
+
+use crate::{
+    actor::{borders, layout::LayoutManager, wm_controller},
+    config::Config,
+    sys::{
+        app::{running_apps, WindowInfo},
+        permissions::Permission,
+        screen::{self, ScreenCache},
+    },
+};
+
+/// Prints the config, permissions, screens, spaces, and windows the window
+/// manager would see, and what it would do with each window.
+///
+/// Returns false if anything would keep the window manager from working.
+pub fn run(config: &Config, restore_file: &Path) -> bool {
+    let mut ok = true;
+    println!("Config: ok");
+    // The spaces turned off before the last restart stay off.
+    let mut disabled_spaces = HashSet::new();
+    if restore_file.exists() {
+        match LayoutManager::load(restore_file.to_path_buf()) {
+            Ok(layout) => {
+                println!("Saved layout: ok");
+                disabled_spaces = layout.disabled_spaces().clone();
+            }
+            Err(e) => {
+                println!("Saved layout: {e}");
+                ok = false;
+            }
+        }
+    }
+
+    println!("\nPermissions:");
+    for permission in [Permission::Accessibility, Permission::InputMonitoring] {
+        let granted = permission.is_granted();
+        println!(
+            "  {permission:?}: {}",
+            if granted { "granted" } else { "missing" }
+        );
+        if !granted && permission.is_required() {
+            ok = false;
+        }
+    }
+
+    println!("\nScreens:");
+    let mut displays = Vec::new();
+    match MainThreadMarker::new() {
+        Some(mtm) => {
+            let mut screens = ScreenCache::new(mtm);
+            let frames = screens.update_screen_config();
+            displays = screens.displays();
+            for (frame, display) in frames.iter().zip(&displays) {
+                let display_config =
+                    config.displays.iter().find(|config| config.display.matches(display));
+                let status = match display_config {
+                    Some(config) if config.disabled => "disabled by config",
+                    Some(_) => "managed, with display settings",
+                    None => "managed",
+                };
+                println!(
+                    "  {} ({:?}) at {}: {status}",
+                    display.name,
+                    display.uuid,
+                    describe_frame(*frame)
+                );
+            }
+        }
+        None => println!("  (must run on the main thread to list screens)"),
+    }
+
+    println!("\nSpaces:");
+    for space in screen::spaces() {
+        let display = displays
+            .iter()
+            .find(|display| display.uuid == space.display)
+            .and_then(|display| config.displays.iter().find(|c| c.display.matches(display)));
+        // Starting now, the spaces active on each display are the starting
+        // spaces, and no space is new.
+        let is_starting = screen::current_space(&space.display) == Some(space.id);
+        let status = if !space.is_user_space {
+            "full screen app, never managed"
+        } else if disabled_spaces.contains(&space.uuid) {
+            "turned off before the last restart"
+        } else if display.is_some_and(|c| c.disabled) {
+            "display disabled by config"
+        } else if wm_controller::is_space_activated(
+            &config.spaces,
+            display,
+            &disabled_spaces,
+            &space,
+            is_starting,
+            false,
+        ) {
+            "managed"
+        } else {
+            "not managed"
+        };
+        println!("  {:?} ({:?}): {status}", space.id, space.uuid);
+    }
+    if config.spaces.new_spaces {
+        println!("  Spaces created later: managed");
+    }
+
+    println!("\nWindows:");
+    for (pid, info) in running_apps(None) {
+        let Ok(elements) = AXUIElement::application(pid).windows() else {
+            continue;
+        };
+        let windows: Vec<WindowInfo> = elements
+            .iter()
+            .filter_map(|element| WindowInfo::try_from(&*element).ok())
+            .collect();
+        if windows.is_empty() {
+            continue;
+        }
+        let name = info.localized_name.as_deref().unwrap_or("?");
+        let bundle_id = info.bundle_id.as_deref().unwrap_or("?");
+        println!("  {name} ({bundle_id}, pid {pid})");
+        let limit = info.app_id().and_then(|id| config.tiling.window_limits.get(id));
+        if let Some(limit) = limit {
+            println!("    windows beyond the first {limit} are stacked");
+        }
+        for window in &windows {
+            let action = describe_action(config, window);
+            let border = borders::Window {
+                id: window.sys_id,
+                frame: window.frame,
+                app: info.app_id().map(Arc::from),
+                title: Arc::from(window.title.as_str()),
+                is_focused: false,
+                is_floating: !window.is_tileable(),
+            };
+            let rules: Vec<_> = config
+                .borders
+                .rules
+                .iter()
+                .enumerate()
+                .filter(|(_, rule)| rule.matches(&border))
+                .map(|(idx, _)| idx.to_string())
+                .collect();
+            print!(
+                "    {:?} at {}: {action}",
+                window.title,
+                describe_frame(window.frame)
+            );
+            if config.borders.enabled && !rules.is_empty() {
+                print!("; border rules {} match", rules.join(", "));
+            }
+            println!();
+        }
+    }
+    ok
+}
+
+/// What the window manager would do with a window.
+fn describe_action(config: &Config, window: &WindowInfo) -> String {
+    if window.is_pip {
+        return match config.pip.corner {
+            Some(corner) => format!("Picture-in-Picture, kept in front in the {corner:?} corner"),
+            None => "Picture-in-Picture, kept in front".to_string(),
+        };
+    }
+    if window.is_minimized {
+        return "minimized".to_string();
+    }
+    if window.is_fullscreen {
+        return "full screen, left alone".to_string();
+    }
+    if window.is_tileable() {
+        return "tiled".to_string();
+    }
+    match window.subrole.as_str() {
+        "AXDialog" | "AXSystemDialog" => "dialog, centered over its parent".to_string(),
+        _ if window.parent.is_some() => "attached to its parent, left alone".to_string(),
+        subrole => format!("floating ({subrole})"),
+    }
+}
+
+fn describe_frame(frame: CGRect) -> String {
+    format!(
+        "({}, {}) {}x{}",
+        frame.origin.x, frame.origin.y, frame.size.width, frame.size.height
+    )
+}
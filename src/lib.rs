@@ -1,6 +1,11 @@
 #![cfg(not(test))]
 
 pub mod actor;
+pub mod config;
+pub mod crash_loop;
+pub mod dry_run;
+pub mod logging;
 pub mod metrics;
 pub mod model;
 pub mod sys;
+pub mod update;
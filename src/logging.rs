@@ -0,0 +1,194 @@
+//! Log output.
+//!
+//! Besides the console, logs are written to a rotating file in
+//! `~/Library/Logs/nimbus` and kept in a bounded in-memory buffer, so that
+//! recent activity can be collected for bug reports.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+use tracing_tree::time::UtcDateTime;
+
+use crate::{config::LogFormat, metrics};
+
+/// The size at which the log file is rotated.
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The number of log files to keep, including the current one.
+const LOG_FILES: usize = 5;
+
+/// The number of recent log lines to keep in memory.
+const RECENT_LINES: usize = 2000;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Installs the global tracing subscriber.
+///
+/// The console shows what `RUST_LOG` asks for (errors only by default), while
+/// the log file and in-memory buffer always include at least info messages.
+pub fn init(format: LogFormat) {
+    let console_filter = || EnvFilter::from_default_env();
+    let tree = (format == LogFormat::Tree).then(|| {
+        tracing_tree::HierarchicalLayer::default()
+            .with_indent_amount(2)
+            .with_indent_lines(true)
+            .with_deferred_spans(true)
+            .with_span_retrace(true)
+            .with_targets(true)
+            .with_timer(UtcDateTime::default())
+            .with_filter(console_filter())
+    });
+    let json = (format == LogFormat::Json).then(|| {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(io::stderr)
+            .with_filter(console_filter())
+    });
+    let file = match RotatingFile::open(log_dir()) {
+        Ok(file) => {
+            Some(tracing_subscriber::fmt::layer().with_ansi(false).with_writer(Mutex::new(file)))
+        }
+        Err(e) => {
+            eprintln!("warning: could not open log file: {e}");
+            None
+        }
+    };
+    let recent = tracing_subscriber::fmt::layer().with_ansi(false).with_writer(|| RecentWriter);
+    tracing_subscriber::registry()
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with(metrics::timing_layer())
+        .with(tree)
+        .with(json)
+        .with(file)
+        .with(recent)
+        .init();
+}
+
+pub fn log_dir() -> PathBuf {
+    dirs::home_dir().unwrap().join("Library/Logs/nimbus")
+}
+
+/// Returns the log files, starting with the current one.
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    (0..LOG_FILES).map(|n| log_path(dir, n)).filter(|path| path.exists()).collect()
+}
+
+/// Returns the most recent log lines, oldest first.
+pub fn recent_logs() -> Vec<String> {
+    RECENT.lock().unwrap().iter().cloned().collect()
+}
+
+fn log_path(dir: &Path, n: usize) -> PathBuf {
+    match n {
+        0 => dir.join("nimbus.log"),
+        n => dir.join(format!("nimbus.{n}.log")),
+    }
+}
+
+/// A log file that is moved aside once it grows past [`MAX_LOG_SIZE`].
+struct RotatingFile {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: PathBuf) -> io::Result<RotatingFile> {
+        fs::create_dir_all(&dir)?;
+        let file = File::options().create(true).append(true).open(log_path(&dir, 0))?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            dir,
+            file,
+            size,
+            max_size: MAX_LOG_SIZE,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..LOG_FILES).rev() {
+            if let Err(e) = fs::rename(log_path(&self.dir, n - 1), log_path(&self.dir, n)) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e);
+                }
+            }
+        }
+        self.file = File::create(log_path(&self.dir, 0))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Appends each formatted event to the in-memory buffer.
+struct RecentWriter;
+
+impl Write for RecentWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut recent = RECENT.lock().unwrap();
+        if recent.len() == RECENT_LINES {
+            recent.pop_front();
+        }
+        recent.push_back(String::from_utf8_lossy(buf).trim_end().to_string());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_rotates_log_files() {
+        let dir = std::env::temp_dir().join(format!("nimbus-log-test-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        let mut file = RotatingFile::open(dir.clone()).unwrap();
+        file.max_size = 10;
+        for n in 0..LOG_FILES + 2 {
+            file.write_all(format!("line {n:04}\n").as_bytes()).unwrap();
+        }
+        let files = log_files(&dir);
+        assert_eq!(files.len(), LOG_FILES);
+        assert_eq!(
+            fs::read_to_string(&files[0]).unwrap(),
+            format!("line {:04}\n", LOG_FILES + 1)
+        );
+        assert_eq!(
+            fs::read_to_string(&files[LOG_FILES - 1]).unwrap(),
+            "line 0002\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
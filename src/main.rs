@@ -1,75 +1,368 @@
 mod actor;
+mod config;
+mod crash_loop;
+mod dry_run;
+mod logging;
 mod metrics;
 mod model;
 mod sys;
+mod update;
 
 use std::path::PathBuf;
 
+use actor::borders::{self, Borders};
+use actor::ipc::{self, IpcServer};
 use actor::layout::LayoutManager;
+use actor::mouse::{self, Mouse};
 use actor::notification_center::NotificationCenter;
-use actor::reactor::Reactor;
+use actor::reactor::{self, Query, QueryResponse, Reactor};
+use actor::split_handles::{self, SplitHandles};
 use actor::wm_controller::{self, WmController};
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use config::{Config, DefaultSpaceActivation, LogFormat};
 
 use sys::executor::Executor;
+use sys::permissions::{self, Permission};
 use tokio::join;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-use tracing_tree::time::UtcDateTime;
 
 #[derive(Parser)]
 struct Cli {
-    /// Only run the window manager on the current space.
+    /// Only run the window manager on the current space of each display.
+    ///
+    /// This overrides the `spaces.default` setting in the config file.
     #[arg(long)]
     one: bool,
 
+    /// Check the config, permissions, and saved layout, and print which
+    /// windows would be managed and how, without moving anything.
     #[arg(long)]
     validate: bool,
 
     #[arg(long)]
     restore: bool,
+
+    /// Start without managing any space, to recover from a bad config or a
+    /// crash. Windows are still tracked, and spaces can be turned back on one
+    /// at a time with the key binding for toggling a space.
+    ///
+    /// A config file with errors is ignored, and the saved layout is not
+    /// restored. This also happens automatically if nimbus keeps restarting.
+    #[arg(long)]
+    safe_mode: bool,
+
+    /// How to format log output.
+    ///
+    /// This overrides the `logging.format` setting in the config file.
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Query the state of the running window manager.
+    #[command(subcommand)]
+    Query(QueryCommand),
+    /// Run commands in the running window manager, like
+    /// `'Layout(MoveFocus(Left))'`.
+    ///
+    /// Multiple commands run in order as a batch, with the layout updated once
+    /// at the end. Exits with an error if a command did nothing, without
+    /// running the rest.
+    Send {
+        /// The commands, in the same format as the config file.
+        #[arg(required = true)]
+        commands: Vec<String>,
+    },
+    /// Collect logs, config, and window manager state for a bug report.
+    Report {
+        /// Where to write the report, instead of the current directory.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Work with the config file.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Switch between the profiles in the config file.
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Save or restore the layout of the space on the main screen.
+    #[command(subcommand)]
+    Layout(LayoutCommand),
+    /// Find windows on any space.
+    #[command(subcommand)]
+    Window(WindowCommand),
+    /// Look for a newer release and open its page.
+    Update {
+        /// Only report whether there is a newer release.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Tools for diagnosing bugs in nimbus itself.
+    #[cfg(debug_assertions)]
+    #[command(subcommand)]
+    Debug(DebugCommand),
+}
+
+#[derive(Subcommand)]
+enum QueryCommand {
+    /// List known windows, starting with the frontmost.
+    Windows,
+    /// Show the power source and whether animations are throttled for it.
+    Power,
+    /// Show the focused window's app, title, frame, space, and whether it is
+    /// floating. Exits with an error if no window is focused.
+    Focused {
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// List running apps with the CPU time their threads have used, busiest
+    /// first. An app that floods us with notifications shows up at the top.
+    Apps,
+    /// List the spaces nimbus has a layout for, with their window counts and
+    /// layout kinds, for status bar workspace widgets.
+    Spaces {
+        /// Keep running and print the spaces again, as one line of JSON,
+        /// whenever they change.
+        #[arg(long)]
+        watch: bool,
+    },
+    /// List the stacked and tabbed containers on the main screen, with the
+    /// title, app and icon of each window in them and which one is selected,
+    /// for status bars to show as tabs.
+    Stacks {
+        /// Keep running and print the stacks again, as one line of JSON,
+        /// whenever they change, including when another window in a stack is
+        /// selected.
+        #[arg(long)]
+        watch: bool,
+    },
+}
+
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+enum OutputFormat {
+    #[default]
+    Json,
+    /// One `key: value` line per field.
+    Text,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// Use the named profile instead of any schedule, now and whenever nimbus
+    /// starts on this machine. The layout is kept.
+    Use { name: String },
+    /// Go back to choosing profiles by their schedules.
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum LayoutCommand {
+    /// Print the layout as JSON, with windows identified by app and title.
+    Export,
+    /// Rearrange the windows into a layout printed by `nimbus layout export`.
+    ///
+    /// Windows that match nothing in the layout are added at the end.
+    Import {
+        /// The file to read, instead of stdin.
+        file: Option<PathBuf>,
+    },
+    /// Rebuild the layout to match where the windows are now, and resume
+    /// tiling. Pause tiling and arrange the windows by hand first.
+    Capture,
+}
+
+#[derive(Subcommand)]
+enum WindowCommand {
+    /// Bring a window from any space to the current one and focus it.
+    ///
+    /// The window is the frontmost one whose title, app name, or bundle id
+    /// contains the query, ignoring case.
+    Pull {
+        query: String,
+        /// Switch to the window's space instead of moving the window.
+        #[arg(long)]
+        switch: bool,
+    },
+    /// Focus the next window of the focused app, switching spaces if needed.
+    Next,
+    /// Focus the previous window of the focused app, switching spaces if
+    /// needed.
+    Prev,
+    /// Reopen the most recently closed window where it was.
+    Reopen,
+}
+
+#[cfg(debug_assertions)]
+#[derive(Subcommand)]
+enum DebugCommand {
+    /// Handle made-up events in the running window manager as if apps had
+    /// sent them, like `'WindowDestroyed((pid: 123, idx: 4567))'`, to
+    /// reproduce a bug against the real layout.
+    Inject {
+        /// The events, in the same format as the config file.
+        #[arg(required = true)]
+        events: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Write a config file with every option documented and set to its
+    /// default.
+    Init {
+        /// Overwrite an existing config file.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check the config file for errors without starting the window manager.
+    Check {
+        /// The config file to check, instead of the default.
+        file: Option<PathBuf>,
+    },
 }
 
 fn main() {
     let opt: Cli = Parser::parse();
 
-    tracing_subscriber::registry()
-        .with(EnvFilter::from_default_env())
-        .with(metrics::timing_layer())
-        .with(
-            tracing_tree::HierarchicalLayer::default()
-                .with_indent_amount(2)
-                .with_indent_lines(true)
-                .with_deferred_spans(true)
-                .with_span_retrace(true)
-                .with_targets(true)
-                .with_timer(UtcDateTime::default()),
-        )
-        .init();
+    if let Some(command) = opt.command {
+        run_client(command);
+        return;
+    }
+
+    let loaded = if opt.validate {
+        Config::load(&config_file())
+    } else {
+        load_config()
+    };
+    // Starts are counted against the default threshold if the config has
+    // errors.
+    let threshold = loaded.as_ref().map(|config| config.crash_loop.threshold()).unwrap_or_default();
+    let crash_loop =
+        (!opt.safe_mode && !opt.validate && detect_crash_loop(threshold)).then_some(threshold);
+    let safe_mode = opt.safe_mode || crash_loop.is_some();
+
+    let mut config = match loaded {
+        Ok(config) => config,
+        Err(e) if safe_mode => {
+            eprintln!("warning: ignoring config in safe mode: {e}");
+            Config::default()
+        }
+        Err(e) => {
+            eprintln!("error: could not load config: {e}");
+            std::process::exit(1);
+        }
+    };
+    if safe_mode {
+        // Nothing should move windows until the user turns a space back on.
+        config.focus_mode = Default::default();
+    }
+    if opt.one {
+        config.spaces.default = DefaultSpaceActivation::StartingSpaces;
+    }
+    if let Some(format) = opt.log_format {
+        config.logging.format = format;
+    }
+
+    logging::init(config.logging.format);
     install_panic_hook();
 
     if opt.validate {
-        LayoutManager::load(restore_file()).unwrap();
-        return;
+        let ok = dry_run::run(&config, &restore_file());
+        std::process::exit(if ok { 0 } else { 1 });
     }
 
-    let layout = if opt.restore {
+    let mut needed = vec![Permission::Accessibility];
+    if !config.hot_corners.is_empty()
+        || config.window_drag.is_enabled()
+        || config.title_bar.double_click.is_some()
+        || config.power.idle_after().is_some()
+        || config.key_bindings.repeat.is_some()
+        || wm_controller::binds_cycling(&restore_file(), &config.profiles)
+    {
+        needed.push(Permission::InputMonitoring);
+    }
+    permissions::ensure_granted(&needed);
+
+    if let Some(threshold) = crash_loop {
+        tracing::warn!(
+            "Started more than {} times in {:?}; starting in safe mode",
+            threshold.max_starts,
+            threshold.period,
+        );
+    } else if safe_mode {
+        tracing::warn!("Starting in safe mode; no spaces are managed");
+    }
+    let mut layout = if opt.restore && !safe_mode {
         LayoutManager::load(restore_file()).unwrap()
     } else {
         LayoutManager::new()
     };
-    let events_tx = Reactor::spawn(layout);
+    let disabled_spaces = layout.disabled_spaces().clone();
+    layout.set_split_new_windows(config.tiling.split_new_windows);
+    layout.set_balance_on_close(config.tiling.balance_on_close);
+    layout.set_window_limits(config.tiling.window_limits.clone());
+    layout.set_adopt_strategy(config.tiling.adopt);
+    let (split_handles_tx, split_handles_rx) = split_handles::channel();
+    let (borders_tx, borders_rx) = borders::channel();
+    let events_tx = Reactor::spawn(
+        layout,
+        split_handles_tx.clone(),
+        borders_tx.clone(),
+        reactor::Settings::new(&config),
+    );
+    let split_handles = SplitHandles::new(events_tx.clone(), &split_handles_tx, split_handles_rx);
 
+    let (mouse_tx, mouse_rx) = mouse::channel();
+    let hot_corners = config.hot_corners;
+    let window_drag = config.window_drag;
+    let title_bar = config.title_bar;
+    let border_rules = config.borders.rules.clone();
+    let selected_profile = selected_profile(&config);
+    let borders = Borders::new(config.borders, borders_rx);
     let config = wm_controller::Config {
-        one_space: opt.one,
+        space_activation: config.spaces,
+        pause_while_screen_sharing: config.tiling.pause_while_screen_sharing,
+        bind_keys_by: config.key_bindings.bind_by,
+        key_repeat: config.key_bindings.key_repeat(),
         restore_file: restore_file(),
+        displays: config.displays,
+        power: config.power,
+        overlays: config.overlays,
+        check_for_updates: config.updates.check,
+        watch_focus_mode: config.focus_mode.is_enabled(),
+        border_rules,
+        window_limits: config.tiling.window_limits,
+        profiles: config.profiles,
+        selected_profile,
+        safe_mode,
+        disabled_spaces,
+        crash_loop,
     };
-    let (wm_controller, wm_controller_sender) = WmController::new(config, events_tx);
+    let (wm_controller, wm_controller_sender) =
+        WmController::new(config, events_tx, mouse_tx.clone(), borders_tx);
+    if let Err(e) = IpcServer::spawn(socket_path(), wm_controller_sender.clone()) {
+        tracing::warn!("Could not start IPC server: {e}");
+    }
+    let mouse = Mouse::new(
+        hot_corners,
+        window_drag,
+        title_bar,
+        wm_controller_sender.clone(),
+        &mouse_tx,
+        mouse_rx,
+    );
     let notification_center = NotificationCenter::new(wm_controller_sender);
 
     Executor::run(async move {
         join!(
             wm_controller.run(),
-            notification_center.watch_for_notifications()
+            notification_center.watch_for_notifications(),
+            split_handles.run(),
+            mouse.run(),
+            borders.run(),
         );
     });
 }
@@ -78,10 +371,486 @@ fn config_dir() -> PathBuf {
     dirs::home_dir().unwrap().join(".nimbus")
 }
 
+fn config_file() -> PathBuf {
+    config_dir().join("config.ron")
+}
+
 fn restore_file() -> PathBuf {
     config_dir().join("layout.ron")
 }
 
+fn socket_path() -> PathBuf {
+    config_dir().join("nimbus.sock")
+}
+
+/// A copy of the config file from the last time it loaded, which is used
+/// instead while the config file has errors.
+fn last_good_config_file() -> PathBuf {
+    config_dir().join("config.last-good.ron")
+}
+
+/// Loads the config file, or the last version of it that loaded if it has
+/// errors, so that a mistake doesn't stop the window manager.
+fn load_config() -> anyhow::Result<Config> {
+    let file = config_file();
+    let err = match Config::load(&file) {
+        Ok(config) => {
+            if file.exists() {
+                if let Err(e) = std::fs::copy(&file, last_good_config_file()) {
+                    eprintln!("warning: could not save a copy of the config: {e}");
+                }
+            }
+            return Ok(config);
+        }
+        Err(err) => err,
+    };
+    match Config::load_existing(&last_good_config_file()) {
+        Ok(config) => {
+            eprintln!("warning: {err}");
+            eprintln!("warning: using the config from the last time it loaded");
+            Ok(config)
+        }
+        Err(_) => Err(err),
+    }
+}
+
+/// When nimbus last started, for detecting crash loops.
+fn starts_file() -> PathBuf {
+    config_dir().join("starts")
+}
+
+fn detect_crash_loop(threshold: crash_loop::Threshold) -> bool {
+    match crash_loop::record_start(&starts_file(), threshold) {
+        Ok(false) => {
+            crash_loop::clear_starts_on_success(starts_file(), threshold.period);
+            false
+        }
+        Ok(true) => true,
+        Err(e) => {
+            eprintln!("warning: could not record start: {e}");
+            false
+        }
+    }
+}
+
+/// The profile chosen with `nimbus profile use`. This is kept out of the
+/// config file so the config can be shared between machines.
+fn profile_file() -> PathBuf {
+    config_dir().join("profile")
+}
+
+/// Returns the profile saved by `nimbus profile use`, if the config still
+/// has it.
+fn selected_profile(config: &Config) -> Option<String> {
+    let name = std::fs::read_to_string(profile_file()).ok()?.trim().to_string();
+    if config.profiles.iter().any(|profile| profile.name == name) {
+        Some(name)
+    } else {
+        tracing::warn!("Ignoring the selected profile {name:?}, which is not in the config");
+        None
+    }
+}
+
+fn run_client(command: Command) {
+    let message = match command {
+        Command::Query(QueryCommand::Windows) => ipc::Message::Query(Query::Windows),
+        Command::Query(QueryCommand::Power) => ipc::Message::Query(Query::Power),
+        Command::Query(QueryCommand::Apps) => ipc::Message::Query(Query::Apps),
+        Command::Query(QueryCommand::Spaces { watch: false }) => ipc::Message::Query(Query::Spaces),
+        Command::Query(QueryCommand::Spaces { watch: true }) => {
+            watch(ipc::Message::SubscribeSpaces);
+            return;
+        }
+        Command::Query(QueryCommand::Stacks { watch: false }) => ipc::Message::Query(Query::Stacks),
+        Command::Query(QueryCommand::Stacks { watch: true }) => {
+            watch(ipc::Message::SubscribeStacks);
+            return;
+        }
+        Command::Query(QueryCommand::Focused { format }) => {
+            query_focused(format);
+            return;
+        }
+        Command::Send { commands } => {
+            let mut commands: Vec<_> = commands
+                .iter()
+                .map(|command| match ron::from_str(command) {
+                    Ok(command) => command,
+                    Err(e) => {
+                        eprintln!("error: invalid command {command:?}: {e}");
+                        std::process::exit(2);
+                    }
+                })
+                .collect();
+            if commands.len() == 1 {
+                ipc::Message::Command(commands.pop().unwrap())
+            } else {
+                ipc::Message::Batch(commands)
+            }
+        }
+        Command::Report { output } => {
+            if let Err(e) = write_report(output) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Command::Config(ConfigCommand::Init { force }) => {
+            if let Err(e) = write_default_config(force) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Command::Profile(command) => {
+            let name = match command {
+                ProfileCommand::Use { name } => Some(name),
+                ProfileCommand::Clear => None,
+            };
+            if let Err(e) = use_profile(name) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Command::Layout(LayoutCommand::Export) => {
+            if let Err(e) = export_layout() {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Command::Layout(LayoutCommand::Import { file }) => {
+            let spec = match read_layout(file) {
+                Ok(spec) => spec,
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    std::process::exit(2);
+                }
+            };
+            ipc::Message::Command(actor::reactor::Command::ImportLayout(spec))
+        }
+        Command::Window(WindowCommand::Pull { query, switch: false }) => {
+            ipc::Message::Command(actor::reactor::Command::PullWindow(query))
+        }
+        Command::Window(WindowCommand::Pull { query, switch: true }) => {
+            ipc::Message::Command(actor::reactor::Command::GoToWindow(query))
+        }
+        Command::Window(WindowCommand::Next) => {
+            ipc::Message::Command(actor::reactor::Command::FocusNextWindowOfApp)
+        }
+        Command::Window(WindowCommand::Prev) => {
+            ipc::Message::Command(actor::reactor::Command::FocusPrevWindowOfApp)
+        }
+        Command::Window(WindowCommand::Reopen) => {
+            ipc::Message::Command(actor::reactor::Command::ReopenClosedWindow)
+        }
+        Command::Layout(LayoutCommand::Capture) => {
+            ipc::Message::Command(actor::reactor::Command::CaptureLayout)
+        }
+        #[cfg(debug_assertions)]
+        Command::Debug(DebugCommand::Inject { events }) => {
+            let events = events
+                .iter()
+                .map(|event| match ron::from_str(event) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        eprintln!("error: invalid event {event:?}: {e}");
+                        std::process::exit(2);
+                    }
+                })
+                .collect();
+            ipc::Message::InjectEvents(events)
+        }
+        Command::Update { check } => {
+            if let Err(e) = run_update(check) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Command::Config(ConfigCommand::Check { file }) => {
+            // There is nothing to check in a file that was named but doesn't
+            // exist, unlike the default file, which is optional.
+            let result = match &file {
+                Some(file) => Config::load_existing(file),
+                None => Config::load(&config_file()),
+            };
+            let file = file.unwrap_or_else(config_file);
+            match result {
+                Ok(_) => println!("{}: ok", file.display()),
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+    };
+    match ipc::send_message(&socket_path(), &message) {
+        Ok(ipc::Response::Command { result: Ok(()), .. }) => (),
+        Ok(ipc::Response::Command { result: Err(e), .. }) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        Ok(ipc::Response::Batch { results, .. }) => {
+            if let Some(Err(e)) = results.last() {
+                eprintln!("error: command {} failed: {e}", results.len());
+                std::process::exit(1);
+            }
+        }
+        Ok(ipc::Response::Query(response)) => {
+            println!("{}", serde_json::to_string_pretty(&response).unwrap());
+        }
+        Ok(ipc::Response::Logs(lines)) => {
+            for line in lines {
+                println!("{line}");
+            }
+        }
+        Ok(ipc::Response::Profile(_)) => (),
+        #[cfg(debug_assertions)]
+        Ok(ipc::Response::Injected) => (),
+        Ok(ipc::Response::Spaces(spaces)) => {
+            println!("{}", serde_json::to_string_pretty(&spaces).unwrap());
+        }
+        Ok(ipc::Response::Stacks(stacks)) => {
+            println!("{}", serde_json::to_string_pretty(&stacks).unwrap());
+        }
+        Ok(ipc::Response::Error(e)) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: could not connect to nimbus: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints each update sent for the subscription `message` as a line of JSON.
+fn watch(message: ipc::Message) {
+    let on_response = |response: ipc::Response| match response {
+        ipc::Response::Spaces(spaces) => {
+            println!("{}", serde_json::to_string(&spaces).unwrap());
+        }
+        ipc::Response::Stacks(stacks) => {
+            println!("{}", serde_json::to_string(&stacks).unwrap());
+        }
+        ipc::Response::Error(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        response => eprintln!("error: unexpected response: {response:?}"),
+    };
+    if let Err(e) = ipc::subscribe(&socket_path(), &message, on_response) {
+        eprintln!("error: lost connection to nimbus: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn query_focused(format: OutputFormat) {
+    let window = match ipc::send_message(&socket_path(), &ipc::Message::Query(Query::Focused)) {
+        Ok(ipc::Response::Query(QueryResponse::Focused(window))) => window,
+        Ok(ipc::Response::Error(e)) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        Ok(response) => {
+            eprintln!("error: unexpected response: {response:?}");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: could not connect to nimbus: {e}");
+            std::process::exit(1);
+        }
+    };
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&window).unwrap()),
+        OutputFormat::Text => {
+            if let Some(window) = &window {
+                let frame = window.frame;
+                println!("bundle_id: {}", window.bundle_id.as_deref().unwrap_or(""));
+                println!("title: {}", window.title);
+                println!(
+                    "frame: {} {} {} {}",
+                    frame.origin.x, frame.origin.y, frame.size.width, frame.size.height
+                );
+                // Space ids serialize as plain numbers.
+                let space = window.space.map(|space| serde_json::to_string(&space).unwrap());
+                println!("space: {}", space.unwrap_or_default());
+                println!("floating: {}", window.is_floating);
+            }
+        }
+    }
+    if window.is_none() {
+        std::process::exit(1);
+    }
+}
+
+fn write_default_config(force: bool) -> anyhow::Result<()> {
+    let file = config_file();
+    if file.exists() && !force {
+        anyhow::bail!(
+            "{} already exists; use --force to overwrite it",
+            file.display()
+        );
+    }
+    let bindings: Vec<_> = wm_controller::key_bindings(&restore_file())
+        .into_iter()
+        .map(|(hotkey, cmd)| {
+            (
+                sys::hotkey::describe_hotkey(&hotkey),
+                wm_controller::describe_command(&cmd),
+            )
+        })
+        .collect();
+    std::fs::create_dir_all(config_dir())?;
+    std::fs::write(&file, config::default_config_file(&bindings))?;
+    println!("Wrote {}", file.display());
+    Ok(())
+}
+
+/// Saves the profile to use on this machine, or clears it if `None`, and
+/// switches the running window manager to it.
+fn use_profile(name: Option<String>) -> anyhow::Result<()> {
+    let config = Config::load(&config_file())?;
+    if let Some(name) = &name {
+        if !config.profiles.iter().any(|profile| &profile.name == name) {
+            anyhow::bail!("no profile named {name:?} in {}", config_file().display());
+        }
+    }
+    match &name {
+        Some(name) => {
+            std::fs::create_dir_all(config_dir())?;
+            std::fs::write(profile_file(), name)?;
+        }
+        None => match std::fs::remove_file(profile_file()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => (),
+        },
+    }
+    match ipc::send_message(&socket_path(), &ipc::Message::UseProfile(name)) {
+        Ok(ipc::Response::Profile(Some(active))) => println!("Using profile {active:?}"),
+        Ok(ipc::Response::Profile(None)) => println!("Using the settings outside of profiles"),
+        Ok(ipc::Response::Error(e)) => anyhow::bail!(e),
+        Ok(response) => anyhow::bail!("unexpected response: {response:?}"),
+        Err(_) => println!("nimbus is not running; the profile will be used when it starts"),
+    }
+    Ok(())
+}
+
+fn export_layout() -> anyhow::Result<()> {
+    let message = ipc::Message::Query(Query::SpaceLayout);
+    match ipc::send_message(&socket_path(), &message) {
+        Ok(ipc::Response::Query(QueryResponse::SpaceLayout(Some(spec)))) => {
+            println!("{}", serde_json::to_string_pretty(&spec)?);
+            Ok(())
+        }
+        Ok(ipc::Response::Query(QueryResponse::SpaceLayout(None))) => {
+            anyhow::bail!("the space on the main screen is not managed")
+        }
+        Ok(ipc::Response::Error(e)) => anyhow::bail!(e),
+        Ok(response) => anyhow::bail!("unexpected response: {response:?}"),
+        Err(e) => anyhow::bail!("could not connect to nimbus: {e}"),
+    }
+}
+
+fn read_layout(file: Option<PathBuf>) -> anyhow::Result<model::LayoutSpec> {
+    let buf = match &file {
+        Some(file) => std::fs::read(file)?,
+        None => {
+            let mut buf = vec![];
+            std::io::Read::read_to_end(&mut std::io::stdin(), &mut buf)?;
+            buf
+        }
+    };
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+/// Reports whether there is a newer release, and opens its page unless
+/// `check_only` is set.
+fn run_update(check_only: bool) -> anyhow::Result<()> {
+    let Some(release) = update::check_for_update()? else {
+        println!("nimbus {} is up to date", update::CURRENT_VERSION);
+        return Ok(());
+    };
+    println!(
+        "nimbus {} is available (you have {})",
+        release.version,
+        update::CURRENT_VERSION
+    );
+    println!("Changelog: {}", release.url);
+    if !check_only {
+        std::process::Command::new("open").arg(&release.url).status()?;
+    }
+    Ok(())
+}
+
+/// Writes a bug report directory and archives it.
+///
+/// State from the running window manager is included if it can be reached;
+/// otherwise the report still contains the config and log files.
+fn write_report(output: Option<PathBuf>) -> anyhow::Result<()> {
+    use std::fs;
+    let secs = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+    let name = format!("nimbus-report-{secs}");
+    let parent = output.unwrap_or_else(|| PathBuf::from("."));
+    let dir = parent.join(&name);
+    fs::create_dir_all(&dir)?;
+
+    if config_file().exists() {
+        fs::copy(config_file(), dir.join("config.ron"))?;
+    }
+    for file in logging::log_files(&logging::log_dir()) {
+        fs::copy(&file, dir.join(file.file_name().unwrap()))?;
+    }
+
+    let socket = socket_path();
+    let mut errors = vec![];
+    let mut request = |message: ipc::Message, file: &str| {
+        let contents = match ipc::send_message(&socket, &message) {
+            Ok(ipc::Response::Query(QueryResponse::Layout(layout))) => layout,
+            Ok(ipc::Response::Query(response)) => serde_json::to_string_pretty(&response).unwrap(),
+            Ok(ipc::Response::Logs(lines)) => lines.join("\n"),
+            Ok(
+                ipc::Response::Command { .. }
+                | ipc::Response::Batch { .. }
+                | ipc::Response::Profile(_)
+                | ipc::Response::Spaces(_)
+                | ipc::Response::Stacks(_),
+            ) => return,
+            #[cfg(debug_assertions)]
+            Ok(ipc::Response::Injected) => return,
+            Ok(ipc::Response::Error(e)) => return errors.push(format!("{file}: {e}")),
+            Err(e) => return errors.push(format!("{file}: could not connect to nimbus: {e}")),
+        };
+        if let Err(e) = fs::write(dir.join(file), contents) {
+            errors.push(format!("{file}: {e}"));
+        }
+    };
+    request(ipc::Message::RecentLogs, "recent.log");
+    request(ipc::Message::Query(Query::Windows), "windows.json");
+    request(ipc::Message::Query(Query::Layout), "layout.ron");
+    if !errors.is_empty() {
+        fs::write(dir.join("errors.txt"), errors.join("\n"))?;
+    }
+
+    let archive = format!("{name}.tar.gz");
+    let status = std::process::Command::new("tar")
+        .arg("-czf")
+        .arg(&archive)
+        .arg(&name)
+        .current_dir(&parent)
+        .status();
+    match status {
+        Ok(status) if status.success() => {
+            fs::remove_dir_all(&dir)?;
+            println!("Wrote {}", parent.join(archive).display());
+        }
+        _ => println!("Wrote {}", dir.display()),
+    }
+    Ok(())
+}
+
 #[cfg(panic = "unwind")]
 fn install_panic_hook() {
     // Abort on panic instead of propagating panics to the main thread.
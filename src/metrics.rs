@@ -1,14 +1,92 @@
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    fmt, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
 
+use serde::{Deserialize, Serialize};
+use tracing::warn;
 use tracing_timing::{group, Histogram};
 
 pub type TimingLayer = tracing_timing::TimingLayer<group::ByName, group::ByMessage>;
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum MetricsCommand {
     ShowTiming,
 }
 
+/// Something timed for the live timing overlay and checked against a
+/// budget.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Timing {
+    /// The reactor handling one event, not counting animations.
+    EventHandling,
+    /// Calculating the layout and sending the new frames to apps, not
+    /// counting animations. Apps move their windows on their own threads, so
+    /// slow apps do not count against this.
+    Layout,
+    /// An app thread handling one request, which is mostly accessibility
+    /// calls. Keyed by bundle id.
+    AppRequest(String),
+    /// An app coming to the front after we activated it to raise one of its
+    /// windows. Keyed by bundle id.
+    Activation(String),
+    /// The time between two frames of an animation.
+    AnimationFrame,
+}
+
+impl Timing {
+    /// How long this may take before we warn that it is slow, or `None` if it
+    /// is checked elsewhere.
+    pub fn budget(&self) -> Option<Duration> {
+        match self {
+            Timing::EventHandling => Some(Duration::from_millis(100)),
+            Timing::Layout => Some(Duration::from_millis(50)),
+            Timing::AppRequest(_) => Some(APP_REQUEST_BUDGET),
+            // Checked against the app's raise timeout.
+            Timing::Activation(_) => None,
+            Timing::AnimationFrame => Some(Duration::from_millis(50)),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timing::EventHandling => write!(f, "Handling an event"),
+            Timing::Layout => write!(f, "Updating the layout"),
+            Timing::AppRequest(app) => write!(f, "A request to {app}"),
+            Timing::Activation(app) => write!(f, "Activating {app}"),
+            Timing::AnimationFrame => write!(f, "An animation frame"),
+        }
+    }
+}
+
+/// The budget of [`Timing::AppRequest`], which app threads check before
+/// building the timing.
+pub const APP_REQUEST_BUDGET: Duration = Duration::from_millis(200);
+
+/// The number of apps listed in the live timings, slowest first.
+const MAX_APPS: usize = 10;
+
+/// How often to warn about the same thing going over its budget. Whatever
+/// is slow tends to be slow every time, so more warnings only add noise.
+const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+static LIVE_ENABLED: AtomicBool = AtomicBool::new(false);
+static LIVE: Mutex<BTreeMap<Timing, Histogram<u64>>> = Mutex::new(BTreeMap::new());
+
+/// What went over its budget, and what it was handling, if known.
+type WarningKey = (Timing, Option<&'static str>);
+
+/// When we last warned about each [`WarningKey`], and how many warnings were
+/// held back since.
+static WARNINGS: Mutex<BTreeMap<WarningKey, (Instant, usize)>> = Mutex::new(BTreeMap::new());
+
 pub fn timing_layer() -> TimingLayer {
     tracing_timing::Builder::default()
         //.events(group::ByName)
@@ -47,3 +125,187 @@ fn print_histograms(timing_layer: &TimingLayer) {
         println!();
     });
 }
+
+/// Starts or stops collecting live timings. Starting clears the timings
+/// collected before.
+pub fn set_live(enabled: bool) {
+    LIVE.lock().unwrap().clear();
+    LIVE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether live timings are being collected.
+pub fn is_live() -> bool {
+    LIVE_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Warns if something took longer than its budget, and records how long it
+/// took if live timings are being collected.
+pub fn record(timing: Timing, elapsed: Duration) {
+    record_handling(timing, None, elapsed);
+}
+
+/// Like [`record`], naming what was handled, like the kind of event, in the
+/// warning.
+///
+/// Warnings about the same timing and `what` are sent at most once every
+/// [`WARNING_INTERVAL`].
+pub fn record_handling(timing: Timing, what: Option<&'static str>, elapsed: Duration) {
+    if let Some(budget) = timing.budget().filter(|&budget| elapsed > budget) {
+        let key = (timing.clone(), what);
+        if let Some(held) = should_warn(&mut WARNINGS.lock().unwrap(), key, Instant::now()) {
+            let what = what.map(|what| format!(" ({what})")).unwrap_or_default();
+            let held = match held {
+                0 => String::new(),
+                held => format!("; {held} more times since the last warning"),
+            };
+            warn!("{timing}{what} took {elapsed:.1?}, over its budget of {budget:?}{held}");
+        }
+    }
+    if !is_live() {
+        return;
+    }
+    let mut live = LIVE.lock().unwrap();
+    let histogram = live
+        .entry(timing)
+        .or_insert_with(|| Histogram::new_with_max(100_000_000, 2).unwrap());
+    histogram.saturating_record(elapsed.as_nanos().try_into().unwrap_or(u64::MAX));
+}
+
+/// Returns whether to warn about `key` at `now`, and if so how many warnings
+/// about it were held back since the last one.
+fn should_warn(
+    warnings: &mut BTreeMap<WarningKey, (Instant, usize)>,
+    key: WarningKey,
+    now: Instant,
+) -> Option<usize> {
+    match warnings.get_mut(&key) {
+        Some((last, held)) if now.duration_since(*last) < WARNING_INTERVAL => {
+            *held += 1;
+            None
+        }
+        Some((last, held)) => {
+            *last = now;
+            Some(mem::take(held))
+        }
+        None => {
+            warnings.insert(key, (now, 0));
+            Some(0)
+        }
+    }
+}
+
+/// Returns the live timings collected so far as a table.
+pub fn live_report() -> String {
+    report(&LIVE.lock().unwrap())
+}
+
+fn report(timings: &BTreeMap<Timing, Histogram<u64>>) -> String {
+    let ns = |nanos| format!("{:.1?}", Duration::from_nanos(nanos));
+    let row = |name: &str, h: &Histogram<u64>| {
+        format!(
+            "{name:<32} {:>7} {:>9} {:>9} {:>9} {:>9}",
+            h.len(),
+            ns(h.value_at_quantile(0.50)),
+            ns(h.value_at_quantile(0.90)),
+            ns(h.value_at_quantile(0.99)),
+            ns(h.max()),
+        )
+    };
+    let mut lines = vec![format!(
+        "{:<32} {:>7} {:>9} {:>9} {:>9} {:>9}",
+        "", "count", "p50", "p90", "p99", "max"
+    )];
+    if let Some(h) = timings.get(&Timing::EventHandling) {
+        lines.push(row("Event handling", h));
+    }
+    if let Some(h) = timings.get(&Timing::Layout) {
+        lines.push(row("Layout", h));
+    }
+    if let Some(h) = timings.get(&Timing::AnimationFrame) {
+        lines.push(row("Animation frame", h));
+    }
+    let sections: [(&str, fn(&Timing) -> Option<&String>); 2] = [
+        ("Accessibility requests", |timing| match timing {
+            Timing::AppRequest(app) => Some(app),
+            _ => None,
+        }),
+        ("Activations", |timing| match timing {
+            Timing::Activation(app) => Some(app),
+            _ => None,
+        }),
+    ];
+    for (title, app_of) in sections {
+        let mut apps: Vec<_> =
+            timings.iter().filter_map(|(timing, h)| Some((app_of(timing)?, h))).collect();
+        if apps.is_empty() {
+            continue;
+        }
+        apps.sort_by_key(|(_, h)| std::cmp::Reverse(h.value_at_quantile(0.99)));
+        lines.push(String::new());
+        lines.push(format!("{title}, slowest apps first:"));
+        for (app, h) in apps.into_iter().take(MAX_APPS) {
+            lines.push(row(app, h));
+        }
+    }
+    if lines.len() == 1 {
+        lines.push("Nothing timed yet.".to_string());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::Instant};
+
+    use tracing_timing::Histogram;
+
+    use super::{report, should_warn, Timing, WARNING_INTERVAL};
+
+    #[test]
+    fn it_limits_warnings_per_kind() {
+        let start = Instant::now();
+        let mut warnings = BTreeMap::new();
+        let event = |kind| (Timing::EventHandling, Some(kind));
+        assert_eq!(Some(0), should_warn(&mut warnings, event("A"), start));
+        assert_eq!(None, should_warn(&mut warnings, event("A"), start));
+        assert_eq!(None, should_warn(&mut warnings, event("A"), start));
+        // Other kinds have their own limit.
+        assert_eq!(Some(0), should_warn(&mut warnings, event("B"), start));
+        let app = (Timing::AppRequest("com.slow".into()), None);
+        assert_eq!(Some(0), should_warn(&mut warnings, app, start));
+
+        let later = start + WARNING_INTERVAL;
+        assert_eq!(Some(2), should_warn(&mut warnings, event("A"), later));
+        assert_eq!(None, should_warn(&mut warnings, event("A"), later));
+    }
+
+    #[test]
+    fn it_lists_slowest_apps_first() {
+        let histogram = |nanos: &[u64]| {
+            let mut h = Histogram::new_with_max(100_000_000, 2).unwrap();
+            for &n in nanos {
+                h.record(n).unwrap();
+            }
+            h
+        };
+        let mut timings = BTreeMap::new();
+        timings.insert(Timing::EventHandling, histogram(&[1_000, 2_000]));
+        timings.insert(Timing::AppRequest("com.fast".into()), histogram(&[10_000]));
+        timings.insert(
+            Timing::AppRequest("com.slow".into()),
+            histogram(&[5_000_000]),
+        );
+        timings.insert(
+            Timing::Activation("com.fast".into()),
+            histogram(&[50_000_000]),
+        );
+        let text = report(&timings);
+        let lines: Vec<_> = text.lines().collect();
+        assert!(lines[1].starts_with("Event handling"), "{text}");
+        assert!(lines[4].starts_with("com.slow"), "{text}");
+        assert!(lines[5].starts_with("com.fast"), "{text}");
+        assert_eq!(lines[7], "Activations, slowest apps first:", "{text}");
+        assert!(lines[8].starts_with("com.fast"), "{text}");
+        assert!(report(&BTreeMap::new()).ends_with("Nothing timed yet."));
+    }
+}
@@ -1,12 +1,15 @@
 //! This module defines the [`Tree`][tree::Tree] data structure, on which all
 //! layout logic is defined.
 
+mod adopt;
 mod layout;
 mod layout_tree;
 mod selection;
 mod tree;
 mod window;
 
+pub use adopt::AdoptStrategy;
 #[allow(unused_imports)]
-pub use layout::{Direction, LayoutKind, Orientation};
-pub use layout_tree::{LayoutId, LayoutTree};
+pub use layout::{Direction, LayoutKind, Orientation, Split, SplitOrientation};
+pub use layout_tree::{Group, LayoutId, LayoutSpec, LayoutTree, Place, WindowMatcher};
+pub use tree::NodeId;
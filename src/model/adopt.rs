@@ -0,0 +1,251 @@
+//! Arranging the windows that were already open when a space is first tiled,
+//! or that the user arranged by hand.
+
+use icrate::Foundation::CGRect;
+use serde::{Deserialize, Serialize};
+
+use super::LayoutKind;
+use crate::actor::app::WindowId;
+
+/// How to arrange the windows that were already open on a space when we
+/// start tiling it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum AdoptStrategy {
+    /// Side by side, in the order the apps report them.
+    #[default]
+    Order,
+    /// In columns, keeping each window close to where it was.
+    Grid,
+    /// All in one stack.
+    Stack,
+    /// The largest window on the left, and the rest above one another on the
+    /// right.
+    Master,
+}
+
+/// The shape a strategy gives the top level of a layout: the kind of the root
+/// container and its children, each a single window or a vertical container
+/// of several.
+#[derive(Debug, PartialEq)]
+pub(super) struct Arrangement {
+    pub root: LayoutKind,
+    pub columns: Vec<Vec<WindowId>>,
+}
+
+/// Arranges `windows`, given where each is now, or returns `None` to leave
+/// them in the order they were added.
+pub(super) fn arrange(
+    strategy: AdoptStrategy,
+    mut windows: Vec<(WindowId, CGRect)>,
+) -> Option<Arrangement> {
+    let mid = |frame: &CGRect| {
+        (
+            frame.origin.x + frame.size.width / 2.0,
+            frame.origin.y + frame.size.height / 2.0,
+        )
+    };
+    let by_y = |a: &(WindowId, CGRect), b: &(WindowId, CGRect)| mid(&a.1).1.total_cmp(&mid(&b.1).1);
+    let ids = |windows: &[(WindowId, CGRect)]| windows.iter().map(|&(wid, _)| wid).collect();
+    match strategy {
+        AdoptStrategy::Order => None,
+        AdoptStrategy::Stack => Some(Arrangement {
+            root: LayoutKind::Stacked,
+            columns: windows.iter().map(|&(wid, _)| vec![wid]).collect(),
+        }),
+        AdoptStrategy::Grid => {
+            let count = windows.len();
+            let columns = (count as f64).sqrt().ceil().max(1.0) as usize;
+            let rows = count.div_ceil(columns).max(1);
+            windows.sort_by(|a, b| mid(&a.1).0.total_cmp(&mid(&b.1).0));
+            let columns = windows
+                .chunks_mut(rows)
+                .map(|column| {
+                    column.sort_by(by_y);
+                    ids(column)
+                })
+                .collect();
+            Some(Arrangement {
+                root: LayoutKind::Horizontal,
+                columns,
+            })
+        }
+        AdoptStrategy::Master => {
+            let area = |frame: &CGRect| frame.size.width * frame.size.height;
+            let master = (0..windows.len()).max_by(|&a, &b| {
+                // Prefer the first of equally large windows.
+                area(&windows[a].1).total_cmp(&area(&windows[b].1)).then(b.cmp(&a))
+            })?;
+            let master = windows.remove(master).0;
+            windows.sort_by(by_y);
+            let mut columns = vec![vec![master]];
+            if !windows.is_empty() {
+                columns.push(ids(&windows));
+            }
+            Some(Arrangement {
+                root: LayoutKind::Horizontal,
+                columns,
+            })
+        }
+    }
+}
+
+/// A layout tree inferred from where windows are.
+#[derive(Debug, PartialEq)]
+pub(super) enum Shape {
+    Window(WindowId),
+    /// A container, with the share of it each child takes up.
+    Container {
+        kind: LayoutKind,
+        children: Vec<(f64, Shape)>,
+    },
+}
+
+/// Windows that overlap by less than this many points are still treated as
+/// side by side, since arranging windows by hand is not exact.
+const OVERLAP_TOLERANCE: f64 = 20.0;
+
+/// Infers a layout tree from where `windows` are, which must not be empty.
+///
+/// Windows that can be separated by a vertical line go side by side, and
+/// those separated by a horizontal line one above the other. Windows that
+/// overlap so that no line separates them are stacked.
+pub(super) fn infer(windows: Vec<(WindowId, CGRect)>) -> Shape {
+    if let [(wid, _)] = windows[..] {
+        return Shape::Window(wid);
+    }
+    let along_x = |frame: &CGRect| (frame.origin.x, frame.origin.x + frame.size.width);
+    let along_y = |frame: &CGRect| (frame.origin.y, frame.origin.y + frame.size.height);
+    for (kind, span) in [
+        (
+            LayoutKind::Horizontal,
+            &along_x as &dyn Fn(&CGRect) -> (f64, f64),
+        ),
+        (LayoutKind::Vertical, &along_y),
+    ] {
+        let groups = separate(windows.clone(), span);
+        if groups.len() < 2 {
+            continue;
+        }
+        let extent = |group: &[(WindowId, CGRect)]| {
+            let start = group.iter().map(|(_, f)| span(f).0).fold(f64::INFINITY, f64::min);
+            let end = group.iter().map(|(_, f)| span(f).1).fold(f64::NEG_INFINITY, f64::max);
+            (end - start).max(1.0)
+        };
+        let total: f64 = groups.iter().map(|group| extent(group)).sum();
+        let children =
+            groups.into_iter().map(|group| (extent(&group) / total, infer(group))).collect();
+        return Shape::Container { kind, children };
+    }
+    let share = 1.0 / windows.len() as f64;
+    Shape::Container {
+        kind: LayoutKind::Stacked,
+        children: windows.into_iter().map(|(wid, _)| (share, Shape::Window(wid))).collect(),
+    }
+}
+
+/// Splits `windows` into groups that don't overlap along one axis, in order
+/// along it. `span` gives the start and end of a frame along the axis.
+fn separate(
+    mut windows: Vec<(WindowId, CGRect)>,
+    span: &dyn Fn(&CGRect) -> (f64, f64),
+) -> Vec<Vec<(WindowId, CGRect)>> {
+    windows.sort_by(|a, b| span(&a.1).0.total_cmp(&span(&b.1).0));
+    let mut groups: Vec<Vec<(WindowId, CGRect)>> = vec![];
+    let mut end = f64::NEG_INFINITY;
+    for window in windows {
+        let (start, window_end) = span(&window.1);
+        match groups.last_mut() {
+            Some(group) if start < end - OVERLAP_TOLERANCE => group.push(window),
+            _ => groups.push(vec![window]),
+        }
+        end = end.max(window_end);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use icrate::Foundation::{CGPoint, CGRect, CGSize};
+
+    use super::{arrange, infer, AdoptStrategy, Arrangement, Shape};
+    use crate::{actor::app::WindowId, model::LayoutKind};
+
+    fn w(idx: u32) -> WindowId {
+        WindowId::new(1, idx)
+    }
+
+    fn frame(x: f64, y: f64, size: f64) -> CGRect {
+        CGRect::new(CGPoint::new(x, y), CGSize::new(size, size))
+    }
+
+    #[test]
+    fn it_arranges_windows_by_strategy() {
+        // Two windows on the left, one above the other, and two on the right.
+        let windows = vec![
+            (w(1), frame(600., 500., 100.)),
+            (w(2), frame(0., 500., 100.)),
+            (w(3), frame(0., 0., 400.)),
+            (w(4), frame(600., 0., 100.)),
+        ];
+        assert_eq!(None, arrange(AdoptStrategy::Order, windows.clone()));
+        assert_eq!(
+            Some(Arrangement {
+                root: LayoutKind::Horizontal,
+                columns: vec![vec![w(3), w(2)], vec![w(4), w(1)]],
+            }),
+            arrange(AdoptStrategy::Grid, windows.clone())
+        );
+        assert_eq!(
+            Some(Arrangement {
+                root: LayoutKind::Horizontal,
+                columns: vec![vec![w(3)], vec![w(4), w(1), w(2)]],
+            }),
+            arrange(AdoptStrategy::Master, windows.clone())
+        );
+        assert_eq!(
+            LayoutKind::Stacked,
+            arrange(AdoptStrategy::Stack, windows).unwrap().root
+        );
+    }
+
+    #[test]
+    fn it_infers_layouts_from_positions() {
+        let rect =
+            |x, y, width, height| CGRect::new(CGPoint::new(x, y), CGSize::new(width, height));
+        // One window on the left half, and two above one another on the
+        // right, slightly overlapping as windows arranged by hand do.
+        let shape = infer(vec![
+            (w(2), rect(500., 0., 500., 505.)),
+            (w(1), rect(0., 0., 510., 1000.)),
+            (w(3), rect(500., 500., 500., 500.)),
+        ]);
+        let Shape::Container {
+            kind: LayoutKind::Horizontal,
+            children,
+        } = shape
+        else {
+            panic!("Expected a horizontal container, got {shape:?}");
+        };
+        assert_eq!(2, children.len());
+        assert_eq!(Shape::Window(w(1)), children[0].1);
+        assert!((children[0].0 - 0.5).abs() < 0.01);
+        let Shape::Container {
+            kind: LayoutKind::Vertical,
+            children: right,
+        } = &children[1].1
+        else {
+            panic!("Expected a vertical container");
+        };
+        let right: Vec<_> = right.iter().map(|(_, shape)| shape).collect();
+        assert_eq!(vec![&Shape::Window(w(2)), &Shape::Window(w(3))], right);
+
+        // Windows on top of each other are stacked.
+        let Shape::Container { kind, .. } = infer(vec![
+            (w(1), rect(0., 0., 800., 800.)),
+            (w(2), rect(100., 100., 800., 800.)),
+        ]) else {
+            panic!("Expected a container");
+        };
+        assert_eq!(LayoutKind::Stacked, kind);
+    }
+}
@@ -10,6 +10,21 @@ use super::{
 };
 use crate::{actor::app::WindowId, sys::geometry::Round};
 
+/// The boundary between two adjacent children of a split container.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Split {
+    /// The child before the boundary.
+    pub node: NodeId,
+    pub orientation: Orientation,
+    /// The boundary, as a line with zero width (for horizontal containers) or
+    /// height (for vertical containers).
+    pub frame: CGRect,
+}
+
+/// The width or height of a shaded node, in points; about the height of a
+/// title bar.
+const SHADED_SIZE: f64 = 28.0;
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct Layout {
     info: slotmap::SecondaryMap<NodeId, LayoutInfo>,
@@ -47,6 +62,27 @@ pub enum Orientation {
     Vertical,
 }
 
+/// The orientation of a new split.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplitOrientation {
+    Horizontal,
+    Vertical,
+    /// Split wide nodes side by side and tall nodes one above the other.
+    Auto,
+}
+
+impl SplitOrientation {
+    /// Returns the orientation for splitting a node of the given size.
+    pub fn resolve(self, size: CGSize) -> Orientation {
+        match self {
+            SplitOrientation::Horizontal => Orientation::Horizontal,
+            SplitOrientation::Vertical => Orientation::Vertical,
+            SplitOrientation::Auto if size.width >= size.height => Orientation::Horizontal,
+            SplitOrientation::Auto => Orientation::Vertical,
+        }
+    }
+}
+
 impl LayoutKind {
     pub fn orientation(self) -> Orientation {
         use LayoutKind::*;
@@ -63,6 +99,17 @@ impl LayoutKind {
             _ => false,
         }
     }
+
+    /// The kind with the other orientation.
+    fn rotated(self) -> Self {
+        use LayoutKind::*;
+        match self {
+            Horizontal => Vertical,
+            Vertical => Horizontal,
+            Tabbed => Stacked,
+            Stacked => Tabbed,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -120,6 +167,10 @@ struct LayoutInfo {
     kind: LayoutKind,
     /// The last ungrouped layout of this node.
     last_ungrouped_kind: LayoutKind,
+    /// Whether this node is collapsed to a thin bar, leaving its siblings the
+    /// rest of its parent. It keeps its size for when it is expanded again.
+    #[serde(default)]
+    is_shaded: bool,
 }
 
 impl Layout {
@@ -159,6 +210,14 @@ impl Layout {
         }
     }
 
+    /// Swaps the orientation of `node`, and of the layout it returns to when
+    /// ungrouped.
+    pub(super) fn rotate_kind(&mut self, node: NodeId) {
+        let info = &mut self.info[node];
+        info.kind = info.kind.rotated();
+        info.last_ungrouped_kind = info.last_ungrouped_kind.rotated();
+    }
+
     pub(super) fn kind(&self, node: NodeId) -> LayoutKind {
         self.info[node].kind
     }
@@ -167,6 +226,14 @@ impl Layout {
         self.info[node].last_ungrouped_kind
     }
 
+    pub(super) fn is_shaded(&self, node: NodeId) -> bool {
+        self.info[node].is_shaded
+    }
+
+    pub(super) fn set_shaded(&mut self, node: NodeId, is_shaded: bool) {
+        self.info[node].is_shaded = is_shaded;
+    }
+
     pub(super) fn proportion(&self, map: &NodeMap, node: NodeId) -> Option<f64> {
         let Some(parent) = node.parent(map) else { return None };
         Some(f64::from(self.info[node].size) / f64::from(self.info[parent].total))
@@ -184,12 +251,34 @@ impl Layout {
         self.info[node].size += share;
     }
 
+    /// Sets the share of its parent `node` takes up, relative to its
+    /// siblings.
+    pub(super) fn set_share(&mut self, map: &NodeMap, node: NodeId, share: f32) {
+        let parent = node.parent(map).unwrap();
+        self.info[parent].total += share - self.info[node].size;
+        self.info[node].size = share;
+    }
+
+    /// Gives each child of `node` an equal share of it.
+    pub(super) fn balance(&mut self, map: &NodeMap, node: NodeId) {
+        let mut total = 0.0;
+        for child in node.children(map) {
+            self.info[child].size = 1.0;
+            total += 1.0;
+        }
+        self.info[node].total = total;
+    }
+
     pub(super) fn debug(&self, node: NodeId, is_container: bool) -> String {
         let info = &self.info[node];
+        let shaded = if info.is_shaded { " shaded" } else { "" };
         if is_container {
-            format!("{:?} [size {} total={}]", info.kind, info.size, info.total)
+            format!(
+                "{:?} [size {} total={}{shaded}]",
+                info.kind, info.size, info.total
+            )
         } else {
-            format!("[size {}]", info.size)
+            format!("[size {}{shaded}]", info.size)
         }
     }
 
@@ -229,40 +318,144 @@ impl Layout {
                     self.apply(map, window, child, rect, sizes);
                 }
             }
-            Horizontal => {
-                let mut x = rect.origin.x;
-                let total = self.info[node].total;
-                for child in node.children(map) {
-                    let ratio = f64::from(self.info[child].size) / f64::from(total);
-                    let rect = CGRect {
-                        origin: CGPoint { x, y: rect.origin.y },
-                        size: CGSize {
-                            width: rect.size.width * ratio,
-                            height: rect.size.height,
-                        },
-                    }
-                    .round();
+            Horizontal | Vertical => {
+                for (child, rect) in self.split_rects(map, node, rect) {
                     self.apply(map, window, child, rect, sizes);
-                    x = rect.max().x;
                 }
             }
-            Vertical => {
-                let mut y = rect.origin.y;
-                let total = self.info[node].total;
-                for child in node.children(map) {
-                    let ratio = f64::from(self.info[child].size) / f64::from(total);
-                    let rect = CGRect {
-                        origin: CGPoint { x: rect.origin.x, y },
-                        size: CGSize {
-                            width: rect.size.width,
-                            height: rect.size.height * ratio,
-                        },
-                    }
-                    .round();
-                    self.apply(map, window, child, rect, sizes);
-                    y = rect.max().y;
-                }
+        }
+    }
+
+    /// Returns the frame of `node` within the tree at `root`, which fills
+    /// `rect`, or `None` if `node` is not in the tree.
+    ///
+    /// Every child of a group fills the whole group.
+    pub(super) fn get_node_rect(
+        &self,
+        map: &NodeMap,
+        root: NodeId,
+        node: NodeId,
+        rect: CGRect,
+    ) -> Option<CGRect> {
+        let path: Vec<NodeId> = node.ancestors(map).collect();
+        if path.last() != Some(&root) {
+            return None;
+        }
+        let mut rect = rect;
+        for pair in path.windows(2).rev() {
+            let (child, parent) = (pair[0], pair[1]);
+            if self.info[parent].kind.is_group() {
+                continue;
             }
+            rect = self
+                .split_rects(map, parent, rect)
+                .into_iter()
+                .find_map(|(node, rect)| (node == child).then_some(rect))?;
+        }
+        Some(rect)
+    }
+
+    /// Divides `rect` among the children of a split container.
+    ///
+    /// Rather than rounding each child's frame on its own, which can leave
+    /// one-pixel gaps or overlaps between neighbors, we round the position of
+    /// each boundary. Adjacent children then share an edge exactly, the
+    /// leftover fractions of a pixel go to the same children every time, and
+    /// the last child always ends at the edge of the container.
+    ///
+    /// Shaded children get [`SHADED_SIZE`] points each, and the others share
+    /// the rest in proportion to their sizes.
+    fn split_rects(&self, map: &NodeMap, node: NodeId, rect: CGRect) -> Vec<(NodeId, CGRect)> {
+        let orientation = self.info[node].kind.orientation();
+        let rect = rect.round();
+        let (start, length) = match orientation {
+            Orientation::Horizontal => (rect.origin.x, rect.size.width),
+            Orientation::Vertical => (rect.origin.y, rect.size.height),
+        };
+        let children: Vec<_> = node.children(map).collect();
+        // Shading is ignored if it would leave no room for the rest.
+        let shaded_count = children.iter().filter(|&&child| self.is_shaded(child)).count();
+        let shaded_length = SHADED_SIZE * shaded_count as f64;
+        let use_shading = shaded_count < children.len() && shaded_length < length;
+        let is_collapsed = |child: NodeId| use_shading && self.is_shaded(child);
+        let total: f64 = children
+            .iter()
+            .filter(|&&child| !is_collapsed(child))
+            .map(|&child| f64::from(self.info[child].size))
+            .sum();
+        let flexible = if use_shading {
+            length - shaded_length
+        } else {
+            length
+        };
+        let mut covered = 0.0;
+        let mut shaded_before = 0.0;
+        let mut edge = start;
+        children
+            .iter()
+            .enumerate()
+            .map(|(idx, &child)| {
+                if is_collapsed(child) {
+                    shaded_before += SHADED_SIZE;
+                } else {
+                    covered += f64::from(self.info[child].size);
+                }
+                let next_edge = if idx + 1 == children.len() {
+                    start + length
+                } else {
+                    (start + shaded_before + flexible * covered / total).round()
+                };
+                let child_rect = match orientation {
+                    Orientation::Horizontal => CGRect::new(
+                        CGPoint::new(edge, rect.origin.y),
+                        CGSize::new(next_edge - edge, rect.size.height),
+                    ),
+                    Orientation::Vertical => CGRect::new(
+                        CGPoint::new(rect.origin.x, edge),
+                        CGSize::new(rect.size.width, next_edge - edge),
+                    ),
+                };
+                edge = next_edge;
+                (child, child_rect)
+            })
+            .collect()
+    }
+
+    pub(super) fn get_splits(&self, map: &NodeMap, root: NodeId, rect: CGRect) -> Vec<Split> {
+        let mut splits = vec![];
+        self.collect_splits(map, root, rect, &mut splits);
+        splits
+    }
+
+    fn collect_splits(&self, map: &NodeMap, node: NodeId, rect: CGRect, splits: &mut Vec<Split>) {
+        // Only one child of a group is visible at a time, and we don't track
+        // which one here, so don't offer to resize anything inside them.
+        let kind = self.info[node].kind;
+        if kind.is_group() {
+            return;
+        }
+        let orientation = kind.orientation();
+        let rects = self.split_rects(map, node, rect);
+        for pair in rects.windows(2) {
+            let (before, before_rect) = pair[0];
+            let frame = match orientation {
+                Orientation::Horizontal => CGRect::new(
+                    CGPoint::new(before_rect.max().x, rect.origin.y),
+                    CGSize::new(0.0, rect.size.height),
+                ),
+                Orientation::Vertical => CGRect::new(
+                    CGPoint::new(rect.origin.x, before_rect.max().y),
+                    CGSize::new(rect.size.width, 0.0),
+                ),
+            };
+            splits.push(Split {
+                node: before,
+                orientation,
+                frame,
+            });
+        }
+        for (child, rect) in rects {
+            self.collect_splits(map, child, rect, splits);
         }
     }
 }
@@ -305,4 +498,121 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn it_collapses_shaded_nodes() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        let a1 = tree.add_window(layout, root, WindowId::new(1, 1));
+        let a2 = tree.add_container(root, LayoutKind::Vertical);
+        let b1 = tree.add_window(layout, a2, WindowId::new(1, 2));
+        let b2 = tree.add_window(layout, a2, WindowId::new(1, 3));
+
+        let screen = rect(0, 0, 2000, 1000);
+        let frames = |tree: &LayoutTree| {
+            let mut frames = tree.calculate_layout(layout, screen);
+            frames.sort_by_key(|&(wid, _)| wid);
+            frames.into_iter().map(|(_, frame)| frame).collect::<Vec<_>>()
+        };
+        assert!(tree.toggle_shade(b1));
+        assert_eq!(
+            frames(&tree),
+            vec![
+                rect(0, 0, 1000, 1000),
+                rect(1000, 0, 1000, 28),
+                rect(1000, 28, 1000, 972)
+            ]
+        );
+
+        // Some tile in each container has to stay expanded.
+        assert!(!tree.toggle_shade(b2));
+        assert!(tree.toggle_shade(a1));
+        assert_eq!(
+            frames(&tree),
+            vec![
+                rect(0, 0, 28, 1000),
+                rect(28, 0, 1972, 28),
+                rect(28, 28, 1972, 972)
+            ]
+        );
+
+        // Expanding restores the old sizes.
+        assert!(tree.toggle_shade(a1));
+        assert!(tree.toggle_shade(b1));
+        assert_eq!(
+            frames(&tree),
+            vec![
+                rect(0, 0, 1000, 1000),
+                rect(1000, 0, 1000, 500),
+                rect(1000, 500, 1000, 500)
+            ]
+        );
+    }
+
+    #[test]
+    fn it_finds_the_boundaries_between_children() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        let a1 = tree.add_window(layout, root, WindowId::new(1, 1));
+        let a2 = tree.add_container(root, LayoutKind::Vertical);
+        let b1 = tree.add_window(layout, a2, WindowId::new(1, 2));
+        let _b2 = tree.add_window(layout, a2, WindowId::new(1, 3));
+        let a3 = tree.add_container(root, LayoutKind::Tabbed);
+        let _c1 = tree.add_window(layout, a3, WindowId::new(1, 4));
+        let _c2 = tree.add_window(layout, a3, WindowId::new(1, 5));
+
+        let screen = rect(0, 0, 3000, 1000);
+        assert_eq!(
+            tree.calculate_splits(layout, screen),
+            vec![
+                Split {
+                    node: a1,
+                    orientation: Orientation::Horizontal,
+                    frame: rect(1000, 0, 0, 1000),
+                },
+                Split {
+                    node: a2,
+                    orientation: Orientation::Horizontal,
+                    frame: rect(2000, 0, 0, 1000),
+                },
+                Split {
+                    node: b1,
+                    orientation: Orientation::Vertical,
+                    frame: rect(1000, 500, 1000, 0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_distributes_leftover_pixels_between_children() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        for idx in 1..=3 {
+            tree.add_window(layout, root, WindowId::new(1, idx));
+        }
+        let a4 = tree.add_container(root, LayoutKind::Vertical);
+        for idx in 4..=6 {
+            tree.add_window(layout, a4, WindowId::new(1, idx));
+        }
+
+        // Neither the screen nor its quarters line up with whole points.
+        let screen = CGRect::new(CGPoint::new(2.5, 2.5), CGSize::new(1001.0, 995.0));
+        let mut frames = tree.calculate_layout(layout, screen);
+        frames.sort_by_key(|&(wid, _)| wid);
+        assert_eq!(
+            frames,
+            vec![
+                (WindowId::new(1, 1), rect(3, 3, 250, 995)),
+                (WindowId::new(1, 2), rect(253, 3, 251, 995)),
+                (WindowId::new(1, 3), rect(504, 3, 250, 995)),
+                (WindowId::new(1, 4), rect(754, 3, 250, 332)),
+                (WindowId::new(1, 5), rect(754, 335, 250, 331)),
+                (WindowId::new(1, 6), rect(754, 666, 250, 332)),
+            ]
+        );
+    }
 }
@@ -1,11 +1,12 @@
-use std::{iter, mem};
+use std::{collections::HashMap, iter, mem};
 
 use icrate::Foundation::CGRect;
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use super::{
-    layout::{Direction, Layout, LayoutKind},
+    adopt::{self, AdoptStrategy, Shape},
+    layout::{Direction, Layout, LayoutKind, Orientation, Split},
     selection::Selection,
     tree::{self, Tree},
     window::Window,
@@ -23,6 +24,15 @@ use crate::{
 pub struct LayoutTree {
     tree: Tree<Components>,
     layout_roots: slotmap::SlotMap<LayoutId, OwnedNode>,
+    /// Whether removing a window gives its siblings equal shares of their
+    /// container, rather than growing each in proportion to its size. This
+    /// comes from the config, so it isn't saved.
+    #[serde(skip)]
+    balance_on_close: bool,
+    /// The most tiles each app's windows can take up in a layout. This comes
+    /// from the config, so it isn't saved.
+    #[serde(skip)]
+    window_limits: HashMap<pid_t, usize>,
 }
 
 #[derive(Default, Serialize, Deserialize)]
@@ -59,14 +69,71 @@ slotmap::new_key_type! {
     pub struct LayoutId;
 }
 
+/// A layout tree with windows identified by what they are instead of by id,
+/// so it can be saved and applied to other windows later.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutSpec {
+    Container {
+        kind: LayoutKind,
+        /// The share of the parent container, from 0 to 1.
+        share: f64,
+        children: Vec<LayoutSpec>,
+    },
+    Window {
+        share: f64,
+        window: WindowMatcher,
+    },
+}
+
+/// Identifies a window by its app and title, which unlike its id stay the
+/// same across restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WindowMatcher {
+    /// The bundle id of the app.
+    pub app: Option<String>,
+    /// Text the title contains.
+    pub title: Option<String>,
+}
+
+/// A stacked or tabbed container, as status bars need it to show which
+/// windows it holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub kind: LayoutKind,
+    /// The window shown for each child, in order. A child that is a container
+    /// is shown as the window last selected in it.
+    pub windows: Vec<WindowId>,
+    /// The index in `windows` of the selected child.
+    pub selected: usize,
+    /// The frame of the container if the layout filled the frame it was
+    /// calculated for.
+    pub frame: CGRect,
+}
+
+/// Where a window is among its siblings, so another window can be put in its
+/// place later.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    After(WindowId),
+    Before(WindowId),
+}
+
 impl LayoutTree {
     pub fn new() -> LayoutTree {
         LayoutTree {
             tree: Tree::with_observer(Components::default()),
             layout_roots: Default::default(),
+            balance_on_close: false,
+            window_limits: Default::default(),
         }
     }
 
+    pub fn set_balance_on_close(&mut self, balance_on_close: bool) {
+        self.balance_on_close = balance_on_close;
+    }
+
     pub fn create_layout(&mut self) -> LayoutId {
         let root = OwnedNode::new_root_in(&mut self.tree, "layout_root");
         self.layout_roots.insert(root)
@@ -93,6 +160,13 @@ impl LayoutTree {
         dest_layout
     }
 
+    /// Removes the layout along with all of its nodes.
+    pub fn remove_layout(&mut self, layout: LayoutId) {
+        if let Some(mut root) = self.layout_roots.remove(layout) {
+            root.remove(&mut self.tree);
+        }
+    }
+
     pub fn add_window(&mut self, layout: LayoutId, parent: NodeId, wid: WindowId) -> NodeId {
         let node = self.tree.mk_node().push_back(parent);
         self.tree.data.window.set_window(layout, node, wid);
@@ -115,15 +189,76 @@ impl LayoutTree {
         }
     }
 
+    /// Limits the number of tiles the windows of `app` take up in each
+    /// layout, or removes the limit if `limit` is `None`.
+    pub fn set_window_limit(&mut self, app: pid_t, limit: Option<usize>) {
+        match limit {
+            Some(limit) => self.window_limits.insert(app, limit),
+            None => self.window_limits.remove(&app),
+        };
+    }
+
+    /// Whether the windows of `app` already take up as many tiles in `layout`
+    /// as its limit allows.
+    pub fn is_at_window_limit(&self, layout: LayoutId, app: pid_t) -> bool {
+        self.window_limits
+            .get(&app)
+            .is_some_and(|&limit| self.app_window_nodes(layout, app).len() >= limit)
+    }
+
+    /// Adds a window to `parent`, or to a stack with the app's other windows
+    /// if they already take up as many tiles as the app's limit allows.
+    pub fn add_window_within_limit(
+        &mut self,
+        layout: LayoutId,
+        parent: NodeId,
+        wid: WindowId,
+    ) -> NodeId {
+        if !self.is_at_window_limit(layout, wid.pid) {
+            return self.add_window(layout, parent, wid);
+        }
+        let app_windows = self.app_window_nodes(layout, wid.pid);
+        let stack = app_windows
+            .iter()
+            .filter_map(|node| node.parent(self.map()))
+            .find(|&parent| self.layout(parent) == LayoutKind::Stacked);
+        let parent = match (stack, app_windows.last()) {
+            (Some(stack), _) => stack,
+            (None, Some(&last)) => self.nest_in_container(layout, last, LayoutKind::Stacked),
+            (None, None) => parent,
+        };
+        self.add_window(layout, parent, wid)
+    }
+
+    fn app_window_nodes(&self, layout: LayoutId, app: pid_t) -> Vec<NodeId> {
+        self.root(layout)
+            .traverse_preorder(self.map())
+            .filter(|&node| self.window_at(node).is_some_and(|wid| wid.pid == app))
+            .collect()
+    }
+
     pub fn remove_window(&mut self, wid: WindowId) {
         for (_, node) in self.tree.data.window.take_nodes_for(wid) {
-            node.detach(&mut self.tree).remove();
+            self.remove_window_node(node);
         }
     }
 
     pub fn remove_windows_for_app(&mut self, pid: pid_t) {
         for (_, _, node) in self.tree.data.window.take_nodes_for_app(pid) {
-            node.detach(&mut self.tree).remove();
+            self.remove_window_node(node);
+        }
+    }
+
+    fn remove_window_node(&mut self, node: NodeId) {
+        // Containers left empty are removed along with the window, so the
+        // space goes to the first ancestor with other children.
+        let parent = node
+            .ancestors(self.map())
+            .skip(1)
+            .find(|&parent| parent.children(self.map()).nth(1).is_some());
+        node.detach(&mut self.tree).remove();
+        if let (true, Some(parent)) = (self.balance_on_close, parent) {
+            self.tree.data.layout.balance(&self.tree.map, parent);
         }
     }
 
@@ -155,15 +290,16 @@ impl LayoutTree {
                     current.next();
                 }
                 (Some(des), None) => {
-                    self.add_window(layout, root, *des);
+                    self.add_window_within_limit(layout, root, *des);
                     desired.next();
                 }
                 (Some(des), Some((cur, _))) if des < cur => {
-                    self.add_window(layout, root, *des);
+                    self.add_window_within_limit(layout, root, *des);
                     desired.next();
                 }
                 (_, Some((_, node))) => {
-                    node.detach(&mut self.tree).remove();
+                    let node = *node;
+                    self.remove_window_node(node);
                     current.next();
                 }
                 (None, None) => break,
@@ -179,13 +315,273 @@ impl LayoutTree {
         self.tree.data.window.at(node)
     }
 
-    #[allow(dead_code)]
+    /// Exchanges the places of two windows in the layout, keeping the size of
+    /// each tile. Returns false if either window is not in the layout.
+    pub fn swap_windows(&mut self, layout: LayoutId, a: WindowId, b: WindowId) -> bool {
+        let (Some(node_a), Some(node_b)) =
+            (self.window_node(layout, a), self.window_node(layout, b))
+        else {
+            return false;
+        };
+        if node_a != node_b {
+            self.tree.data.window.swap(layout, node_a, node_b);
+        }
+        true
+    }
+
+    /// Returns the number of windows in the layout.
+    pub fn window_count(&self, layout: LayoutId) -> usize {
+        self.root(layout)
+            .traverse_preorder(self.map())
+            .filter(|&node| self.window_at(node).is_some())
+            .count()
+    }
+
+    /// Returns the window to focus when `wid` leaves the layout: the next
+    /// window in the tree, or the previous one if `wid` is the last.
+    pub fn window_after(&self, layout: LayoutId, wid: WindowId) -> Option<WindowId> {
+        let windows: Vec<WindowId> = self
+            .root(layout)
+            .traverse_preorder(self.map())
+            .filter_map(|node| self.window_at(node))
+            .collect();
+        let idx = windows.iter().position(|&other| other == wid)?;
+        windows.get(idx + 1).or_else(|| windows.get(idx.checked_sub(1)?)).copied()
+    }
+
+    /// Returns the window next to `wid` in its container, preferring the one
+    /// before it.
+    pub fn window_place(&self, layout: LayoutId, wid: WindowId) -> Option<Place> {
+        let node = self.window_node(layout, wid)?;
+        let map = self.map();
+        let prev = node.prev_sibling(map).and_then(|sibling| self.window_at(sibling));
+        let next = node.next_sibling(map).and_then(|sibling| self.window_at(sibling));
+        prev.map(Place::After).or(next.map(Place::Before))
+    }
+
+    /// Adds a window at `place`, or returns `None` if the window it is next to
+    /// is not in the layout.
+    pub fn add_window_at(
+        &mut self,
+        layout: LayoutId,
+        place: Place,
+        wid: WindowId,
+    ) -> Option<NodeId> {
+        let (Place::After(sibling) | Place::Before(sibling)) = place;
+        let sibling = self.window_node(layout, sibling)?;
+        let node = match place {
+            Place::After(_) => self.tree.mk_node().insert_after(sibling),
+            Place::Before(_) => self.tree.mk_node().insert_before(sibling),
+        };
+        self.tree.data.window.set_window(layout, node, wid);
+        Some(node)
+    }
+
     pub fn add_container(&mut self, parent: NodeId, kind: LayoutKind) -> NodeId {
         let node = self.tree.mk_node().push_back(parent);
         self.tree.data.layout.set_kind(node, kind);
         node
     }
 
+    /// Describes `layout`, using `describe` to identify each window.
+    pub fn export(
+        &self,
+        layout: LayoutId,
+        describe: impl Fn(WindowId) -> WindowMatcher,
+    ) -> LayoutSpec {
+        self.export_node(self.root(layout), &describe)
+    }
+
+    fn export_node(
+        &self,
+        node: NodeId,
+        describe: &impl Fn(WindowId) -> WindowMatcher,
+    ) -> LayoutSpec {
+        let share = self.tree.data.layout.proportion(self.map(), node).unwrap_or(1.0);
+        if let Some(wid) = self.window_at(node) {
+            return LayoutSpec::Window { share, window: describe(wid) };
+        }
+        LayoutSpec::Container {
+            kind: self.layout(node),
+            share,
+            children: node
+                .children(self.map())
+                .map(|child| self.export_node(child, describe))
+                .collect(),
+        }
+    }
+
+    /// Rebuilds `layout` from `spec`, with the windows already in it.
+    ///
+    /// Each window in the spec takes the first window in the layout for which
+    /// `matches` returns true. Windows in the spec with no match are left out,
+    /// along with any containers left empty, and windows in the layout that
+    /// match nothing are added to the root afterward.
+    pub fn import(
+        &mut self,
+        layout: LayoutId,
+        spec: &LayoutSpec,
+        matches: impl Fn(&WindowMatcher, WindowId) -> bool,
+    ) {
+        let root = self.root(layout);
+        let mut unused: Vec<WindowId> = root
+            .traverse_preorder(self.map())
+            .filter_map(|node| self.window_at(node))
+            .collect();
+        let children: Vec<NodeId> = root.children(self.map()).collect();
+        for child in children {
+            child.detach(&mut self.tree).remove();
+        }
+        match spec {
+            LayoutSpec::Container { kind, children, .. } => {
+                self.set_layout(root, *kind);
+                for child in children {
+                    self.import_node(layout, root, child, &mut unused, &matches);
+                }
+            }
+            LayoutSpec::Window { .. } => {
+                self.import_node(layout, root, spec, &mut unused, &matches);
+            }
+        }
+        for wid in unused {
+            self.add_window(layout, root, wid);
+        }
+    }
+
+    fn import_node(
+        &mut self,
+        layout: LayoutId,
+        parent: NodeId,
+        spec: &LayoutSpec,
+        unused: &mut Vec<WindowId>,
+        matches: &impl Fn(&WindowMatcher, WindowId) -> bool,
+    ) {
+        match spec {
+            LayoutSpec::Window { share, window } => {
+                let Some(idx) = unused.iter().position(|&wid| matches(window, wid)) else {
+                    return;
+                };
+                let node = self.add_window(layout, parent, unused.remove(idx));
+                self.tree.data.layout.set_share(&self.tree.map, node, *share as f32);
+            }
+            LayoutSpec::Container { kind, share, children } => {
+                let node = self.add_container(parent, *kind);
+                self.tree.data.layout.set_share(&self.tree.map, node, *share as f32);
+                for child in children {
+                    self.import_node(layout, node, child, unused, matches);
+                }
+                if node.is_empty(self.map()) {
+                    node.detach(&mut self.tree).remove();
+                }
+            }
+        }
+    }
+
+    /// Rearranges the windows in `layout` by `strategy`, using `frame` to
+    /// find where each window is now. Windows without a frame are added to
+    /// the root afterward.
+    pub fn adopt(
+        &mut self,
+        layout: LayoutId,
+        strategy: AdoptStrategy,
+        frame: impl Fn(WindowId) -> Option<CGRect>,
+    ) {
+        let (placed, unplaced) = self.window_frames(layout, frame);
+        let Some(arrangement) = adopt::arrange(strategy, placed) else {
+            return;
+        };
+        let root = self.clear_root(layout);
+        self.set_layout(root, arrangement.root);
+        for column in arrangement.columns {
+            match &column[..] {
+                [wid] => {
+                    self.add_window(layout, root, *wid);
+                }
+                _ => {
+                    let container = self.add_container(root, LayoutKind::Vertical);
+                    for wid in column {
+                        self.add_window(layout, container, wid);
+                    }
+                }
+            }
+        }
+        for wid in unplaced {
+            self.add_window(layout, root, wid);
+        }
+    }
+
+    /// Rebuilds `layout` to match where its windows are, using `frame` to
+    /// find each window. Windows without a frame are added to the root
+    /// afterward.
+    pub fn capture(&mut self, layout: LayoutId, frame: impl Fn(WindowId) -> Option<CGRect>) {
+        let (placed, unplaced) = self.window_frames(layout, frame);
+        if placed.is_empty() {
+            return;
+        }
+        let shape = adopt::infer(placed);
+        let root = self.clear_root(layout);
+        match shape {
+            Shape::Container { kind, children } => {
+                self.set_layout(root, kind);
+                for (share, child) in children {
+                    self.add_shape(layout, root, share, child);
+                }
+            }
+            Shape::Window(wid) => {
+                self.add_window(layout, root, wid);
+            }
+        }
+        for wid in unplaced {
+            self.add_window(layout, root, wid);
+        }
+    }
+
+    /// Returns the windows in `layout` that `frame` gives a frame for, with
+    /// their frames, and the windows it doesn't, both in layout order.
+    fn window_frames(
+        &self,
+        layout: LayoutId,
+        frame: impl Fn(WindowId) -> Option<CGRect>,
+    ) -> (Vec<(WindowId, CGRect)>, Vec<WindowId>) {
+        let mut placed = vec![];
+        let mut unplaced = vec![];
+        let windows = self
+            .root(layout)
+            .traverse_preorder(self.map())
+            .filter_map(|node| self.window_at(node));
+        for wid in windows {
+            match frame(wid) {
+                Some(frame) => placed.push((wid, frame)),
+                None => unplaced.push(wid),
+            }
+        }
+        (placed, unplaced)
+    }
+
+    /// Removes everything under the root of `layout`, returning the root.
+    fn clear_root(&mut self, layout: LayoutId) -> NodeId {
+        let root = self.root(layout);
+        let children: Vec<NodeId> = root.children(self.map()).collect();
+        for child in children {
+            child.detach(&mut self.tree).remove();
+        }
+        root
+    }
+
+    fn add_shape(&mut self, layout: LayoutId, parent: NodeId, share: f64, shape: Shape) {
+        let node = match shape {
+            Shape::Window(wid) => self.add_window(layout, parent, wid),
+            Shape::Container { kind, children } => {
+                let node = self.add_container(parent, kind);
+                for (share, child) in children {
+                    self.add_shape(layout, node, share, child);
+                }
+                node
+            }
+        };
+        self.tree.data.layout.set_share(&self.tree.map, node, share as f32);
+    }
+
     pub fn select(&mut self, selection: NodeId) {
         self.tree.data.selection.select(&self.tree.map, selection)
     }
@@ -221,6 +617,52 @@ impl LayoutTree {
         )
     }
 
+    /// Returns the frame `node` would have if the layout filled `frame`.
+    pub fn calculate_node_frame(
+        &self,
+        layout: LayoutId,
+        node: NodeId,
+        frame: CGRect,
+    ) -> Option<CGRect> {
+        self.tree
+            .data
+            .layout
+            .get_node_rect(&self.tree.map, self.root(layout), node, frame)
+    }
+
+    /// Returns the boundaries between tiles that can be dragged to resize
+    /// them.
+    pub fn calculate_splits(&self, layout: LayoutId, frame: CGRect) -> Vec<Split> {
+        self.tree.data.layout.get_splits(&self.tree.map, self.root(layout), frame)
+    }
+
+    /// Moves the boundary after `node` toward the right or bottom by
+    /// `screen_ratio` of the screen size, or the other way if negative.
+    ///
+    /// Returns false if `node` is no longer in `layout` or has no boundary
+    /// after it.
+    pub fn move_split(
+        &mut self,
+        layout: LayoutId,
+        node: NodeId,
+        orientation: Orientation,
+        screen_ratio: f64,
+    ) -> bool {
+        if !self.tree.map.contains(node)
+            || node.ancestors(&self.tree.map).last() != Some(self.root(layout))
+        {
+            return false;
+        }
+        let direction = match orientation {
+            Orientation::Horizontal => Direction::Right,
+            Orientation::Vertical => Direction::Down,
+        };
+        if self.move_over(node, direction).is_none() {
+            return false;
+        }
+        self.resize(node, screen_ratio, direction)
+    }
+
     pub fn traverse(&self, from: NodeId, direction: Direction) -> Option<NodeId> {
         let map = &self.tree.map;
         let node =
@@ -243,6 +685,62 @@ impl LayoutTree {
         .last()
     }
 
+    /// Returns the nth child (starting at 1) of the container holding the
+    /// selection, or of the top level if the whole layout is selected. If the
+    /// child is a container, the window last selected in it is returned.
+    pub fn nth_child_of_selection(&self, layout: LayoutId, n: usize) -> Option<NodeId> {
+        let map = &self.tree.map;
+        let selection = self.selection(layout);
+        let container = selection.parent(map).unwrap_or(selection);
+        let child = container.children(map).nth(n.checked_sub(1)?)?;
+        Some(self.last_selected_leaf(child))
+    }
+
+    /// Follows the selection down from `node` to a leaf, taking the first
+    /// child of containers that have never had one selected.
+    fn last_selected_leaf(&self, node: NodeId) -> NodeId {
+        let map = &self.tree.map;
+        iter::successors(Some(node), |&node| {
+            self.tree.data.selection.local_selection(map, node).or(node.first_child(map))
+        })
+        .last()
+        .unwrap()
+    }
+
+    /// Returns the stacked and tabbed containers in the layout, outermost
+    /// first, with their frames if the layout filled `frame`. Containers with
+    /// no windows are left out.
+    pub fn groups(&self, layout: LayoutId, frame: CGRect) -> Vec<Group> {
+        let map = self.map();
+        self.containers(layout)
+            .into_iter()
+            .filter(|&node| self.tree.data.layout.kind(node).is_group())
+            .filter_map(|node| {
+                let selected_child = self.tree.data.selection.last_selection(map, node);
+                let mut selected = 0;
+                let mut windows = vec![];
+                for child in node.children(map) {
+                    let Some(wid) = self.window_at(self.last_selected_leaf(child)) else {
+                        continue;
+                    };
+                    if Some(child) == selected_child {
+                        selected = windows.len();
+                    }
+                    windows.push(wid);
+                }
+                if windows.is_empty() {
+                    return None;
+                }
+                Some(Group {
+                    kind: self.tree.data.layout.kind(node),
+                    windows,
+                    selected,
+                    frame: self.calculate_node_frame(layout, node, frame)?,
+                })
+            })
+            .collect()
+    }
+
     fn move_over(&self, from: NodeId, direction: Direction) -> Option<NodeId> {
         let Some(parent) = from.parent(&self.tree.map) else {
             return None;
@@ -400,6 +898,47 @@ impl LayoutTree {
         parent
     }
 
+    /// Flips the layout along `orientation`, so that windows side by side
+    /// trade sides for [`Orientation::Horizontal`], and windows above one
+    /// another trade places for [`Orientation::Vertical`]. Tiles keep their
+    /// sizes, and the order of tabs and stacks is kept.
+    pub fn mirror(&mut self, layout: LayoutId, orientation: Orientation) {
+        let kind = LayoutKind::from(orientation);
+        for node in self.containers(layout) {
+            if self.tree.data.layout.kind(node) == kind {
+                self.reverse_children(node);
+            }
+        }
+    }
+
+    /// Turns the layout a quarter turn clockwise, swapping the orientation of
+    /// every container. Windows side by side end up above one another, the
+    /// leftmost on top.
+    pub fn rotate(&mut self, layout: LayoutId) {
+        for node in self.containers(layout) {
+            // The top of a column ends up on the right of a row.
+            if self.tree.data.layout.kind(node) == LayoutKind::Vertical {
+                self.reverse_children(node);
+            }
+            self.tree.data.layout.rotate_kind(node);
+        }
+    }
+
+    fn containers(&self, layout: LayoutId) -> Vec<NodeId> {
+        self.root(layout)
+            .traverse_preorder(self.map())
+            .filter(|&node| self.window_at(node).is_none())
+            .collect()
+    }
+
+    /// Reverses the order of the children of `node`, keeping their sizes.
+    fn reverse_children(&mut self, node: NodeId) {
+        let children: Vec<NodeId> = node.children(self.map()).collect();
+        for child in children {
+            child.detach(&mut self.tree).push_front(node);
+        }
+    }
+
     pub fn resize(&mut self, node: NodeId, screen_ratio: f64, direction: Direction) -> bool {
         // Pick an ancestor to resize that has a sibling in the given direction.
         let can_resize = |&node: &NodeId| -> bool {
@@ -441,6 +980,32 @@ impl LayoutTree {
         true
     }
 
+    /// Collapses `node`, or its nearest ancestor in a split container, to a
+    /// thin bar, leaving the rest of the container to its siblings. Expands it
+    /// again if it is already collapsed.
+    ///
+    /// Returns false if there is nothing to collapse, or no sibling that
+    /// could take up the space.
+    pub fn toggle_shade(&mut self, node: NodeId) -> bool {
+        let map = &self.tree.map;
+        let layout = &self.tree.data.layout;
+        let in_split =
+            |node: &NodeId| node.parent(map).is_some_and(|parent| !layout.kind(parent).is_group());
+        let Some(node) = node.ancestors(map).find(in_split) else {
+            return false;
+        };
+        if layout.is_shaded(node) {
+            self.tree.data.layout.set_shaded(node, false);
+            return true;
+        }
+        let parent = node.parent(map).unwrap();
+        if !parent.children(map).any(|child| child != node && !layout.is_shaded(child)) {
+            return false;
+        }
+        self.tree.data.layout.set_shaded(node, true);
+        true
+    }
+
     /// Call this during a user resize to have the model respond appropriately.
     ///
     /// Only two edges are allowed to change at a time.
@@ -629,6 +1194,23 @@ mod tests {
         assert_eq!(2, root.children(tree.map()).count());
     }
 
+    #[test]
+    fn remove_layout() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        tree.add_window(layout, root, w(1, 1));
+        let copy = tree.clone_layout(layout);
+        assert!(tree.window_node(copy, w(1, 1)).is_some());
+
+        tree.remove_layout(copy);
+        assert_eq!(None, tree.window_node(copy, w(1, 1)));
+        assert!(tree.window_node(layout, w(1, 1)).is_some());
+        assert_eq!(1, tree.layout_roots.len());
+        // Removing it again does nothing.
+        tree.remove_layout(copy);
+    }
+
     #[test]
     fn traverse() {
         let mut tree = LayoutTree::new();
@@ -789,6 +1371,139 @@ mod tests {
         assert_eq!(left, right);
     }
 
+    #[test]
+    fn mirror_and_rotate() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        let a1 = tree.add_window(layout, root, w(1, 1));
+        let a2 = tree.add_container(root, LayoutKind::Vertical);
+        tree.add_window(layout, a2, w(2, 1));
+        tree.add_window(layout, a2, w(2, 2));
+        // The left window takes up a quarter of the screen.
+        tree.resize(a1, -0.25, Direction::Right);
+
+        let screen = CGRect::new(CGPoint::new(0., 0.), CGSize::new(1000., 1000.));
+        let frames = |tree: &LayoutTree| {
+            let mut frames = tree.calculate_layout(layout, screen);
+            frames.sort_by_key(|&(wid, _)| wid);
+            frames
+                .into_iter()
+                .map(|(_, frame)| {
+                    let (origin, size) = (frame.origin, frame.size);
+                    (origin.x, origin.y, size.width, size.height)
+                })
+                .collect::<Vec<_>>()
+        };
+        let original = frames(&tree);
+        assert_eq!(
+            original,
+            vec![
+                (0., 0., 250., 1000.),
+                (250., 0., 750., 500.),
+                (250., 500., 750., 500.)
+            ]
+        );
+
+        tree.mirror(layout, Orientation::Horizontal);
+        assert_eq!(
+            frames(&tree),
+            vec![
+                (750., 0., 250., 1000.),
+                (0., 0., 750., 500.),
+                (0., 500., 750., 500.)
+            ]
+        );
+        tree.mirror(layout, Orientation::Horizontal);
+        tree.mirror(layout, Orientation::Vertical);
+        assert_eq!(
+            frames(&tree),
+            vec![
+                (0., 0., 250., 1000.),
+                (250., 500., 750., 500.),
+                (250., 0., 750., 500.)
+            ]
+        );
+        tree.mirror(layout, Orientation::Vertical);
+
+        tree.rotate(layout);
+        assert_eq!(
+            frames(&tree),
+            vec![
+                (0., 0., 1000., 250.),
+                (500., 250., 500., 750.),
+                (0., 250., 500., 750.)
+            ]
+        );
+        for _ in 0..3 {
+            tree.rotate(layout);
+        }
+        assert_eq!(original, frames(&tree));
+    }
+
+    #[test]
+    fn swap_windows() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        let a1 = tree.add_window(layout, root, w(1, 1));
+        let a2 = tree.add_container(root, LayoutKind::Vertical);
+        let b1 = tree.add_window(layout, a2, w(2, 1));
+        let b2 = tree.add_window(layout, a2, w(2, 2));
+        let other = tree.clone_layout(layout);
+
+        assert!(tree.swap_windows(layout, w(1, 1), w(2, 2)));
+        assert_eq!(Some(w(2, 2)), tree.window_at(a1));
+        assert_eq!(Some(w(2, 1)), tree.window_at(b1));
+        assert_eq!(Some(w(1, 1)), tree.window_at(b2));
+        assert_eq!(Some(b2), tree.window_node(layout, w(1, 1)));
+        assert_eq!(Some(a1), tree.window_node(layout, w(2, 2)));
+
+        // Copies of the layout keep their own order.
+        let other_root = tree.root(other);
+        assert_eq!(
+            Some(w(1, 1)),
+            tree.window_at(other_root.first_child(tree.map()).unwrap())
+        );
+
+        assert!(!tree.swap_windows(layout, w(1, 1), w(3, 1)));
+    }
+
+    #[test]
+    fn groups() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        tree.add_window(layout, root, w(1, 1));
+        let stack = tree.add_container(root, LayoutKind::Stacked);
+        let b1 = tree.add_window(layout, stack, w(2, 1));
+        let column = tree.add_container(stack, LayoutKind::Vertical);
+        tree.add_window(layout, column, w(3, 1));
+        let c2 = tree.add_window(layout, column, w(3, 2));
+        let screen = rect(0, 0, 1000, 1000);
+
+        tree.select(c2);
+        let groups = tree.groups(layout, screen);
+        assert_eq!(
+            vec![Group {
+                kind: LayoutKind::Stacked,
+                windows: vec![w(2, 1), w(3, 2)],
+                selected: 1,
+                frame: rect(500, 0, 500, 1000),
+            }],
+            groups
+        );
+
+        // The column is still shown as the window last selected in it.
+        tree.select(b1);
+        let groups = tree.groups(layout, screen);
+        assert_eq!(vec![w(2, 1), w(3, 2)], groups[0].windows);
+        assert_eq!(0, groups[0].selected);
+
+        tree.set_layout(stack, LayoutKind::Horizontal);
+        assert!(tree.groups(layout, screen).is_empty());
+    }
+
     #[test]
     fn nest_in_container() {
         let mut tree = LayoutTree::new();
@@ -1032,4 +1747,222 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn move_split() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        let a1 = tree.add_window(layout, root, WindowId::new(1, 1));
+        let a2 = tree.add_container(root, LayoutKind::Vertical);
+        let b1 = tree.add_window(layout, a2, WindowId::new(2, 1));
+        let b2 = tree.add_window(layout, a2, WindowId::new(2, 2));
+        let screen = rect(0, 0, 2000, 1000);
+
+        assert!(tree.move_split(layout, a1, Orientation::Horizontal, 0.25));
+        assert!(tree.move_split(layout, b1, Orientation::Vertical, -0.1));
+        assert_frames_are(
+            tree.calculate_layout(layout, screen),
+            [
+                (WindowId::new(1, 1), rect(0, 0, 1500, 1000)),
+                (WindowId::new(2, 1), rect(1500, 0, 500, 400)),
+                (WindowId::new(2, 2), rect(1500, 400, 500, 600)),
+            ],
+        );
+
+        // There is nothing after the last child to move.
+        assert!(!tree.move_split(layout, b2, Orientation::Vertical, 0.1));
+        // The split may be gone by the time the user drags it.
+        tree.remove_window(WindowId::new(1, 1));
+        assert!(!tree.move_split(layout, a1, Orientation::Horizontal, 0.1));
+    }
+
+    #[test]
+    fn balance_on_close() {
+        let setup = |balance_on_close| {
+            let mut tree = LayoutTree::new();
+            tree.set_balance_on_close(balance_on_close);
+            let layout = tree.create_layout();
+            let root = tree.root(layout);
+            let a1 = tree.add_window(layout, root, WindowId::new(1, 1));
+            tree.add_window(layout, root, WindowId::new(1, 2));
+            tree.add_window(layout, root, WindowId::new(1, 3));
+            let a4 = tree.add_container(root, LayoutKind::Vertical);
+            tree.add_window(layout, a4, WindowId::new(2, 1));
+            // Make the first window wider than the others.
+            assert!(tree.move_split(layout, a1, Orientation::Horizontal, 0.1));
+            (tree, layout, a1)
+        };
+        let screen = rect(0, 0, 2000, 1000);
+
+        let (mut tree, layout, _) = setup(false);
+        tree.remove_window(WindowId::new(1, 3));
+        assert_frames_are(
+            tree.calculate_layout(layout, screen),
+            [
+                (WindowId::new(1, 1), rect(0, 0, 933, 1000)),
+                (WindowId::new(1, 2), rect(933, 0, 400, 1000)),
+                (WindowId::new(2, 1), rect(1333, 0, 667, 1000)),
+            ],
+        );
+
+        let (mut tree, layout, a1) = setup(true);
+        tree.remove_window(WindowId::new(1, 3));
+        assert_frames_are(
+            tree.calculate_layout(layout, screen),
+            [
+                (WindowId::new(1, 1), rect(0, 0, 667, 1000)),
+                (WindowId::new(1, 2), rect(667, 0, 666, 1000)),
+                (WindowId::new(2, 1), rect(1333, 0, 667, 1000)),
+            ],
+        );
+        // Removing the only window in a container balances the container's
+        // siblings instead.
+        assert!(tree.move_split(layout, a1, Orientation::Horizontal, 0.1));
+        tree.remove_window(WindowId::new(2, 1));
+        assert_frames_are(
+            tree.calculate_layout(layout, screen),
+            [
+                (WindowId::new(1, 1), rect(0, 0, 1000, 1000)),
+                (WindowId::new(1, 2), rect(1000, 0, 1000, 1000)),
+            ],
+        );
+    }
+
+    #[test]
+    fn window_limits() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        tree.set_window_limit(1, Some(2));
+        tree.add_window(layout, root, WindowId::new(2, 1));
+        for idx in 1..=4 {
+            tree.add_window_within_limit(layout, root, WindowId::new(1, idx));
+        }
+        tree.add_window_within_limit(layout, root, WindowId::new(2, 2));
+        let screen = rect(0, 0, 3000, 1000);
+        assert_frames_are(
+            tree.calculate_layout(layout, screen),
+            [
+                (WindowId::new(2, 1), rect(0, 0, 750, 1000)),
+                (WindowId::new(1, 1), rect(750, 0, 750, 1000)),
+                (WindowId::new(1, 2), rect(1500, 0, 750, 1000)),
+                (WindowId::new(1, 3), rect(1500, 0, 750, 1000)),
+                (WindowId::new(1, 4), rect(1500, 0, 750, 1000)),
+                (WindowId::new(2, 2), rect(2250, 0, 750, 1000)),
+            ],
+        );
+        assert!(tree.is_at_window_limit(layout, 1));
+        assert!(!tree.is_at_window_limit(layout, 2));
+
+        // Windows can tile again once the limit is lifted.
+        tree.set_window_limit(1, None);
+        tree.add_window_within_limit(layout, root, WindowId::new(1, 5));
+        assert_eq!(tree.calculate_layout(layout, screen).len(), 7);
+        assert_eq!(
+            tree.calculate_layout(layout, screen)[6].1,
+            rect(2400, 0, 600, 1000)
+        );
+    }
+
+    #[test]
+    fn window_after() {
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        tree.add_window(layout, root, WindowId::new(1, 1));
+        let container = tree.add_container(root, LayoutKind::Vertical);
+        tree.add_window(layout, container, WindowId::new(1, 2));
+        tree.add_window(layout, container, WindowId::new(1, 3));
+        tree.add_window(layout, root, WindowId::new(1, 4));
+
+        assert_eq!(
+            Some(WindowId::new(1, 2)),
+            tree.window_after(layout, WindowId::new(1, 1))
+        );
+        assert_eq!(
+            Some(WindowId::new(1, 4)),
+            tree.window_after(layout, WindowId::new(1, 3))
+        );
+        assert_eq!(
+            Some(WindowId::new(1, 3)),
+            tree.window_after(layout, WindowId::new(1, 4))
+        );
+        assert_eq!(None, tree.window_after(layout, WindowId::new(2, 1)));
+
+        let other = tree.create_layout();
+        let root = tree.root(other);
+        tree.add_window(other, root, WindowId::new(1, 1));
+        assert_eq!(None, tree.window_after(other, WindowId::new(1, 1)));
+    }
+
+    #[test]
+    fn export_and_import() {
+        let title = |wid: WindowId| WindowMatcher {
+            app: None,
+            title: Some(format!("{wid:?}")),
+        };
+        let matches = |matcher: &WindowMatcher, wid: WindowId| *matcher == title(wid);
+        let mut tree = LayoutTree::new();
+        let layout = tree.create_layout();
+        let root = tree.root(layout);
+        let a1 = tree.add_window(layout, root, w(1, 1));
+        let container = tree.add_container(root, LayoutKind::Vertical);
+        tree.add_window(layout, container, w(2, 1));
+        tree.add_window(layout, container, w(2, 2));
+        tree.resize(a1, 0.25, Direction::Right);
+        let spec = tree.export(layout, title);
+        let LayoutSpec::Container {
+            kind: LayoutKind::Horizontal,
+            children,
+            ..
+        } = &spec
+        else {
+            panic!("Expected a horizontal root: {spec:?}");
+        };
+        assert!(matches!(children[0], LayoutSpec::Window { share, .. } if share == 0.75));
+
+        // Apply the layout to a flat tree with one window missing and one
+        // extra, which goes at the end.
+        let other = tree.create_layout();
+        let root = tree.root(other);
+        tree.add_window(other, root, w(2, 2));
+        tree.add_window(other, root, w(3, 1));
+        tree.add_window(other, root, w(1, 1));
+        tree.import(other, &spec, matches);
+        let screen = rect(0, 0, 1000, 1000);
+        assert_eq!(
+            tree.calculate_layout(other, screen),
+            vec![
+                (w(1, 1), rect(0, 0, 375, 1000)),
+                (w(2, 2), rect(375, 0, 125, 1000)),
+                (w(3, 1), rect(500, 0, 500, 1000)),
+            ]
+        );
+        assert_eq!(
+            tree.export(other, title),
+            LayoutSpec::Container {
+                kind: LayoutKind::Horizontal,
+                share: 1.0,
+                children: vec![
+                    LayoutSpec::Window {
+                        share: 0.375,
+                        window: title(w(1, 1))
+                    },
+                    LayoutSpec::Container {
+                        kind: LayoutKind::Vertical,
+                        share: 0.125,
+                        children: vec![LayoutSpec::Window {
+                            share: 1.0,
+                            window: title(w(2, 2))
+                        }],
+                    },
+                    LayoutSpec::Window {
+                        share: 0.5,
+                        window: title(w(3, 1))
+                    },
+                ],
+            }
+        );
+    }
 }
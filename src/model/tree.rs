@@ -43,6 +43,11 @@ impl NodeMap {
         NodeMap { map: SlotMap::default() }
     }
 
+    /// Returns whether the node still exists.
+    pub fn contains(&self, node: NodeId) -> bool {
+        self.map.contains_key(node)
+    }
+
     pub fn capacity(&self) -> usize {
         self.map.capacity()
     }
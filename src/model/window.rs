@@ -45,6 +45,21 @@ impl Window {
         self.window_nodes.entry(wid).or_default().push(WindowNodeInfo { layout, node });
     }
 
+    /// Exchanges the windows at two nodes of a layout, which must both have
+    /// one.
+    pub fn swap(&mut self, layout: LayoutId, a: NodeId, b: NodeId) {
+        let wid_a = self.windows[a];
+        let wid_b = self.windows[b];
+        self.windows[a] = wid_b;
+        self.windows[b] = wid_a;
+        for (wid, from, to) in [(wid_a, a, b), (wid_b, b, a)] {
+            let nodes = self.window_nodes.get_mut(&wid).into_iter().flatten();
+            for info in nodes.filter(|info| info.layout == layout && info.node == from) {
+                info.node = to;
+            }
+        }
+    }
+
     pub fn set_capacity(&mut self, capacity: usize) {
         self.windows.set_capacity(capacity);
         // There's not currently a stable way to do this for BTreeMap.
@@ -1,10 +1,25 @@
 //! Utilities for interfacing with OS-specific APIs.
 
+pub mod announce;
 pub mod app;
+pub mod appearance;
+pub mod clock;
+pub mod dock;
+pub mod event;
 pub mod executor;
+pub mod focus;
 pub mod geometry;
 pub mod hotkey;
+pub mod idle;
+pub mod keyboard;
+pub mod menu;
 pub mod observer;
+pub mod overlay;
+pub mod permissions;
+pub mod power;
+pub mod process;
 pub mod run_loop;
 pub mod screen;
+pub mod thread;
+pub mod window_managers;
 pub mod window_server;
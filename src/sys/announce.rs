@@ -0,0 +1,40 @@
+//! Speaking messages through screen readers like VoiceOver.
+
+use icrate::{
+    objc2::rc::Id,
+    AppKit::{
+        NSAccessibilityAnnouncementKey, NSAccessibilityAnnouncementRequestedNotification,
+        NSAccessibilityPostNotificationWithUserInfo, NSAccessibilityPriorityHigh,
+        NSAccessibilityPriorityKey, NSApplication,
+    },
+    Foundation::{MainThreadMarker, NSDictionary, NSNumber, NSString},
+};
+
+use super::run_loop;
+
+/// Asks the screen reader, if one is running, to read `message` out loud.
+///
+/// This can be called from any thread; the announcement is posted from the
+/// main thread, where AppKit expects it.
+pub fn announce(message: String) {
+    run_loop::run_on_main_thread(move || {
+        let mtm = MainThreadMarker::new().unwrap();
+        let app = NSApplication::sharedApplication(mtm);
+        let message = NSString::from_str(&message);
+        let priority = NSNumber::new_isize(NSAccessibilityPriorityHigh);
+        unsafe {
+            let info = NSDictionary::from_vec(
+                &[NSAccessibilityAnnouncementKey, NSAccessibilityPriorityKey],
+                vec![
+                    Id::into_super(Id::into_super(message)),
+                    Id::into_super(Id::into_super(Id::into_super(priority))),
+                ],
+            );
+            NSAccessibilityPostNotificationWithUserInfo(
+                &app,
+                NSAccessibilityAnnouncementRequestedNotification,
+                Some(&info),
+            );
+        }
+    });
+}
@@ -1,13 +1,26 @@
+use std::{path::Path, thread};
+
 use accessibility::{AXUIElement, AXUIElementAttributes};
-use accessibility_sys::{kAXStandardWindowSubrole, kAXWindowRole};
+use accessibility_sys::{
+    kAXErrorSuccess, kAXMinimizedAttribute, kAXSizeAttribute, kAXStandardWindowSubrole,
+    kAXWindowRole, AXUIElementCopyAttributeValue, AXUIElementIsAttributeSettable,
+};
+use core_foundation::{
+    base::{Boolean, CFType, CFTypeRef, TCFType},
+    boolean::CFBoolean,
+    string::CFString,
+};
 use icrate::{
-    objc2::{msg_send, rc::Id},
+    ns_string,
+    objc2::{class, msg_send, msg_send_id, rc::Id, ClassType},
     AppKit::{NSRunningApplication, NSWorkspace},
-    Foundation::{CGRect, NSString},
+    Foundation::{CGRect, NSBundle, NSString, NSURL},
 };
+use tracing::warn;
 
 use super::geometry::ToICrate;
-use super::window_server::WindowServerId;
+use super::process;
+use super::window_server::{self, WindowServerId};
 
 pub use accessibility_sys::pid_t;
 
@@ -15,16 +28,32 @@ pub fn running_apps(bundle: Option<String>) -> impl Iterator<Item = (pid_t, AppI
     unsafe { NSWorkspace::sharedWorkspace().runningApplications() }
         .into_iter()
         .flat_map(move |app| {
-            let bundle_id = app.bundle_id()?.to_string();
+            let bundle_id = app.bundle_id().map(|id| id.to_string());
             if let Some(filter) = &bundle {
-                if !bundle_id.contains(filter) {
+                if !bundle_id.is_some_and(|id| id.contains(filter)) {
                     return None;
                 }
             }
-            Some((app.pid(), AppInfo::from(&*app)))
+            let info = AppInfo::from(&*app);
+            // Processes without a bundle are only tracked as part of an app.
+            if info.bundle_id.is_none() && info.group.is_none() {
+                return None;
+            }
+            Some((app.pid(), info))
         })
 }
 
+/// Opens the app at `bundle_path` the way the Finder does, launching it if it
+/// is not running. Apps without windows usually open one when opened again.
+pub fn open_app(bundle_path: &str) {
+    let bundle_path = bundle_path.to_owned();
+    thread::spawn(move || {
+        if let Err(e) = std::process::Command::new("open").arg(&bundle_path).status() {
+            warn!("Could not open {bundle_path}: {e}");
+        }
+    });
+}
+
 pub trait NSRunningApplicationExt {
     fn pid(&self) -> pid_t;
     fn bundle_id(&self) -> Option<Id<NSString>>;
@@ -48,34 +77,229 @@ impl NSRunningApplicationExt for NSRunningApplication {
 pub struct AppInfo {
     pub bundle_id: Option<String>,
     pub localized_name: Option<String>,
+    /// The path of the app bundle, or of the executable for apps without one.
+    pub bundle_path: Option<String>,
+    /// The path of the app's `.icns` icon file, if it has one outside of an
+    /// asset catalog.
+    pub icon_path: Option<String>,
+    /// For helper processes that show windows on behalf of another app, the
+    /// bundle id of that app. Rules and queries treat the helper as part of
+    /// it.
+    pub group: Option<String>,
+}
+
+impl AppInfo {
+    /// The bundle id that rules should match: that of the app's group, if it
+    /// has one.
+    pub fn app_id(&self) -> Option<&str> {
+        self.group.as_deref().or(self.bundle_id.as_deref())
+    }
 }
 
 impl From<&NSRunningApplication> for AppInfo {
     fn from(app: &NSRunningApplication) -> Self {
+        let bundle_url = unsafe { app.bundleURL() };
+        let bundle = bundle_url.as_deref().and_then(|url| unsafe { NSBundle::bundleWithURL(url) });
         AppInfo {
             bundle_id: app.bundle_id().as_deref().map(ToString::to_string),
             localized_name: app.localized_name().as_deref().map(ToString::to_string),
+            bundle_path: bundle_url
+                .as_deref()
+                .and_then(|url| unsafe { url.path() })
+                .map(|path| path.to_string()),
+            icon_path: bundle.as_deref().and_then(icon_path),
+            group: activation_group(app),
         }
     }
 }
 
+/// Returns the bundle id of the app that a helper process belongs to.
+///
+/// Chrome and Edge run web apps as shims with bundle ids like
+/// `com.google.Chrome.app.<id>`, and Java apps often show their windows from
+/// a process without a bundle that the app's launcher started. Only processes
+/// whose executable is inside the bundle of the app that started them count,
+/// so that programs started from a terminal aren't treated as part of it.
+fn activation_group(app: &NSRunningApplication) -> Option<String> {
+    if let Some(bundle_id) = app.bundle_id() {
+        let parent = shim_parent(&bundle_id.to_string())?.to_string();
+        let running = unsafe {
+            NSRunningApplication::runningApplicationsWithBundleIdentifier(&NSString::from_str(
+                &parent,
+            ))
+        };
+        return (!running.is_empty()).then_some(parent);
+    }
+    let parent_pid = process::parent_pid(app.pid())?;
+    let parent: Option<Id<NSRunningApplication>> = unsafe {
+        msg_send_id![class!(NSRunningApplication), runningApplicationWithProcessIdentifier: parent_pid]
+    };
+    let parent = parent?;
+    let executable = url_path(unsafe { app.executableURL() })?;
+    let parent_bundle = url_path(unsafe { parent.bundleURL() })?;
+    if !is_in_bundle(&executable, &parent_bundle) {
+        return None;
+    }
+    parent.bundle_id().map(|id| id.to_string())
+}
+
+fn url_path(url: Option<Id<NSURL>>) -> Option<String> {
+    unsafe { url?.path() }.map(|path| path.to_string())
+}
+
+/// Returns whether the file at `path` is inside the bundle at `bundle_path`.
+fn is_in_bundle(path: &str, bundle_path: &str) -> bool {
+    Path::new(path).starts_with(bundle_path)
+}
+
+/// Returns the bundle id of the browser that a web app shim's bundle id is
+/// derived from.
+fn shim_parent(bundle_id: &str) -> Option<&str> {
+    bundle_id.split_once(".app.").map(|(parent, _)| parent)
+}
+
+/// Finds the icon named by `CFBundleIconFile` in the bundle's Info.plist.
+///
+/// Apps whose icon is only in `Assets.car` have no file we could point to.
+fn icon_path(bundle: &NSBundle) -> Option<String> {
+    let name = unsafe { bundle.objectForInfoDictionaryKey(ns_string!("CFBundleIconFile")) }?;
+    let is_string: bool = unsafe { msg_send![&*name, isKindOfClass: NSString::class()] };
+    if !is_string {
+        return None;
+    }
+    let name: Id<NSString> = unsafe { Id::cast(name) };
+    // The extension is optional in the plist.
+    let name = name.to_string();
+    let name = NSString::from_str(name.strip_suffix(".icns").unwrap_or(&name));
+    let path = unsafe { bundle.pathForResource_ofType(Some(&name), Some(ns_string!("icns"))) }?;
+    Some(path.to_string())
+}
+
 #[derive(Debug)]
 pub struct WindowInfo {
     pub is_standard: bool,
     pub title: String,
     pub frame: CGRect,
     pub sys_id: WindowServerId,
+    /// The accessibility subrole, e.g. `AXStandardWindow` or `AXDialog`.
+    pub subrole: String,
+    /// The window server level; 0 for normal windows.
+    pub level: i32,
+    pub is_resizable: bool,
+    pub is_minimized: bool,
+    pub is_fullscreen: bool,
+    /// For sheets and some dialogs, the window they belong to.
+    pub parent: Option<WindowServerId>,
+    /// Whether this is a Picture-in-Picture video window.
+    pub is_pip: bool,
+}
+
+impl WindowInfo {
+    /// Whether the window belongs in the layout.
+    pub fn is_tileable(&self) -> bool {
+        self.is_standard && !self.is_pip
+    }
 }
 
 impl TryFrom<&AXUIElement> for WindowInfo {
     type Error = accessibility::Error;
     fn try_from(element: &AXUIElement) -> Result<Self, accessibility::Error> {
+        let subrole = if element.role()? == kAXWindowRole {
+            element.subrole()?.to_string()
+        } else {
+            String::new()
+        };
+        let sys_id = WindowServerId::try_from(element)?;
+        let title = element.title()?.to_string();
         Ok(WindowInfo {
-            is_standard: element.role()? == kAXWindowRole
-                && element.subrole()? == kAXStandardWindowSubrole,
-            title: element.title()?.to_string(),
+            is_standard: subrole == kAXStandardWindowSubrole,
+            is_pip: PIP_TITLES.contains(&title.as_str()),
+            title,
             frame: element.frame()?.to_icrate(),
-            sys_id: WindowServerId::try_from(element)?,
+            sys_id,
+            subrole,
+            level: window_server::window_level(sys_id).unwrap_or(0),
+            is_resizable: is_settable(element, kAXSizeAttribute),
+            is_minimized: bool_attribute(element, kAXMinimizedAttribute).unwrap_or(false),
+            is_fullscreen: bool_attribute(element, "AXFullScreen").unwrap_or(false),
+            parent: parent_window(element),
         })
     }
 }
+
+/// Returns the window that `element` belongs to, if its accessibility parent
+/// is a window rather than the app.
+fn parent_window(element: &AXUIElement) -> Option<WindowServerId> {
+    let parent = element.parent().ok()?;
+    if parent.role().ok()? != kAXWindowRole {
+        return None;
+    }
+    WindowServerId::try_from(&parent).ok()
+}
+
+/// The titles browsers give their Picture-in-Picture windows. Some of these
+/// are standard windows at the normal level, so the title is the only
+/// reliable way to tell them apart.
+const PIP_TITLES: &[&str] = &[
+    "Picture-in-Picture",
+    "Picture in Picture",
+    "Picture in picture",
+];
+
+fn bool_attribute(element: &AXUIElement, attribute: &str) -> Option<bool> {
+    let attribute = CFString::new(attribute);
+    let mut value: CFTypeRef = std::ptr::null();
+    let err = unsafe {
+        AXUIElementCopyAttributeValue(
+            element.as_concrete_TypeRef(),
+            attribute.as_concrete_TypeRef(),
+            &mut value,
+        )
+    };
+    if err != kAXErrorSuccess || value.is_null() {
+        return None;
+    }
+    let value = unsafe { CFType::wrap_under_create_rule(value) };
+    value.downcast::<CFBoolean>().map(Into::into)
+}
+
+fn is_settable(element: &AXUIElement, attribute: &str) -> bool {
+    let attribute = CFString::new(attribute);
+    let mut settable: Boolean = 0;
+    let err = unsafe {
+        AXUIElementIsAttributeSettable(
+            element.as_concrete_TypeRef(),
+            attribute.as_concrete_TypeRef(),
+            &mut settable,
+        )
+    };
+    err == kAXErrorSuccess && settable != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_in_bundle, shim_parent};
+
+    #[test]
+    fn it_finds_the_browser_of_web_app_shims() {
+        assert_eq!(
+            Some("com.google.Chrome"),
+            shim_parent("com.google.Chrome.app.kjgfgldnnfoeklkmfkjfagphfepbbdan")
+        );
+        assert_eq!(None, shim_parent("com.apple.Safari"));
+    }
+
+    #[test]
+    fn it_only_groups_executables_inside_the_parent_bundle() {
+        let bundle = "/Applications/IntelliJ IDEA.app";
+        assert!(is_in_bundle(
+            "/Applications/IntelliJ IDEA.app/Contents/jbr/Contents/Home/bin/java",
+            bundle
+        ));
+        assert!(!is_in_bundle("/usr/local/bin/java", bundle));
+        assert!(!is_in_bundle(
+            "/Applications/IntelliJ IDEA.app.old/Contents/MacOS/idea",
+            bundle
+        ));
+    }
+}
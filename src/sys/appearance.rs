@@ -0,0 +1,23 @@
+//! The system-wide light or dark appearance.
+
+use icrate::{
+    AppKit::{NSAppearanceNameAqua, NSAppearanceNameDarkAqua, NSApplication},
+    Foundation::{MainThreadMarker, NSArray},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Appearance {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// Returns the appearance the system is currently using.
+pub fn current_appearance(mtm: MainThreadMarker) -> Appearance {
+    let app = NSApplication::sharedApplication(mtm);
+    let names = unsafe { NSArray::from_slice(&[NSAppearanceNameAqua, NSAppearanceNameDarkAqua]) };
+    match app.effectiveAppearance().bestMatchFromAppearancesWithNames(&names) {
+        Some(name) if unsafe { NSAppearanceNameDarkAqua } == &*name => Appearance::Dark,
+        _ => Appearance::Light,
+    }
+}
@@ -0,0 +1,159 @@
+//! Access to the current time, abstracted so tests can control it.
+
+use std::{
+    fmt::Debug,
+    mem, ptr, thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    /// Sleeps until `deadline`, or returns immediately if it has passed.
+    fn sleep_until(&self, deadline: Instant) {
+        let now = self.now();
+        if deadline > now {
+            self.sleep(deadline - now);
+        }
+    }
+}
+
+/// The real clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A day of the week.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+
+    /// Converts from `tm_wday`, which starts at 0 for Sunday.
+    fn from_tm(wday: i32) -> Weekday {
+        Self::ALL[(wday + 6).rem_euclid(7) as usize]
+    }
+
+    pub fn prev(self) -> Weekday {
+        Self::ALL[(self as usize + 6) % 7]
+    }
+}
+
+/// A time of day to the minute, written like `"09:30"` in config files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    pub fn new(hour: u16, minute: u16) -> Option<TimeOfDay> {
+        (hour < 24 && minute < 60).then_some(TimeOfDay(hour * 60 + minute))
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parsed = value
+            .split_once(':')
+            .and_then(|(hour, minute)| TimeOfDay::new(hour.parse().ok()?, minute.parse().ok()?));
+        parsed.ok_or_else(|| format!("{value:?} is not a time like \"09:30\""))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> String {
+        format!("{:02}:{:02}", time.0 / 60, time.0 % 60)
+    }
+}
+
+/// The day and time in the user's time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub weekday: Weekday,
+    pub time: TimeOfDay,
+}
+
+/// Returns the current day and time in the user's time zone.
+pub fn local_time() -> LocalTime {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe {
+        let now = libc::time(ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    LocalTime {
+        weekday: Weekday::from_tm(tm.tm_wday),
+        time: TimeOfDay(tm.tm_hour as u16 * 60 + tm.tm_min as u16),
+    }
+}
+
+#[cfg(any(test, feature = "bench"))]
+pub use virtual_clock::VirtualClock;
+
+#[cfg(any(test, feature = "bench"))]
+mod virtual_clock {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A clock that only moves forward when told to. Sleeping advances the
+    /// clock by the requested duration and returns immediately.
+    ///
+    /// Clones share the same time.
+    #[derive(Debug, Clone)]
+    pub struct VirtualClock {
+        now: Arc<Mutex<Instant>>,
+    }
+
+    impl VirtualClock {
+        pub fn new() -> VirtualClock {
+            VirtualClock {
+                now: Arc::new(Mutex::new(Instant::now())),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for VirtualClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration);
+        }
+    }
+}
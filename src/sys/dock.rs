@@ -0,0 +1,131 @@
+//! Reading the state of the Dock.
+//!
+//! There is no public API for an app requesting the user's attention. When
+//! one does, its Dock icon bounces, and the accessibility frame of the icon
+//! moves with it, so we look for icons lifted out of line with the others.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use accessibility::{AXUIElement, AXUIElementAttributes};
+
+use super::{
+    app::{pid_t, running_apps},
+    geometry::ToICrate,
+    idle::{self, IdleMonitor},
+};
+
+const DOCK_BUNDLE_ID: &str = "com.apple.dock";
+
+/// How far an icon has to be out of line with the rest to count as bouncing,
+/// in points. Icons the pointer is over are magnified, which moves them less
+/// than this.
+const BOUNCE_HEIGHT: f64 = 8.0;
+
+/// Returns the running apps whose Dock icons are bouncing.
+pub fn bouncing_apps() -> HashSet<pid_t> {
+    let Some((dock_pid, _)) = running_apps(Some(DOCK_BUNDLE_ID.to_string()))
+        .find(|(_, info)| info.bundle_id.as_deref() == Some(DOCK_BUNDLE_ID))
+    else {
+        return HashSet::new();
+    };
+    let dock = AXUIElement::application(dock_pid);
+    let Ok(lists) = dock.children() else {
+        return HashSet::new();
+    };
+    let mut titles = Vec::new();
+    for list in lists.iter() {
+        let Ok(list_frame) = list.frame() else { continue };
+        let Ok(items) = list.children() else { continue };
+        // Icons bounce away from the edge of the screen the Dock is on.
+        let horizontal = list_frame.size.width >= list_frame.size.height;
+        let mut icons = Vec::new();
+        for item in items.iter() {
+            if !item.subrole().is_ok_and(|subrole| subrole == "AXApplicationDockItem") {
+                continue;
+            }
+            let (Ok(title), Ok(frame)) = (item.title(), item.frame()) else {
+                continue;
+            };
+            let frame = frame.to_icrate();
+            let offset = if horizontal {
+                frame.origin.y
+            } else {
+                frame.origin.x
+            };
+            icons.push((title.to_string(), offset));
+        }
+        let offsets: Vec<f64> = icons.iter().map(|&(_, offset)| offset).collect();
+        for idx in lifted(&offsets) {
+            titles.push(icons[idx].0.clone());
+        }
+    }
+    if titles.is_empty() {
+        return HashSet::new();
+    }
+    // Dock items are named after the app, so we match them up by name.
+    let pids: HashMap<String, pid_t> = running_apps(None)
+        .filter_map(|(pid, info)| Some((info.localized_name?, pid)))
+        .collect();
+    titles.iter().filter_map(|title| pids.get(title).copied()).collect()
+}
+
+/// Returns the indexes of the icons whose offsets are out of line with the
+/// offset most icons share.
+fn lifted(offsets: &[f64]) -> Vec<usize> {
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for offset in offsets {
+        *counts.entry(offset.round() as i64).or_default() += 1;
+    }
+    // With fewer than two icons in line there is nothing to compare with.
+    let Some((&rest, _)) =
+        counts.iter().filter(|&(_, &count)| count > 1).max_by_key(|&(_, count)| count)
+    else {
+        return Vec::new();
+    };
+    let rest = rest as f64;
+    (0..offsets.len())
+        .filter(|&idx| (offsets[idx] - rest).abs() > BOUNCE_HEIGHT)
+        .collect()
+}
+
+/// Calls `on_attention` on a background thread with each app whose Dock icon
+/// starts bouncing, which is how apps ask for attention.
+///
+/// The icons are checked every `interval`. Apps asking for attention once
+/// only bounce for about a second, so the interval should be shorter than
+/// that.
+pub fn watch_for_attention(
+    interval: Duration,
+    idle: IdleMonitor,
+    on_attention: impl Fn(pid_t) + Send + 'static,
+) {
+    let mut bouncing = HashSet::new();
+    idle::watch_changes(
+        interval,
+        idle,
+        Some(HashSet::new()),
+        bouncing_apps,
+        move |new_bouncing| {
+            for pid in new_bouncing.difference(&bouncing) {
+                on_attention(*pid);
+            }
+            bouncing = new_bouncing.clone();
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lifted;
+
+    #[test]
+    fn it_finds_icons_out_of_line() {
+        assert_eq!(vec![2], lifted(&[1000.0, 1000.2, 960.0, 999.8]));
+        // Magnified icons are only nudged.
+        assert!(lifted(&[1000.0, 996.0, 1000.0]).is_empty());
+        assert!(lifted(&[1000.0]).is_empty());
+    }
+}
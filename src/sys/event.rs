@@ -0,0 +1,361 @@
+//! Helpers for reading the state of input devices.
+
+use std::cell::Cell;
+
+use core_foundation::{
+    base::TCFType,
+    mach_port::CFMachPortInvalidate,
+    runloop::{kCFRunLoopCommonModes, CFRunLoop, CFRunLoopSource},
+};
+use core_graphics::{
+    event::{
+        CGEvent, CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions,
+        CGEventTapPlacement, CGEventType, EventField,
+    },
+    event_source::{CGEventSource, CGEventSourceStateID},
+};
+use icrate::Foundation::CGPoint;
+use serde::{Deserialize, Serialize};
+
+use super::geometry::ToICrate;
+
+/// Returns the current position of the mouse cursor, in CG (top-left origin)
+/// coordinates.
+pub fn cursor_position() -> Option<CGPoint> {
+    let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState).ok()?;
+    let event = CGEvent::new(source).ok()?;
+    Some(event.location().to_icrate())
+}
+
+/// Returns the modifier keys that are held down right now.
+pub fn held_modifiers() -> CGEventFlags {
+    let flags = unsafe { CGEventSourceFlagsState(CGEventSourceStateID::CombinedSessionState) };
+    CGEventFlags::from_bits_truncate(flags)
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventSourceFlagsState(state: CGEventSourceStateID) -> u64;
+}
+
+/// Calls a function whenever a key is pressed, until dropped.
+///
+/// The tap only listens to events; it does not stop them from reaching their
+/// destination.
+pub struct KeyPressTap(Tap);
+
+impl KeyPressTap {
+    /// Starts watching for key presses on the current thread's run loop.
+    /// Repeats from holding a key down are ignored.
+    ///
+    /// Returns `None` if the tap could not be created, which usually means we
+    /// lack accessibility permissions.
+    pub fn new(on_press: impl Fn() + 'static) -> Option<KeyPressTap> {
+        let tap = Tap::listen(vec![CGEventType::KeyDown], move |_type, event| {
+            if event.get_integer_value_field(EventField::KEYBOARD_EVENT_AUTOREPEAT) == 0 {
+                on_press();
+            }
+        })?;
+        Some(KeyPressTap(tap))
+    }
+}
+
+/// Calls a function whenever a key is released or a modifier key is pressed
+/// or released, until dropped.
+///
+/// Like [`KeyPressTap`], this only listens to events.
+pub struct KeyReleaseTap(Tap);
+
+impl KeyReleaseTap {
+    /// Starts watching for key releases on the current thread's run loop.
+    ///
+    /// Returns `None` if the tap could not be created.
+    pub fn new(on_release: impl Fn() + 'static) -> Option<KeyReleaseTap> {
+        let events = vec![CGEventType::KeyUp, CGEventType::FlagsChanged];
+        let tap = Tap::listen(events, move |_type, _event| on_release())?;
+        Some(KeyReleaseTap(tap))
+    }
+}
+
+/// Calls a function with the modifier keys that are held whenever one is
+/// pressed or released, until dropped.
+///
+/// Like [`KeyPressTap`], this only listens to events.
+pub struct ModifiersTap(Tap);
+
+impl ModifiersTap {
+    /// Starts watching the modifier keys on the current thread's run loop.
+    ///
+    /// Returns `None` if the tap could not be created.
+    pub fn new(on_change: impl Fn(CGEventFlags) + 'static) -> Option<ModifiersTap> {
+        let events = vec![CGEventType::FlagsChanged];
+        let tap = Tap::listen(events, move |_type, event| on_change(event.get_flags()))?;
+        Some(ModifiersTap(tap))
+    }
+}
+
+/// Calls a function whenever the user presses a key, clicks, scrolls, or moves
+/// the mouse, until dropped.
+///
+/// Like [`KeyPressTap`], this only listens to events.
+pub struct InputTap(Tap);
+
+impl InputTap {
+    /// Starts watching for input on the current thread's run loop. The
+    /// callback runs for every event, so it should be cheap.
+    ///
+    /// Returns `None` if the tap could not be created.
+    pub fn new(on_input: impl Fn() + 'static) -> Option<InputTap> {
+        let events = vec![
+            CGEventType::KeyDown,
+            CGEventType::FlagsChanged,
+            CGEventType::MouseMoved,
+            CGEventType::LeftMouseDown,
+            CGEventType::RightMouseDown,
+            CGEventType::OtherMouseDown,
+            CGEventType::ScrollWheel,
+        ];
+        let tap = Tap::listen(events, move |_type, _event| on_input())?;
+        Some(InputTap(tap))
+    }
+}
+
+/// A movement of the mouse, or a press, drag, or release of the left mouse
+/// button, at a position in CG (top-left origin) coordinates.
+#[derive(Debug, Clone, Copy)]
+pub enum MouseEvent {
+    /// The mouse moved with no buttons pressed.
+    Moved(CGPoint),
+    Down(CGPoint),
+    Dragged(CGPoint),
+    Up(CGPoint),
+}
+
+/// Calls a function whenever the mouse moves or the left mouse button is
+/// pressed, dragged, or released, until dropped.
+///
+/// Like [`KeyPressTap`], this only listens to events.
+pub struct MouseTap(Tap);
+
+impl MouseTap {
+    /// Starts watching the mouse on the current thread's run loop.
+    ///
+    /// Returns `None` if the tap could not be created.
+    pub fn new(on_event: impl Fn(MouseEvent) + 'static) -> Option<MouseTap> {
+        let events = vec![
+            CGEventType::MouseMoved,
+            CGEventType::LeftMouseDown,
+            CGEventType::LeftMouseDragged,
+            CGEventType::LeftMouseUp,
+        ];
+        let tap = Tap::listen(events, move |ty, event| {
+            let position = event.location().to_icrate();
+            match ty {
+                CGEventType::MouseMoved => on_event(MouseEvent::Moved(position)),
+                CGEventType::LeftMouseDown => on_event(MouseEvent::Down(position)),
+                CGEventType::LeftMouseDragged => on_event(MouseEvent::Dragged(position)),
+                CGEventType::LeftMouseUp => on_event(MouseEvent::Up(position)),
+                _ => (),
+            }
+        })?;
+        Some(MouseTap(tap))
+    }
+}
+
+/// A modifier key, as named in the config file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifierKey {
+    Shift,
+    Ctrl,
+    Alt,
+    Cmd,
+}
+
+impl ModifierKey {
+    fn flag(self) -> CGEventFlags {
+        match self {
+            ModifierKey::Shift => CGEventFlags::CGEventFlagShift,
+            ModifierKey::Ctrl => CGEventFlags::CGEventFlagControl,
+            ModifierKey::Alt => CGEventFlags::CGEventFlagAlternate,
+            ModifierKey::Cmd => CGEventFlags::CGEventFlagCommand,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+}
+
+/// A drag with the left or right mouse button while holding the modifier keys
+/// given to [`DragTap`], at a position in CG (top-left origin) coordinates.
+#[derive(Debug, Clone, Copy)]
+pub enum DragEvent {
+    Started(MouseButton, CGPoint),
+    Moved(CGPoint),
+    Ended(CGPoint),
+}
+
+/// Calls a function whenever the user drags with the left or right mouse
+/// button while holding a set of modifier keys, until dropped.
+///
+/// Unlike the other taps, this one keeps the drags it reports from reaching
+/// the app under the cursor, which would otherwise take them as clicks.
+pub struct DragTap(Tap);
+
+impl DragTap {
+    /// Starts watching for drags on the current thread's run loop. Drags only
+    /// count if exactly `modifiers` are held when they start, and `can_drag`
+    /// returns true for where they start. Other clicks reach the app as usual.
+    ///
+    /// Returns `None` if the tap could not be created.
+    pub fn new(
+        modifiers: &[ModifierKey],
+        can_drag: impl Fn(CGPoint) -> bool + 'static,
+        on_drag: impl Fn(DragEvent) + 'static,
+    ) -> Option<DragTap> {
+        let all = [
+            ModifierKey::Shift,
+            ModifierKey::Ctrl,
+            ModifierKey::Alt,
+            ModifierKey::Cmd,
+        ]
+        .into_iter()
+        .fold(CGEventFlags::empty(), |flags, key| flags | key.flag());
+        let wanted = modifiers.iter().fold(CGEventFlags::empty(), |flags, key| flags | key.flag());
+        let events = vec![
+            CGEventType::LeftMouseDown,
+            CGEventType::LeftMouseDragged,
+            CGEventType::LeftMouseUp,
+            CGEventType::RightMouseDown,
+            CGEventType::RightMouseDragged,
+            CGEventType::RightMouseUp,
+        ];
+        // The button of the drag in progress. Its other events are ours too,
+        // even if the modifiers are released before the button.
+        let dragging = Cell::new(None);
+        let tap = Tap::filter(events, move |ty, event| {
+            let position = event.location().to_icrate();
+            let (button, drag_event) = match ty {
+                CGEventType::LeftMouseDown | CGEventType::RightMouseDown => {
+                    if dragging.get().is_some()
+                        || event.get_flags() & all != wanted
+                        || !can_drag(position)
+                    {
+                        return false;
+                    }
+                    let button = match ty {
+                        CGEventType::LeftMouseDown => MouseButton::Left,
+                        _ => MouseButton::Right,
+                    };
+                    dragging.set(Some(button));
+                    (button, DragEvent::Started(button, position))
+                }
+                CGEventType::LeftMouseDragged => (MouseButton::Left, DragEvent::Moved(position)),
+                CGEventType::RightMouseDragged => (MouseButton::Right, DragEvent::Moved(position)),
+                CGEventType::LeftMouseUp => (MouseButton::Left, DragEvent::Ended(position)),
+                CGEventType::RightMouseUp => (MouseButton::Right, DragEvent::Ended(position)),
+                _ => return false,
+            };
+            if dragging.get() != Some(button) {
+                return false;
+            }
+            if let DragEvent::Ended(_) = drag_event {
+                dragging.set(None);
+            }
+            on_drag(drag_event);
+            true
+        })?;
+        Some(DragTap(tap))
+    }
+}
+
+/// Calls a function whenever the user double-clicks with the left mouse
+/// button, until dropped.
+///
+/// The function gets where the second click was, in CG (top-left origin)
+/// coordinates, and returns whether it handled the double-click. If it did,
+/// the click is kept from reaching the app under the cursor.
+pub struct DoubleClickTap(Tap);
+
+impl DoubleClickTap {
+    /// Starts watching for double-clicks on the current thread's run loop.
+    ///
+    /// Returns `None` if the tap could not be created.
+    pub fn new(on_double_click: impl Fn(CGPoint) -> bool + 'static) -> Option<DoubleClickTap> {
+        let events = vec![CGEventType::LeftMouseDown, CGEventType::LeftMouseUp];
+        // Whether we kept the last press from the app, so its release must
+        // be kept from it too.
+        let handled = Cell::new(false);
+        let tap = Tap::filter(events, move |ty, event| match ty {
+            CGEventType::LeftMouseDown => {
+                let clicks = event.get_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE);
+                handled.set(clicks == 2 && on_double_click(event.location().to_icrate()));
+                handled.get()
+            }
+            _ => handled.replace(false),
+        })?;
+        Some(DoubleClickTap(tap))
+    }
+}
+
+/// An event tap on the current thread's run loop.
+struct Tap {
+    tap: CGEventTap<'static>,
+    source: CFRunLoopSource,
+}
+
+impl Tap {
+    /// Creates a tap that only listens to events.
+    fn listen(
+        events: Vec<CGEventType>,
+        callback: impl Fn(CGEventType, &CGEvent) + 'static,
+    ) -> Option<Tap> {
+        Tap::new(CGEventTapOptions::ListenOnly, events, move |ty, event| {
+            callback(ty, event);
+            false
+        })
+    }
+
+    /// Creates a tap that keeps the events `callback` returns true for from
+    /// reaching their destination.
+    fn filter(
+        events: Vec<CGEventType>,
+        callback: impl Fn(CGEventType, &CGEvent) -> bool + 'static,
+    ) -> Option<Tap> {
+        Tap::new(CGEventTapOptions::Default, events, callback)
+    }
+
+    fn new(
+        options: CGEventTapOptions,
+        events: Vec<CGEventType>,
+        callback: impl Fn(CGEventType, &CGEvent) -> bool + 'static,
+    ) -> Option<Tap> {
+        let tap = CGEventTap::new(
+            CGEventTapLocation::Session,
+            CGEventTapPlacement::HeadInsertEventTap,
+            options,
+            events,
+            move |_proxy, ty, event| {
+                if callback(ty, event) {
+                    // The window server drops events whose type is null.
+                    event.set_type(CGEventType::Null);
+                }
+                None
+            },
+        )
+        .ok()?;
+        let source = tap.mach_port.create_runloop_source(0).ok()?;
+        CFRunLoop::get_current().add_source(&source, unsafe { kCFRunLoopCommonModes });
+        tap.enable();
+        Some(Tap { tap, source })
+    }
+}
+
+impl Drop for Tap {
+    fn drop(&mut self) {
+        unsafe { CFMachPortInvalidate(self.tap.mach_port.as_concrete_TypeRef()) };
+        CFRunLoop::get_current().remove_source(&self.source, unsafe { kCFRunLoopCommonModes });
+    }
+}
@@ -0,0 +1,70 @@
+//! Reading whether a Focus, like Do Not Disturb, is on.
+//!
+//! This uses `INFocusStatusCenter` from the Intents framework, which only
+//! answers once the user has allowed us to see their Focus status. We ask the
+//! first time we look; until the user allows it, a Focus always looks off.
+
+use std::{sync::Once, time::Duration};
+
+use icrate::{
+    block2::Block,
+    objc2::{
+        msg_send, msg_send_id,
+        rc::Id,
+        runtime::{AnyClass, AnyObject},
+    },
+    Foundation::NSNumber,
+};
+use tracing::debug;
+
+use super::idle::{self, IdleMonitor};
+
+/// Values of `INFocusStatusAuthorizationStatus`.
+const AUTHORIZATION_NOT_DETERMINED: isize = 0;
+const AUTHORIZATION_AUTHORIZED: isize = 3;
+
+/// Returns whether a Focus is on.
+pub fn is_focus_active() -> bool {
+    // The class is only there on macOS 12 and later.
+    let Some(class) = AnyClass::get("INFocusStatusCenter") else {
+        return false;
+    };
+    let center: Id<AnyObject> = unsafe { msg_send_id![class, defaultCenter] };
+    let authorization: isize = unsafe { msg_send![&center, authorizationStatus] };
+    match authorization {
+        AUTHORIZATION_AUTHORIZED => (),
+        AUTHORIZATION_NOT_DETERMINED => {
+            // Only ask once, even if the user leaves the prompt up.
+            static REQUEST: Once = Once::new();
+            REQUEST.call_once(|| {
+                debug!("Asking for access to the Focus status");
+                let handler: Option<&Block<(isize,), ()>> = None;
+                let _: () = unsafe {
+                    msg_send![&center, requestAuthorizationWithCompletionHandler: handler]
+                };
+            });
+            return false;
+        }
+        _ => return false,
+    }
+    let status: Option<Id<AnyObject>> = unsafe { msg_send_id![&center, focusStatus] };
+    let Some(status) = status else { return false };
+    // Nil when the status is unknown.
+    let is_focused: Option<Id<NSNumber>> = unsafe { msg_send_id![&status, isFocused] };
+    is_focused.is_some_and(|is_focused| is_focused.boolValue())
+}
+
+/// Calls `on_change` on a background thread with whether a Focus is on, and
+/// again whenever that changes, checking every `interval`.
+pub fn watch_focus(
+    interval: Duration,
+    idle: IdleMonitor,
+    on_change: impl Fn(bool) + Send + 'static,
+) {
+    idle::watch_changes(interval, idle, None, is_focus_active, move |&active| {
+        on_change(active)
+    });
+}
+
+#[link(name = "Intents", kind = "framework")]
+extern "C" {}
@@ -1,5 +1,6 @@
 use core_graphics_types::geometry as cg;
-use icrate::Foundation as ic;
+use icrate::{AppKit::NSScreen, Foundation as ic};
+use serde::{Deserialize, Serialize};
 
 pub trait ToICrate<T> {
     fn to_icrate(&self) -> T;
@@ -131,3 +132,335 @@ pub trait SameAs: IsWithin + Sized {
 impl SameAs for ic::CGRect {}
 impl SameAs for ic::CGPoint {}
 impl SameAs for ic::CGSize {}
+
+pub trait Inset {
+    /// Shrinks the rectangle by `amount` on each side, keeping its center.
+    fn inset(&self, amount: f64) -> Self;
+}
+
+impl Inset for ic::CGRect {
+    fn inset(&self, amount: f64) -> Self {
+        let amount = amount.min(self.size.width / 2.0).min(self.size.height / 2.0);
+        ic::CGRect::new(
+            ic::CGPoint::new(self.origin.x + amount, self.origin.y + amount),
+            ic::CGSize::new(
+                self.size.width - 2.0 * amount,
+                self.size.height - 2.0 * amount,
+            ),
+        )
+    }
+}
+
+pub trait Rescale {
+    /// Moves the rectangle from the area `from` to the area `to`, keeping its
+    /// center over the same part of the area. Its size scales with the area,
+    /// but by the same factor in both directions so that it keeps its shape,
+    /// which means it still fits in `to` if it fit in `from`.
+    fn rescale(&self, from: Self, to: Self) -> Self;
+}
+
+impl Rescale for ic::CGRect {
+    fn rescale(&self, from: Self, to: Self) -> Self {
+        let scale_x = to.size.width / from.size.width;
+        let scale_y = to.size.height / from.size.height;
+        let scale = scale_x.min(scale_y);
+        let size = ic::CGSize::new(self.size.width * scale, self.size.height * scale);
+        let mid = self.mid();
+        let mid = ic::CGPoint::new(
+            to.origin.x + (mid.x - from.origin.x) * scale_x,
+            to.origin.y + (mid.y - from.origin.y) * scale_y,
+        );
+        ic::CGRect::new(
+            ic::CGPoint::new(mid.x - size.width / 2.0, mid.y - size.height / 2.0),
+            size,
+        )
+    }
+}
+
+pub trait Contains<T> {
+    fn contains(&self, other: T) -> bool;
+}
+
+impl Contains<ic::CGPoint> for ic::CGRect {
+    fn contains(&self, point: ic::CGPoint) -> bool {
+        let (min, max) = (self.min(), self.max());
+        min.x <= point.x && point.x < max.x && min.y <= point.y && point.y < max.y
+    }
+}
+
+impl Contains<ic::CGRect> for ic::CGRect {
+    fn contains(&self, other: ic::CGRect) -> bool {
+        let (min, max) = (self.min(), self.max());
+        let (other_min, other_max) = (other.min(), other.max());
+        min.x <= other_min.x && min.y <= other_min.y && other_max.x <= max.x && other_max.y <= max.y
+    }
+}
+
+pub trait ClampTo: Sized {
+    /// Moves the rectangle onto the nearest of `areas` if its center is
+    /// outside all of them, shrinking it to fit. A rectangle whose center is
+    /// in one of them is left alone, so it can still span several.
+    fn clamp_to<I>(&self, areas: I) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+        I::IntoIter: Clone;
+}
+
+impl ClampTo for ic::CGRect {
+    fn clamp_to<I>(&self, areas: I) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+        I::IntoIter: Clone,
+    {
+        let mid = self.mid();
+        let areas = areas.into_iter();
+        if areas.clone().any(|area| area.contains(mid)) {
+            return *self;
+        }
+        let areas = areas.filter(|area| area.size.width > 0.0 && area.size.height > 0.0);
+        let distance = |area: &ic::CGRect| {
+            let (min, max) = (area.min(), area.max());
+            let dx = (min.x - mid.x).max(mid.x - max.x).max(0.0);
+            let dy = (min.y - mid.y).max(mid.y - max.y).max(0.0);
+            dx * dx + dy * dy
+        };
+        let Some(area) = areas.min_by(|a, b| distance(a).total_cmp(&distance(b))) else {
+            return *self;
+        };
+        let size = ic::CGSize::new(
+            self.size.width.min(area.size.width),
+            self.size.height.min(area.size.height),
+        );
+        let origin = ic::CGPoint::new(
+            self.origin.x.clamp(area.min().x, area.max().x - size.width),
+            self.origin.y.clamp(area.min().y, area.max().y - size.height),
+        );
+        ic::CGRect::new(origin, size)
+    }
+}
+
+/// A corner of a screen or window.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// Returns a rect of `size` in this corner of `area`.
+    pub fn place(self, size: ic::CGSize, area: ic::CGRect) -> ic::CGRect {
+        let x = match self {
+            Corner::TopLeft | Corner::BottomLeft => area.min().x,
+            Corner::TopRight | Corner::BottomRight => area.max().x - size.width,
+        };
+        let y = match self {
+            Corner::TopLeft | Corner::TopRight => area.min().y,
+            Corner::BottomLeft | Corner::BottomRight => area.max().y - size.height,
+        };
+        ic::CGRect::new(ic::CGPoint::new(x, y), size)
+    }
+}
+
+/// Converts screen positions between the coordinate spaces used by CG and
+/// Cocoa.
+///
+/// Core Graphics and the accessibility API put the origin at the top left of
+/// the main screen (the one with the menu bar), with y increasing downward.
+/// Cocoa puts it at the bottom left of the main screen, with y increasing
+/// upward. The two agree on x, so only the height of the main screen is needed
+/// to convert. Screens to the left of the main screen have negative x in both,
+/// and screens above or below it have negative y in one of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenCoordinates {
+    main_screen_height: f64,
+}
+
+impl ScreenCoordinates {
+    pub fn new(main_screen_height: f64) -> Self {
+        ScreenCoordinates { main_screen_height }
+    }
+
+    /// Uses the main screen as currently reported by Cocoa.
+    pub fn current(mtm: ic::MainThreadMarker) -> Self {
+        // The main screen is always first in the list.
+        let screens = NSScreen::screens(mtm);
+        Self::new(screens.first().map(|screen| screen.frame().size.height).unwrap_or(0.0))
+    }
+
+    /// Converts a point from CG to Cocoa coordinates.
+    pub fn point_to_cocoa(&self, point: ic::CGPoint) -> ic::CGPoint {
+        ic::CGPoint::new(point.x, self.main_screen_height - point.y)
+    }
+
+    /// Converts a point from Cocoa to CG coordinates.
+    pub fn point_from_cocoa(&self, point: ic::CGPoint) -> ic::CGPoint {
+        // Flipping is its own inverse.
+        self.point_to_cocoa(point)
+    }
+
+    /// Converts a rect from CG to Cocoa coordinates. Its origin moves from the
+    /// top left corner to the bottom left.
+    pub fn rect_to_cocoa(&self, rect: ic::CGRect) -> ic::CGRect {
+        let bottom_left = ic::CGPoint::new(rect.origin.x, rect.origin.y + rect.size.height);
+        ic::CGRect::new(self.point_to_cocoa(bottom_left), rect.size)
+    }
+
+    /// Converts a rect from Cocoa to CG coordinates. Its origin moves from the
+    /// bottom left corner to the top left.
+    pub fn rect_from_cocoa(&self, rect: ic::CGRect) -> ic::CGRect {
+        let top_left = ic::CGPoint::new(rect.origin.x, rect.origin.y + rect.size.height);
+        ic::CGRect::new(self.point_from_cocoa(top_left), rect.size)
+    }
+}
+
+/// Serde definitions for CoreGraphics types, for use with `#[serde(with)]`.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ic::CGRect")]
+pub struct CGRectDef {
+    #[serde(with = "CGPointDef")]
+    pub origin: ic::CGPoint,
+    #[serde(with = "CGSizeDef")]
+    pub size: ic::CGSize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ic::CGPoint")]
+pub struct CGPointDef {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ic::CGSize")]
+pub struct CGSizeDef {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use icrate::Foundation::{CGPoint, CGRect, CGSize};
+
+    use super::{ClampTo, Rescale, ScreenCoordinates};
+
+    fn rect(x: f64, y: f64, w: f64, h: f64) -> CGRect {
+        CGRect::new(CGPoint::new(x, y), CGSize::new(w, h))
+    }
+
+    const MAIN_HEIGHT: f64 = 900.0;
+
+    #[test]
+    fn it_rescales_rects_between_areas() {
+        let from = rect(0., 0., 1000., 800.);
+        let to = rect(-2000., 100., 2000., 1600.);
+        assert_eq!(
+            rect(-1500., 300., 1000., 800.),
+            rect(250., 100., 500., 400.).rescale(from, to)
+        );
+        assert_eq!(to, from.rescale(from, to));
+
+        // Windows keep their shape on areas of another shape.
+        let to = rect(-2000., 100., 2000., 400.);
+        assert_eq!(
+            rect(-1125., 150., 250., 200.),
+            rect(250., 100., 500., 400.).rescale(from, to)
+        );
+        assert_eq!(rect(-1250., 100., 500., 400.), from.rescale(from, to));
+    }
+
+    #[test]
+    fn it_clamps_rects_onto_the_nearest_area() {
+        let areas = [rect(0., 0., 1000., 800.), rect(1000., 0., 500., 400.)];
+        // Spanning both areas is fine.
+        let spanning = rect(800., 100., 400., 200.);
+        assert_eq!(spanning, spanning.clamp_to(areas));
+        assert_eq!(
+            rect(1100., 200., 400., 200.),
+            rect(1400., 300., 400., 200.).clamp_to(areas)
+        );
+        assert_eq!(
+            rect(0., 0., 1000., 300.),
+            rect(-3000., -500., 2000., 300.).clamp_to(areas)
+        );
+        // Empty areas are ignored.
+        let far = rect(5000., 5000., 10., 10.);
+        assert_eq!(far, far.clamp_to([rect(0., 0., 0., 0.)]));
+    }
+
+    #[test]
+    fn it_maps_the_main_screen_onto_itself() {
+        let coords = ScreenCoordinates::new(MAIN_HEIGHT);
+        let main = rect(0.0, 0.0, 1440.0, MAIN_HEIGHT);
+        assert_eq!(main, coords.rect_to_cocoa(main));
+        assert_eq!(main, coords.rect_from_cocoa(main));
+        assert_eq!(
+            CGPoint::new(0.0, MAIN_HEIGHT),
+            coords.point_to_cocoa(CGPoint::new(0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn it_converts_windows_on_the_main_screen() {
+        let coords = ScreenCoordinates::new(MAIN_HEIGHT);
+        // A window just below the menu bar.
+        let cg = rect(100.0, 25.0, 600.0, 400.0);
+        let cocoa = rect(100.0, 475.0, 600.0, 400.0);
+        assert_eq!(cocoa, coords.rect_to_cocoa(cg));
+        assert_eq!(cg, coords.rect_from_cocoa(cocoa));
+    }
+
+    #[test]
+    fn it_converts_screens_left_of_the_main_screen() {
+        let coords = ScreenCoordinates::new(MAIN_HEIGHT);
+        // A taller screen to the left, with its top 200 points above the top
+        // of the main screen.
+        let cg = rect(-1920.0, -200.0, 1920.0, 1200.0);
+        let cocoa = rect(-1920.0, -100.0, 1920.0, 1200.0);
+        assert_eq!(cocoa, coords.rect_to_cocoa(cg));
+        assert_eq!(cg, coords.rect_from_cocoa(cocoa));
+    }
+
+    #[test]
+    fn it_converts_screens_above_the_main_screen() {
+        let coords = ScreenCoordinates::new(MAIN_HEIGHT);
+        let cg = rect(-240.0, -1080.0, 1920.0, 1080.0);
+        let cocoa = rect(-240.0, MAIN_HEIGHT, 1920.0, 1080.0);
+        assert_eq!(cocoa, coords.rect_to_cocoa(cg));
+        assert_eq!(cg, coords.rect_from_cocoa(cocoa));
+        // The bottom left corner of the upper screen touches the main screen.
+        assert_eq!(
+            CGPoint::new(-240.0, MAIN_HEIGHT),
+            coords.point_to_cocoa(CGPoint::new(-240.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn it_converts_screens_below_the_main_screen() {
+        let coords = ScreenCoordinates::new(MAIN_HEIGHT);
+        let cg = rect(0.0, MAIN_HEIGHT, 1280.0, 800.0);
+        let cocoa = rect(0.0, -800.0, 1280.0, 800.0);
+        assert_eq!(cocoa, coords.rect_to_cocoa(cg));
+        assert_eq!(cg, coords.rect_from_cocoa(cocoa));
+    }
+
+    #[test]
+    fn it_round_trips_across_a_display_arrangement() {
+        let coords = ScreenCoordinates::new(MAIN_HEIGHT);
+        let rects = [
+            rect(0.0, 0.0, 1440.0, MAIN_HEIGHT),
+            rect(-2560.0, -540.0, 2560.0, 1440.0),
+            rect(1440.0, 450.0, 1080.0, 1920.0),
+            rect(-2000.5, -1300.25, 10.5, 20.75),
+        ];
+        for r in rects {
+            assert_eq!(r, coords.rect_from_cocoa(coords.rect_to_cocoa(r)));
+            assert_eq!(r, coords.rect_to_cocoa(coords.rect_from_cocoa(r)));
+            assert_eq!(
+                r.origin,
+                coords.point_from_cocoa(coords.point_to_cocoa(r.origin))
+            );
+        }
+    }
+}
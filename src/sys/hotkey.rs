@@ -1,34 +1,294 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use core_graphics::event::CGEventFlags;
 use livesplit_hotkey::{ConsumePreference, Hook};
 pub use livesplit_hotkey::{Hotkey, KeyCode, Modifiers};
-use tracing::info_span;
+use tracing::{info_span, warn};
 
-use crate::actor::{
-    reactor::Command,
-    wm_controller::{Sender, WmCommand, WmEvent},
-};
+use super::event::{self, KeyReleaseTap, ModifiersTap};
+use crate::actor::wm_controller::{Sender, WmCommand, WmEvent};
+
+/// The shortest time between repeats, however much they accelerate.
+const MIN_REPEAT_INTERVAL: Duration = Duration::from_millis(16);
+
+/// How a binding repeats while its keys are held down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyRepeat {
+    /// The time before the first repeat.
+    pub delay: Duration,
+    /// The time between the first and second repeats.
+    pub interval: Duration,
+    /// How much faster each repeat comes than the one before it, or 1 for a
+    /// steady rate.
+    pub acceleration: f64,
+}
+
+impl KeyRepeat {
+    /// Returns the time to wait before each repeat.
+    fn waits(self) -> impl Iterator<Item = Duration> {
+        let intervals = std::iter::successors(Some(self.interval), move |interval| {
+            Some(interval.div_f64(self.acceleration).max(MIN_REPEAT_INTERVAL))
+        });
+        std::iter::once(self.delay).chain(intervals)
+    }
+}
+
+/// The repeating binding whose keys are held down.
+#[derive(Default)]
+struct Held {
+    hotkey: Option<Hotkey>,
+    /// Counts presses, so that a repeat thread can tell that its keys were
+    /// released even if they were pressed again since.
+    presses: u64,
+}
+
+/// The modifiers that keep the last cycling binding's cycle open, and the
+/// command that ends it once they are released.
+type Cycle = Option<(CGEventFlags, WmCommand)>;
 
 pub struct HotkeyManager {
     hook: Hook,
     events_tx: Sender,
+    bindings: Vec<(Hotkey, WmCommand)>,
+    repeat: Option<KeyRepeat>,
+    held: Arc<Mutex<Held>>,
+    _release_tap: Option<KeyReleaseTap>,
+    cycle: Arc<Mutex<Cycle>>,
+    /// Watches for the end of cycles, once a cycling binding is registered.
+    cycle_tap: Option<ModifiersTap>,
 }
 
 impl HotkeyManager {
-    pub fn new(events_tx: Sender) -> Self {
+    /// Creates a manager whose repeating bindings repeat as set by `repeat`,
+    /// or not at all if it is `None`.
+    ///
+    /// This must be called on a thread with a run loop, which watches for the
+    /// keys of repeating bindings being released.
+    pub fn new(events_tx: Sender, repeat: Option<KeyRepeat>) -> Self {
         let hook = Hook::with_consume_preference(ConsumePreference::MustConsume).unwrap();
-        HotkeyManager { hook, events_tx }
+        let held = Arc::new(Mutex::new(Held::default()));
+        let release_tap = repeat.and_then(|_| {
+            let held = held.clone();
+            let tap = KeyReleaseTap::new(move || held.lock().unwrap().hotkey = None);
+            if tap.is_none() {
+                warn!("Could not watch key releases; bindings will not repeat");
+            }
+            tap
+        });
+        HotkeyManager {
+            hook,
+            events_tx,
+            bindings: vec![],
+            repeat: repeat.filter(|_| release_tap.is_some()),
+            held,
+            _release_tap: release_tap,
+            cycle: Arc::new(Mutex::new(None)),
+            cycle_tap: None,
+        }
     }
 
-    pub fn register(&self, modifiers: Modifiers, key_code: KeyCode, cmd: Command) {
-        self.register_wm(modifiers, key_code, WmCommand::ReactorCommand(cmd))
+    pub fn register(&mut self, hotkey: Hotkey, cmd: WmCommand) {
+        self.bindings.push((hotkey, cmd.clone()));
+        let events_tx = self.events_tx.clone();
+        self.hook
+            .register(hotkey, move || {
+                let key_code = hotkey.key_code;
+                let span = info_span!("hotkey::press", ?key_code);
+                events_tx.send((span, WmEvent::Command(cmd.clone()))).unwrap()
+            })
+            .unwrap();
+    }
+
+    /// Registers a binding that runs `cmd` again and again while its keys are
+    /// held down, if repeating is turned on.
+    pub fn register_repeating(&mut self, hotkey: Hotkey, cmd: WmCommand) {
+        let Some(repeat) = self.repeat else {
+            return self.register(hotkey, cmd);
+        };
+        self.bindings.push((hotkey, cmd.clone()));
+        let events_tx = self.events_tx.clone();
+        let held = self.held.clone();
+        self.hook
+            .register(hotkey, move || {
+                let presses = {
+                    let mut held = held.lock().unwrap();
+                    if held.hotkey == Some(hotkey) {
+                        // The system repeats keys that are held down too; we
+                        // repeat at our own rate instead.
+                        return;
+                    }
+                    held.hotkey = Some(hotkey);
+                    held.presses += 1;
+                    held.presses
+                };
+                let key_code = hotkey.key_code;
+                let send = {
+                    let events_tx = events_tx.clone();
+                    let cmd = cmd.clone();
+                    move |is_repeat| {
+                        let span = info_span!("hotkey::press", ?key_code, is_repeat);
+                        events_tx.send((span, WmEvent::Command(cmd.clone()))).is_ok()
+                    }
+                };
+                send(false);
+                let held = held.clone();
+                thread::spawn(move || {
+                    for wait in repeat.waits() {
+                        thread::sleep(wait);
+                        let held = held.lock().unwrap();
+                        if held.presses != presses || held.hotkey.is_none() {
+                            return;
+                        }
+                        if !send(true) {
+                            return;
+                        }
+                    }
+                });
+            })
+            .unwrap();
     }
 
-    pub fn register_wm(&self, modifiers: Modifiers, key_code: KeyCode, cmd: WmCommand) {
+    /// Registers a binding that cycles through things like Cmd+Tab: `cmd`
+    /// runs on every press, and `end_cmd` once the modifiers of the binding
+    /// other than Shift are released, so Shift can be pressed along the way to
+    /// cycle backwards.
+    ///
+    /// If key releases cannot be watched, `end_cmd` runs right after every
+    /// press instead.
+    pub fn register_cycling(&mut self, hotkey: Hotkey, cmd: WmCommand, end_cmd: WmCommand) {
+        if self.cycle_tap.is_none() {
+            let cycle = self.cycle.clone();
+            let events_tx = self.events_tx.clone();
+            self.cycle_tap = ModifiersTap::new(move |flags| {
+                let mut cycle = cycle.lock().unwrap();
+                let Some((held, _)) = &*cycle else { return };
+                if flags.contains(*held) {
+                    return;
+                }
+                let (_, end_cmd) = cycle.take().unwrap();
+                let span = info_span!("hotkey::release");
+                _ = events_tx.send((span, WmEvent::Command(end_cmd)));
+            });
+            if self.cycle_tap.is_none() {
+                warn!("Could not watch key releases; cycles will end after every press");
+            }
+        }
+        self.bindings.push((hotkey, cmd.clone()));
         let events_tx = self.events_tx.clone();
+        let cycle = self.cycle.clone();
+        let can_watch = self.cycle_tap.is_some();
+        let held = event_flags(hotkey.modifiers.difference(Modifiers::SHIFT));
         self.hook
-            .register(Hotkey { modifiers, key_code }, move || {
+            .register(hotkey, move || {
+                let key_code = hotkey.key_code;
                 let span = info_span!("hotkey::press", ?key_code);
-                events_tx.send((span, WmEvent::Command(cmd.clone()))).unwrap()
+                // Holding the lock keeps the tap from ending the cycle before
+                // it is recorded.
+                let mut cycle = cycle.lock().unwrap();
+                events_tx.send((span.clone(), WmEvent::Command(cmd.clone()))).unwrap();
+                // The modifiers can be released before we get here, in which
+                // case the tap has already seen that and ignored it.
+                if can_watch && event::held_modifiers().contains(held) {
+                    *cycle = Some((held, end_cmd.clone()));
+                } else {
+                    *cycle = None;
+                    events_tx.send((span, WmEvent::Command(end_cmd.clone()))).unwrap();
+                }
             })
             .unwrap();
     }
+
+    /// Returns the registered hotkeys and their commands, in the order they
+    /// were registered.
+    pub fn bindings(&self) -> &[(Hotkey, WmCommand)] {
+        &self.bindings
+    }
+}
+
+/// Formats a hotkey the way it is written in the documentation, e.g.
+/// `Alt + Shift + KeyH`.
+pub fn describe_hotkey(hotkey: &Hotkey) -> String {
+    let mut parts = vec![];
+    for (modifier, name) in MODIFIER_NAMES {
+        if hotkey.modifiers.contains(modifier) {
+            parts.push(name.to_string());
+        }
+    }
+    parts.push(format!("{:?}", hotkey.key_code));
+    parts.join(" + ")
+}
+
+/// Reads a hotkey written the way [`describe_hotkey`] formats it.
+pub fn parse_hotkey(text: &str) -> Option<Hotkey> {
+    let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+    let key_code = parts.pop()?.parse().ok()?;
+    let mut modifiers = Modifiers::empty();
+    for part in parts {
+        let (modifier, _) = MODIFIER_NAMES.into_iter().find(|&(_, name)| name == part)?;
+        modifiers |= modifier;
+    }
+    Some(Hotkey { modifiers, key_code })
+}
+
+/// The modifiers in the order they are written, and their names.
+const MODIFIER_NAMES: [(Modifiers, &str); 4] = [
+    (Modifiers::CONTROL, "Ctrl"),
+    (Modifiers::ALT, "Alt"),
+    (Modifiers::SHIFT, "Shift"),
+    (Modifiers::META, "Cmd"),
+];
+
+/// Returns the event flags for `modifiers`.
+fn event_flags(modifiers: Modifiers) -> CGEventFlags {
+    [
+        (Modifiers::SHIFT, CGEventFlags::CGEventFlagShift),
+        (Modifiers::CONTROL, CGEventFlags::CGEventFlagControl),
+        (Modifiers::ALT, CGEventFlags::CGEventFlagAlternate),
+        (Modifiers::META, CGEventFlags::CGEventFlagCommand),
+    ]
+    .into_iter()
+    .filter(|&(modifier, _)| modifiers.contains(modifier))
+    .fold(CGEventFlags::empty(), |flags, (_, flag)| flags | flag)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{describe_hotkey, parse_hotkey, Hotkey, KeyCode, KeyRepeat, Modifiers};
+
+    #[test]
+    fn it_accelerates_repeats() {
+        let ms = Duration::from_millis;
+        let repeat = KeyRepeat {
+            delay: ms(300),
+            interval: ms(100),
+            acceleration: 2.0,
+        };
+        let waits: Vec<_> = repeat.waits().take(5).collect();
+        assert_eq!(vec![ms(300), ms(100), ms(50), ms(25), ms(16)], waits);
+
+        let steady = KeyRepeat { acceleration: 1.0, ..repeat };
+        assert_eq!(
+            vec![ms(300), ms(100), ms(100)],
+            steady.waits().take(3).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_parses_described_hotkeys() {
+        let hotkey = Hotkey {
+            modifiers: Modifiers::ALT | Modifiers::SHIFT,
+            key_code: KeyCode::KeyH,
+        };
+        assert_eq!("Alt + Shift + KeyH", describe_hotkey(&hotkey));
+        assert_eq!(Some(hotkey), parse_hotkey("Alt + Shift + KeyH"));
+        assert_eq!(Some(hotkey), parse_hotkey("Shift+Alt+KeyH"));
+        assert_eq!(None, parse_hotkey("Hyper + KeyH"));
+        assert_eq!(None, parse_hotkey("Alt + H"));
+    }
 }
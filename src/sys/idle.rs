@@ -0,0 +1,112 @@
+//! Detection of when the user has stopped using the computer, so background
+//! work can pause to save power.
+
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use core_graphics::event_source::CGEventSourceStateID;
+
+use super::event::InputTap;
+
+/// Returns how long it has been since the last keyboard, mouse, or trackpad
+/// input from the user.
+pub fn time_since_input() -> Duration {
+    let secs = unsafe {
+        CGEventSourceSecondsSinceLastEventType(
+            CGEventSourceStateID::HIDSystemState,
+            K_CG_ANY_INPUT_EVENT_TYPE,
+        )
+    };
+    Duration::try_from_secs_f64(secs).unwrap_or_default()
+}
+
+/// How often to check for input while idle if we can't watch for it directly.
+const FALLBACK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Lets background threads pause while the user is idle.
+///
+/// The user is idle once there has been no input for the threshold. Threads
+/// waiting in [`IdleMonitor::wait_until_active`] are woken by the tap returned
+/// from [`IdleMonitor::watch_input`] as soon as input arrives.
+#[derive(Clone, Debug)]
+pub struct IdleMonitor {
+    threshold: Option<Duration>,
+    /// Whether any thread is waiting for input.
+    waiting: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl IdleMonitor {
+    /// Creates a monitor that considers the user idle after `threshold`, or
+    /// never if it is `None`.
+    pub fn new(threshold: Option<Duration>) -> Self {
+        IdleMonitor {
+            threshold,
+            waiting: Default::default(),
+        }
+    }
+
+    /// Starts watching for input on the current thread's run loop, which
+    /// wakes any waiting threads. The monitor falls back to polling the idle
+    /// time if this returns `None`.
+    pub fn watch_input(&self) -> Option<InputTap> {
+        self.threshold?;
+        let waiting = self.waiting.clone();
+        InputTap::new(move || {
+            let (lock, cvar) = &*waiting;
+            let mut waiting = lock.lock().unwrap();
+            if *waiting {
+                *waiting = false;
+                cvar.notify_all();
+            }
+        })
+    }
+
+    /// Blocks the current thread until the user is not idle, returning
+    /// immediately if they aren't.
+    pub fn wait_until_active(&self) {
+        let Some(threshold) = self.threshold else { return };
+        let (lock, cvar) = &*self.waiting;
+        let mut waiting = lock.lock().unwrap();
+        while time_since_input() >= threshold {
+            *waiting = true;
+            waiting = cvar.wait_timeout(waiting, FALLBACK_CHECK_INTERVAL).unwrap().0;
+        }
+    }
+}
+
+/// Calls `on_change` on a background thread whenever the value `read`
+/// returns changes from `initial`, or with the first value too if `initial`
+/// is `None`.
+///
+/// This is for state the system doesn't notify us about, so it polls every
+/// `interval`. Polling pauses while `idle` says the user is idle.
+pub fn watch_changes<T: PartialEq + Send + 'static>(
+    interval: Duration,
+    idle: IdleMonitor,
+    initial: Option<T>,
+    read: impl Fn() -> T + Send + 'static,
+    mut on_change: impl FnMut(&T) + Send + 'static,
+) {
+    thread::spawn(move || {
+        let mut value = initial;
+        loop {
+            idle.wait_until_active();
+            let new_value = read();
+            if value.as_ref() != Some(&new_value) {
+                on_change(&new_value);
+                value = Some(new_value);
+            }
+            thread::sleep(interval);
+        }
+    });
+}
+
+const K_CG_ANY_INPUT_EVENT_TYPE: u32 = !0;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventSourceSecondsSinceLastEventType(state: CGEventSourceStateID, event_type: u32) -> f64;
+}
@@ -0,0 +1,199 @@
+//! Reading the keyboard layout chosen in the input menu.
+//!
+//! Hotkeys are registered by the position of the key, named after the key in
+//! that position on a US keyboard. To bind them by the character they type
+//! instead, we look up which key types each character in the current layout.
+
+use std::ffi::c_void;
+
+use core_foundation::{
+    base::{CFType, CFTypeRef, TCFType},
+    data::{CFData, CFDataRef},
+    string::CFStringRef,
+};
+use icrate::Foundation::NSString;
+
+use super::hotkey::KeyCode;
+
+/// The keys that type a character, with their virtual key codes and the
+/// character each types on a US keyboard.
+const KEYS: &[(KeyCode, u16, char)] = {
+    use KeyCode::*;
+    &[
+        (KeyA, 0, 'a'),
+        (KeyS, 1, 's'),
+        (KeyD, 2, 'd'),
+        (KeyF, 3, 'f'),
+        (KeyH, 4, 'h'),
+        (KeyG, 5, 'g'),
+        (KeyZ, 6, 'z'),
+        (KeyX, 7, 'x'),
+        (KeyC, 8, 'c'),
+        (KeyV, 9, 'v'),
+        (KeyB, 11, 'b'),
+        (KeyQ, 12, 'q'),
+        (KeyW, 13, 'w'),
+        (KeyE, 14, 'e'),
+        (KeyR, 15, 'r'),
+        (KeyY, 16, 'y'),
+        (KeyT, 17, 't'),
+        (Digit1, 18, '1'),
+        (Digit2, 19, '2'),
+        (Digit3, 20, '3'),
+        (Digit4, 21, '4'),
+        (Digit6, 22, '6'),
+        (Digit5, 23, '5'),
+        (Equal, 24, '='),
+        (Digit9, 25, '9'),
+        (Digit7, 26, '7'),
+        (Minus, 27, '-'),
+        (Digit8, 28, '8'),
+        (Digit0, 29, '0'),
+        (BracketRight, 30, ']'),
+        (KeyO, 31, 'o'),
+        (KeyU, 32, 'u'),
+        (BracketLeft, 33, '['),
+        (KeyI, 34, 'i'),
+        (KeyP, 35, 'p'),
+        (KeyL, 37, 'l'),
+        (KeyJ, 38, 'j'),
+        (Quote, 39, '\''),
+        (KeyK, 40, 'k'),
+        (Semicolon, 41, ';'),
+        (Backslash, 42, '\\'),
+        (Comma, 43, ','),
+        (Slash, 44, '/'),
+        (KeyN, 45, 'n'),
+        (KeyM, 46, 'm'),
+        (Period, 47, '.'),
+        (Backquote, 50, '`'),
+    ]
+};
+
+/// The character each key types without modifiers in a keyboard layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardLayout(Vec<(KeyCode, char)>);
+
+impl KeyboardLayout {
+    /// Returns the layout chosen in the input menu, or `None` if it can't be
+    /// read. Input methods, like those for Chinese and Japanese, use the
+    /// layout of the keyboard they type with.
+    pub fn current() -> Option<Self> {
+        unsafe {
+            let source = TISCopyCurrentKeyboardLayoutInputSource();
+            if source.is_null() {
+                return None;
+            }
+            let source = CFType::wrap_under_create_rule(source);
+            let data =
+                TISGetInputSourceProperty(source.as_CFTypeRef(), kTISPropertyUnicodeKeyLayoutData);
+            if data.is_null() {
+                return None;
+            }
+            let data = CFData::wrap_under_get_rule(data as CFDataRef);
+            let layout = data.bytes().as_ptr() as *const c_void;
+            let keyboard_type = LMGetKbdType() as u32;
+            let keys = KEYS
+                .iter()
+                .filter_map(|&(key, code, _)| Some((key, translate(layout, code, keyboard_type)?)))
+                .collect();
+            Some(KeyboardLayout(keys))
+        }
+    }
+
+    /// Returns the key that types the character `key` types on a US
+    /// keyboard. If no key in this layout types it, like the digits on a
+    /// French keyboard, the key in the same position is used.
+    pub fn key_for_us_char(&self, key: KeyCode) -> KeyCode {
+        let Some(&(_, _, want)) = KEYS.iter().find(|(k, ..)| *k == key) else {
+            return key;
+        };
+        if self.0.contains(&(key, want)) {
+            return key;
+        }
+        self.0.iter().find(|(_, c)| *c == want).map_or(key, |&(k, _)| k)
+    }
+}
+
+/// Returns the character typed by the key with virtual key code `code`,
+/// without modifiers.
+unsafe fn translate(layout: *const c_void, code: u16, keyboard_type: u32) -> Option<char> {
+    let mut dead_key_state = 0;
+    let mut buf = [0u16; 4];
+    let mut len = 0;
+    let status = UCKeyTranslate(
+        layout,
+        code,
+        K_UC_KEY_ACTION_DISPLAY,
+        0,
+        keyboard_type,
+        K_UC_KEY_TRANSLATE_NO_DEAD_KEYS_MASK,
+        &mut dead_key_state,
+        buf.len(),
+        &mut len,
+        buf.as_mut_ptr(),
+    );
+    if status != 0 || len != 1 {
+        return None;
+    }
+    char::from_u32(buf[0] as u32)?.to_lowercase().next()
+}
+
+/// The name of the distributed notification posted when the user chooses
+/// another keyboard layout.
+pub fn layout_changed_notification() -> &'static NSString {
+    // SAFETY: CFString is toll-free bridged with NSString, and the constant
+    // lives as long as the process.
+    unsafe { &*(kTISNotifySelectedKeyboardInputSourceChanged as *const NSString) }
+}
+
+const K_UC_KEY_ACTION_DISPLAY: u16 = 3;
+const K_UC_KEY_TRANSLATE_NO_DEAD_KEYS_MASK: u32 = 1;
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+    static kTISNotifySelectedKeyboardInputSourceChanged: CFStringRef;
+    fn TISCopyCurrentKeyboardLayoutInputSource() -> CFTypeRef;
+    fn TISGetInputSourceProperty(source: CFTypeRef, key: CFStringRef) -> CFTypeRef;
+    fn LMGetKbdType() -> u8;
+    fn UCKeyTranslate(
+        key_layout: *const c_void,
+        virtual_key_code: u16,
+        key_action: u16,
+        modifier_key_state: u32,
+        keyboard_type: u32,
+        key_translate_options: u32,
+        dead_key_state: *mut u32,
+        max_string_length: usize,
+        actual_string_length: *mut usize,
+        unicode_string: *mut u16,
+    ) -> i32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyCode::*, KeyboardLayout};
+
+    #[test]
+    fn it_finds_keys_by_character() {
+        // Part of a French AZERTY layout.
+        let azerty = KeyboardLayout(vec![
+            (KeyQ, 'a'),
+            (KeyA, 'q'),
+            (KeyW, 'z'),
+            (KeyZ, 'w'),
+            (KeyH, 'h'),
+            (Digit1, '&'),
+            (Semicolon, 'm'),
+            (KeyM, ','),
+        ]);
+        assert_eq!(KeyQ, azerty.key_for_us_char(KeyA));
+        assert_eq!(KeyA, azerty.key_for_us_char(KeyQ));
+        assert_eq!(Semicolon, azerty.key_for_us_char(KeyM));
+        assert_eq!(KeyH, azerty.key_for_us_char(KeyH));
+        assert_eq!(KeyM, azerty.key_for_us_char(Comma));
+        // No key types a 1 without shift, so the key stays where it is.
+        assert_eq!(Digit1, azerty.key_for_us_char(Digit1));
+    }
+}
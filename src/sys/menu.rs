@@ -0,0 +1,163 @@
+//! Finding and pressing items in an app's menus.
+//!
+//! Apps that can reopen closed windows only offer it in their menus, so this
+//! is the one way to ask them for it.
+
+use accessibility::{AXUIElement, AXUIElementActions, AXUIElementAttributes};
+use accessibility_sys::{kAXErrorSuccess, AXUIElementCopyAttributeValue};
+use core_foundation::{
+    base::{CFType, CFTypeRef, TCFType},
+    boolean::CFBoolean,
+    string::CFString,
+};
+use tracing::debug;
+
+/// Titles of the menu items apps use to reopen the last closed window.
+const REOPEN_TITLES: &[&str] = &[
+    "Reopen Last Closed Window",
+    "Reopen Closed Window",
+    "Reopen Closed Tab",
+];
+
+/// Titles of the menus that list recently closed windows by name, like
+/// History > Recently Closed in browsers.
+const HISTORY_MENUS: &[&str] = &["History", "Recently Closed", "Recently Closed Windows"];
+
+/// How deep to look into submenus. Recently closed windows are listed one
+/// level down, as in History > Recently Closed.
+const MAX_DEPTH: usize = 3;
+
+/// Presses the item in the menus of `app` that reopens the closed window
+/// titled `title`, and returns whether there was one.
+///
+/// An item named after the window in a menu of recently closed windows is
+/// preferred over one that reopens whichever window was closed last. Items
+/// elsewhere that happen to share the window's title are never pressed.
+pub fn press_reopen_item(app: &AXUIElement, title: &str) -> bool {
+    let Some(menu_bar) = element_attribute(app, "AXMenuBar") else {
+        return false;
+    };
+    let mut items = vec![];
+    collect_items(&menu_bar, MAX_DEPTH, false, &mut items);
+    let Some(item) = choose_item(&items, title) else {
+        return false;
+    };
+    match item.element.press() {
+        Ok(()) => true,
+        Err(e) => {
+            debug!(title = ?item.title, "Could not press menu item: {e:?}");
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MenuItem<E> {
+    title: String,
+    /// Whether the item is in one of [`HISTORY_MENUS`].
+    in_history: bool,
+    element: E,
+}
+
+/// Returns the item that reopens the closed window titled `title`.
+fn choose_item<'a, E>(items: &'a [MenuItem<E>], title: &str) -> Option<&'a MenuItem<E>> {
+    let named = items
+        .iter()
+        .find(|item| item.in_history && !title.is_empty() && item.title == title);
+    named.or_else(|| items.iter().find(|item| REOPEN_TITLES.contains(&item.title.as_str())))
+}
+
+/// Adds the enabled items under `element` to `items`. `in_history` is set
+/// when `element` is in one of [`HISTORY_MENUS`].
+fn collect_items(
+    element: &AXUIElement,
+    depth: usize,
+    in_history: bool,
+    items: &mut Vec<MenuItem<AXUIElement>>,
+) {
+    let Ok(children) = element.children() else {
+        return;
+    };
+    for child in children.iter() {
+        let role = child.role().map(|role| role.to_string()).unwrap_or_default();
+        let title = child.title().map(|title| title.to_string()).ok();
+        if role == "AXMenuItem" && is_enabled(&child) {
+            if let Some(title) = &title {
+                items.push(MenuItem {
+                    title: title.clone(),
+                    in_history,
+                    element: child.clone(),
+                });
+            }
+        }
+        // Menu bar items and menu items with a submenu have it as their only
+        // child, which in turn has the items. Only the items count as a level.
+        let depth = if role == "AXMenu" {
+            Some(depth)
+        } else {
+            depth.checked_sub(1)
+        };
+        let in_history =
+            in_history || title.is_some_and(|title| HISTORY_MENUS.contains(&title.as_str()));
+        if let Some(depth) = depth {
+            collect_items(&child, depth, in_history, items);
+        }
+    }
+}
+
+fn is_enabled(element: &AXUIElement) -> bool {
+    let Some(value) = attribute(element, "AXEnabled") else {
+        return true;
+    };
+    value.downcast::<CFBoolean>().map_or(true, bool::from)
+}
+
+fn element_attribute(element: &AXUIElement, attribute_name: &str) -> Option<AXUIElement> {
+    attribute(element, attribute_name)?.downcast::<AXUIElement>()
+}
+
+fn attribute(element: &AXUIElement, attribute: &str) -> Option<CFType> {
+    let attribute = CFString::new(attribute);
+    let mut value: CFTypeRef = std::ptr::null();
+    let err = unsafe {
+        AXUIElementCopyAttributeValue(
+            element.as_concrete_TypeRef(),
+            attribute.as_concrete_TypeRef(),
+            &mut value,
+        )
+    };
+    if err != kAXErrorSuccess || value.is_null() {
+        return None;
+    }
+    Some(unsafe { CFType::wrap_under_create_rule(value) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{choose_item, MenuItem};
+
+    fn item(title: &str, in_history: bool) -> MenuItem<()> {
+        MenuItem {
+            title: title.to_string(),
+            in_history,
+            element: (),
+        }
+    }
+
+    #[test]
+    fn it_only_reopens_windows_from_history_menus() {
+        let items = [
+            item("Notes", false),
+            item("Reopen Closed Window", false),
+            item("Notes", true),
+        ];
+        let chosen = |title| choose_item(&items, title).map(|item| (&*item.title, item.in_history));
+        assert_eq!(Some(("Notes", true)), chosen("Notes"));
+        assert_eq!(Some(("Reopen Closed Window", false)), chosen("Drafts"));
+        assert_eq!(
+            Some(("Reopen Closed Window", false)),
+            choose_item(&items[..2], "Notes").map(|item| (&*item.title, item.in_history))
+        );
+        assert!(choose_item(&items[..1], "Notes").is_none());
+    }
+}
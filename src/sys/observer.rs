@@ -9,7 +9,9 @@ use accessibility_sys::{
 use core_foundation::{
     base::TCFType,
     declare_TCFType, impl_TCFType,
-    runloop::{kCFRunLoopCommonModes, CFRunLoopAddSource, CFRunLoopGetCurrent},
+    runloop::{
+        kCFRunLoopCommonModes, CFRunLoopAddSource, CFRunLoopGetCurrent, CFRunLoopSourceInvalidate,
+    },
     string::{CFString, CFStringRef},
 };
 
@@ -97,6 +99,11 @@ unsafe fn destruct<T>(ptr: *mut ()) {
 impl Drop for Observer {
     fn drop(&mut self) {
         unsafe {
+            // Make sure the run loop stops calling the callback before we free
+            // it, in case we are replacing the observer while the thread runs.
+            CFRunLoopSourceInvalidate(AXObserverGetRunLoopSource(
+                self.observer.as_concrete_TypeRef(),
+            ));
             ManuallyDrop::drop(&mut self.observer);
             (self.dtor)(self.callback);
         }
@@ -0,0 +1,231 @@
+//! Borderless windows that float above all other windows, used to show
+//! information to the user.
+//!
+//! Overlays never take focus, and ignore the mouse unless asked to with
+//! [`OverlayWindow::set_ignores_mouse`], so they do not interfere with the
+//! windows being managed.
+
+use icrate::{
+    objc2::rc::Id,
+    AppKit::{
+        NSBackingStoreBuffered, NSBox, NSBoxCustom, NSColor, NSFont, NSNoTitle, NSScreen,
+        NSTextField, NSWindow, NSWindowAbove, NSWindowCollectionBehaviorCanJoinAllSpaces,
+        NSWindowCollectionBehaviorStationary, NSWindowLevel, NSWindowStyleMaskBorderless,
+    },
+    Foundation::{CGPoint, CGRect, CGSize, MainThreadMarker, NSString},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    appearance::Appearance,
+    geometry::ScreenCoordinates,
+    window_server::{self, WindowServerId},
+};
+
+/// The level of the overlay windows. This is kCGPopUpMenuWindowLevel, which
+/// is above normal, floating, and status bar windows.
+const OVERLAY_LEVEL: NSWindowLevel = 101;
+
+/// Padding around the text in a text overlay, in points.
+const TEXT_PADDING: f64 = 20.0;
+
+/// A color as sRGB components and alpha, each from 0 to 1.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Color(pub f64, pub f64, pub f64, pub f64);
+
+impl Color {
+    pub const CLEAR: Color = Color(0.0, 0.0, 0.0, 0.0);
+
+    fn to_ns(self) -> Id<NSColor> {
+        let Color(red, green, blue, alpha) = self;
+        unsafe { NSColor::colorWithSRGBRed_green_blue_alpha(red, green, blue, alpha) }
+    }
+}
+
+/// The colors overlays are drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: Color,
+    pub text: Color,
+    pub focused_border: Color,
+    pub unfocused_border: Color,
+}
+
+impl Palette {
+    /// Returns the default colors for `appearance`, chosen to stand out
+    /// against windows drawn in that appearance.
+    pub fn default_for(appearance: Appearance) -> Palette {
+        match appearance {
+            Appearance::Light => Palette {
+                background: Color(0.96, 0.96, 0.96, 0.92),
+                text: Color(0.1, 0.1, 0.1, 1.0),
+                focused_border: Color(0.0, 0.42, 0.9, 1.0),
+                unfocused_border: Color(0.55, 0.55, 0.55, 0.6),
+            },
+            Appearance::Dark => Palette {
+                background: Color(0.1, 0.1, 0.1, 0.85),
+                text: Color(1.0, 1.0, 1.0, 1.0),
+                focused_border: Color(0.3, 0.6, 1.0, 1.0),
+                unfocused_border: Color(0.4, 0.4, 0.4, 0.6),
+            },
+        }
+    }
+}
+
+pub struct OverlayWindow {
+    window: Id<NSWindow>,
+    mtm: MainThreadMarker,
+}
+
+impl OverlayWindow {
+    pub fn new(mtm: MainThreadMarker) -> OverlayWindow {
+        let frame = CGRect::new(CGPoint::new(0.0, 0.0), CGSize::new(1.0, 1.0));
+        let window = unsafe {
+            NSWindow::initWithContentRect_styleMask_backing_defer(
+                mtm.alloc(),
+                frame,
+                NSWindowStyleMaskBorderless,
+                NSBackingStoreBuffered,
+                false,
+            )
+        };
+        unsafe {
+            window.setReleasedWhenClosed(false);
+            window.setCollectionBehavior(
+                NSWindowCollectionBehaviorCanJoinAllSpaces | NSWindowCollectionBehaviorStationary,
+            );
+        }
+        window.setLevel(OVERLAY_LEVEL);
+        window.setOpaque(false);
+        window.setHasShadow(false);
+        window.setIgnoresMouseEvents(true);
+        let overlay = OverlayWindow { window, mtm };
+        overlay.set_background(Palette::default_for(Appearance::Dark).background);
+        overlay
+    }
+
+    pub fn set_background(&self, color: Color) {
+        self.window.setBackgroundColor(Some(&color.to_ns()));
+    }
+
+    /// Replaces the contents of the window with `text` in a monospaced font,
+    /// resizes the window to fit, and centers it on the main screen.
+    pub fn set_text(&self, text: &str, color: Color) {
+        let label = unsafe {
+            let label = NSTextField::labelWithString(&NSString::from_str(text), self.mtm);
+            label.setFont(NSFont::userFixedPitchFontOfSize(14.0).as_deref());
+            label.setTextColor(Some(&color.to_ns()));
+            label.sizeToFit();
+            label
+        };
+        let text_size = label.frame().size;
+        let size = CGSize::new(
+            text_size.width + 2.0 * TEXT_PADDING,
+            text_size.height + 2.0 * TEXT_PADDING,
+        );
+        unsafe { label.setFrameOrigin(CGPoint::new(TEXT_PADDING, TEXT_PADDING)) };
+
+        // Cocoa frames have their origin at the bottom left of the main screen.
+        let screen = NSScreen::mainScreen(self.mtm)
+            .map(|screen| screen.visibleFrame())
+            .unwrap_or(CGRect::new(CGPoint::new(0.0, 0.0), size));
+        let origin = CGPoint::new(
+            screen.origin.x + (screen.size.width - size.width) / 2.0,
+            screen.origin.y + (screen.size.height - size.height) / 2.0,
+        );
+        self.window.setFrame_display(CGRect::new(origin, size), true);
+        let content = self.window.contentView().unwrap();
+        for view in unsafe { content.subviews() }.iter() {
+            unsafe { view.removeFromSuperview() };
+        }
+        unsafe { content.addSubview(&label) };
+    }
+
+    /// Replaces the contents of the window with a border of `width` points
+    /// just inside its edges, leaving the rest transparent.
+    pub fn set_border(&self, width: f64, color: Color) {
+        self.set_box(width, color, Color::CLEAR);
+    }
+
+    /// Like [`OverlayWindow::set_border`], but fills the inside with `fill`.
+    pub fn set_filled_box(&self, width: f64, border: Color, fill: Color) {
+        self.set_box(width, border, fill);
+    }
+
+    /// Like [`OverlayWindow::set_filled_box`], but shows `text` in its
+    /// center.
+    pub fn set_labeled_box(
+        &self,
+        text: &str,
+        width: f64,
+        border: Color,
+        fill: Color,
+        text_color: Color,
+    ) {
+        let content = self.set_box(width, border, fill);
+        let label = unsafe {
+            let label = NSTextField::labelWithString(&NSString::from_str(text), self.mtm);
+            label.setFont(Some(&NSFont::boldSystemFontOfSize(16.0)));
+            label.setTextColor(Some(&text_color.to_ns()));
+            label.sizeToFit();
+            label
+        };
+        let bounds = content.frame().size;
+        let size = label.frame().size;
+        unsafe {
+            label.setFrameOrigin(CGPoint::new(
+                ((bounds.width - size.width) / 2.0).max(0.0),
+                ((bounds.height - size.height) / 2.0).max(0.0),
+            ));
+            content.addSubview(&label);
+        }
+    }
+
+    fn set_box(&self, width: f64, border: Color, fill: Color) -> Id<NSBox> {
+        self.set_background(Color::CLEAR);
+        let frame = self.window.contentView().unwrap().frame();
+        let content = unsafe {
+            let content = NSBox::initWithFrame(self.mtm.alloc(), frame);
+            content.setBoxType(NSBoxCustom);
+            content.setTitlePosition(NSNoTitle);
+            content.setBorderWidth(width);
+            content.setBorderColor(&border.to_ns());
+            content.setFillColor(&fill.to_ns());
+            content
+        };
+        self.window.setContentView(Some(&content));
+        content
+    }
+
+    /// Moves the window to `frame`, given in CG (top-left origin) coordinates.
+    pub fn set_frame(&self, frame: CGRect) {
+        let frame = ScreenCoordinates::current(self.mtm).rect_to_cocoa(frame);
+        self.window.setFrame_display(frame, true);
+    }
+
+    /// Sets whether clicks pass through the window to whatever is below it.
+    /// This is true by default.
+    ///
+    /// Note that the window receives clicks even in fully transparent areas
+    /// when this is false.
+    pub fn set_ignores_mouse(&self, ignores: bool) {
+        self.window.setIgnoresMouseEvents(ignores);
+    }
+
+    pub fn show(&self) {
+        unsafe { self.window.orderFrontRegardless() };
+    }
+
+    /// Shows the window directly above the window server window `id`, which
+    /// can belong to another app, instead of above every window. Other
+    /// windows in front of `id` stay in front of the overlay.
+    pub fn show_above(&self, id: WindowServerId) {
+        let level = window_server::window_level(id).unwrap_or(0);
+        self.window.setLevel(level as NSWindowLevel);
+        unsafe { self.window.orderWindow_relativeTo(NSWindowAbove, id.as_u32() as isize) };
+    }
+
+    pub fn hide(&self) {
+        self.window.orderOut(None);
+    }
+}
@@ -0,0 +1,278 @@
+//! Checking for and walking the user through granting the privacy
+//! permissions we need.
+//!
+//! Without these the window manager can't do anything useful, and macOS
+//! doesn't tell the user why, so we show our own alerts that link to the
+//! right pane of System Settings.
+
+use std::{ffi::c_void, process, ptr, time::Duration};
+
+use accessibility_sys::{AXIsProcessTrusted, AXIsProcessTrustedWithOptions};
+use core_foundation::{
+    base::{CFOptionFlags, TCFType},
+    boolean::CFBoolean,
+    dictionary::{CFDictionary, CFDictionaryRef},
+    string::{CFString, CFStringRef},
+};
+use tracing::{info, warn};
+
+use super::idle::{self, IdleMonitor};
+
+/// A privacy permission the window manager uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Needed to read and move windows. Nothing works without it.
+    Accessibility,
+    /// Needed to watch the keyboard and mouse, for hot corners and pausing
+    /// while idle.
+    InputMonitoring,
+}
+
+impl Permission {
+    pub fn is_granted(self) -> bool {
+        match self {
+            Permission::Accessibility => unsafe { AXIsProcessTrusted() },
+            Permission::InputMonitoring => unsafe { CGPreflightListenEventAccess() },
+        }
+    }
+
+    /// Whether the window manager can run without this permission.
+    pub fn is_required(self) -> bool {
+        self == Permission::Accessibility
+    }
+
+    /// Adds us to the list in System Settings so the user only has to flip
+    /// the switch.
+    fn register(self) {
+        match self {
+            // Failed AX calls don't reliably add us to the list, but asking
+            // with the prompt option does.
+            Permission::Accessibility => {
+                let options = CFDictionary::from_CFType_pairs(&[(
+                    CFString::from_static_string("AXTrustedCheckOptionPrompt"),
+                    CFBoolean::true_value(),
+                )]);
+                _ = unsafe { AXIsProcessTrustedWithOptions(options.as_concrete_TypeRef()) };
+            }
+            Permission::InputMonitoring => _ = unsafe { CGRequestListenEventAccess() },
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "Accessibility",
+            Permission::InputMonitoring => "Input Monitoring",
+        }
+    }
+
+    fn explanation(self) -> &'static str {
+        match self {
+            Permission::Accessibility => {
+                "Nimbus needs Accessibility access to find and arrange your windows."
+            }
+            Permission::InputMonitoring => {
+                "Nimbus uses Input Monitoring for hot corners and to pause background \
+                 work while you are away. Everything else works without it."
+            }
+        }
+    }
+
+    fn settings_url(self) -> &'static str {
+        match self {
+            Permission::Accessibility => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility"
+            }
+            Permission::InputMonitoring => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_ListenEvent"
+            }
+        }
+    }
+}
+
+/// How often to check whether the user has granted a permission.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Walks the user through granting each of `permissions` that is missing,
+/// returning once they have all been granted or skipped.
+///
+/// This blocks the current thread and exits the process if the user quits
+/// instead of granting a required permission.
+pub fn ensure_granted(permissions: &[Permission]) {
+    for &permission in permissions {
+        if permission.is_granted() {
+            continue;
+        }
+        info!("Missing {} permission", permission.name());
+        let skip = if permission.is_required() {
+            "Quit"
+        } else {
+            "Skip"
+        };
+        let header = format!("Nimbus needs {} access", permission.name());
+        let message = format!(
+            "{}\n\nOpen System Settings and turn on Nimbus under Privacy & Security > {}.",
+            permission.explanation(),
+            permission.name(),
+        );
+        match show_alert(&header, &message, &["Open System Settings", skip]) {
+            Some(0) => (),
+            _ if permission.is_required() => process::exit(1),
+            _ => continue,
+        }
+        permission.register();
+        if let Err(e) = process::Command::new("open").arg(permission.settings_url()).status() {
+            warn!("Could not open System Settings: {e}");
+        }
+        if !wait_for_grant(permission, skip) && permission.is_required() {
+            process::exit(1);
+        }
+    }
+}
+
+/// Calls `on_change` on a background thread whenever `permission` is revoked
+/// or granted again while we run, checking every `interval`.
+pub fn watch_permission(
+    permission: Permission,
+    interval: Duration,
+    idle: IdleMonitor,
+    on_change: impl Fn(bool) + Send + 'static,
+) {
+    let granted = Some(permission.is_granted());
+    idle::watch_changes(
+        interval,
+        idle,
+        granted,
+        move || permission.is_granted(),
+        move |&granted| {
+            info!(granted, "{} permission changed", permission.name());
+            on_change(granted);
+        },
+    );
+}
+
+/// Shows a waiting alert until the permission is granted, returning false if
+/// the user dismisses it first.
+fn wait_for_grant(permission: Permission, skip: &str) -> bool {
+    let header = format!("Waiting for {} access", permission.name());
+    let message = "Nimbus will continue automatically once access is turned on.";
+    let Some(alert) = Alert::new(&header, message, &[skip]) else {
+        // Keep waiting without anything to show.
+        while !permission.is_granted() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        return true;
+    };
+    loop {
+        if permission.is_granted() {
+            info!("{} permission granted", permission.name());
+            return true;
+        }
+        if alert.wait_for_response(POLL_INTERVAL).is_some() {
+            return permission.is_granted();
+        }
+    }
+}
+
+/// Shows an alert with up to three buttons and waits for the user to choose
+/// one, returning its index.
+fn show_alert(header: &str, message: &str, buttons: &[&str]) -> Option<usize> {
+    let alert = Alert::new(header, message, buttons)?;
+    alert.wait_for_response(Duration::ZERO)
+}
+
+/// An alert shown with `CFUserNotification`, which works before (and
+/// without) an `NSApplication`.
+struct Alert(CFUserNotificationRef);
+
+impl Alert {
+    fn new(header: &str, message: &str, buttons: &[&str]) -> Option<Alert> {
+        let key = |key: CFStringRef| unsafe { CFString::wrap_under_get_rule(key) };
+        let button_keys = unsafe {
+            [
+                kCFUserNotificationDefaultButtonTitleKey,
+                kCFUserNotificationAlternateButtonTitleKey,
+                kCFUserNotificationOtherButtonTitleKey,
+            ]
+        };
+        let mut pairs = vec![
+            (
+                key(unsafe { kCFUserNotificationAlertHeaderKey }),
+                CFString::new(header),
+            ),
+            (
+                key(unsafe { kCFUserNotificationAlertMessageKey }),
+                CFString::new(message),
+            ),
+        ];
+        for (&button_key, button) in button_keys.iter().zip(buttons) {
+            pairs.push((key(button_key), CFString::new(button)));
+        }
+        let dict = CFDictionary::from_CFType_pairs(&pairs);
+        let mut error = 0;
+        let notification = unsafe {
+            CFUserNotificationCreate(
+                ptr::null(),
+                0.0,
+                K_CF_USER_NOTIFICATION_NOTE_ALERT_LEVEL,
+                &mut error,
+                dict.as_concrete_TypeRef(),
+            )
+        };
+        if notification.is_null() || error != 0 {
+            warn!("Could not show alert: error {error}");
+            return None;
+        }
+        Some(Alert(notification))
+    }
+
+    /// Waits up to `timeout` for the user to press a button, or forever if it
+    /// is zero, returning the index of the button.
+    fn wait_for_response(&self, timeout: Duration) -> Option<usize> {
+        let mut flags: CFOptionFlags = 0;
+        let result =
+            unsafe { CFUserNotificationReceiveResponse(self.0, timeout.as_secs_f64(), &mut flags) };
+        (result == 0).then_some(flags as usize & 0x3)
+    }
+}
+
+impl Drop for Alert {
+    fn drop(&mut self) {
+        unsafe {
+            CFUserNotificationCancel(self.0);
+            core_foundation::base::CFRelease(self.0 as *const c_void);
+        }
+    }
+}
+
+type CFUserNotificationRef = *mut c_void;
+
+const K_CF_USER_NOTIFICATION_NOTE_ALERT_LEVEL: CFOptionFlags = 1;
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFUserNotificationAlertHeaderKey: CFStringRef;
+    static kCFUserNotificationAlertMessageKey: CFStringRef;
+    static kCFUserNotificationDefaultButtonTitleKey: CFStringRef;
+    static kCFUserNotificationAlternateButtonTitleKey: CFStringRef;
+    static kCFUserNotificationOtherButtonTitleKey: CFStringRef;
+
+    fn CFUserNotificationCreate(
+        allocator: *const c_void,
+        timeout: f64,
+        flags: CFOptionFlags,
+        error: *mut i32,
+        dictionary: CFDictionaryRef,
+    ) -> CFUserNotificationRef;
+    fn CFUserNotificationReceiveResponse(
+        notification: CFUserNotificationRef,
+        timeout: f64,
+        response_flags: *mut CFOptionFlags,
+    ) -> i32;
+    fn CFUserNotificationCancel(notification: CFUserNotificationRef) -> i32;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightListenEventAccess() -> bool;
+    fn CGRequestListenEventAccess() -> bool;
+}
@@ -0,0 +1,85 @@
+//! Access to the state of the computer's power source.
+
+use std::time::Duration;
+
+use core_foundation::{
+    array::{CFArray, CFArrayRef},
+    base::{CFType, CFTypeRef, TCFType},
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    string::{CFString, CFStringRef},
+};
+use serde::{Deserialize, Serialize};
+
+use super::idle::{self, IdleMonitor};
+
+/// Where the computer is drawing power from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct PowerSource {
+    pub on_battery: bool,
+    /// The charge of the internal battery in percent, if there is one.
+    pub battery_percent: Option<f64>,
+}
+
+/// Returns the current power source.
+pub fn power_source() -> PowerSource {
+    let info = unsafe { IOPSCopyPowerSourcesInfo() };
+    if info.is_null() {
+        return PowerSource::default();
+    }
+    let info = unsafe { CFType::wrap_under_create_rule(info) };
+    let providing = unsafe { IOPSGetProvidingPowerSourceType(info.as_CFTypeRef()) };
+    let on_battery = !providing.is_null()
+        && unsafe { CFString::wrap_under_get_rule(providing) } == "Battery Power";
+
+    let list = unsafe { IOPSCopyPowerSourcesList(info.as_CFTypeRef()) };
+    if list.is_null() {
+        return PowerSource {
+            on_battery,
+            battery_percent: None,
+        };
+    }
+    let list: CFArray<CFType> = unsafe { CFArray::wrap_under_create_rule(list) };
+    let battery_percent = list.iter().find_map(|source| {
+        let desc =
+            unsafe { IOPSGetPowerSourceDescription(info.as_CFTypeRef(), source.as_CFTypeRef()) };
+        if desc.is_null() {
+            return None;
+        }
+        let desc: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_get_rule(desc) };
+        let ty: CFString = desc.find(CFString::from_static_string("Type"))?.downcast()?;
+        if ty != "InternalBattery" {
+            return None;
+        }
+        let current = get_num(&desc, "Current Capacity")?;
+        let max = get_num(&desc, "Max Capacity")?;
+        (max > 0.0).then(|| 100.0 * current / max)
+    });
+    PowerSource { on_battery, battery_percent }
+}
+
+fn get_num(dict: &CFDictionary<CFString, CFType>, key: &'static str) -> Option<f64> {
+    let item: CFNumber = dict.find(CFString::from_static_string(key))?.downcast()?;
+    item.to_f64()
+}
+
+/// Calls `on_change` on a background thread with the current power source,
+/// and again whenever it changes, checking every `interval`.
+pub fn watch_power_source(
+    interval: Duration,
+    idle: IdleMonitor,
+    on_change: impl Fn(PowerSource) + Send + 'static,
+) {
+    idle::watch_changes(interval, idle, None, power_source, move |&source| {
+        on_change(source)
+    });
+}
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOPSCopyPowerSourcesInfo() -> CFTypeRef;
+    fn IOPSCopyPowerSourcesList(blob: CFTypeRef) -> CFArrayRef;
+    fn IOPSGetPowerSourceDescription(blob: CFTypeRef, source: CFTypeRef) -> CFDictionaryRef;
+    fn IOPSGetProvidingPowerSourceType(snapshot: CFTypeRef) -> CFStringRef;
+}
@@ -0,0 +1,120 @@
+//! Helpers for watching other processes.
+
+use std::{
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+    sync::Arc,
+    thread,
+};
+
+use accessibility_sys::pid_t;
+use tracing::warn;
+
+/// Notifies when processes exit, using kqueue.
+///
+/// This works even when a process crashes without cleaning up after itself.
+#[derive(Clone)]
+pub struct ExitWatcher {
+    kq: Arc<OwnedFd>,
+}
+
+impl ExitWatcher {
+    /// Creates a watcher and spawns a thread that calls `on_exit` with the pid
+    /// of each watched process when it exits.
+    pub fn spawn(on_exit: impl Fn(pid_t) + Send + 'static) -> io::Result<ExitWatcher> {
+        let kq = unsafe { libc::kqueue() };
+        if kq < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let kq = Arc::new(unsafe { OwnedFd::from_raw_fd(kq) });
+        let this = ExitWatcher { kq };
+        let thread_kq = this.kq.clone();
+        thread::spawn(move || wait_for_exits(&thread_kq, on_exit));
+        Ok(this)
+    }
+
+    /// Starts watching `pid`.
+    ///
+    /// Returns an error if the process does not exist.
+    pub fn watch(&self, pid: pid_t) -> io::Result<()> {
+        let change = libc::kevent {
+            ident: pid as libc::uintptr_t,
+            filter: libc::EVFILT_PROC,
+            flags: libc::EV_ADD | libc::EV_ONESHOT,
+            fflags: libc::NOTE_EXIT,
+            data: 0,
+            udata: ptr::null_mut(),
+        };
+        let res = unsafe {
+            libc::kevent(
+                self.kq.as_raw_fd(),
+                &change,
+                1,
+                ptr::null_mut(),
+                0,
+                ptr::null(),
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Returns the pid of the process that started `pid`, if it is still known.
+pub fn parent_pid(pid: pid_t) -> Option<pid_t> {
+    let mut info: libc::proc_bsdinfo = unsafe { mem::zeroed() };
+    let size = mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+    let res = unsafe {
+        libc::proc_pidinfo(
+            pid,
+            libc::PROC_PIDTBSDINFO,
+            0,
+            &mut info as *mut _ as *mut libc::c_void,
+            size,
+        )
+    };
+    (res == size).then_some(info.pbi_ppid as pid_t)
+}
+
+/// Returns the names of all running processes, including the ones without an
+/// app bundle, which NSWorkspace doesn't list.
+pub fn process_names() -> Vec<String> {
+    let count = unsafe { libc::proc_listallpids(ptr::null_mut(), 0) };
+    if count <= 0 {
+        return Vec::new();
+    }
+    // Leave room for processes started since we asked.
+    let mut pids: Vec<pid_t> = vec![0; count as usize + 32];
+    let size = (pids.len() * mem::size_of::<pid_t>()) as libc::c_int;
+    let count = unsafe { libc::proc_listallpids(pids.as_mut_ptr().cast(), size) };
+    pids.truncate(count.max(0) as usize);
+    pids.into_iter().filter_map(process_name).collect()
+}
+
+fn process_name(pid: pid_t) -> Option<String> {
+    let mut buf = [0u8; 64];
+    let len = unsafe { libc::proc_name(pid, buf.as_mut_ptr().cast(), buf.len() as u32) };
+    (len > 0).then(|| String::from_utf8_lossy(&buf[..len as usize]).into_owned())
+}
+
+fn wait_for_exits(kq: &OwnedFd, on_exit: impl Fn(pid_t)) {
+    loop {
+        let mut event: libc::kevent = unsafe { mem::zeroed() };
+        let res =
+            unsafe { libc::kevent(kq.as_raw_fd(), ptr::null(), 0, &mut event, 1, ptr::null()) };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            warn!("Stopped watching for process exits: {err}");
+            return;
+        }
+        if res == 1 && event.filter == libc::EVFILT_PROC && event.fflags & libc::NOTE_EXIT != 0 {
+            on_exit(event.ident as pid_t);
+        }
+    }
+}
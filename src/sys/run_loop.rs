@@ -1,13 +1,15 @@
 //! Helpers for managing run loops.
 
-use std::{ffi::c_void, mem, ptr};
+use std::{ffi::c_void, mem, ptr, time::Duration};
 
 use core_foundation::{
     base::TCFType,
+    date::CFDate,
     mach_port::CFIndex,
     runloop::{
         kCFRunLoopCommonModes, CFRunLoop, CFRunLoopSource, CFRunLoopSourceContext,
-        CFRunLoopSourceCreate, CFRunLoopSourceSignal, CFRunLoopWakeUp,
+        CFRunLoopSourceCreate, CFRunLoopSourceSignal, CFRunLoopTimer, CFRunLoopTimerContext,
+        CFRunLoopTimerRef, CFRunLoopWakeUp,
     },
 };
 
@@ -52,20 +54,6 @@ impl WakeupHandle {
             let handler = unsafe { &mut *(info as *mut Handler<F>) };
             (handler.func)();
         }
-        extern "C" fn retain<F>(info: *const c_void) -> *const c_void {
-            // SAFETY: As above.
-            let handler = unsafe { &mut *(info as *mut Handler<F>) };
-            handler.ref_count += 1;
-            info
-        }
-        extern "C" fn release<F>(info: *const c_void) {
-            // SAFETY: As above.
-            let handler = unsafe { &mut *(info as *mut Handler<F>) };
-            handler.ref_count -= 1;
-            if handler.ref_count == 0 {
-                mem::drop(unsafe { Box::from_raw(info as *mut Handler<F>) });
-            }
-        }
 
         let mut context = CFRunLoopSourceContext {
             version: 0,
@@ -102,6 +90,78 @@ impl WakeupHandle {
     }
 }
 
+/// Calls `handler` once on the current thread's run loop after `delay`.
+///
+/// Like [`WakeupHandle`], the handler is run in all common modes, and only
+/// while the run loop is running.
+pub fn run_after<F: FnOnce() + 'static>(delay: Duration, handler: F) {
+    let handler = Box::into_raw(Box::new(Handler {
+        ref_count: 0,
+        func: Some(handler),
+    }));
+
+    extern "C" fn fire<F: FnOnce() + 'static>(_timer: CFRunLoopTimerRef, info: *mut c_void) {
+        // SAFETY: The timer only fires on the thread that created it, and the
+        // mutable reference lives only during the function call.
+        let handler = unsafe { &mut *(info as *mut Handler<Option<F>>) };
+        if let Some(func) = handler.func.take() {
+            func();
+        }
+    }
+
+    let mut context = CFRunLoopTimerContext {
+        version: 0,
+        info: handler as *mut c_void,
+        retain: Some(retain::<Option<F>>),
+        release: Some(release::<Option<F>>),
+        copyDescription: None,
+    };
+    let fire_date = CFDate::now().abs_time() + delay.as_secs_f64();
+    // A timer that does not repeat is removed from the run loop, and releases
+    // the handler, after it fires.
+    let timer = CFRunLoopTimer::new(fire_date, 0.0, 0, 0, fire::<F>, &mut context);
+    CFRunLoop::get_current().add_timer(&timer, unsafe { kCFRunLoopCommonModes });
+}
+
+/// Calls `handler` once on the main thread's run loop, from any thread.
+pub fn run_on_main_thread<F: FnOnce() + Send + 'static>(handler: F) {
+    extern "C" fn fire<F: FnOnce() + Send + 'static>(_timer: CFRunLoopTimerRef, info: *mut c_void) {
+        // SAFETY: The timer does not repeat, so this is the only call and
+        // nothing else owns the handler.
+        let func = unsafe { Box::from_raw(info as *mut F) };
+        func();
+    }
+
+    // The handler is freed when the timer fires instead of being reference
+    // counted, since the timer is created and fired on different threads.
+    let mut context = CFRunLoopTimerContext {
+        version: 0,
+        info: Box::into_raw(Box::new(handler)) as *mut c_void,
+        retain: None,
+        release: None,
+        copyDescription: None,
+    };
+    let timer = CFRunLoopTimer::new(CFDate::now().abs_time(), 0.0, 0, 0, fire::<F>, &mut context);
+    CFRunLoop::get_main().add_timer(&timer, unsafe { kCFRunLoopCommonModes });
+}
+
+extern "C" fn retain<F>(info: *const c_void) -> *const c_void {
+    // SAFETY: Only the thread that owns the run loop source or timer calls
+    // this, and the mutable reference lives only during the function call.
+    let handler = unsafe { &mut *(info as *mut Handler<F>) };
+    handler.ref_count += 1;
+    info
+}
+
+extern "C" fn release<F>(info: *const c_void) {
+    // SAFETY: As above.
+    let handler = unsafe { &mut *(info as *mut Handler<F>) };
+    handler.ref_count -= 1;
+    if handler.ref_count == 0 {
+        mem::drop(unsafe { Box::from_raw(info as *mut Handler<F>) });
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
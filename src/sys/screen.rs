@@ -1,9 +1,11 @@
-use std::{ffi::c_int, mem::MaybeUninit, num::NonZeroU64};
+use std::{collections::HashMap, ffi::c_int, mem::MaybeUninit, num::NonZeroU64, time::Duration};
 
 use bitflags::bitflags;
 use core_foundation::{
     array::{CFArray, CFArrayRef},
-    base::TCFType,
+    base::{CFType, TCFType},
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
     string::{CFString, CFStringRef},
 };
 use core_graphics::display::{CGDisplayBounds, CGGetActiveDisplayList};
@@ -16,23 +18,176 @@ use icrate::{
 use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 
-use crate::sys::geometry::ToICrate;
+use crate::sys::{
+    geometry::{ScreenCoordinates, ToICrate},
+    idle::{self, IdleMonitor},
+    window_server::WindowServerId,
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[repr(transparent)]
 pub struct SpaceId(NonZeroU64);
 
-#[cfg(test)]
+#[cfg(any(test, feature = "bench"))]
 impl SpaceId {
     pub fn new(id: u64) -> SpaceId {
         SpaceId(NonZeroU64::new(id).unwrap())
     }
 }
 
+/// A persistent identifier for a space.
+///
+/// [`SpaceId`]s are assigned by the window server each time it starts and can
+/// change when displays are attached or detached. Anything about a space that
+/// should be remembered across those events needs to be keyed on this instead.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SpaceUuid(String);
+
+#[cfg(test)]
+impl SpaceUuid {
+    pub fn new(uuid: &str) -> SpaceUuid {
+        SpaceUuid(uuid.to_owned())
+    }
+}
+
+/// A persistent identifier for a display.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DisplayUuid(String);
+
+#[cfg(test)]
+impl DisplayUuid {
+    pub fn new(uuid: &str) -> DisplayUuid {
+        DisplayUuid(uuid.to_owned())
+    }
+}
+
+/// The display a screen belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayInfo {
+    pub uuid: DisplayUuid,
+    /// The name of the display as shown in System Settings.
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpaceInfo {
+    pub id: SpaceId,
+    pub uuid: SpaceUuid,
+    /// The display the space belongs to.
+    pub display: DisplayUuid,
+    /// Whether this is a regular desktop, as opposed to a full screen app.
+    pub is_user_space: bool,
+}
+
+/// Returns every space known to the window server, on all displays.
+pub fn spaces() -> Vec<SpaceInfo> {
+    let displays: CFArray<CFDictionary<CFString, CFType>> = unsafe {
+        CFArray::wrap_under_create_rule(CGSCopyManagedDisplaySpaces(CGSMainConnectionID()))
+    };
+    let display_key = CFString::from_static_string("Display Identifier");
+    let spaces_key = CFString::from_static_string("Spaces");
+    let id_key = CFString::from_static_string("ManagedSpaceID");
+    let uuid_key = CFString::from_static_string("uuid");
+    let type_key = CFString::from_static_string("type");
+    let mut result = vec![];
+    for display in displays.iter() {
+        let Some(display_uuid) = display.find(&display_key).and_then(|s| s.downcast::<CFString>())
+        else {
+            continue;
+        };
+        let Some(spaces) = display.find(&spaces_key).and_then(|s| s.downcast::<CFArray>()) else {
+            continue;
+        };
+        for space in spaces.iter() {
+            let space: CFDictionary<CFString, CFType> =
+                unsafe { CFDictionary::wrap_under_get_rule(*space as CFDictionaryRef) };
+            let id = space.find(&id_key).and_then(|n| n.downcast::<CFNumber>()?.to_i64());
+            let uuid = space.find(&uuid_key).and_then(|s| s.downcast::<CFString>());
+            let (Some(id), Some(uuid)) = (id, uuid) else { continue };
+            let Some(id) = NonZeroU64::new(id as u64) else { continue };
+            let kind = space.find(&type_key).and_then(|n| n.downcast::<CFNumber>()?.to_i64());
+            result.push(SpaceInfo {
+                id: SpaceId(id),
+                uuid: SpaceUuid(uuid.to_string()),
+                display: DisplayUuid(display_uuid.to_string()),
+                is_user_space: kind == Some(0),
+            });
+        }
+    }
+    result
+}
+
+/// Moves a window to another space.
+pub fn move_window_to_space(window: WindowServerId, space: SpaceId) {
+    let windows = CFArray::from_CFTypes(&[CFNumber::from(i64::from(window.as_u32()))]);
+    unsafe {
+        CGSMoveWindowsToManagedSpace(
+            CGSMainConnectionID(),
+            windows.as_concrete_TypeRef(),
+            space.0.get(),
+        )
+    }
+}
+
+/// Returns the space a window is on, if it is on exactly one.
+///
+/// Windows that are on every space, like sticky windows, have no space.
+pub fn window_space(window: WindowServerId) -> Option<SpaceId> {
+    let windows = CFArray::from_CFTypes(&[CFNumber::from(i64::from(window.as_u32()))]);
+    let spaces: CFArray<CFNumber> = unsafe {
+        CFArray::wrap_under_create_rule(CGSCopySpacesForWindows(
+            CGSMainConnectionID(),
+            CGSSpaceMask::ALL_SPACES,
+            windows.as_concrete_TypeRef(),
+        ))
+    };
+    if spaces.len() != 1 {
+        return None;
+    }
+    let id = spaces.get(0)?.to_i64()?;
+    NonZeroU64::new(id as u64).map(SpaceId)
+}
+
+/// Makes `space` the current space of its display.
+///
+/// This happens without the usual animation.
+pub fn switch_to_space(space: &SpaceInfo) {
+    let cid = unsafe { CGSMainConnectionID() };
+    let display = CFString::new(&space.display.0);
+    let cur_space = unsafe { CGSManagedDisplayGetCurrentSpace(cid, display.as_concrete_TypeRef()) };
+    if cur_space == space.id.0.get() {
+        return;
+    }
+    let as_array = |id: u64| CFArray::from_CFTypes(&[CFNumber::from(id as i64)]);
+    unsafe {
+        CGSShowSpaces(cid, as_array(space.id.0.get()).as_concrete_TypeRef());
+        CGSManagedDisplaySetCurrentSpace(cid, display.as_concrete_TypeRef(), space.id.0.get());
+        CGSHideSpaces(cid, as_array(cur_space).as_concrete_TypeRef());
+    }
+}
+
+/// Returns the space currently shown on a display.
+pub fn current_space(display: &DisplayUuid) -> Option<SpaceId> {
+    let display = CFString::new(&display.0);
+    let id = unsafe {
+        CGSManagedDisplayGetCurrentSpace(CGSMainConnectionID(), display.as_concrete_TypeRef())
+    };
+    NonZeroU64::new(id).map(SpaceId)
+}
+
+/// Returns the uuid of every space known to the window server, on all
+/// displays.
+pub fn space_uuids() -> HashMap<SpaceId, SpaceUuid> {
+    spaces().into_iter().map(|space| (space.id, space.uuid)).collect()
+}
+
 /// Calculates the screen and space configuration.
 pub struct ScreenCache<S: System = Actual> {
     system: S,
     uuids: Vec<CFString>,
+    displays: Vec<DisplayInfo>,
 }
 
 impl ScreenCache<Actual> {
@@ -43,7 +198,11 @@ impl ScreenCache<Actual> {
 
 impl<S: System> ScreenCache<S> {
     fn new_with(system: S) -> ScreenCache<S> {
-        ScreenCache { uuids: vec![], system }
+        ScreenCache {
+            uuids: vec![],
+            displays: vec![],
+            system,
+        }
     }
 
     /// Returns a list containing the usable frame for each screen.
@@ -59,6 +218,7 @@ impl<S: System> ScreenCache<S> {
         let mut cg_screens = self.system.cg_screens().unwrap();
         debug!("cg_screens={cg_screens:?}");
         if cg_screens.is_empty() {
+            self.displays = vec![];
             return vec![];
         };
 
@@ -80,31 +240,34 @@ impl<S: System> ScreenCache<S> {
         debug!("ns_screens={ns_screens:?}");
 
         // The main screen has origin (0, 0) in both coordinate systems.
-        let ns_origin_y = cg_screens[0].bounds.max().y;
+        let coords = ScreenCoordinates::new(cg_screens[0].bounds.size.height);
 
-        let visible_frames = cg_screens
+        let (visible_frames, displays) = cg_screens
             .iter()
-            .flat_map(|&CGScreenInfo { cg_id, .. }| {
+            .zip(&self.uuids)
+            .flat_map(|(&CGScreenInfo { cg_id, .. }, uuid)| {
                 let Some(ns_screen) = ns_screens.iter().find(|s| s.cg_id == cg_id) else {
                     warn!("Can't find NSScreen corresponding to screen number {cg_id}");
                     return None;
                 };
-                let converted = CGRect {
-                    origin: CGPoint {
-                        x: ns_screen.visible_frame.origin.x,
-                        // Take the original origin, in converted coordinates,
-                        // and move up to the top-left of the visible frame.
-                        // This is the converted origin of the visible frame.
-                        y: ns_origin_y - ns_screen.visible_frame.max().y,
-                    },
-                    size: ns_screen.visible_frame.size,
+                let converted = coords.rect_from_cocoa(ns_screen.visible_frame);
+                let display = DisplayInfo {
+                    uuid: DisplayUuid(uuid.to_string()),
+                    name: ns_screen.name.clone(),
                 };
-                Some(converted)
+                Some((converted, display))
             })
-            .collect();
+            .unzip();
+        self.displays = displays;
         visible_frames
     }
 
+    /// Returns the display of each screen returned by the last call to
+    /// [`ScreenCache::update_screen_config`], in the same order.
+    pub fn displays(&self) -> Vec<DisplayInfo> {
+        self.displays.clone()
+    }
+
     /// Returns a list of the active spaces on each screen. The order
     /// corresponds to the screens returned by `screen_frames`.
     pub fn get_screen_spaces(&self) -> Vec<Option<SpaceId>> {
@@ -122,6 +285,19 @@ impl<S: System> ScreenCache<S> {
     }
 }
 
+/// Returns the indices of `frames` in the order the displays are arranged:
+/// left to right, then top to bottom for displays that line up vertically.
+///
+/// Commands number displays in this order, starting at 1.
+pub fn arrangement_order(frames: &[CGRect]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..frames.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (frames[a].origin, frames[b].origin);
+        a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y))
+    });
+    order
+}
+
 #[allow(private_interfaces)]
 pub trait System {
     fn cg_screens(&self) -> Result<Vec<CGScreenInfo>, CGError>;
@@ -141,6 +317,7 @@ struct NSScreenInfo {
     frame: CGRect,
     visible_frame: CGRect,
     cg_id: CGDirectDisplayID,
+    name: String,
 }
 
 type CGDirectDisplayID = u32;
@@ -151,27 +328,7 @@ pub struct Actual {
 #[allow(private_interfaces)]
 impl System for Actual {
     fn cg_screens(&self) -> Result<Vec<CGScreenInfo>, CGError> {
-        const MAX_SCREENS: usize = 64;
-        let mut ids: MaybeUninit<[CGDirectDisplayID; MAX_SCREENS]> = MaybeUninit::uninit();
-        let mut count: u32 = 0;
-        let ids = unsafe {
-            let err = CGGetActiveDisplayList(
-                MAX_SCREENS as u32,
-                ids.as_mut_ptr() as *mut CGDirectDisplayID,
-                &mut count,
-            );
-            if err != kCGErrorSuccess {
-                return Err(err);
-            }
-            std::slice::from_raw_parts(ids.as_ptr() as *const u32, count as usize)
-        };
-        Ok(ids
-            .iter()
-            .map(|&cg_id| CGScreenInfo {
-                cg_id,
-                bounds: unsafe { CGDisplayBounds(cg_id).to_icrate() },
-            })
-            .collect())
+        cg_screens()
     }
 
     fn uuid_for_rect(&self, rect: CGRect) -> CFString {
@@ -206,12 +363,86 @@ impl System for Actual {
                     frame: s.frame(),
                     visible_frame: s.visibleFrame(),
                     cg_id,
+                    name: unsafe { s.localizedName() }.to_string(),
                 })
             })
             .collect()
     }
 }
 
+/// Returns the bounds of every active display in CG (top-left origin)
+/// coordinates.
+///
+/// Unlike [`ScreenCache`], this does not need the main thread.
+pub fn active_display_bounds() -> Vec<CGRect> {
+    match cg_screens() {
+        Ok(screens) => screens.into_iter().map(|s| s.bounds).collect(),
+        Err(err) => {
+            warn!("Could not read active displays: {err}");
+            vec![]
+        }
+    }
+}
+
+fn cg_screens() -> Result<Vec<CGScreenInfo>, CGError> {
+    const MAX_SCREENS: usize = 64;
+    let mut ids: MaybeUninit<[CGDirectDisplayID; MAX_SCREENS]> = MaybeUninit::uninit();
+    let mut count: u32 = 0;
+    let ids = unsafe {
+        let err = CGGetActiveDisplayList(
+            MAX_SCREENS as u32,
+            ids.as_mut_ptr() as *mut CGDirectDisplayID,
+            &mut count,
+        );
+        if err != kCGErrorSuccess {
+            return Err(err);
+        }
+        std::slice::from_raw_parts(ids.as_ptr() as *const u32, count as usize)
+    };
+    Ok(ids
+        .iter()
+        .map(|&cg_id| CGScreenInfo {
+            cg_id,
+            bounds: unsafe { CGDisplayBounds(cg_id).to_icrate() },
+        })
+        .collect())
+}
+
+/// Returns whether another process is capturing the screen, for example to
+/// share or record it.
+pub fn is_screen_captured() -> bool {
+    unsafe { CGSIsScreenWatcherPresent() }
+}
+
+/// Calls `on_change` on a background thread whenever screen capture starts or
+/// stops, checking every `interval`.
+pub fn watch_screen_capture(
+    interval: Duration,
+    idle: IdleMonitor,
+    on_change: impl Fn(bool) + Send + 'static,
+) {
+    idle::watch_changes(
+        interval,
+        idle,
+        Some(false),
+        is_screen_captured,
+        move |&captured| on_change(captured),
+    );
+}
+
+/// Calls `on_change` on a background thread with the spaces on every display
+/// whenever spaces are added, removed, or reordered in Mission Control,
+/// checking every `interval`.
+pub fn watch_spaces(
+    interval: Duration,
+    idle: IdleMonitor,
+    on_change: impl Fn(Vec<SpaceInfo>) + Send + 'static,
+) {
+    idle::watch_changes(interval, idle, Some(spaces()), spaces, move |spaces| {
+        on_change(spaces.clone())
+    });
+}
+
 /// Utilities for querying the current system configuration. For diagnostic purposes only.
 #[allow(dead_code)]
 pub mod diagnostic {
@@ -258,6 +489,12 @@ extern "C" {
     fn CGSCopyManagedDisplaySpaces(cid: c_int) -> CFArrayRef;
     fn CGSManagedDisplayGetCurrentSpace(cid: c_int, uuid: CFStringRef) -> u64;
     fn CGSCopyBestManagedDisplayForRect(cid: c_int, rect: CGRect) -> CFStringRef;
+    fn CGSCopySpacesForWindows(cid: c_int, mask: CGSSpaceMask, windows: CFArrayRef) -> CFArrayRef;
+    fn CGSMoveWindowsToManagedSpace(cid: c_int, windows: CFArrayRef, space: u64);
+    fn CGSManagedDisplaySetCurrentSpace(cid: c_int, display: CFStringRef, space: u64);
+    fn CGSShowSpaces(cid: c_int, spaces: CFArrayRef);
+    fn CGSHideSpaces(cid: c_int, spaces: CFArrayRef);
+    fn CGSIsScreenWatcherPresent() -> bool;
 }
 
 bitflags! {
@@ -291,7 +528,10 @@ mod test {
     use core_foundation::string::CFString;
     use icrate::Foundation::{CGPoint, CGRect, CGSize};
 
-    use super::{CGScreenInfo, NSScreenInfo, ScreenCache, System};
+    use super::{
+        arrangement_order, CGScreenInfo, DisplayInfo, DisplayUuid, NSScreenInfo, ScreenCache,
+        System,
+    };
 
     struct Stub {
         cg_screens: Vec<CGScreenInfo>,
@@ -331,6 +571,7 @@ mod test {
                         CGPoint::new(0.0, 76.0),
                         CGSize::new(3840.0, 2059.0),
                     ),
+                    name: "External".to_string(),
                 },
                 NSScreenInfo {
                     cg_id: 1,
@@ -339,6 +580,7 @@ mod test {
                         CGPoint::new(3840.0, 98.0),
                         CGSize::new(1512.0, 950.0),
                     ),
+                    name: "Built-in".to_string(),
                 },
             ],
         };
@@ -350,5 +592,62 @@ mod test {
             ],
             sc.update_screen_config()
         );
+        let display = |name: &str| DisplayInfo {
+            uuid: DisplayUuid::new("stub"),
+            name: name.to_string(),
+        };
+        assert_eq!(
+            vec![display("External"), display("Built-in")],
+            sc.displays()
+        );
+    }
+
+    #[test]
+    fn it_calculates_the_visible_frame_of_screens_with_negative_coordinates() {
+        let rect = |x, y, w, h| CGRect::new(CGPoint::new(x, y), CGSize::new(w, h));
+        let stub = Stub {
+            cg_screens: vec![
+                // Above and to the left of the main screen.
+                CGScreenInfo {
+                    cg_id: 2,
+                    bounds: rect(-1000.0, -1080.0, 1920.0, 1080.0),
+                },
+                CGScreenInfo {
+                    cg_id: 1,
+                    bounds: rect(0.0, 0.0, 1512.0, 982.0),
+                },
+            ],
+            ns_screens: vec![
+                NSScreenInfo {
+                    cg_id: 1,
+                    frame: rect(0.0, 0.0, 1512.0, 982.0),
+                    visible_frame: rect(0.0, 0.0, 1512.0, 950.0),
+                    name: "Built-in".to_string(),
+                },
+                NSScreenInfo {
+                    cg_id: 2,
+                    frame: rect(-1000.0, 982.0, 1920.0, 1080.0),
+                    visible_frame: rect(-1000.0, 982.0, 1920.0, 1055.0),
+                    name: "External".to_string(),
+                },
+            ],
+        };
+        let mut sc = ScreenCache::new_with(stub);
+        assert_eq!(
+            vec![
+                rect(0.0, 32.0, 1512.0, 950.0),
+                rect(-1000.0, -1055.0, 1920.0, 1055.0),
+            ],
+            sc.update_screen_config()
+        );
+    }
+
+    #[test]
+    fn it_orders_displays_by_arrangement() {
+        let rect = |x, y| CGRect::new(CGPoint::new(x, y), CGSize::new(1000.0, 1000.0));
+        // The main screen is first, with a screen stacked above it and one to
+        // its left.
+        let frames = [rect(0.0, 0.0), rect(0.0, -1000.0), rect(-1000.0, 200.0)];
+        assert_eq!(vec![2, 1, 0], arrangement_order(&frames));
     }
 }
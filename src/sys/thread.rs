@@ -0,0 +1,39 @@
+//! Measuring the CPU time used by threads.
+
+use std::{mem, time::Duration};
+
+/// A thread whose CPU time can be read from other threads.
+///
+/// This holds the thread's Mach port name without a reference to it, so once
+/// the thread exits the name may be reused for another thread.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadCpu(libc::mach_port_t);
+
+impl ThreadCpu {
+    pub fn current() -> ThreadCpu {
+        ThreadCpu(unsafe { libc::pthread_mach_thread_np(libc::pthread_self()) })
+    }
+
+    /// Returns the CPU time the thread has used, in both user and system
+    /// mode, or None if the thread is gone.
+    pub fn time(&self) -> Option<Duration> {
+        let mut info: libc::thread_basic_info = unsafe { mem::zeroed() };
+        let mut count = libc::THREAD_BASIC_INFO_COUNT;
+        let res = unsafe {
+            libc::thread_info(
+                self.0,
+                libc::THREAD_BASIC_INFO as libc::thread_flavor_t,
+                &mut info as *mut _ as libc::thread_info_t,
+                &mut count,
+            )
+        };
+        if res != libc::KERN_SUCCESS {
+            return None;
+        }
+        let time = |time: libc::time_value_t| {
+            Duration::from_secs(time.seconds as u64)
+                + Duration::from_micros(time.microseconds as u64)
+        };
+        Some(time(info.user_time) + time(info.system_time))
+    }
+}
@@ -0,0 +1,73 @@
+//! Finding other programs that move windows around.
+//!
+//! Nothing reports which process moved a window, so when something fights
+//! with us over a window we can only point at the likely suspects.
+
+use core_foundation::{
+    base::{Boolean, TCFType},
+    string::{CFString, CFStringRef},
+};
+use icrate::AppKit::NSWorkspace;
+
+use super::{app::NSRunningApplicationExt, process};
+
+/// Apps known to move windows, by bundle id.
+const WINDOW_MANAGER_APPS: &[(&str, &str)] = &[
+    ("bobko.aerospace", "AeroSpace"),
+    ("com.amethyst.Amethyst", "Amethyst"),
+    ("com.crowdcafe.windowmagnet", "Magnet"),
+    ("com.hegenberg.BetterTouchTool", "BetterTouchTool"),
+    ("com.knollsoft.Hookshot", "Hookshot"),
+    ("com.knollsoft.Rectangle", "Rectangle"),
+    ("com.manytricks.Moom", "Moom"),
+];
+
+/// Window managers that run without a bundle, by process name. NSWorkspace
+/// only lists apps, so these are found in the process list.
+const WINDOW_MANAGER_PROCESSES: &[&str] = &["yabai"];
+
+/// Returns the names of running programs that are known to move windows, and
+/// of macOS features that do so if they are turned on.
+pub fn other_window_managers() -> Vec<String> {
+    let mut names = vec![];
+    for app in unsafe { NSWorkspace::sharedWorkspace().runningApplications() } {
+        let bundle_id = app.bundle_id().map(|id| id.to_string());
+        let known = WINDOW_MANAGER_APPS
+            .iter()
+            .find(|(id, _)| bundle_id.as_deref() == Some(id))
+            .map(|(_, name)| name.to_string());
+        names.extend(known);
+    }
+    for name in process::process_names() {
+        if WINDOW_MANAGER_PROCESSES.contains(&&*name) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if is_stage_manager_enabled() {
+        names.push("Stage Manager".to_string());
+    }
+    names
+}
+
+fn is_stage_manager_enabled() -> bool {
+    let key = CFString::from_static_string("GloballyEnabled");
+    let app = CFString::from_static_string("com.apple.WindowManager");
+    let mut exists = 0;
+    let enabled = unsafe {
+        CFPreferencesGetAppBooleanValue(
+            key.as_concrete_TypeRef(),
+            app.as_concrete_TypeRef(),
+            &mut exists,
+        )
+    };
+    exists != 0 && enabled != 0
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFPreferencesGetAppBooleanValue(
+        key: CFStringRef,
+        application_id: CFStringRef,
+        key_exists_and_has_valid_format: *mut Boolean,
+    ) -> Boolean;
+}
@@ -1,3 +1,8 @@
+use std::{
+    ffi::{c_int, c_void},
+    fmt::Debug,
+};
+
 use super::geometry::ToICrate;
 
 use accessibility::AXUIElement;
@@ -15,9 +20,10 @@ use core_graphics::{
     },
     window::{
         kCGWindowBounds, kCGWindowLayer, kCGWindowListOptionExcludeDesktopElements,
-        kCGWindowNumber, kCGWindowOwnerPID,
+        kCGWindowNumber, kCGWindowOwnerPID, CGWindowListCreateDescriptionFromArray,
     },
 };
+use core_graphics_types::base::{kCGErrorSuccess, CGError};
 use icrate::Foundation::CGRect;
 
 /// The window ID used by the window server.
@@ -29,7 +35,10 @@ use icrate::Foundation::CGRect;
 pub struct WindowServerId(CGWindowID);
 
 impl WindowServerId {
-    #[cfg(test)]
+    /// Stands in for the id of windows the window server has no id for.
+    pub const NONE: WindowServerId = WindowServerId(kCGNullWindowID);
+
+    #[cfg(any(test, feature = "bench"))]
     pub fn new(id: CGWindowID) -> Self {
         WindowServerId(id)
     }
@@ -37,6 +46,12 @@ impl WindowServerId {
     pub fn as_u32(&self) -> u32 {
         self.0
     }
+
+    /// Whether this is a real id. The window server reports 0 for windows it
+    /// has no id for.
+    pub fn is_valid(&self) -> bool {
+        *self != WindowServerId::NONE
+    }
 }
 
 impl Into<u32> for WindowServerId {
@@ -66,7 +81,6 @@ pub struct WindowServerInfo {
 
 /// Returns a list of windows visible on the screen, in order starting with the
 /// frontmost.
-#[allow(dead_code)]
 pub fn get_visible_windows() -> Vec<WindowServerInfo> {
     // Note that the ordering is not documented. But
     // NSWindow::windowNumbersWithOptions *is* documented to return the windows
@@ -87,20 +101,166 @@ pub fn get_visible_windows() -> Vec<WindowServerInfo> {
             };
             layer == 0
         })
-        .filter_map(|win| {
-            let id = get_num(&win, unsafe { kCGWindowNumber })?;
-            let pid = get_num(&win, unsafe { kCGWindowOwnerPID })?;
-            let frame: CFDictionary = win.find(unsafe { kCGWindowBounds })?.downcast()?;
-            let frame = core_graphics_types::geometry::CGRect::from_dict_representation(&frame)?;
-            Some(WindowServerInfo {
-                id: WindowServerId(id.try_into().ok()?),
-                pid: pid.try_into().ok()?,
-                frame: frame.to_icrate(),
-            })
-        })
+        .filter_map(|win| make_info(&win))
         .collect::<Vec<_>>()
 }
 
+/// Returns information about each of the given windows that still exists.
+///
+/// This makes a single request to the window server, which is much faster
+/// than reading the frame of each window through the accessibility API.
+pub fn get_windows(ids: &[WindowServerId]) -> Vec<WindowServerInfo> {
+    if ids.is_empty() {
+        return vec![];
+    }
+    // The array holds raw window ids, not CFNumbers.
+    let ids: Vec<*const c_void> = ids.iter().map(|id| id.0 as usize as *const c_void).collect();
+    let ids = CFArray::from_copyable(&ids);
+    let windows = unsafe { CGWindowListCreateDescriptionFromArray(ids.as_concrete_TypeRef()) };
+    if windows.is_null() {
+        return vec![];
+    }
+    let windows: CFArray<CFDictionary<CFString, CFType>> =
+        unsafe { CFArray::wrap_under_create_rule(windows) };
+    windows.iter().filter_map(|win| make_info(&win)).collect()
+}
+
+fn make_info(win: &CFDictionary<CFString, CFType>) -> Option<WindowServerInfo> {
+    let id = get_num(win, unsafe { kCGWindowNumber })?;
+    let pid = get_num(win, unsafe { kCGWindowOwnerPID })?;
+    let frame: CFDictionary = win.find(unsafe { kCGWindowBounds })?.downcast()?;
+    let frame = core_graphics_types::geometry::CGRect::from_dict_representation(&frame)?;
+    Some(WindowServerInfo {
+        id: WindowServerId(id.try_into().ok()?),
+        pid: pid.try_into().ok()?,
+        frame: frame.to_icrate(),
+    })
+}
+
+/// The window server queries made by the reactor, abstracted so tests can
+/// answer them.
+///
+/// Window ids can be reused after a window closes, so callers should check
+/// that the pid of each window matches the app they expect.
+pub trait WindowServer: Debug + Send + Sync {
+    /// See [`get_visible_windows`].
+    fn visible_windows(&self) -> Vec<WindowServerInfo>;
+
+    /// See [`get_windows`].
+    fn windows(&self, ids: &[WindowServerId]) -> Vec<WindowServerInfo>;
+
+    /// See [`set_window_appearance`].
+    fn set_appearance(
+        &self,
+        id: WindowServerId,
+        appearance: WindowAppearance,
+    ) -> Result<(), CGError>;
+}
+
+/// The real window server.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemWindowServer;
+
+impl WindowServer for SystemWindowServer {
+    fn visible_windows(&self) -> Vec<WindowServerInfo> {
+        get_visible_windows()
+    }
+
+    fn windows(&self, ids: &[WindowServerId]) -> Vec<WindowServerInfo> {
+        get_windows(ids)
+    }
+
+    fn set_appearance(
+        &self,
+        id: WindowServerId,
+        appearance: WindowAppearance,
+    ) -> Result<(), CGError> {
+        set_window_appearance(id, appearance)
+    }
+}
+
+/// Returns the window level of a window, which is 0 for normal windows.
+pub fn window_level(id: WindowServerId) -> Option<i32> {
+    let mut level = 0;
+    let err = unsafe { CGSGetWindowLevel(CGSMainConnectionID(), id.0, &mut level) };
+    (err == kCGErrorSuccess).then_some(level)
+}
+
+/// Where to move a window in the window server ordering.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum WindowOrder {
+    Above = 1,
+    Below = -1,
+    Out = 0,
+}
+
+/// Moves a window above or below another window, or to the front or back of
+/// its level if `relative_to` is `None`.
+///
+/// Unlike raising a window with the accessibility API, this does not bring
+/// any other windows of the owning app forward. However, the window server
+/// may refuse to reorder windows owned by other processes, so callers need a
+/// fallback.
+pub fn order_window(
+    id: WindowServerId,
+    order: WindowOrder,
+    relative_to: Option<WindowServerId>,
+) -> Result<(), CGError> {
+    let relative_to = relative_to.map(|id| id.0).unwrap_or(0);
+    let err = unsafe { CGSOrderWindow(CGSMainConnectionID(), id.0, order as c_int, relative_to) };
+    if err != kCGErrorSuccess {
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// How the window server draws a window, apart from its contents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowAppearance {
+    /// From 0 for invisible to 1 for opaque.
+    pub opacity: f64,
+    pub has_shadow: bool,
+}
+
+impl Default for WindowAppearance {
+    fn default() -> Self {
+        WindowAppearance { opacity: 1.0, has_shadow: true }
+    }
+}
+
+/// The window tag that turns off a window's shadow.
+const NO_SHADOW_TAG: c_int = 1 << 3;
+
+/// Changes how the window server draws a window.
+///
+/// The window server usually only lets the process that owns a window change
+/// it, so this fails for windows of other apps on many systems.
+pub fn set_window_appearance(
+    id: WindowServerId,
+    appearance: WindowAppearance,
+) -> Result<(), CGError> {
+    let cid = unsafe { CGSMainConnectionID() };
+    let err = unsafe { CGSSetWindowAlpha(cid, id.0, appearance.opacity as f32) };
+    if err != kCGErrorSuccess {
+        return Err(err);
+    }
+    // Tags are given as a bit field, in an array of ints.
+    let tags = [NO_SHADOW_TAG, 0];
+    let err = unsafe {
+        if appearance.has_shadow {
+            CGSClearWindowTags(cid, id.0, tags.as_ptr(), 64)
+        } else {
+            CGSSetWindowTags(cid, id.0, tags.as_ptr(), 64)
+        }
+    };
+    if err != kCGErrorSuccess {
+        return Err(err);
+    }
+    Ok(())
+}
+
 fn get_num(dict: &CFDictionary<CFString, CFType>, key: CFStringRef) -> Option<i64> {
     let item: CFNumber = dict.find(key)?.downcast()?;
     Some(item.to_i64()?)
@@ -109,3 +269,15 @@ fn get_num(dict: &CFDictionary<CFString, CFType>, key: CFStringRef) -> Option<i6
 extern "C" {
     fn _AXUIElementGetWindow(elem: AXUIElementRef, wid: *mut CGWindowID) -> AXError;
 }
+
+// These are exported by CoreGraphics, which forwards them to SkyLight.
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGSMainConnectionID() -> c_int;
+    fn CGSGetWindowLevel(cid: c_int, wid: CGWindowID, level: *mut c_int) -> CGError;
+    fn CGSOrderWindow(cid: c_int, wid: CGWindowID, mode: c_int, relative_to: CGWindowID)
+        -> CGError;
+    fn CGSSetWindowAlpha(cid: c_int, wid: CGWindowID, alpha: f32) -> CGError;
+    fn CGSSetWindowTags(cid: c_int, wid: CGWindowID, tags: *const c_int, size: c_int) -> CGError;
+    fn CGSClearWindowTags(cid: c_int, wid: CGWindowID, tags: *const c_int, size: c_int) -> CGError;
+}
@@ -0,0 +1,142 @@
+//! Checking GitHub for newer releases.
+//!
+//! We don't install updates ourselves; the user is pointed at the release
+//! page instead.
+
+use std::{process, thread, time::Duration};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/nimbuswm/nimbus/releases/latest";
+
+/// The version of the running binary.
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How often the background checker looks for a new release.
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    /// The version without any leading `v`.
+    pub version: String,
+    /// The release page, which has the changelog.
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    html_url: String,
+}
+
+/// Fetches the latest release.
+///
+/// This shells out to `curl`, which ships with macOS, rather than pulling in
+/// an HTTP client for one request a day.
+pub fn latest_release() -> anyhow::Result<Release> {
+    let output = process::Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--max-time",
+            "30",
+        ])
+        .args(["--header", "Accept: application/vnd.github+json"])
+        .arg(LATEST_RELEASE_URL)
+        .output()
+        .context("could not run curl")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!(
+            "could not fetch the latest release: {}",
+            stderr.trim()
+        ));
+    }
+    let release: GithubRelease =
+        serde_json::from_slice(&output.stdout).context("could not read the latest release")?;
+    Ok(Release {
+        version: release.tag_name.trim_start_matches('v').to_string(),
+        url: release.html_url,
+    })
+}
+
+/// Returns the latest release if it is newer than the running version.
+pub fn check_for_update() -> anyhow::Result<Option<Release>> {
+    let release = latest_release()?;
+    Ok(is_newer(&release.version, CURRENT_VERSION).then_some(release))
+}
+
+/// Calls `on_update` on a background thread whenever a new release is found,
+/// checking now and then once a day.
+///
+/// Each release is only reported once.
+pub fn watch_for_updates(on_update: impl Fn(Release) + Send + 'static) {
+    thread::spawn(move || {
+        let mut reported = None;
+        loop {
+            match check_for_update() {
+                Ok(Some(release)) if reported.as_ref() != Some(&release.version) => {
+                    reported = Some(release.version.clone());
+                    on_update(release);
+                }
+                Ok(_) => debug!("No update available"),
+                Err(e) => warn!("Could not check for updates: {e:#}"),
+            }
+            thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+/// Returns whether `latest` is a newer version than `current`.
+///
+/// Versions that can't be parsed are never newer, so a strange tag doesn't
+/// nag the user.
+fn is_newer(latest: &str, current: &str) -> bool {
+    match (parse_version(latest), parse_version(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => false,
+    }
+}
+
+/// Parses the numeric part of a `major.minor.patch` version, ignoring any
+/// pre-release or build suffix.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_newer, parse_version};
+
+    #[test]
+    fn it_parses_versions() {
+        assert_eq!(Some((1, 2, 3)), parse_version("1.2.3"));
+        assert_eq!(Some((1, 2, 3)), parse_version("v1.2.3"));
+        assert_eq!(Some((0, 4, 0)), parse_version("0.4"));
+        assert_eq!(Some((2, 0, 1)), parse_version("2.0.1-beta.1"));
+        assert_eq!(None, parse_version("nightly"));
+        assert_eq!(None, parse_version("1.2.3.4"));
+    }
+
+    #[test]
+    fn it_compares_versions() {
+        assert!(is_newer("0.2.0", "0.1.9"));
+        assert!(is_newer("v1.0.0", "0.10.0"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.0.9", "0.1.0"));
+        assert!(!is_newer("latest", "0.1.0"));
+    }
+}